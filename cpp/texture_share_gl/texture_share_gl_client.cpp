@@ -40,6 +40,45 @@ const ShmemDataInternal *TextureShareGlClient::ClientImageDataGuard::read() cons
 	return gl_client_image_data_guard_read(this->_data);
 }
 
+TextureShareGlClient::ClientImageList::ClientImageList(::ClientImageList *list)
+	: _list(list)
+{}
+
+TextureShareGlClient::ClientImageList::~ClientImageList()
+{
+	gl_client_image_list_destroy(this->_list);
+	this->_list = nullptr;
+}
+
+TextureShareGlClient::ClientImageList::ClientImageList(ClientImageList &&other)
+	: _list(std::move(other._list))
+{
+	other._list = nullptr;
+}
+
+TextureShareGlClient::ClientImageList &TextureShareGlClient::ClientImageList::operator=(
+	ClientImageList &&other)
+{
+	gl_client_image_list_destroy(this->_list);
+	this->_list = std::move(other._list);
+	other._list = nullptr;
+
+	return *this;
+}
+
+bool TextureShareGlClient::ClientImageList::is_valid() const
+{
+	return this->_list != nullptr;
+}
+
+const ClientImageListEntry *TextureShareGlClient::ClientImageList::next()
+{
+	if(!this->_list)
+		return nullptr;
+
+	return gl_client_image_list_next(this->_list);
+}
+
 TextureShareGlClient::TextureShareGlClient() {}
 
 TextureShareGlClient::TextureShareGlClient::TextureShareGlClient(TextureShareGlClient &&other)
@@ -99,12 +138,14 @@ void TextureShareGlClient::destroy_client()
 }
 
 ImageLookupResult TextureShareGlClient::init_image(const char *image_name, uint32_t width, uint32_t height,
-                                                   ImgFormat format, bool overwrite_existing)
+                                                   ImgFormat format, bool overwrite_existing,
+                                                   uint32_t buffer_count)
 {
 	if(!this->_client)
 		return ImageLookupResult::Error;
 
-	return gl_client_init_image(this->_client, image_name, width, height, format, overwrite_existing);
+	return gl_client_init_image_with_buffer_count(this->_client, image_name, width, height, format,
+	                                              overwrite_existing, buffer_count);
 }
 
 ImageLookupResult TextureShareGlClient::find_image(const char *image_name, bool force_update)
@@ -124,6 +165,30 @@ TextureShareGlClient::ClientImageDataGuard TextureShareGlClient::find_image_data
 	return ClientImageDataGuard(gl_client_find_image_data(this->_client, image_name, force_update));
 }
 
+ImageLookupResult TextureShareGlClient::rename_image(const char *old_image_name, const char *new_image_name)
+{
+	if(!this->_client)
+		return ImageLookupResult::Error;
+
+	return gl_client_rename_image(this->_client, old_image_name, new_image_name);
+}
+
+ImageLookupResult TextureShareGlClient::delete_image(const char *image_name)
+{
+	if(!this->_client)
+		return ImageLookupResult::Error;
+
+	return gl_client_delete_image(this->_client, image_name);
+}
+
+TextureShareGlClient::ClientImageList TextureShareGlClient::list_images()
+{
+	if(!this->_client)
+		return nullptr;
+
+	return ClientImageList(gl_client_list_images(this->_client));
+}
+
 int TextureShareGlClient::send_image(const char *image_name, GLuint src_texture_id, GLenum src_texture_target,
                                      bool invert, GLuint prev_fbo, const struct GlImageExtent *extents)
 {
@@ -143,3 +208,116 @@ int TextureShareGlClient::recv_image(const char *image_name, GLuint dst_texture_
 	return gl_client_recv_image(this->_client, image_name, dst_texture_id, dst_texture_target, invert, prev_fbo,
 	                            extents);
 }
+
+int TextureShareGlClient::read_pixels(const char *image_name, bool invert, uint8_t *pixels, size_t pixels_size)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_read_pixels(this->_client, image_name, invert, pixels, pixels_size);
+}
+
+int TextureShareGlClient::start_read_pixels(const char *image_name, bool invert)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_start_read_pixels(this->_client, image_name, invert);
+}
+
+int TextureShareGlClient::poll_read_pixels(const char *image_name, uint8_t *pixels, size_t pixels_size)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_poll_read_pixels(this->_client, image_name, pixels, pixels_size);
+}
+
+int TextureShareGlClient::write_pixels(const char *image_name, const uint8_t *pixels, size_t pixels_size,
+                                       size_t stride, bool invert)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_write_pixels(this->_client, image_name, pixels, pixels_size, stride, invert);
+}
+
+void TextureShareGlClient::set_wait_for_copy(bool wait_for_copy)
+{
+	if(!this->_client)
+		return;
+
+	gl_client_set_wait_for_copy(this->_client, wait_for_copy);
+}
+
+void TextureShareGlClient::set_use_semaphores(bool use_semaphores)
+{
+	if(!this->_client)
+		return;
+
+	gl_client_set_use_semaphores(this->_client, use_semaphores);
+}
+
+int TextureShareGlClient::set_use_ram_images(bool use_ram_images)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_set_use_ram_images(this->_client, use_ram_images);
+}
+
+int TextureShareGlClient::poll_copy_image()
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_poll_copy_image(this->_client);
+}
+
+int TextureShareGlClient::subscribe(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_subscribe(this->_client, image_name);
+}
+
+int TextureShareGlClient::unsubscribe(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_unsubscribe(this->_client, image_name);
+}
+
+int TextureShareGlClient::poll_event(ClientImageEvent *event)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_poll_event(this->_client, event);
+}
+
+int TextureShareGlClient::last_frame(const char *image_name, ClientFrameInfo *frame)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_last_frame(this->_client, image_name, frame);
+}
+
+int TextureShareGlClient::has_new_frame(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_has_new_frame(this->_client, image_name);
+}
+
+int TextureShareGlClient::wait_for_frame(const char *image_name, uint64_t timeout_in_millis)
+{
+	if(!this->_client)
+		return -1;
+
+	return gl_client_wait_for_frame(this->_client, image_name, timeout_in_millis);
+}
@@ -3,6 +3,8 @@
 extern "C" {
 #include "texture_share_gl/texture_share_gl_client.h"
 }
+#include <cstddef>
+#include <cstdint>
 #include <string_view>
 
 class TextureShareGlClient
@@ -25,6 +27,23 @@ class TextureShareGlClient
 		::ClientImageDataGuard *_data = nullptr;
 	};
 
+	struct ClientImageList
+	{
+		ClientImageList(::ClientImageList *list);
+		~ClientImageList();
+
+		ClientImageList(const ClientImageList &)            = delete;
+		ClientImageList &operator=(const ClientImageList &) = delete;
+		ClientImageList(ClientImageList &&);
+		ClientImageList &operator=(ClientImageList &&);
+
+		bool is_valid() const;
+		const ClientImageListEntry *next();
+
+		private:
+		::ClientImageList *_list = nullptr;
+	};
+
 	static constexpr std::string_view DEFAULT_SHMEM_PREFIX  = VK_SERVER_DEFAULT_SHMEM_PREFIX;
 	static constexpr std::string_view DEFAULT_LOCKFILE_PATH = VK_SERVER_DEFAULT_LOCKFILE_PATH;
 	static constexpr std::string_view DEFAULT_SOCKET_PATH   = VK_SERVER_DEFAULT_SOCKET_PATH;
@@ -51,17 +70,47 @@ class TextureShareGlClient
 	void destroy_client();
 
 	ImageLookupResult init_image(const char *image_name, uint32_t width, uint32_t height, ImgFormat format,
-	                             bool overwrite_existing);
+	                             bool overwrite_existing, uint32_t buffer_count = 1);
 
 	ImageLookupResult find_image(const char *image_name, bool force_update);
 	ClientImageDataGuard find_image_data(const char *image_name, bool force_update);
 
+	ImageLookupResult rename_image(const char *old_image_name, const char *new_image_name);
+
+	ImageLookupResult delete_image(const char *image_name);
+
+	ClientImageList list_images();
+
 	int send_image(const char *image_name, GLuint src_texture_id, GLenum src_texture_target, bool invert,
 	               GLuint prev_fbo, const struct GlImageExtent *extents);
 
 	int recv_image(const char *image_name, GLuint dst_texture_id, GLenum dst_texture_target, bool invert,
 	               GLuint prev_fbo, const struct GlImageExtent *extents);
 
+	// Copy the tightly packed pixels into a buffer of pixels_size bytes
+	int read_pixels(const char *image_name, bool invert, uint8_t *pixels, size_t pixels_size);
+
+	// Start an asynchronous read, poll_read_pixels returns 0 until the pixels are available
+	int start_read_pixels(const char *image_name, bool invert);
+	int poll_read_pixels(const char *image_name, uint8_t *pixels, size_t pixels_size);
+
+	// A stride of 0 means the rows of pixels are tightly packed
+	int write_pixels(const char *image_name, const uint8_t *pixels, size_t pixels_size, size_t stride = 0,
+	                 bool invert = false);
+
+	void set_wait_for_copy(bool wait_for_copy);
+	void set_use_semaphores(bool use_semaphores);
+	int set_use_ram_images(bool use_ram_images);
+	int poll_copy_image();
+
+	int subscribe(const char *image_name = nullptr);
+	int unsubscribe(const char *image_name = nullptr);
+	int poll_event(ClientImageEvent *event);
+
+	int last_frame(const char *image_name, ClientFrameInfo *frame);
+	int has_new_frame(const char *image_name);
+	int wait_for_frame(const char *image_name, uint64_t timeout_in_millis);
+
 	private:
 	struct GlClient *_client = nullptr;
 };
@@ -39,6 +39,45 @@ const ShmemDataInternal *TextureShareVkClient::ClientImageDataGuard::read() cons
 	return vk_client_image_data_guard_read(this->_data);
 }
 
+TextureShareVkClient::ClientImageList::ClientImageList(::ClientImageList *list)
+	: _list(list)
+{}
+
+TextureShareVkClient::ClientImageList::~ClientImageList()
+{
+	vk_client_image_list_destroy(this->_list);
+	this->_list = nullptr;
+}
+
+TextureShareVkClient::ClientImageList::ClientImageList(ClientImageList &&other)
+	: _list(std::move(other._list))
+{
+	other._list = nullptr;
+}
+
+TextureShareVkClient::ClientImageList &TextureShareVkClient::ClientImageList::operator=(
+	ClientImageList &&other)
+{
+	vk_client_image_list_destroy(this->_list);
+	this->_list = std::move(other._list);
+	other._list = nullptr;
+
+	return *this;
+}
+
+bool TextureShareVkClient::ClientImageList::is_valid() const
+{
+	return this->_list != nullptr;
+}
+
+const ClientImageListEntry *TextureShareVkClient::ClientImageList::next()
+{
+	if(!this->_list)
+		return nullptr;
+
+	return vk_client_image_list_next(this->_list);
+}
+
 TextureShareVkClient::TextureShareVkClient() {}
 
 TextureShareVkClient::TextureShareVkClient::TextureShareVkClient(TextureShareVkClient &&other)
@@ -93,12 +132,14 @@ void TextureShareVkClient::destroy_client()
 }
 
 ImageLookupResult TextureShareVkClient::init_image(const char *image_name, uint32_t width, uint32_t height,
-                                                   ImgFormat format, bool overwrite_existing)
+                                                   ImgFormat format, bool overwrite_existing,
+                                                   uint32_t buffer_count)
 {
 	if(!this->_client)
 		return ImageLookupResult::Error;
 
-	return vk_client_init_image(this->_client, image_name, width, height, format, overwrite_existing);
+	return vk_client_init_image_with_buffer_count(this->_client, image_name, width, height, format,
+	                                              overwrite_existing, buffer_count);
 }
 
 ImageLookupResult TextureShareVkClient::find_image(const char *image_name, bool force_update)
@@ -118,6 +159,30 @@ TextureShareVkClient::ClientImageDataGuard TextureShareVkClient::find_image_data
 	return ClientImageDataGuard(vk_client_find_image_data(this->_client, image_name, force_update));
 }
 
+ImageLookupResult TextureShareVkClient::rename_image(const char *old_image_name, const char *new_image_name)
+{
+	if(!this->_client)
+		return ImageLookupResult::Error;
+
+	return vk_client_rename_image(this->_client, old_image_name, new_image_name);
+}
+
+ImageLookupResult TextureShareVkClient::delete_image(const char *image_name)
+{
+	if(!this->_client)
+		return ImageLookupResult::Error;
+
+	return vk_client_delete_image(this->_client, image_name);
+}
+
+TextureShareVkClient::ClientImageList TextureShareVkClient::list_images()
+{
+	if(!this->_client)
+		return nullptr;
+
+	return ClientImageList(vk_client_list_images(this->_client));
+}
+
 int TextureShareVkClient::send_image(const char *image_name, VkImage image, VkImageLayout orig_layout,
                                      VkImageLayout target_layout, VkFence fence, VkOffset3D *extents)
 {
@@ -135,3 +200,130 @@ int TextureShareVkClient::recv_image(const char *image_name, VkImage image, VkIm
 
 	return vk_client_recv_image(this->_client, image_name, image, orig_layout, target_layout, fence, extents);
 }
+
+int TextureShareVkClient::cmd_send_image(const char *image_name, VkCommandBuffer cmd_buf, VkImage image,
+                                         VkImageLayout orig_layout, VkImageLayout target_layout,
+                                         ClientTimelineSync *timeline_sync, VkOffset3D *extents)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_cmd_send_image(this->_client, image_name, cmd_buf, image, orig_layout, target_layout, extents,
+	                                timeline_sync);
+}
+
+int TextureShareVkClient::cmd_send_image_submitted(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_cmd_send_image_submitted(this->_client, image_name);
+}
+
+int TextureShareVkClient::cmd_send_image_discarded(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_cmd_send_image_discarded(this->_client, image_name);
+}
+
+int TextureShareVkClient::cmd_recv_image(const char *image_name, VkCommandBuffer cmd_buf, VkImage image,
+                                         VkImageLayout orig_layout, VkImageLayout target_layout,
+                                         ClientTimelineSync *timeline_sync, VkOffset3D *extents)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_cmd_recv_image(this->_client, image_name, cmd_buf, image, orig_layout, target_layout, extents,
+	                                timeline_sync);
+}
+
+int TextureShareVkClient::read_pixels(const char *image_name, uint8_t *pixels, size_t pixels_size)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_read_pixels(this->_client, image_name, pixels, pixels_size);
+}
+
+int TextureShareVkClient::write_pixels(const char *image_name, const uint8_t *pixels, size_t pixels_size,
+                                       size_t stride)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_write_pixels(this->_client, image_name, pixels, pixels_size, stride);
+}
+
+void TextureShareVkClient::set_wait_for_copy(bool wait_for_copy)
+{
+	if(!this->_client)
+		return;
+
+	vk_client_set_wait_for_copy(this->_client, wait_for_copy);
+}
+
+int TextureShareVkClient::set_use_ram_images(bool use_ram_images)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_set_use_ram_images(this->_client, use_ram_images);
+}
+
+int TextureShareVkClient::poll_copy_image()
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_poll_copy_image(this->_client);
+}
+
+int TextureShareVkClient::subscribe(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_subscribe(this->_client, image_name);
+}
+
+int TextureShareVkClient::unsubscribe(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_unsubscribe(this->_client, image_name);
+}
+
+int TextureShareVkClient::poll_event(ClientImageEvent *event)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_poll_event(this->_client, event);
+}
+
+int TextureShareVkClient::last_frame(const char *image_name, ClientFrameInfo *frame)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_last_frame(this->_client, image_name, frame);
+}
+
+int TextureShareVkClient::has_new_frame(const char *image_name)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_has_new_frame(this->_client, image_name);
+}
+
+int TextureShareVkClient::wait_for_frame(const char *image_name, uint64_t timeout_in_millis)
+{
+	if(!this->_client)
+		return -1;
+
+	return vk_client_wait_for_frame(this->_client, image_name, timeout_in_millis);
+}
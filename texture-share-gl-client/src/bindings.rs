@@ -1,12 +1,17 @@
 use std::{
 	borrow::Cow,
 	ffi::CStr,
-	ptr::{null_mut, NonNull},
+	ptr::{self, null_mut, NonNull},
 	time::Duration,
 };
 
 use libc::{c_char, c_int};
-use texture_share_ipc::platform::{img_data::ImgFormat, ReadLockGuard, ShmemDataInternal};
+use texture_share_ipc::platform::{
+	img_data::{ImgData, ImgFormat},
+	ipc_commands::{ImageEvent, ImageEventKind, ImageListEntry},
+	FrameInfo, ReadLockGuard, ShmemDataInternal,
+};
+use texture_share_ipc::uuid;
 
 use crate::GlClient;
 use crate::{gl_shared_image::GlImageExtent, opengl::glad};
@@ -35,6 +40,114 @@ impl<'a> ClientImageDataGuard<'a> {
 	}
 }
 
+// Entry returned by the image list iterator. gpu_device_uuids points to gpu_device_count
+// uuid pairs and stays valid until the list is destroyed
+#[repr(C)]
+struct ClientImageListEntry {
+	image_name: [u8; 1024],
+	handle_id: u32,
+	width: u32,
+	height: u32,
+	format: ImgFormat,
+	gpu_device_count: u32,
+	gpu_device_uuids: *const [u64; 2],
+}
+
+#[repr(C)]
+enum ClientImageEventKind {
+	Created = 0,
+	Resized = 1,
+	Deleted = 2,
+	FrameAvailable = 3,
+}
+
+#[repr(C)]
+struct ClientImageEvent {
+	kind: ClientImageEventKind,
+	image_name: [u8; 1024],
+	handle_id: u32,
+	width: u32,
+	height: u32,
+	format: ImgFormat,
+}
+
+impl From<ImageEvent> for ClientImageEvent {
+	fn from(event: ImageEvent) -> Self {
+		ClientImageEvent {
+			kind: match event.kind {
+				ImageEventKind::Created => ClientImageEventKind::Created,
+				ImageEventKind::Resized => ClientImageEventKind::Resized,
+				ImageEventKind::Deleted => ClientImageEventKind::Deleted,
+				ImageEventKind::FrameAvailable => ClientImageEventKind::FrameAvailable,
+			},
+			image_name: ImgData::convert_shmem_str_to_array(&event.image_name).unwrap_or([0; 1024]),
+			handle_id: event.handle_id,
+			width: event.width,
+			height: event.height,
+			format: event.format,
+		}
+	}
+}
+
+// Sequence number and CLOCK_MONOTONIC timestamp in nanoseconds of a frame
+#[repr(C)]
+struct ClientFrameInfo {
+	number: u64,
+	timestamp_ns: u64,
+}
+
+impl From<FrameInfo> for ClientFrameInfo {
+	fn from(frame: FrameInfo) -> Self {
+		ClientFrameInfo {
+			number: frame.number,
+			timestamp_ns: frame.timestamp_ns,
+		}
+	}
+}
+
+struct ClientImageList {
+	entries: Vec<ClientImageListEntry>,
+	_gpu_device_uuids: Vec<Vec<[u64; 2]>>,
+	next: usize,
+}
+
+impl ClientImageList {
+	fn new(images: Vec<ImageListEntry>) -> ClientImageList {
+		let gpu_device_uuids: Vec<Vec<[u64; 2]>> = images
+			.iter()
+			.map(|x| {
+				x.gpu_device_uuids
+					.iter()
+					.map(|uuid| {
+						let pair = uuid::Uuid::from_u128(*uuid).as_u64_pair();
+						[pair.0, pair.1]
+					})
+					.collect()
+			})
+			.collect();
+
+		let entries = images
+			.iter()
+			.zip(gpu_device_uuids.iter())
+			.map(|(x, uuids)| ClientImageListEntry {
+				image_name: ImgData::convert_shmem_str_to_array(&x.image_name).unwrap_or([0; 1024]),
+				handle_id: x.handle_id,
+				width: x.width,
+				height: x.height,
+				format: x.format,
+				gpu_device_count: uuids.len() as u32,
+				gpu_device_uuids: uuids.as_ptr(),
+			})
+			.collect();
+
+		ClientImageList {
+			entries,
+			_gpu_device_uuids: gpu_device_uuids,
+			next: 0,
+		}
+	}
+}
+
 #[no_mangle]
 extern "C" fn gl_client_initialize_external_gl() -> bool {
 	GlClient::initialize_gl_external()
@@ -129,6 +242,36 @@ extern "C" fn gl_client_init_image(
 	}
 }
 
+#[no_mangle]
+extern "C" fn gl_client_init_image_with_buffer_count(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	width: u32,
+	height: u32,
+	format: ImgFormat,
+	overwrite_existing: bool,
+	buffer_count: u32,
+) -> ImageLookupResult {
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.init_image_with_buffer_count(
+			&get_str(&image_name),
+			width,
+			height,
+			format,
+			overwrite_existing,
+			buffer_count,
+		) {
+		Ok(Some(true)) => return ImageLookupResult::RequiresUpdate,
+		Ok(Some(false)) => return ImageLookupResult::Found,
+		Ok(None) => return ImageLookupResult::NotFound,
+		Err(e) => {
+			println!("Failed to init image with err '{:}'", e);
+			return ImageLookupResult::Error;
+		}
+	}
+}
+
 #[no_mangle]
 extern "C" fn gl_client_find_image(
 	gl_client: *mut GlClient,
@@ -150,6 +293,78 @@ extern "C" fn gl_client_find_image(
 	}
 }
 
+#[no_mangle]
+extern "C" fn gl_client_rename_image(
+	gl_client: *mut GlClient,
+	old_image_name: *const c_char,
+	new_image_name: *const c_char,
+) -> ImageLookupResult {
+	let res = unsafe { gl_client.as_mut() }
+		.unwrap()
+		.rename_image(&get_str(&old_image_name), &get_str(&new_image_name));
+
+	match res {
+		Ok(Some(())) => return ImageLookupResult::Found,
+		Ok(None) => return ImageLookupResult::NotFound,
+		Err(e) => {
+			println!("Failed to rename image with err '{:}'", e);
+			return ImageLookupResult::Error;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn gl_client_delete_image(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+) -> ImageLookupResult {
+	let res = unsafe { gl_client.as_mut() }
+		.unwrap()
+		.delete_image(&get_str(&image_name));
+
+	match res {
+		Ok(Some(())) => return ImageLookupResult::Found,
+		Ok(None) => return ImageLookupResult::NotFound,
+		Err(e) => {
+			println!("Failed to delete image with err '{:}'", e);
+			return ImageLookupResult::Error;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn gl_client_list_images(gl_client: *mut GlClient) -> *mut ClientImageList {
+	match unsafe { gl_client.as_mut() }.unwrap().list_images() {
+		Ok(Some(images)) => Box::into_raw(Box::new(ClientImageList::new(images))),
+		Ok(None) => null_mut(),
+		Err(e) => {
+			println!("Failed to list images with err '{:}'", e);
+			null_mut()
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn gl_client_image_list_next(
+	image_list: *mut ClientImageList,
+) -> *const ClientImageListEntry {
+	let image_list = unsafe { image_list.as_mut() }.unwrap();
+	match image_list.entries.get(image_list.next) {
+		Some(entry) => {
+			image_list.next += 1;
+			entry
+		}
+		None => ptr::null(),
+	}
+}
+
+#[no_mangle]
+extern "C" fn gl_client_image_list_destroy(image_list: Option<NonNull<ClientImageList>>) {
+	if let Some(image_list) = image_list {
+		drop(unsafe { Box::from_raw(image_list.as_ptr()) });
+	}
+}
+
 #[no_mangle]
 extern "C" fn gl_client_find_image_data<'a>(
 	gl_client: *mut GlClient,
@@ -279,3 +494,297 @@ extern "C" fn gl_client_recv_image(
 		}
 	}
 }
+
+// Copies the tightly packed pixels of image_name into pixels, which holds pixels_size bytes.
+// Returns 1 on success, 0 if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_read_pixels(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	invert: bool,
+	pixels: *mut u8,
+	pixels_size: usize,
+) -> c_int {
+	let gl_client = unsafe { gl_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+
+	match gl_client.read_pixels(image_name, invert) {
+		Ok(Some(data)) if data.len() <= pixels_size => {
+			unsafe { ptr::copy_nonoverlapping(data.as_ptr(), pixels, data.len()) };
+			return 1;
+		}
+		Ok(Some(data)) => {
+			println!(
+				"Failed to read pixels, {} bytes are required but only {} are available",
+				data.len(),
+				pixels_size
+			);
+			return -1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to read pixels with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Starts reading image_name into a pixel buffer, gl_client_poll_read_pixels collects the pixels.
+// Returns 1 on success, 0 if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_start_read_pixels(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	invert: bool,
+) -> c_int {
+	let gl_client = unsafe { gl_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+
+	match gl_client.start_read_pixels(image_name, invert) {
+		Ok(Some(_)) => return 1,
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to start pixel read with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Returns 1 if the pixels were copied, 0 if the read is still in flight, -1 on failure or if the
+// image wasn't found
+#[no_mangle]
+extern "C" fn gl_client_poll_read_pixels(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	pixels: *mut u8,
+	pixels_size: usize,
+) -> c_int {
+	let gl_client = unsafe { gl_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+	let pixels = match pixels.is_null() {
+		true => &mut [],
+		false => unsafe { std::slice::from_raw_parts_mut(pixels, pixels_size) },
+	};
+
+	match gl_client.poll_read_pixels(image_name, pixels) {
+		Ok(Some(true)) => return 1,
+		Ok(Some(false)) => return 0,
+		Ok(None) => {
+			println!("Failed to poll pixel read, image '{}' wasn't found", image_name);
+			return -1;
+		}
+		Err(e) => {
+			println!("Failed to poll pixel read with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Writes pixels_size bytes with rows that are stride bytes apart into image_name. A stride of 0
+// means the rows are tightly packed. Returns 1 on success, 0 if the image wasn't found, -1 on
+// failure
+#[no_mangle]
+extern "C" fn gl_client_write_pixels(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	pixels: *const u8,
+	pixels_size: usize,
+	stride: usize,
+	invert: bool,
+) -> c_int {
+	let gl_client = unsafe { gl_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+	let pixels = match pixels.is_null() {
+		true => &[],
+		false => unsafe { std::slice::from_raw_parts(pixels, pixels_size) },
+	};
+
+	match gl_client.write_pixels(image_name, pixels, stride, invert) {
+		Ok(Some(_)) => return 1,
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to write pixels with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn gl_client_set_wait_for_copy(gl_client: *mut GlClient, wait_for_copy: bool) {
+	unsafe { gl_client.as_mut() }
+		.unwrap()
+		.set_wait_for_copy(wait_for_copy);
+}
+
+// Disable for drivers without GL_EXT_semaphore_fd or GL_NV_timeline_semaphore
+#[no_mangle]
+extern "C" fn gl_client_set_use_semaphores(gl_client: *mut GlClient, use_semaphores: bool) {
+	unsafe { gl_client.as_mut() }
+		.unwrap()
+		.set_use_semaphores(use_semaphores);
+}
+
+// Share images through RAM if GPU memory can't be shared with the server. Enabled by default if
+// the GL context has no device uuid. Returns 1 on success, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_set_use_ram_images(gl_client: *mut GlClient, use_ram_images: bool) -> c_int {
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.set_use_ram_images(use_ram_images)
+	{
+		Ok(_) => 1,
+		Err(e) => {
+			println!("Failed to set RAM image mode with error '{:}'", e);
+			-1
+		}
+	}
+}
+
+// Returns 1 if an outstanding copy completed, 0 if none completed yet, -1 if it failed
+#[no_mangle]
+extern "C" fn gl_client_poll_copy_image(gl_client: *mut GlClient) -> c_int {
+	match unsafe { gl_client.as_mut() }.unwrap().poll_copy_image() {
+		Ok(Some(res)) if res.success => return 1,
+		Ok(Some(res)) => {
+			if let Some(err) = res.error {
+				println!("Failed to copy image with error '{:}'", err);
+			}
+			return -1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to poll image copy with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Subscribes to events of image_name, or of all images if image_name is NULL.
+// Returns 1 on success, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_subscribe(gl_client: *mut GlClient, image_name: *const c_char) -> c_int {
+	let image_name = match image_name.is_null() {
+		true => None,
+		false => Some(get_str(&image_name)),
+	};
+
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.subscribe(image_name.as_deref())
+	{
+		Ok(Some(())) => return 1,
+		Ok(None) => return -1,
+		Err(e) => {
+			println!("Failed to subscribe with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn gl_client_unsubscribe(gl_client: *mut GlClient, image_name: *const c_char) -> c_int {
+	let image_name = match image_name.is_null() {
+		true => None,
+		false => Some(get_str(&image_name)),
+	};
+
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.unsubscribe(image_name.as_deref())
+	{
+		Ok(Some(())) => return 1,
+		Ok(None) => return -1,
+		Err(e) => {
+			println!("Failed to unsubscribe with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Returns 1 if an event was written to event, 0 if no event is queued, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_poll_event(
+	gl_client: *mut GlClient,
+	event: *mut ClientImageEvent,
+) -> c_int {
+	match unsafe { gl_client.as_mut() }.unwrap().poll_event() {
+		Ok(Some(res)) => {
+			unsafe { event.write(res.into()) };
+			return 1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to poll image events with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Writes the latest frame of image_name to frame. Returns 1 on success, 0 if the image wasn't
+// found, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_last_frame(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	frame: *mut ClientFrameInfo,
+) -> c_int {
+	let image_name = &get_str(&image_name);
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.last_frame(image_name)
+	{
+		Ok(Some(res)) => {
+			unsafe { frame.write(res.into()) };
+			return 1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to get last frame with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Returns 1 if a frame was published since the client last sent or received image_name, 0 if
+// not or if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_has_new_frame(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+) -> c_int {
+	let image_name = &get_str(&image_name);
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.has_new_frame(image_name)
+	{
+		Ok(Some(true)) => return 1,
+		Ok(_) => return 0,
+		Err(e) => {
+			println!("Failed to check for new frames with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Sleeps until a frame was published that the client didn't send or receive yet. Returns 1 if a
+// new frame is available, 0 if the timeout expired or the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn gl_client_wait_for_frame(
+	gl_client: *mut GlClient,
+	image_name: *const c_char,
+	timeout_in_millis: u64,
+) -> c_int {
+	let image_name = &get_str(&image_name);
+	let timeout = Duration::from_millis(timeout_in_millis);
+	match unsafe { gl_client.as_mut() }
+		.unwrap()
+		.wait_for_frame(image_name, timeout)
+	{
+		Ok(Some(true)) => return 1,
+		Ok(_) => return 0,
+		Err(e) => {
+			println!("Failed to wait for frame with error '{:}'", e);
+			return -1;
+		}
+	}
+}
@@ -1,29 +1,188 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use texture_share_ipc::platform::daemon_launch::server_connect_and_daemon_launch;
 use texture_share_ipc::platform::{ReadLockGuard, Timeout};
 
 use std::io::{Error, ErrorKind};
-use std::{mem::ManuallyDrop, os::fd::OwnedFd, time::Duration};
+use std::{os::fd::OwnedFd, time::Duration};
 
 use texture_share_ipc::platform::img_data::{ImgData, ImgFormat};
 use texture_share_ipc::platform::ipc_commands::{
-	CommCopyImage, CommFindImage, CommInitImage, CommandData, CommandMsg, CommandTag,
+	features, ClientKind, CommCopyImage, CommDeleteImage, CommFindImage, CommInitImage,
+	CommListImages, CommRamTransfer, CommRenameImage, CommSubscribe, CommandMsg, ErrorCode,
+	ImageEvent, ImageListEntry, RamTransferDirection, ResultCopyImage, ResultError, ResultMsg,
 };
-use texture_share_ipc::platform::ShmemDataInternal;
-use texture_share_ipc::{IpcConnection, IpcShmem};
+use texture_share_ipc::platform::{monotonic_time_ns, FrameInfo, ShmemDataInternal};
+use texture_share_ipc::uuid;
+use texture_share_ipc::{IpcConnection, IpcShmem, RamImage};
 
-use crate::gl_shared_image::{GlImageExtent, GlSharedImage};
+use crate::gl_shared_image::{GlImageExtent, GlPixelBuffer, GlSharedImage, GlSharedImageData};
 use crate::opengl::glad;
 
 pub struct ImageData {
 	pub ipc_info: IpcShmem,
-	pub vk_shared_image: GlSharedImage,
+	// Ring of images, indexed by the slots in the shared memory. Images shared through RAM have a
+	// single local texture instead, which holds a copy of the RAM image
+	pub vk_shared_images: Vec<GlSharedImage>,
+	pub ram_image: Option<RamImage>,
+	// Staging buffer of the pixel functions, created on first use. Writes drop a pending read
+	pixel_buffer: Option<GlPixelBuffer>,
+	// Number of the latest frame this client sent or received
+	received_frame: u64,
+}
+
+impl ImageData {
+	pub fn get_data(&self) -> &GlSharedImageData {
+		self.vk_shared_images[0].get_data()
+	}
+
+	// Takes the pixel buffer out of the image, so that it can be used while blitting a slot
+	fn take_pixel_buffer(&mut self) -> Result<GlPixelBuffer, Error> {
+		match self.pixel_buffer.take() {
+			Some(pixel_buffer) => Ok(pixel_buffer),
+			None => GlPixelBuffer::new()
+				.map_err(|e| Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e))),
+		}
+	}
+
+	// Runs blit_fcn on the slot that should be written or read next. The lock is only held to
+	// reserve and release the slot, so other clients can access the remaining slots meanwhile.
+	// The reservation is recorded for the connection with the id owner, so the server can complete
+	// it if the client dies before submitting
+	fn blit_slot<F: FnOnce(&mut GlSharedImage, &mut u64) -> Result<(), glad::GLuint>>(
+		&mut self,
+		owner: u32,
+		write: bool,
+		blit_fcn: F,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		if self.ram_image.is_some() {
+			return self.blit_ram(write, blit_fcn);
+		}
+
+		let (slot, reserved_value, read_frame) = {
+			let lock = self
+				.ipc_info
+				.acquire_lock(Timeout::Val(GlClient::IPC_TIMEOUT))?;
+			let data = IpcShmem::acquire_data(&lock);
+			let slot = match write {
+				true => data.acquire_write_slot(),
+				false => data.acquire_read_slot(),
+			};
+			(
+				slot,
+				data.reserve_timeline_value(slot, write, owner),
+				data.frame_number,
+			)
+		};
+
+		let mut timeline_value = reserved_value;
+		let mut res = match self.vk_shared_images.get_mut(slot) {
+			Some(shared_image) => blit_fcn(shared_image, &mut timeline_value)
+				.map_err(|e| Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e))),
+			None => Err(Error::new(
+				ErrorKind::InvalidData,
+				"Image was resized on the server, find it again",
+			)),
+		};
+
+		// Accesses that weren't submitted return their value. If later accesses were reserved
+		// meanwhile, the value is signalled instead so they don't wait for it forever
+		if timeline_value == reserved_value {
+			let cancelled = {
+				let lock = self
+					.ipc_info
+					.acquire_lock(Timeout::Val(GlClient::IPC_TIMEOUT))?;
+				IpcShmem::acquire_data(&lock).cancel_timeline_value(slot, reserved_value)
+			};
+			if let (false, Some(shared_image)) = (cancelled, self.vk_shared_images.get(slot)) {
+				let skipped = shared_image.skip_timeline_value(&mut timeline_value);
+				res = res.and_then(|_| {
+					skipped
+						.map_err(|e| Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e)))
+				});
+			}
+		}
+
+		let lock = self
+			.ipc_info
+			.acquire_lock(Timeout::Val(GlClient::IPC_TIMEOUT))?;
+		let data = IpcShmem::acquire_data(&lock);
+		data.complete_timeline_value(slot, reserved_value);
+		match write {
+			true => data.release_write_slot(slot, res.is_ok()),
+			false => data.release_read_slot(slot),
+		}
+		if res.is_ok() {
+			self.received_frame = match write {
+				true => {
+					data.publish_frame(monotonic_time_ns());
+					data.frame_number
+				}
+				false => read_frame,
+			};
+		}
+
+		Ok(res?)
+	}
+
+	fn has_new_frame(&self) -> Result<bool, Box<dyn std::error::Error>> {
+		let data = self.ipc_info.snapshot(GlClient::IPC_TIMEOUT)?;
+		Ok(data.frame_number != self.received_frame)
+	}
+
+	// Blits the local texture of an image shared through RAM. Before reading it is updated from
+	// the RAM image, after writing the RAM image is updated from it
+	fn blit_ram<F: FnOnce(&mut GlSharedImage, &mut u64) -> Result<(), glad::GLuint>>(
+		&mut self,
+		write: bool,
+		blit_fcn: F,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let gl_err =
+			|e: glad::GLuint| Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e));
+		let ram_image = self.ram_image.as_mut().unwrap();
+		let local_image = &mut self.vk_shared_images[0];
+
+		if !write {
+			let rlock = self
+				.ipc_info
+				.acquire_rlock(Timeout::Val(GlClient::IPC_TIMEOUT))?;
+			let rdata = IpcShmem::acquire_rdata(&rlock);
+			local_image.write_pixels(ram_image.as_slice()).map_err(gl_err)?;
+			self.received_frame = rdata.frame_number;
+		}
+
+		// The local texture isn't shared, so no semaphore is involved
+		let mut timeline_value = 0;
+		blit_fcn(local_image, &mut timeline_value).map_err(gl_err)?;
+
+		if write {
+			let lock = self
+				.ipc_info
+				.acquire_lock(Timeout::Val(GlClient::IPC_TIMEOUT))?;
+			let data = IpcShmem::acquire_data(&lock);
+			local_image
+				.read_pixels(ram_image.as_mut_slice())
+				.map_err(gl_err)?;
+			data.publish_frame(monotonic_time_ns());
+			self.received_frame = data.frame_number;
+		}
+
+		Ok(())
+	}
 }
 
 pub struct GlClient {
 	connection: IpcConnection,
 	shared_images: HashMap<String, ImageData>,
 	gpu_device_uuid: u128,
+	wait_for_copy: bool,
+	// Number of copy commands whose result wasn't received yet
+	pending_copies: usize,
+	// Copy results received while polling for events
+	copy_results: VecDeque<ResultMsg>,
+	events: VecDeque<ImageEvent>,
+	// Import the images' semaphores if the driver supports it
+	use_semaphores: bool,
+	use_ram_images: bool,
 	//timeout: Duration,
 }
 
@@ -53,6 +212,9 @@ impl GlClient {
 			));
 		}
 
+		let connection = connection.unwrap();
+		let features = connection.handshake(ClientKind::Gl, features::SUPPORTED)?;
+
 		let shared_images = HashMap::default();
 
 		// if !Self::initialize_gl_external() {
@@ -62,17 +224,18 @@ impl GlClient {
 		// 	));
 		// }
 
-		let gpu_device_uuid = GlSharedImage::get_gpu_device_uuid().map_err(|e| {
-			Error::new(
-				ErrorKind::NotFound,
-				format!("GPU device UUID not found: {}", e.to_string()),
-			)
-		})?;
+		let gpu_device_uuid = GlClient::get_gpu_device_uuid(features)?;
 
 		Ok(GlClient {
-			connection: connection.unwrap(),
+			connection,
 			shared_images,
-			gpu_device_uuid: gpu_device_uuid.as_u128(),
+			gpu_device_uuid: gpu_device_uuid.unwrap_or_default().as_u128(),
+			wait_for_copy: true,
+			pending_copies: 0,
+			copy_results: VecDeque::new(),
+			events: VecDeque::new(),
+			use_semaphores: true,
+			use_ram_images: gpu_device_uuid.is_none(),
 			//timeout,
 		})
 	}
@@ -97,12 +260,8 @@ impl GlClient {
 		// 	));
 		// }
 
-		let gpu_device_uuid = GlSharedImage::get_gpu_device_uuid().map_err(|e| {
-			Error::new(
-				ErrorKind::NotFound,
-				format!("GPU device UUID not found: {}", e.to_string()),
-			)
-		})?;
+		// Checked against the server's features after connecting
+		let gpu_device_uuid = GlSharedImage::get_gpu_device_uuid().ok();
 
 		let conn_fn = || {
 			let connection = match IpcConnection::try_connect(socket_path, client_timeout) {
@@ -121,7 +280,13 @@ impl GlClient {
 			Ok(Some(GlClient {
 				connection: connection.unwrap(),
 				shared_images,
-				gpu_device_uuid: gpu_device_uuid.as_u128(), //timeout,
+				gpu_device_uuid: gpu_device_uuid.unwrap_or_default().as_u128(), //timeout,
+				wait_for_copy: true,
+				pending_copies: 0,
+				copy_results: VecDeque::new(),
+				events: VecDeque::new(),
+				use_semaphores: true,
+				use_ram_images: gpu_device_uuid.is_none(),
 			}))
 		};
 
@@ -135,11 +300,15 @@ impl GlClient {
 			server_ipc_timeout,
 			server_lockfile_timeout,
 			server_spawn_timeout,
-			Some(gpu_device_uuid),
+			gpu_device_uuid,
 			&conn_fn,
 		)?;
 
 		if let Some(client) = res {
+			let features = client
+				.connection
+				.handshake(ClientKind::Gl, features::SUPPORTED)?;
+			GlClient::get_gpu_device_uuid(features)?;
 			return Ok(client);
 		} else {
 			return Err(Error::new(
@@ -149,8 +318,64 @@ impl GlClient {
 		}
 	}
 
-	fn check_for_update(image_data: &ImageData) -> bool {
-		image_data.ipc_info.get_id_unchecked() != image_data.vk_shared_image.get_data().id
+	// Without a device uuid, the context can't import GPU memory. Images are then shared through RAM
+	// if the server supports it
+	fn get_gpu_device_uuid(features: u64) -> Result<Option<uuid::Uuid>, Error> {
+		match GlSharedImage::get_gpu_device_uuid() {
+			Ok(gpu_device_uuid) => Ok(Some(gpu_device_uuid)),
+			Err(_) if features & features::RAM_IMAGES != 0 => Ok(None),
+			Err(e) => Err(Error::new(
+				ErrorKind::NotFound,
+				format!("GPU device UUID not found: {}", e.to_string()),
+			)),
+		}
+	}
+
+	// Share images through RAM instead of GPU memory. Use this if the server can't access this
+	// context's GPU, e.g. in VMs or if producer and consumer run on incompatible GPUs. Enabled by
+	// default if the context has no device uuid. Images found before are dropped
+	pub fn set_use_ram_images(&mut self, use_ram_images: bool) -> Result<(), Error> {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		if use_ram_images && features & features::RAM_IMAGES == 0 {
+			return Err(Error::new(
+				ErrorKind::Unsupported,
+				"Server does not support sharing images through RAM",
+			));
+		}
+		if !use_ram_images && self.gpu_device_uuid == uuid::Uuid::nil().as_u128() {
+			return Err(Error::new(
+				ErrorKind::Unsupported,
+				"GL context has no GPU device UUID",
+			));
+		}
+
+		if use_ram_images != self.use_ram_images {
+			self.shared_images.clear();
+		}
+		self.use_ram_images = use_ram_images;
+
+		Ok(())
+	}
+
+	// Images shared through RAM are requested with a nil GPU uuid
+	fn requested_device_uuid(&self) -> u128 {
+		match self.use_ram_images {
+			true => uuid::Uuid::nil().as_u128(),
+			false => self.gpu_device_uuid,
+		}
+	}
+
+	fn is_ram_image(&self, image_name: &str) -> bool {
+		self.shared_images
+			.get(image_name)
+			.map_or(false, |x| x.ram_image.is_some())
+	}
+
+	fn check_for_update(image_data: &ImageData) -> Result<bool, Box<dyn std::error::Error>> {
+		let id = image_data
+			.ipc_info
+			.get_id_unchecked(GlClient::IPC_TIMEOUT)?;
+		Ok(id != image_data.get_data().id)
 	}
 
 	pub fn init_image(
@@ -161,37 +386,47 @@ impl GlClient {
 		format: ImgFormat,
 		overwrite_existing: bool,
 	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
-		let image_name_buf = ImgData::convert_shmem_str_to_array(image_name);
-		let cmd_msg = CommandMsg {
-			tag: CommandTag::InitImage,
-			data: CommandData {
-				init_img: ManuallyDrop::new(CommInitImage {
-					image_name: image_name_buf,
-					shmem_name: image_name_buf,
-					width,
-					height,
-					format,
-					overwrite_existing,
-					gpu_device_uuid: self.gpu_device_uuid,
-				}),
-			},
-		};
+		self.init_image_with_buffer_count(image_name, width, height, format, overwrite_existing, 1)
+	}
 
-		self.connection.send_command(cmd_msg)?;
+	// Allocates a ring of buffer_count images, so that producers and consumers don't access the
+	// same image at once
+	pub fn init_image_with_buffer_count(
+		&mut self,
+		image_name: &str,
+		width: u32,
+		height: u32,
+		format: ImgFormat,
+		overwrite_existing: bool,
+		buffer_count: u32,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		ImgData::validate_name(image_name)?;
+		let cmd_msg = CommandMsg::InitImage(CommInitImage {
+			image_name: image_name.to_string(),
+			shmem_name: image_name.to_string(),
+			width,
+			height,
+			format,
+			overwrite_existing,
+			gpu_device_uuid: self.requested_device_uuid(),
+			buffer_count,
+		});
+
+		self.send_command(cmd_msg)?;
 
 		// Receive message and check for validity
-		let res_msg = self.connection.recv_result()?;
+		let res_msg = self.recv_result(true)?;
 		let res_data: Option<&ImgData> = match &res_msg {
 			None => Ok(None),
-			Some(msg) => match msg.tag {
-				CommandTag::InitImage => {
-					let data = unsafe { &msg.data.init_img };
+			Some(msg) => match msg {
+				ResultMsg::InitImage(data) => {
 					if data.image_created {
 						Ok(Some(&data.img_data))
 					} else {
 						Ok(None)
 					}
 				}
+				ResultMsg::Error(err) => return Err(Box::new(err.clone())),
 				_ => Err(Box::new(Error::new(
 					ErrorKind::InvalidData,
 					"Received invalid data from server",
@@ -206,14 +441,16 @@ impl GlClient {
 
 		let res_data = res_data.unwrap();
 
-		let mut share_handles = self.connection.recv_ancillary(1)?;
+		let share_handles = self
+			.connection
+			.recv_ancillary(self.share_handle_count(res_data))?;
 
 		self.connection.send_ack()?;
 
-		let res = self.add_new_image(&res_data, &mut share_handles)?;
+		let res = self.add_new_image(&res_data, share_handles)?;
 
 		let res = match res {
-			Some(r) => Some(GlClient::check_for_update(r)),
+			Some(r) => Some(GlClient::check_for_update(r)?),
 			None => None,
 		};
 		Ok(res)
@@ -226,7 +463,7 @@ impl GlClient {
 	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
 		let res = self.find_image_internal(image_name, force_update)?;
 		let res = match res {
-			Some(r) => Some(GlClient::check_for_update(r)),
+			Some(r) => Some(GlClient::check_for_update(r)?),
 			None => None,
 		};
 		Ok(res)
@@ -251,6 +488,53 @@ impl GlClient {
 		Ok(res)
 	}
 
+	// Sequence number and producer timestamp of the latest frame, read without taking the lock.
+	// Images shared through RAM only count frames of producers on a GPU once they were received
+	pub fn last_frame(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<FrameInfo>, Box<dyn std::error::Error>> {
+		let res = self.find_image_internal(image_name, false)?;
+		let res = match res {
+			Some(image_data) => Some(image_data.ipc_info.snapshot(GlClient::IPC_TIMEOUT)?.frame()),
+			None => None,
+		};
+		Ok(res)
+	}
+
+	// Whether a frame was published since this client last sent or received the image
+	pub fn has_new_frame(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		let res = self.find_image_internal(image_name, false)?;
+		let res = match res {
+			Some(image_data) => Some(image_data.has_new_frame()?),
+			None => None,
+		};
+		Ok(res)
+	}
+
+	// Sleeps until a frame was published that this client didn't send or receive yet, so that
+	// consumers don't need to poll. Returns false if the timeout expired first. Images shared
+	// through RAM only see frames of producers on a GPU once they were received, see last_frame
+	pub fn wait_for_frame(
+		&mut self,
+		image_name: &str,
+		timeout: Duration,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		let res = self.find_image_internal(image_name, false)?;
+		let res = match res {
+			Some(image_data) => Some(
+				image_data
+					.ipc_info
+					.wait_for_frame(image_data.received_frame, timeout)?,
+			),
+			None => None,
+		};
+		Ok(res)
+	}
+
 	pub fn send_image(
 		&mut self,
 		image_name: &str,
@@ -265,23 +549,34 @@ impl GlClient {
 		}
 
 		let remote_image = remote_image.unwrap();
+		let extent = &GlImageExtent {
+			top_left: [0, 0],
+			bottom_right: [
+				remote_image.get_data().width as i32,
+				remote_image.get_data().height as i32,
+			],
+		};
 		// recv_image_... is correct, as it's from the perspective of the remove image
-		remote_image
-			.vk_shared_image
-			.recv_blit_image(
-				src_texture_id,
-				src_texture_target,
-				&GlImageExtent {
-					top_left: [0, 0],
-					bottom_right: [
-						remote_image.vk_shared_image.get_data().width as i32,
-						remote_image.vk_shared_image.get_data().height as i32,
-					],
-				},
-				invert,
-				prev_fbo,
-			)
-			.unwrap();
+		remote_image.blit_slot(
+			self.connection.get_id(),
+			true,
+			|shared_image, timeline_value| {
+				shared_image.recv_blit_image(
+					src_texture_id,
+					src_texture_target,
+					extent,
+					invert,
+					prev_fbo,
+					timeline_value,
+				)
+			},
+		)?;
+
+		// Images shared through RAM are written to the GPU images by the server
+		match self.is_ram_image(image_name) {
+			true => self.ram_transfer_cmd(image_name, RamTransferDirection::Upload)?,
+			false => self.copy_image_cmd(image_name)?,
+		}
 
 		Ok(Some(()))
 	}
@@ -302,12 +597,25 @@ impl GlClient {
 
 		let remote_image = remote_image.unwrap();
 		// recv_image_... is correct, as it's from the perspective of the remove image
-		remote_image
-			.vk_shared_image
-			.recv_blit_image(src_texture_id, src_texture_target, extent, invert, prev_fbo)
-			.unwrap();
+		remote_image.blit_slot(
+			self.connection.get_id(),
+			true,
+			|shared_image, timeline_value| {
+				shared_image.recv_blit_image(
+					src_texture_id,
+					src_texture_target,
+					extent,
+					invert,
+					prev_fbo,
+					timeline_value,
+				)
+			},
+		)?;
 
-		self.copy_image_cmd(image_name)?;
+		match self.is_ram_image(image_name) {
+			true => self.ram_transfer_cmd(image_name, RamTransferDirection::Upload)?,
+			false => self.copy_image_cmd(image_name)?,
+		}
 
 		Ok(Some(()))
 	}
@@ -320,29 +628,39 @@ impl GlClient {
 		invert: bool,
 		prev_fbo: glad::GLuint,
 	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		// The server reads the latest frame into images shared through RAM
+		if self.is_ram_image(image_name) {
+			self.ram_transfer_cmd(image_name, RamTransferDirection::Download)?;
+		}
+
 		let remote_image = self.shared_images.get_mut(image_name);
 		if remote_image.is_none() {
 			return Ok(None);
 		}
 
 		let remote_image = remote_image.unwrap();
+		let extent = &GlImageExtent {
+			top_left: [0, 0],
+			bottom_right: [
+				remote_image.get_data().width as i32,
+				remote_image.get_data().height as i32,
+			],
+		};
 		// send_image_... is correct, as it's from the perspective of the remove image
-		remote_image
-			.vk_shared_image
-			.send_blit_image(
-				dst_texture_id,
-				dst_texture_target,
-				&GlImageExtent {
-					top_left: [0, 0],
-					bottom_right: [
-						remote_image.vk_shared_image.get_data().width as i32,
-						remote_image.vk_shared_image.get_data().height as i32,
-					],
-				},
-				invert,
-				prev_fbo,
-			)
-			.unwrap();
+		remote_image.blit_slot(
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.send_blit_image(
+					dst_texture_id,
+					dst_texture_target,
+					extent,
+					invert,
+					prev_fbo,
+					timeline_value,
+				)
+			},
+		)?;
 
 		Ok(Some(()))
 	}
@@ -356,6 +674,11 @@ impl GlClient {
 		prev_fbo: glad::GLuint,
 		extent: &GlImageExtent,
 	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		// The server reads the latest frame into images shared through RAM
+		if self.is_ram_image(image_name) {
+			self.ram_transfer_cmd(image_name, RamTransferDirection::Download)?;
+		}
+
 		let remote_image = self.shared_images.get_mut(image_name);
 		if remote_image.is_none() {
 			return Ok(None);
@@ -363,29 +686,354 @@ impl GlClient {
 
 		let remote_image = remote_image.unwrap();
 		// send_image_... is correct, as it's from the perspective of the remove image
-		remote_image
-			.vk_shared_image
-			.send_blit_image(dst_texture_id, dst_texture_target, extent, invert, prev_fbo)
-			.map_err(|x| {
-				Box::new(std::io::Error::new(
+		remote_image.blit_slot(
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.send_blit_image(
+					dst_texture_id,
+					dst_texture_target,
+					extent,
+					invert,
+					prev_fbo,
+					timeline_value,
+				)
+			},
+		)?;
+
+		Ok(Some(()))
+	}
+
+	// Reads the latest frame into tightly packed pixels. The rows are stored bottom to top if
+	// invert is set, matching the flip of recv_image
+	pub fn read_pixels(
+		&mut self,
+		image_name: &str,
+		invert: bool,
+	) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+		if self.start_read_pixels(image_name, invert)?.is_none() {
+			return Ok(None);
+		}
+
+		let pixels_size = self.shared_images[image_name].vk_shared_images[0].pixel_buffer_size();
+		let mut pixels = vec![0; pixels_size];
+		let timeout_ns = GlClient::IPC_TIMEOUT.as_nanos() as u64;
+		match self.read_pixel_buffer(image_name, &mut pixels, timeout_ns)? {
+			Some(true) => Ok(Some(pixels)),
+			_ => Err(Box::new(Error::new(
+				ErrorKind::TimedOut,
+				"Timed out while waiting for pixels",
+			))),
+		}
+	}
+
+	// Starts reading the latest frame into a pixel buffer without waiting for GL to complete it.
+	// The pixels are collected with poll_read_pixels
+	pub fn start_read_pixels(
+		&mut self,
+		image_name: &str,
+		invert: bool,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		// The server reads the latest frame into images shared through RAM
+		if self.is_ram_image(image_name) {
+			self.ram_transfer_cmd(image_name, RamTransferDirection::Download)?;
+		}
+
+		let remote_image = self.shared_images.get_mut(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let remote_image = remote_image.unwrap();
+		let mut pixel_buffer = remote_image.take_pixel_buffer()?;
+		let res = remote_image.blit_slot(
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.pack_pixels(&mut pixel_buffer, invert, timeline_value)
+			},
+		);
+		remote_image.pixel_buffer = Some(pixel_buffer);
+		res?;
+
+		Ok(Some(()))
+	}
+
+	// Copies the pixels of the last start_read_pixels call. Returns Some(false) while GL hasn't
+	// completed the read
+	pub fn poll_read_pixels(
+		&mut self,
+		image_name: &str,
+		pixels: &mut [u8],
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		self.read_pixel_buffer(image_name, pixels, 0)
+	}
+
+	// Writes pixels into the image. Rows are stride bytes apart, or tightly packed if stride is 0.
+	// They are stored bottom to top if invert is set, matching the flip of send_image
+	pub fn write_pixels(
+		&mut self,
+		image_name: &str,
+		pixels: &[u8],
+		stride: usize,
+		invert: bool,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		let remote_image = self.shared_images.get_mut(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let remote_image = remote_image.unwrap();
+		let row_size = remote_image.vk_shared_images[0].row_size();
+		let height = remote_image.get_data().height as usize;
+		let mut pixel_buffer = remote_image.take_pixel_buffer()?;
+		let res = match pixel_buffer.fill(pixels, stride, row_size, height, invert) {
+			Ok(_) => remote_image.blit_slot(
+				self.connection.get_id(),
+				true,
+				|shared_image, timeline_value| {
+					shared_image.unpack_pixels(&pixel_buffer, timeline_value)
+				},
+			),
+			Err(glad::GL_INVALID_VALUE) => Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!(
+					"{} bytes with a stride of {} are too few for {} rows of {} bytes",
+					pixels.len(),
+					stride,
+					height,
+					row_size
+				),
+			)
+			.into()),
+			Err(e) => Err(Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e)).into()),
+		};
+		remote_image.pixel_buffer = Some(pixel_buffer);
+		res?;
+
+		match self.is_ram_image(image_name) {
+			true => self.ram_transfer_cmd(image_name, RamTransferDirection::Upload)?,
+			false => self.copy_image_cmd(image_name)?,
+		}
+
+		Ok(Some(()))
+	}
+
+	pub fn rename_image(
+		&mut self,
+		old_image_name: &str,
+		new_image_name: &str,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		ImgData::validate_name(old_image_name)?;
+		ImgData::validate_name(new_image_name)?;
+		let cmd_msg = CommandMsg::RenameImage(CommRenameImage {
+			old_image_name: old_image_name.to_string(),
+			new_image_name: new_image_name.to_string(),
+			gpu_device_uuid: self.requested_device_uuid(),
+		});
+		self.send_command(cmd_msg)?;
+
+		let res_msg = self.recv_result(true)?;
+		let res_data = match res_msg {
+			None => return Ok(None),
+			Some(ResultMsg::RenameImage(data)) => data,
+			Some(ResultMsg::Error(err)) => return Err(Box::new(err)),
+			Some(_) => {
+				return Err(Box::new(Error::new(
 					ErrorKind::InvalidData,
-					format!("GL Error: {}", x),
-				))
-			})?;
+					"Received invalid data from server",
+				)))
+			}
+		};
+
+		if !res_data.image_found {
+			return Ok(None);
+		}
+
+		// The image memory is unchanged, so the local image can be kept if it is still up to date
+		if let Some(mut image_data) = self.shared_images.remove(old_image_name) {
+			let shmem_name = ImgData::convert_shmem_array_to_str(&res_data.img_data.shmem_name);
+			if res_data.img_data.data.handle_id == image_data.get_data().id {
+				// A memfd is kept across renames, only named segments are recreated
+				if !shmem_name.is_empty() {
+					image_data.ipc_info = IpcShmem::new(&shmem_name, new_image_name, false)?;
+				}
+				self.shared_images
+					.insert(new_image_name.to_string(), image_data);
+			}
+		}
+
 		Ok(Some(()))
 	}
 
-	fn add_new_image(
+	pub fn delete_image(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		ImgData::validate_name(image_name)?;
+		let cmd_msg = CommandMsg::DeleteImage(CommDeleteImage {
+			image_name: image_name.to_string(),
+		});
+		self.send_command(cmd_msg)?;
+
+		let res_msg = self.recv_result(true)?;
+		let res_data = match res_msg {
+			None => return Ok(None),
+			Some(ResultMsg::DeleteImage(data)) => data,
+			Some(ResultMsg::Error(err)) => return Err(Box::new(err)),
+			Some(_) => {
+				return Err(Box::new(Error::new(
+					ErrorKind::InvalidData,
+					"Received invalid data from server",
+				)))
+			}
+		};
+
+		self.shared_images.remove(image_name);
+
+		match res_data.image_found {
+			true => Ok(Some(())),
+			false => Ok(None),
+		}
+	}
+
+	pub fn list_images(
+		&mut self,
+	) -> Result<Option<Vec<ImageListEntry>>, Box<dyn std::error::Error>> {
+		self.send_command(CommandMsg::ListImages(CommListImages {}))?;
+
+		let res_msg = self.recv_result(true)?;
+		match res_msg {
+			None => Ok(None),
+			Some(ResultMsg::ListImages(data)) => Ok(Some(data.images)),
+			Some(ResultMsg::Error(err)) => Err(Box::new(err)),
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	// Receive events for the given image, or for all images if image_name is None
+	pub fn subscribe(
+		&mut self,
+		image_name: Option<&str>,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		self.subscribe_cmd(image_name, true)
+	}
+
+	pub fn unsubscribe(
+		&mut self,
+		image_name: Option<&str>,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		self.subscribe_cmd(image_name, false)
+	}
+
+	// Returns the oldest queued event without blocking. Events that arrive while waiting for
+	// other replies are queued as well
+	pub fn poll_event(&mut self) -> Result<Option<ImageEvent>, Box<dyn std::error::Error>> {
+		while self.events.is_empty() {
+			match self.connection.recv_result_if_available()? {
+				None => break,
+				Some(ResultMsg::Event(event)) => self.events.push_back(event),
+				Some(res_msg @ (ResultMsg::CopyImage(_) | ResultMsg::Error(_)))
+					if self.pending_copies > self.copy_results.len() =>
+				{
+					self.copy_results.push_back(res_msg)
+				}
+				Some(_) => {
+					return Err(Box::new(Error::new(
+						ErrorKind::InvalidData,
+						"Received invalid data from server",
+					)))
+				}
+			}
+		}
+
+		Ok(self.events.pop_front())
+	}
+
+	// If disabled, send_image returns as soon as the copy to other GPUs was requested. Completion
+	// can then be checked with poll_copy_image or wait_copy_image. Any other command waits for
+	// outstanding copies first
+	pub fn set_wait_for_copy(&mut self, wait_for_copy: bool) {
+		self.wait_for_copy = wait_for_copy;
+	}
+
+	// Returns the result of the oldest outstanding copy if it has completed
+	pub fn poll_copy_image(
+		&mut self,
+	) -> Result<Option<ResultCopyImage>, Box<dyn std::error::Error>> {
+		self.recv_copy_result(false)
+	}
+
+	// Only applies to images imported afterwards
+	pub fn set_use_semaphores(&mut self, use_semaphores: bool) {
+		self.use_semaphores = use_semaphores;
+	}
+
+	pub fn wait_copy_image(
 		&mut self,
+	) -> Result<Option<ResultCopyImage>, Box<dyn std::error::Error>> {
+		self.recv_copy_result(true)
+	}
+
+	// Per slot, the memory fd followed by the timeline semaphore fd if that feature was negotiated
+	fn slot_handle_count(&self) -> usize {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		match features & features::TIMELINE_SEMAPHORE {
+			0 => 1,
+			_ => 2,
+		}
+	}
+
+	// Images shared through RAM come with a single memfd. The IpcShmem fd may precede the image's
+	// handles, see open_ipc_info
+	fn share_handle_count(&self, img_data: &ImgData) -> usize {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		let shmem_handle_count = match features & features::SHMEM_HANDLES {
+			0 => 0,
+			_ => 1,
+		};
+		shmem_handle_count
+			+ match self.use_ram_images {
+				true => 1,
+				false => self.slot_handle_count() * img_data.data.buffer_count.max(1) as usize,
+			}
+	}
+
+	// The server sends the IpcShmem fd ahead of the image's handles if features::SHMEM_HANDLES was
+	// negotiated, otherwise it is opened by name
+	fn open_ipc_info(
+		&self,
 		img_data: &ImgData,
 		share_handles: &mut Vec<OwnedFd>,
-	) -> Result<Option<&ImageData>, Box<dyn std::error::Error>> {
-		// TODO: Update if sharing more handles
-		debug_assert_eq!(share_handles.len(), 1);
-		let fd = share_handles.pop().unwrap();
+	) -> Result<IpcShmem, Box<dyn std::error::Error>> {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		if features & features::SHMEM_HANDLES == 0 {
+			return IpcShmem::new(
+				&ImgData::convert_shmem_array_to_str(&img_data.shmem_name),
+				&ImgData::convert_shmem_array_to_str(&img_data.data.name),
+				false,
+			);
+		}
+
+		if share_handles.is_empty() {
+			return Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Server sent no shared memory handle",
+			)));
+		}
+		IpcShmem::import_handle(share_handles.remove(0))
+	}
 
+	fn add_new_image(
+		&mut self,
+		img_data: &ImgData,
+		share_handles: Vec<OwnedFd>,
+	) -> Result<Option<&ImageData>, Box<dyn std::error::Error>> {
 		let image_name = ImgData::convert_shmem_array_to_str(&img_data.data.name);
-		let image_data = self.create_local_image(img_data, fd)?;
+		let image_data = self.create_local_image(img_data, share_handles)?;
 		self.shared_images
 			.insert(image_name.to_string(), image_data);
 
@@ -395,37 +1043,134 @@ impl GlClient {
 	fn create_local_image(
 		&self,
 		img_data: &ImgData,
-		img_mem_fd: OwnedFd,
+		mut share_handles: Vec<OwnedFd>,
 	) -> Result<ImageData, Box<dyn std::error::Error>> {
-		let shmem = IpcShmem::new(
-			&ImgData::convert_shmem_array_to_str(&img_data.shmem_name),
-			&ImgData::convert_shmem_array_to_str(&img_data.data.name),
-			false,
-		)?;
+		let shmem = self.open_ipc_info(img_data, &mut share_handles)?;
 
-		let vk_shared_image = {
-			let rlock = shmem.acquire_rlock(Timeout::Val(GlClient::IPC_TIMEOUT))?;
-			let _rdata = IpcShmem::acquire_rdata(&rlock);
-
-			let vk_shared_image = GlSharedImage::import_handle(
-				img_mem_fd,
-				img_data.data.width as i32,
-				img_data.data.height as i32,
-				img_data.data.allocation_size,
-				GlSharedImage::get_gl_format(img_data.data.format),
-				GlSharedImage::get_gl_internal_format(img_data.data.format) as u32,
-				img_data.data.handle_id,
-			)
-			.unwrap();
-			vk_shared_image
+		if share_handles.is_empty() {
+			return Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Server sent no memory handle",
+			)));
+		}
+
+		if self.use_ram_images {
+			return GlClient::create_ram_image(shmem, img_data, share_handles);
+		}
+
+		if !GlSharedImage::can_import(img_data.data.format) {
+			return Err(Box::new(Error::new(
+				ErrorKind::Unsupported,
+				format!(
+					"GL can't import {:?} images from GPU memory, share them through RAM instead",
+					img_data.data.format
+				),
+			)));
+		}
+
+		// Resizes while importing are detected through the handle id afterwards
+		let vk_shared_images = {
+			let mut vk_shared_images = Vec::new();
+			let mut share_handles = share_handles.into_iter();
+			while let Some(img_mem_fd) = share_handles.next() {
+				let semaphore_fd = match self.slot_handle_count() {
+					1 => None,
+					_ => share_handles.next(),
+				};
+				let mut vk_shared_image = GlSharedImage::import_handle(
+					img_mem_fd,
+					img_data.data.width as i32,
+					img_data.data.height as i32,
+					img_data.data.allocation_size,
+					GlSharedImage::get_gl_format(img_data.data.format),
+					GlSharedImage::get_gl_internal_format(img_data.data.format) as u32,
+					img_data.data.handle_id,
+				)
+				.map_err(|e| {
+					Error::new(
+						ErrorKind::Unsupported,
+						format!(
+							"Failed to import {:?} image of size {}x{}: GL Error {}",
+							img_data.data.format, img_data.data.width, img_data.data.height, e
+						),
+					)
+				})?;
+
+				// Without a semaphore, blits aren't synchronized with other processes
+				if let Some(semaphore_fd) = semaphore_fd {
+					if self.use_semaphores && GlSharedImage::semaphores_supported() {
+						vk_shared_image
+							.import_semaphore(semaphore_fd)
+							.map_err(|e| {
+								Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e))
+							})?;
+					}
+				}
+				vk_shared_images.push(vk_shared_image);
+			}
+			vk_shared_images
 		};
 
 		Ok(ImageData {
 			ipc_info: shmem,
-			vk_shared_image,
+			vk_shared_images,
+			ram_image: None,
+			pixel_buffer: None,
+			received_frame: 0,
 		})
 	}
 
+	fn create_ram_image(
+		shmem: IpcShmem,
+		img_data: &ImgData,
+		share_handles: Vec<OwnedFd>,
+	) -> Result<ImageData, Box<dyn std::error::Error>> {
+		let ram_fd = share_handles.into_iter().next().unwrap();
+		let ram_image = RamImage::import_handle(ram_fd, &img_data.data)?;
+
+		let local_image = GlSharedImage::new(
+			img_data.data.width as i32,
+			img_data.data.height as i32,
+			ram_image.as_slice().len() as u64,
+			GlSharedImage::get_gl_format(img_data.data.format),
+			GlSharedImage::get_gl_internal_format(img_data.data.format),
+			img_data.data.handle_id,
+		)
+		.map_err(|e| Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e)))?;
+
+		Ok(ImageData {
+			ipc_info: shmem,
+			vk_shared_images: vec![local_image],
+			ram_image: Some(ram_image),
+			pixel_buffer: None,
+			received_frame: 0,
+		})
+	}
+
+	fn read_pixel_buffer(
+		&mut self,
+		image_name: &str,
+		pixels: &mut [u8],
+		timeout_ns: u64,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		let remote_image = self.shared_images.get_mut(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let pixel_buffer = remote_image
+			.unwrap()
+			.pixel_buffer
+			.as_mut()
+			.filter(|x| x.is_read_pending())
+			.ok_or_else(|| Error::new(ErrorKind::NotFound, "No pixel read was started"))?;
+		let res = pixel_buffer
+			.read(pixels, timeout_ns)
+			.map_err(|e| Error::new(ErrorKind::InvalidData, format!("GL Error: {}", e)))?;
+
+		Ok(Some(res))
+	}
+
 	fn find_image_internal(
 		&mut self,
 		image_name: &str,
@@ -448,28 +1193,25 @@ impl GlClient {
 		&mut self,
 		image_name: &str,
 	) -> Result<Option<&ImageData>, Box<dyn std::error::Error>> {
-		let cmd_dat = ManuallyDrop::new(CommFindImage {
-			image_name: ImgData::convert_shmem_str_to_array(image_name),
-			gpu_device_uuid: self.gpu_device_uuid,
+		ImgData::validate_name(image_name)?;
+		let cmd_msg = CommandMsg::FindImage(CommFindImage {
+			image_name: image_name.to_string(),
+			gpu_device_uuid: self.requested_device_uuid(),
 		});
-		let cmd_msg = CommandMsg {
-			tag: CommandTag::FindImage,
-			data: CommandData { find_img: cmd_dat },
-		};
-		self.connection.send_command(cmd_msg)?;
+		self.send_command(cmd_msg)?;
 
-		let res_msg = self.connection.recv_result()?;
+		let res_msg = self.recv_result(true)?;
 		let res_data: Option<&ImgData> = match &res_msg {
 			None => Ok(None),
-			Some(msg) => match msg.tag {
-				CommandTag::FindImage => {
-					let data = unsafe { &msg.data.find_img };
+			Some(msg) => match msg {
+				ResultMsg::FindImage(data) => {
 					if data.image_found {
 						Ok(Some(&data.img_data))
 					} else {
 						Ok(None)
 					}
 				}
+				ResultMsg::Error(err) => return Err(Box::new(err.clone())),
 				_ => Err(Box::new(Error::new(
 					ErrorKind::InvalidData,
 					"Received invalid data from server",
@@ -478,18 +1220,22 @@ impl GlClient {
 		}?;
 
 		if res_data.is_none() {
+			// The image was deleted on the server, so drop any stale local copy
+			if res_msg.is_some() {
+				self.shared_images.remove(image_name);
+			}
 			return Ok(None);
 		}
 
 		let res_data = res_data.unwrap();
 
-		let mut share_handles = self.connection.recv_ancillary(1)?;
+		let share_handles = self
+			.connection
+			.recv_ancillary(self.share_handle_count(res_data))?;
 
 		self.connection.send_ack()?;
 
-		let fd = share_handles.pop().unwrap();
-
-		let image_data = self.create_local_image(&res_data, fd)?;
+		let image_data = self.create_local_image(&res_data, share_handles)?;
 		let local_image = self.shared_images.get_mut(image_name);
 		if local_image.is_none() {
 			self.shared_images
@@ -502,18 +1248,165 @@ impl GlClient {
 	}
 
 	fn copy_image_cmd(&mut self, image_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-		let cmd_dat = ManuallyDrop::new(CommCopyImage {
-			image_name: ImgData::convert_shmem_str_to_array(image_name),
+		let cmd_msg = CommandMsg::CopyImage(CommCopyImage {
+			image_name: image_name.to_string(),
 			gpu_device_uuid: self.gpu_device_uuid,
 		});
-		let cmd_msg = CommandMsg {
-			tag: CommandTag::CopyImage,
-			data: CommandData { copy_img: cmd_dat },
+
+		if !self.wait_for_copy {
+			self.connection.send_command(cmd_msg)?;
+			self.pending_copies += 1;
+			return Ok(());
+		}
+
+		self.send_command(cmd_msg)?;
+		self.pending_copies += 1;
+
+		let res = match self.wait_copy_image()? {
+			Some(res) => res,
+			None => {
+				return Err(Box::new(Error::new(
+					ErrorKind::TimedOut,
+					"Timed out while waiting for image copy",
+				)))
+			}
 		};
-		self.connection.send_command(cmd_msg)?;
+
+		if !res.success {
+			return Err(Box::new(res.error.unwrap_or_else(|| {
+				ResultError::new(ErrorCode::Unknown, "Image copy failed")
+			})));
+		}
 
 		Ok(())
 	}
+
+	fn ram_transfer_cmd(
+		&mut self,
+		image_name: &str,
+		direction: RamTransferDirection,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		self.send_command(CommandMsg::RamTransfer(CommRamTransfer {
+			image_name: image_name.to_string(),
+			direction,
+		}))?;
+
+		let res_msg = self.recv_result(true)?;
+		match res_msg {
+			None => Err(Box::new(Error::new(
+				ErrorKind::TimedOut,
+				"Timed out while waiting for RAM transfer",
+			))),
+			Some(ResultMsg::RamTransfer(data)) => match data.success {
+				true => Ok(()),
+				false => Err(Box::new(data.error.unwrap_or_else(|| {
+					ResultError::new(ErrorCode::Unknown, "RAM transfer failed")
+				}))),
+			},
+			Some(ResultMsg::Error(err)) => Err(Box::new(err)),
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	fn recv_copy_result(
+		&mut self,
+		blocking: bool,
+	) -> Result<Option<ResultCopyImage>, Box<dyn std::error::Error>> {
+		if self.pending_copies == 0 {
+			return Ok(None);
+		}
+
+		let res_msg = match self.copy_results.pop_front() {
+			Some(res_msg) => Some(res_msg),
+			None => self.recv_result(blocking)?,
+		};
+
+		match res_msg {
+			None => Ok(None),
+			Some(ResultMsg::CopyImage(data)) => {
+				self.pending_copies -= 1;
+				Ok(Some(data))
+			}
+			Some(ResultMsg::Error(err)) => {
+				self.pending_copies -= 1;
+				Err(Box::new(err))
+			}
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	// Queues events pushed by the server and returns the next reply
+	fn recv_result(
+		&mut self,
+		blocking: bool,
+	) -> Result<Option<ResultMsg>, Box<dyn std::error::Error>> {
+		loop {
+			let res_msg = match blocking {
+				true => self.connection.recv_result()?,
+				false => self.connection.recv_result_if_available()?,
+			};
+
+			match res_msg {
+				Some(ResultMsg::Event(event)) => self.events.push_back(event),
+				res_msg => return Ok(res_msg),
+			}
+		}
+	}
+
+	fn subscribe_cmd(
+		&mut self,
+		image_name: Option<&str>,
+		subscribe: bool,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		if let Some(image_name) = image_name {
+			ImgData::validate_name(image_name)?;
+		}
+
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		if features & features::EVENTS == 0 {
+			return Err(Box::new(Error::new(
+				ErrorKind::Unsupported,
+				"Server does not support image events",
+			)));
+		}
+
+		self.send_command(CommandMsg::Subscribe(CommSubscribe {
+			image_name: image_name.map(|x| x.to_string()),
+			subscribe,
+		}))?;
+
+		let res_msg = self.recv_result(true)?;
+		match res_msg {
+			None => Ok(None),
+			Some(ResultMsg::Subscribe(_)) => Ok(Some(())),
+			Some(ResultMsg::Error(err)) => Err(Box::new(err)),
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	fn send_command(&mut self, cmd_msg: CommandMsg) -> Result<(), Box<dyn std::error::Error>> {
+		// Results of outstanding copies arrive before the reply to this command
+		while self.pending_copies > 0 {
+			if self.wait_copy_image()?.is_none() {
+				return Err(Box::new(Error::new(
+					ErrorKind::TimedOut,
+					"Timed out while waiting for pending image copies",
+				)));
+			}
+		}
+
+		self.connection.send_command(cmd_msg)?;
+		Ok(())
+	}
 }
 
 #[cfg(test)]
@@ -521,6 +1414,9 @@ mod tests {
 	use std::time::Duration;
 	use std::{fs, thread};
 
+	use texture_share_ipc::platform::ipc_commands::{
+		features, CommandMsg, ResultHello, ResultMsg, PROTOCOL_VERSION,
+	};
 	use texture_share_ipc::IpcSocket;
 
 	use super::GlClient;
@@ -568,7 +1464,24 @@ mod tests {
 
 		let server_socket_fcn = || {
 			let server_socket = _create_server_socket();
-			server_socket.try_accept().unwrap()
+			let res = server_socket.try_accept().unwrap();
+
+			// Answer the client's Hello
+			let conn_lock = server_socket.connections.lock().unwrap();
+			let conn = conn_lock.last().unwrap().borrow();
+			match conn.recv_command().unwrap().unwrap() {
+				CommandMsg::Hello(hello) => assert_eq!(hello.protocol_version, PROTOCOL_VERSION),
+				cmd => panic!("Expected Hello, received {:?}", cmd.tag()),
+			};
+			conn.send_result(ResultMsg::Hello(ResultHello {
+				accepted: true,
+				protocol_version: PROTOCOL_VERSION,
+				features: features::NONE,
+				connection_id: conn.get_id(),
+			}))
+			.unwrap();
+
+			res
 		};
 
 		let server_thread = thread::spawn(server_socket_fcn);
@@ -7,9 +7,15 @@ impl From<ImgFormat> for GlFormat {
 	fn from(value: ImgFormat) -> Self {
 		match value {
 			ImgFormat::B8G8R8 => GlFormat::BGR,
-			ImgFormat::B8G8R8A8 => GlFormat::BGRA,
+			ImgFormat::B8G8R8A8 | ImgFormat::B8G8R8A8Srgb => GlFormat::BGRA,
 			ImgFormat::R8G8B8 => GlFormat::RGB,
-			ImgFormat::R8G8B8A8 => GlFormat::RGBA,
+			ImgFormat::R8G8B8A8
+			| ImgFormat::R8G8B8A8Srgb
+			| ImgFormat::R16G16B16A16
+			| ImgFormat::R16G16B16A16Sfloat
+			| ImgFormat::R32G32B32A32Sfloat
+			| ImgFormat::A2B10G10R10 => GlFormat::RGBA,
+			ImgFormat::R8 | ImgFormat::R16 | ImgFormat::R32Sfloat => GlFormat::RED,
 			ImgFormat::Undefined => GlFormat::FALSE,
 		}
 	}
@@ -22,6 +28,7 @@ impl Into<ImgFormat> for GlFormat {
 			GlFormat::BGRA => ImgFormat::B8G8R8A8,
 			GlFormat::RGB => ImgFormat::R8G8B8,
 			GlFormat::RGBA => ImgFormat::R8G8B8A8,
+			GlFormat::RED => ImgFormat::R8,
 			GlFormat::FALSE => ImgFormat::Undefined,
 			_ => panic!("VkFormat {:?} not implemented", self),
 		}
@@ -1,4 +1,5 @@
 use super::glad;
+use std::ffi::{c_void, CStr};
 use std::{os::fd::OwnedFd, ptr};
 
 use texture_share_ipc::platform::{img_data::ImgFormat, ShmemDataInternal};
@@ -7,6 +8,23 @@ use texture_share_ipc::uuid;
 #[cfg(target_os = "linux")]
 type GlMemoryHandle = OwnedFd;
 
+// GL_NV_timeline_semaphore isn't part of the glad bindings, so it's loaded manually
+const GL_TIMELINE_SEMAPHORE_VALUE_NV: glad::GLenum = 0x9595;
+const GL_SEMAPHORE_TYPE_NV: glad::GLenum = 0x95B3;
+const GL_SEMAPHORE_TYPE_TIMELINE_NV: glad::GLint = 0x95B5;
+
+type PfnGlCreateSemaphoresNV = unsafe extern "C" fn(glad::GLsizei, *mut glad::GLuint);
+type PfnGlSemaphoreParameterivNV =
+	unsafe extern "C" fn(glad::GLuint, glad::GLenum, *const glad::GLint);
+
+#[cfg(target_os = "linux")]
+extern "C" {
+	fn glXGetProcAddressARB(proc_name: *const u8) -> *const c_void;
+}
+
+// Layout the Vulkan side keeps shared images in between accesses
+const SHARED_IMAGE_LAYOUT: glad::GLenum = glad::GL_LAYOUT_TRANSFER_SRC_EXT;
+
 pub struct GlSharedImage {
 	// FBO to render image from/to
 	fbo: glad::GLuint,
@@ -17,6 +35,9 @@ pub struct GlSharedImage {
 	// Image texture
 	texture: glad::GLuint,
 
+	// Imported timeline semaphore, 0 if blits aren't synchronized
+	semaphore: glad::GLuint,
+
 	data: GlSharedImageData,
 }
 
@@ -25,9 +46,24 @@ pub struct GlSharedImageData {
 	pub width: u32,
 	pub height: u32,
 	pub format: glad::GLenum,
+	pub internal_format: glad::GLenum,
 	pub allocation_size: u64,
 }
 
+// Pixel buffer object that stages the tightly packed pixels of an image on the CPU. Reads into it
+// complete asynchronously, a fence tracks when they can be mapped
+pub struct GlPixelBuffer {
+	buffer: glad::GLuint,
+	row_size: usize,
+	height: usize,
+
+	// Fence of the pending read, null if there is none
+	fence: glad::GLsync,
+
+	// Map the rows of the pending read bottom to top
+	invert: bool,
+}
+
 #[repr(C)]
 pub struct GlImageExtent {
 	pub top_left: [glad::GLint; 2],
@@ -46,6 +82,11 @@ impl Drop for GlSharedImage {
 			self.mem = 0;
 		}
 
+		if self.semaphore != 0 {
+			unsafe { glad::glad_glDeleteSemaphoresEXT.unwrap()(1, &self.semaphore) };
+			self.semaphore = 0;
+		}
+
 		if self.fbo != 0 {
 			unsafe { glad::glad_glDeleteFramebuffers.unwrap()(1, &self.fbo) };
 			self.fbo = 0;
@@ -53,6 +94,20 @@ impl Drop for GlSharedImage {
 	}
 }
 
+impl Drop for GlPixelBuffer {
+	fn drop(&mut self) {
+		if !self.fence.is_null() {
+			unsafe { glad::glad_glDeleteSync.unwrap()(self.fence) };
+			self.fence = ptr::null_mut();
+		}
+
+		if self.buffer != 0 {
+			unsafe { glad::glad_glDeleteBuffers.unwrap()(1, &self.buffer) };
+			self.buffer = 0;
+		}
+	}
+}
+
 impl GlSharedImageData {
 	pub fn from_shmem_img_data(data: &ShmemDataInternal) -> GlSharedImageData {
 		GlSharedImageData {
@@ -60,6 +115,7 @@ impl GlSharedImageData {
 			width: data.width,
 			height: data.height,
 			format: GlSharedImage::get_gl_format(data.format),
+			internal_format: GlSharedImage::get_gl_internal_format(data.format) as glad::GLenum,
 			allocation_size: data.allocation_size,
 		}
 	}
@@ -116,9 +172,15 @@ impl GlSharedImage {
 	pub fn get_gl_format(img_format: ImgFormat) -> glad::GLenum {
 		match img_format {
 			ImgFormat::B8G8R8 => glad::GL_BGR,
-			ImgFormat::B8G8R8A8 => glad::GL_BGRA,
+			ImgFormat::B8G8R8A8 | ImgFormat::B8G8R8A8Srgb => glad::GL_BGRA,
 			ImgFormat::R8G8B8 => glad::GL_RGB,
-			ImgFormat::R8G8B8A8 => glad::GL_RGBA,
+			ImgFormat::R8G8B8A8
+			| ImgFormat::R8G8B8A8Srgb
+			| ImgFormat::R16G16B16A16
+			| ImgFormat::R16G16B16A16Sfloat
+			| ImgFormat::R32G32B32A32Sfloat
+			| ImgFormat::A2B10G10R10 => glad::GL_RGBA,
+			ImgFormat::R8 | ImgFormat::R16 | ImgFormat::R32Sfloat => glad::GL_RED,
 			ImgFormat::Undefined => glad::GL_NONE,
 		}
 	}
@@ -129,18 +191,49 @@ impl GlSharedImage {
 			ImgFormat::B8G8R8A8 => glad::GL_RGBA8,
 			ImgFormat::R8G8B8 => glad::GL_RGB8,
 			ImgFormat::R8G8B8A8 => glad::GL_RGBA8,
+			ImgFormat::R8 => glad::GL_R8,
+			ImgFormat::R16 => glad::GL_R16,
+			ImgFormat::R32Sfloat => glad::GL_R32F,
+			ImgFormat::R16G16B16A16 => glad::GL_RGBA16,
+			ImgFormat::R16G16B16A16Sfloat => glad::GL_RGBA16F,
+			ImgFormat::R32G32B32A32Sfloat => glad::GL_RGBA32F,
+			ImgFormat::A2B10G10R10 => glad::GL_RGB10_A2,
+			ImgFormat::R8G8B8A8Srgb | ImgFormat::B8G8R8A8Srgb => glad::GL_SRGB8_ALPHA8,
 			ImgFormat::Undefined => glad::GL_NONE,
 		}
 		.try_into()
 		.unwrap()
 	}
 
-	pub fn get_img_format(gl_format: glad::GLenum) -> ImgFormat {
-		match gl_format {
-			glad::GL_BGR => ImgFormat::B8G8R8,
-			glad::GL_BGRA => ImgFormat::B8G8R8A8,
-			glad::GL_RGB => ImgFormat::R8G8B8,
-			glad::GL_RGBA => ImgFormat::R8G8B8A8,
+	// Type of the pixel data transferred to and from textures of img_format
+	pub fn get_gl_type(img_format: ImgFormat) -> glad::GLenum {
+		match img_format {
+			ImgFormat::R16 | ImgFormat::R16G16B16A16 => glad::GL_UNSIGNED_SHORT,
+			ImgFormat::R16G16B16A16Sfloat => glad::GL_HALF_FLOAT,
+			ImgFormat::R32Sfloat | ImgFormat::R32G32B32A32Sfloat => glad::GL_FLOAT,
+			// A2B10G10R10 stores red in the lowest bits
+			ImgFormat::A2B10G10R10 => glad::GL_UNSIGNED_INT_2_10_10_10_REV,
+			_ => glad::GL_UNSIGNED_BYTE,
+		}
+	}
+
+	// The pixel format alone doesn't determine the channel size, unsized internal formats are
+	// treated as 8 bit formats
+	pub fn get_img_format(gl_format: glad::GLenum, internal_format: glad::GLenum) -> ImgFormat {
+		match (gl_format, internal_format) {
+			(glad::GL_RED, glad::GL_R8) => ImgFormat::R8,
+			(glad::GL_RED, glad::GL_R16) => ImgFormat::R16,
+			(glad::GL_RED, glad::GL_R32F) => ImgFormat::R32Sfloat,
+			(glad::GL_RGBA, glad::GL_RGBA16) => ImgFormat::R16G16B16A16,
+			(glad::GL_RGBA, glad::GL_RGBA16F) => ImgFormat::R16G16B16A16Sfloat,
+			(glad::GL_RGBA, glad::GL_RGBA32F) => ImgFormat::R32G32B32A32Sfloat,
+			(glad::GL_RGBA, glad::GL_RGB10_A2) => ImgFormat::A2B10G10R10,
+			(glad::GL_RGBA, glad::GL_SRGB8_ALPHA8) => ImgFormat::R8G8B8A8Srgb,
+			(glad::GL_BGRA, glad::GL_SRGB8_ALPHA8) => ImgFormat::B8G8R8A8Srgb,
+			(glad::GL_BGR, _) => ImgFormat::B8G8R8,
+			(glad::GL_BGRA, _) => ImgFormat::B8G8R8A8,
+			(glad::GL_RGB, _) => ImgFormat::R8G8B8,
+			(glad::GL_RGBA, _) => ImgFormat::R8G8B8A8,
 			_ => ImgFormat::Undefined,
 		}
 	}
@@ -182,7 +275,10 @@ impl GlSharedImage {
 					height,
 					0,
 					format,
-					glad::GL_UNSIGNED_BYTE,
+					GlSharedImage::get_gl_type(GlSharedImage::get_img_format(
+						format,
+						internal_format as glad::GLenum,
+					)),
 					ptr::null(),
 				)
 			})?;
@@ -194,12 +290,14 @@ impl GlSharedImage {
 			width: width as u32,
 			height: height as u32,
 			format,
+			internal_format: internal_format as glad::GLenum,
 			allocation_size,
 		};
 		Ok(GlSharedImage {
 			fbo: 0,
 			mem: 0,
 			texture,
+			semaphore: 0,
 			data,
 		})
 	}
@@ -216,54 +314,383 @@ impl GlSharedImage {
 	) -> Result<GlSharedImage, glad::GLuint> {
 		use std::os::fd::IntoRawFd;
 
-		let mut texture: glad::GLuint = 0;
-		let mut memory: glad::GLuint = 0;
+		if !GlSharedImage::can_import(GlSharedImage::get_img_format(format, internal_format)) {
+			return Err(glad::GL_INVALID_ENUM);
+		}
 
-		unsafe {
-			check_gl(|| glad::glad_glGenTextures.unwrap()(1, &mut texture as *mut _))?;
-			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, texture))?;
+		let data = GlSharedImageData {
+			id,
+			width: width as u32,
+			height: height as u32,
+			format,
+			internal_format,
+			allocation_size,
+		};
+		// Objects are released by drop if the import fails
+		let mut image = GlSharedImage {
+			fbo: 0,
+			mem: 0,
+			texture: 0,
+			semaphore: 0,
+			data,
+		};
 
-			check_gl(|| glad::glad_glCreateMemoryObjectsEXT.unwrap()(1, &mut memory as *mut _))?;
+		unsafe {
+			check_gl(|| glad::glad_glGenTextures.unwrap()(1, &mut image.texture as *mut _))?;
+			check_gl(|| glad::glad_glCreateMemoryObjectsEXT.unwrap()(1, &mut image.mem as *mut _))?;
 			check_gl(|| {
 				glad::glad_glImportMemoryFdEXT.unwrap()(
-					memory,
+					image.mem,
 					allocation_size,
 					glad::GL_HANDLE_TYPE_OPAQUE_FD_EXT,
 					handle.into_raw_fd(),
 				)
 			})?;
-			check_gl(|| {
+
+			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, image.texture))?;
+			let res = check_gl(|| {
 				glad::glad_glTextureStorageMem2DEXT.unwrap()(
-					texture,
+					image.texture,
 					1,
 					internal_format,
 					width,
 					height,
-					memory,
+					image.mem,
+					0,
+				)
+			});
+			glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, 0);
+			res?;
+		}
+
+		Ok(image)
+	}
+
+	// GL has no internal formats that store blue in the lowest bits, so textures can't alias the
+	// memory of BGR ordered Vulkan images. Such images can still be shared through RAM
+	pub fn can_import(img_format: ImgFormat) -> bool {
+		match img_format {
+			ImgFormat::B8G8R8
+			| ImgFormat::B8G8R8A8
+			| ImgFormat::B8G8R8A8Srgb
+			| ImgFormat::Undefined => false,
+			_ => true,
+		}
+	}
+
+	pub fn get_data(&self) -> &GlSharedImageData {
+		&self.data
+	}
+
+	// Reads the texture into tightly packed pixels, as stored in RAM images
+	pub fn read_pixels(&self, pixels: &mut [u8]) -> Result<(), glad::GLuint> {
+		if pixels.len() < self.pixel_buffer_size() {
+			return Err(glad::GL_INVALID_VALUE);
+		}
+
+		unsafe {
+			let mut alignment: glad::GLint = 0;
+			glad::glad_glGetIntegerv.unwrap()(glad::GL_PACK_ALIGNMENT, &mut alignment);
+			check_gl(|| glad::glad_glPixelStorei.unwrap()(glad::GL_PACK_ALIGNMENT, 1))?;
+			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, self.texture))?;
+			let res = check_gl(|| {
+				glad::glad_glGetTexImage.unwrap()(
+					glad::GL_TEXTURE_2D,
 					0,
+					self.data.format,
+					self.pixel_type(),
+					pixels.as_mut_ptr() as *mut _,
 				)
+			});
+			glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, 0);
+			glad::glad_glPixelStorei.unwrap()(glad::GL_PACK_ALIGNMENT, alignment);
+			res
+		}
+	}
+
+	// Writes tightly packed pixels into the texture
+	pub fn write_pixels(&self, pixels: &[u8]) -> Result<(), glad::GLuint> {
+		if pixels.len() < self.pixel_buffer_size() {
+			return Err(glad::GL_INVALID_VALUE);
+		}
+
+		unsafe {
+			let mut alignment: glad::GLint = 0;
+			glad::glad_glGetIntegerv.unwrap()(glad::GL_UNPACK_ALIGNMENT, &mut alignment);
+			check_gl(|| glad::glad_glPixelStorei.unwrap()(glad::GL_UNPACK_ALIGNMENT, 1))?;
+			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, self.texture))?;
+			let res = check_gl(|| {
+				glad::glad_glTexSubImage2D.unwrap()(
+					glad::GL_TEXTURE_2D,
+					0,
+					0,
+					0,
+					self.data.width as glad::GLsizei,
+					self.data.height as glad::GLsizei,
+					self.data.format,
+					self.pixel_type(),
+					pixels.as_ptr() as *const _,
+				)
+			});
+			glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, 0);
+			glad::glad_glPixelStorei.unwrap()(glad::GL_UNPACK_ALIGNMENT, alignment);
+			res
+		}
+	}
+
+	// Reads the texture into pixel_buffer. The pixels can be mapped once the fence of the buffer
+	// signalled, which doesn't block the caller
+	pub fn pack_pixels(
+		&self,
+		pixel_buffer: &mut GlPixelBuffer,
+		invert: bool,
+		timeline_value: &mut u64,
+	) -> Result<(), glad::GLuint> {
+		self.wait_semaphore(*timeline_value)?;
+
+		unsafe {
+			pixel_buffer.allocate(
+				glad::GL_PIXEL_PACK_BUFFER,
+				self.row_size(),
+				self.data.height as usize,
+				glad::GL_STREAM_READ,
+			)?;
+
+			let mut alignment: glad::GLint = 0;
+			glad::glad_glGetIntegerv.unwrap()(glad::GL_PACK_ALIGNMENT, &mut alignment);
+			check_gl(|| glad::glad_glPixelStorei.unwrap()(glad::GL_PACK_ALIGNMENT, 1))?;
+			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, self.texture))?;
+
+			// With a bound pack buffer, the pointer is an offset into it
+			let res = check_gl(|| {
+				glad::glad_glGetTexImage.unwrap()(
+					glad::GL_TEXTURE_2D,
+					0,
+					self.data.format,
+					self.pixel_type(),
+					ptr::null_mut(),
+				)
+			});
+			glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, 0);
+			glad::glad_glBindBuffer.unwrap()(glad::GL_PIXEL_PACK_BUFFER, 0);
+			glad::glad_glPixelStorei.unwrap()(glad::GL_PACK_ALIGNMENT, alignment);
+			res?;
+		}
+		pixel_buffer.insert_fence(invert)?;
+
+		self.signal_semaphore(timeline_value)
+	}
+
+	// Writes the pixels staged in pixel_buffer into the texture
+	pub fn unpack_pixels(
+		&self,
+		pixel_buffer: &GlPixelBuffer,
+		timeline_value: &mut u64,
+	) -> Result<(), glad::GLuint> {
+		if pixel_buffer.row_size != self.row_size()
+			|| pixel_buffer.height != self.data.height as usize
+		{
+			return Err(glad::GL_INVALID_VALUE);
+		}
+
+		self.wait_semaphore(*timeline_value)?;
+
+		unsafe {
+			let mut alignment: glad::GLint = 0;
+			glad::glad_glGetIntegerv.unwrap()(glad::GL_UNPACK_ALIGNMENT, &mut alignment);
+			check_gl(|| glad::glad_glPixelStorei.unwrap()(glad::GL_UNPACK_ALIGNMENT, 1))?;
+			check_gl(|| {
+				glad::glad_glBindBuffer.unwrap()(glad::GL_PIXEL_UNPACK_BUFFER, pixel_buffer.buffer)
 			})?;
+			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, self.texture))?;
 
-			check_gl(|| glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, 0))?;
+			// With a bound unpack buffer, the pointer is an offset into it
+			let res = check_gl(|| {
+				glad::glad_glTexSubImage2D.unwrap()(
+					glad::GL_TEXTURE_2D,
+					0,
+					0,
+					0,
+					self.data.width as glad::GLsizei,
+					self.data.height as glad::GLsizei,
+					self.data.format,
+					self.pixel_type(),
+					ptr::null(),
+				)
+			});
+			glad::glad_glBindTexture.unwrap()(glad::GL_TEXTURE_2D, 0);
+			glad::glad_glBindBuffer.unwrap()(glad::GL_PIXEL_UNPACK_BUFFER, 0);
+			glad::glad_glPixelStorei.unwrap()(glad::GL_UNPACK_ALIGNMENT, alignment);
+			res?;
 		}
 
-		let data = GlSharedImageData {
-			id,
-			width: width as u32,
-			height: height as u32,
-			format,
-			allocation_size,
+		self.signal_semaphore(timeline_value)
+	}
+
+	pub fn row_size(&self) -> usize {
+		let format = GlSharedImage::get_img_format(self.data.format, self.data.internal_format);
+		self.data.width as usize * format.bytes_per_pixel()
+	}
+
+	pub fn pixel_buffer_size(&self) -> usize {
+		let format = GlSharedImage::get_img_format(self.data.format, self.data.internal_format);
+		self.data.width as usize * self.data.height as usize * format.bytes_per_pixel()
+	}
+
+	fn pixel_type(&self) -> glad::GLenum {
+		GlSharedImage::get_gl_type(GlSharedImage::get_img_format(
+			self.data.format,
+			self.data.internal_format,
+		))
+	}
+
+	// Checks whether the current context can import the server's timeline semaphores
+	pub fn semaphores_supported() -> bool {
+		let mut extension_count: glad::GLint = 0;
+		unsafe { glad::glad_glGetIntegerv.unwrap()(glad::GL_NUM_EXTENSIONS, &mut extension_count) };
+
+		let extensions = (0..extension_count.max(0) as glad::GLuint)
+			.filter_map(|i| unsafe {
+				let name = glad::glad_glGetStringi.unwrap()(glad::GL_EXTENSIONS, i);
+				match name.is_null() {
+					true => None,
+					false => Some(CStr::from_ptr(name as *const _).to_bytes()),
+				}
+			})
+			.collect::<Vec<_>>();
+
+		[
+			b"GL_EXT_semaphore".as_slice(),
+			b"GL_EXT_semaphore_fd".as_slice(),
+			b"GL_NV_timeline_semaphore".as_slice(),
+		]
+		.iter()
+		.all(|ext| extensions.contains(ext))
+	}
+
+	pub fn has_semaphore(&self) -> bool {
+		self.semaphore != 0
+	}
+
+	#[cfg(target_os = "linux")]
+	pub fn import_semaphore(&mut self, handle: GlMemoryHandle) -> Result<(), glad::GLuint> {
+		use std::os::fd::IntoRawFd;
+
+		let (create_semaphores, semaphore_parameteriv) = unsafe {
+			let create_semaphores = glXGetProcAddressARB(b"glCreateSemaphoresNV\0".as_ptr());
+			let semaphore_parameteriv =
+				glXGetProcAddressARB(b"glSemaphoreParameterivNV\0".as_ptr());
+			if create_semaphores.is_null() || semaphore_parameteriv.is_null() {
+				return Err(glad::GL_INVALID_OPERATION);
+			}
+
+			(
+				std::mem::transmute::<_, PfnGlCreateSemaphoresNV>(create_semaphores),
+				std::mem::transmute::<_, PfnGlSemaphoreParameterivNV>(semaphore_parameteriv),
+			)
 		};
-		Ok(GlSharedImage {
-			fbo: 0,
-			mem: 0,
-			texture,
-			data,
-		})
+
+		let mut semaphore: glad::GLuint = 0;
+		unsafe {
+			check_gl(|| create_semaphores(1, &mut semaphore))?;
+
+			// The semaphore type must match the exported Vulkan semaphore
+			let res = check_gl(|| {
+				semaphore_parameteriv(
+					semaphore,
+					GL_SEMAPHORE_TYPE_NV,
+					&GL_SEMAPHORE_TYPE_TIMELINE_NV,
+				)
+			})
+			.and_then(|_| {
+				check_gl(|| {
+					glad::glad_glImportSemaphoreFdEXT.unwrap()(
+						semaphore,
+						glad::GL_HANDLE_TYPE_OPAQUE_FD_EXT,
+						handle.into_raw_fd(),
+					)
+				})
+			});
+			if let Err(e) = res {
+				glad::glad_glDeleteSemaphoresEXT.unwrap()(1, &semaphore);
+				return Err(e);
+			}
+		}
+
+		if self.semaphore != 0 {
+			unsafe { glad::glad_glDeleteSemaphoresEXT.unwrap()(1, &self.semaphore) };
+		}
+		self.semaphore = semaphore;
+
+		Ok(())
 	}
 
-	pub fn get_data(&self) -> &GlSharedImageData {
-		&self.data
+	// Completes a reserved timeline value whose access was never submitted, so that later accesses
+	// don't wait for it forever. The signal is ordered after the accesses before it
+	pub fn skip_timeline_value(&self, timeline_value: &mut u64) -> Result<(), glad::GLuint> {
+		self.wait_semaphore(*timeline_value)?;
+		self.signal_semaphore(timeline_value)
+	}
+
+	// Makes GL wait until the shared timeline reaches timeline_value
+	fn wait_semaphore(&self, timeline_value: u64) -> Result<(), glad::GLuint> {
+		if self.semaphore == 0 {
+			return Ok(());
+		}
+
+		unsafe {
+			check_gl(|| {
+				glad::glad_glSemaphoreParameterui64vEXT.unwrap()(
+					self.semaphore,
+					GL_TIMELINE_SEMAPHORE_VALUE_NV,
+					&timeline_value,
+				)
+			})?;
+			check_gl(|| {
+				glad::glad_glWaitSemaphoreEXT.unwrap()(
+					self.semaphore,
+					0,
+					ptr::null(),
+					1,
+					&self.texture,
+					&SHARED_IMAGE_LAYOUT,
+				)
+			})
+		}
+	}
+
+	// Signals timeline_value + 1 once GL is done with the image, and stores it in timeline_value
+	fn signal_semaphore(&self, timeline_value: &mut u64) -> Result<(), glad::GLuint> {
+		if self.semaphore == 0 {
+			return Ok(());
+		}
+
+		let signal_value = *timeline_value + 1;
+		unsafe {
+			check_gl(|| {
+				glad::glad_glSemaphoreParameterui64vEXT.unwrap()(
+					self.semaphore,
+					GL_TIMELINE_SEMAPHORE_VALUE_NV,
+					&signal_value,
+				)
+			})?;
+			check_gl(|| {
+				glad::glad_glSignalSemaphoreEXT.unwrap()(
+					self.semaphore,
+					0,
+					ptr::null(),
+					1,
+					&self.texture,
+					&SHARED_IMAGE_LAYOUT,
+				)
+			})?;
+
+			// Other processes may wait for the new value as soon as it is stored
+			check_gl(|| glad::glad_glFlush.unwrap()())?;
+		}
+
+		*timeline_value = signal_value;
+		Ok(())
 	}
 
 	fn blit_image(
@@ -376,7 +803,9 @@ impl GlSharedImage {
 		src_dimensions: &GlImageExtent,
 		invert: bool,
 		prev_fbo: glad::GLuint,
+		timeline_value: &mut u64,
 	) -> Result<(), glad::GLuint> {
+		self.wait_semaphore(*timeline_value)?;
 		let blit_fbo = Self::blit_image(
 			src_texture,
 			src_target,
@@ -396,7 +825,7 @@ impl GlSharedImage {
 		)?;
 		self.fbo = blit_fbo;
 
-		Ok(())
+		self.signal_semaphore(timeline_value)
 	}
 
 	pub fn send_blit_image(
@@ -406,7 +835,9 @@ impl GlSharedImage {
 		dst_dimensions: &GlImageExtent,
 		invert: bool,
 		prev_fbo: glad::GLuint,
+		timeline_value: &mut u64,
 	) -> Result<(), glad::GLuint> {
+		self.wait_semaphore(*timeline_value)?;
 		let blit_fbo = Self::blit_image(
 			self.texture,
 			glad::GL_TEXTURE_2D,
@@ -426,6 +857,173 @@ impl GlSharedImage {
 		)?;
 		self.fbo = blit_fbo;
 
+		self.signal_semaphore(timeline_value)
+	}
+}
+
+impl GlPixelBuffer {
+	pub fn new() -> Result<GlPixelBuffer, glad::GLuint> {
+		let mut buffer: glad::GLuint = 0;
+		unsafe { check_gl(|| glad::glad_glGenBuffers.unwrap()(1, &mut buffer))? };
+
+		Ok(GlPixelBuffer {
+			buffer,
+			row_size: 0,
+			height: 0,
+			fence: ptr::null_mut(),
+			invert: false,
+		})
+	}
+
+	pub fn is_read_pending(&self) -> bool {
+		!self.fence.is_null()
+	}
+
+	// Copies height rows of row_size bytes into the buffer. Rows in pixels are stride bytes apart,
+	// or tightly packed if stride is 0. The rows are stored bottom to top if invert is set
+	pub fn fill(
+		&mut self,
+		pixels: &[u8],
+		stride: usize,
+		row_size: usize,
+		height: usize,
+		invert: bool,
+	) -> Result<(), glad::GLuint> {
+		let stride = match stride {
+			0 => row_size,
+			_ => stride,
+		};
+		if stride < row_size || (height > 0 && pixels.len() < stride * (height - 1) + row_size) {
+			return Err(glad::GL_INVALID_VALUE);
+		}
+
+		unsafe {
+			self.allocate(
+				glad::GL_PIXEL_UNPACK_BUFFER,
+				row_size,
+				height,
+				glad::GL_STREAM_DRAW,
+			)?;
+			let res = self.map(glad::GL_PIXEL_UNPACK_BUFFER, glad::GL_MAP_WRITE_BIT, |mapped| {
+				for (row, dst_row) in mapped.chunks_exact_mut(row_size.max(1)).enumerate() {
+					let src_row = match invert {
+						true => height - 1 - row,
+						false => row,
+					};
+					dst_row.copy_from_slice(&pixels[src_row * stride..src_row * stride + row_size]);
+				}
+			});
+			glad::glad_glBindBuffer.unwrap()(glad::GL_PIXEL_UNPACK_BUFFER, 0);
+			res
+		}
+	}
+
+	// Copies the pixels of the pending read into pixels, waiting at most timeout_ns for GL to
+	// complete it. Returns false if the read is still in flight
+	pub fn read(&mut self, pixels: &mut [u8], timeout_ns: u64) -> Result<bool, glad::GLuint> {
+		if self.fence.is_null() {
+			return Err(glad::GL_INVALID_OPERATION);
+		}
+		if pixels.len() < self.row_size * self.height {
+			return Err(glad::GL_INVALID_VALUE);
+		}
+
+		unsafe {
+			match check_gl(|| {
+				glad::glad_glClientWaitSync.unwrap()(
+					self.fence,
+					glad::GL_SYNC_FLUSH_COMMANDS_BIT,
+					timeout_ns,
+				)
+			})? {
+				glad::GL_ALREADY_SIGNALED | glad::GL_CONDITION_SATISFIED => {}
+				glad::GL_TIMEOUT_EXPIRED => return Ok(false),
+				_ => return Err(glad::GL_INVALID_OPERATION),
+			}
+			glad::glad_glDeleteSync.unwrap()(self.fence);
+			self.fence = ptr::null_mut();
+
+			let (row_size, height, invert) = (self.row_size, self.height, self.invert);
+			check_gl(|| glad::glad_glBindBuffer.unwrap()(glad::GL_PIXEL_PACK_BUFFER, self.buffer))?;
+			let res = self.map(glad::GL_PIXEL_PACK_BUFFER, glad::GL_MAP_READ_BIT, |mapped| {
+				for (row, src_row) in mapped.chunks_exact(row_size.max(1)).enumerate() {
+					let dst_row = match invert {
+						true => height - 1 - row,
+						false => row,
+					};
+					pixels[dst_row * row_size..(dst_row + 1) * row_size].copy_from_slice(src_row);
+				}
+			});
+			glad::glad_glBindBuffer.unwrap()(glad::GL_PIXEL_PACK_BUFFER, 0);
+			res?;
+		}
+
+		Ok(true)
+	}
+
+	// Binds the buffer to target and replaces its storage, which drops any pending read
+	unsafe fn allocate(
+		&mut self,
+		target: glad::GLenum,
+		row_size: usize,
+		height: usize,
+		usage: glad::GLenum,
+	) -> Result<(), glad::GLuint> {
+		if !self.fence.is_null() {
+			glad::glad_glDeleteSync.unwrap()(self.fence);
+			self.fence = ptr::null_mut();
+		}
+
+		check_gl(|| glad::glad_glBindBuffer.unwrap()(target, self.buffer))?;
+		check_gl(|| {
+			glad::glad_glBufferData.unwrap()(
+				target,
+				(row_size * height) as glad::GLsizeiptr,
+				ptr::null(),
+				usage,
+			)
+		})?;
+		self.row_size = row_size;
+		self.height = height;
+
+		Ok(())
+	}
+
+	// Maps the buffer bound to target while running map_fcn
+	unsafe fn map<F: FnOnce(&mut [u8])>(
+		&self,
+		target: glad::GLenum,
+		access: glad::GLbitfield,
+		map_fcn: F,
+	) -> Result<(), glad::GLuint> {
+		let size = self.row_size * self.height;
+		if size == 0 {
+			return Ok(());
+		}
+
+		let mapped = check_gl(|| {
+			glad::glad_glMapBufferRange.unwrap()(target, 0, size as glad::GLsizeiptr, access)
+		})?;
+		if mapped.is_null() {
+			return Err(glad::GL_INVALID_OPERATION);
+		}
+
+		map_fcn(std::slice::from_raw_parts_mut(mapped as *mut u8, size));
+		match check_gl(|| glad::glad_glUnmapBuffer.unwrap()(target))? {
+			// The storage was lost while mapped, e.g. on a mode switch
+			0 => Err(glad::GL_INVALID_OPERATION),
+			_ => Ok(()),
+		}
+	}
+
+	fn insert_fence(&mut self, invert: bool) -> Result<(), glad::GLuint> {
+		self.fence = unsafe {
+			check_gl(|| {
+				glad::glad_glFenceSync.unwrap()(glad::GL_SYNC_GPU_COMMANDS_COMPLETE, 0)
+			})?
+		};
+		self.invert = invert;
+
 		Ok(())
 	}
 }
@@ -433,7 +1031,7 @@ impl GlSharedImage {
 #[cfg(test)]
 mod tests {
 	use super::glad;
-	use super::GlSharedImage;
+	use super::{GlPixelBuffer, GlSharedImage};
 	use glfw::fail_on_errors;
 	use glfw::Context;
 
@@ -474,4 +1072,33 @@ mod tests {
 		let _gl_shared_image =
 			GlSharedImage::new(1, 1, 4, glad::GL_RGBA, glad::GL_RGBA as i32, 0).unwrap();
 	}
+
+	#[test]
+	fn gl_shared_image_pixel_buffer() {
+		let _gl_context = _init_gl();
+		let gl_shared_image =
+			GlSharedImage::new(2, 2, 16, glad::GL_RGBA, glad::GL_RGBA8 as i32, 0).unwrap();
+		let mut pixel_buffer = GlPixelBuffer::new().unwrap();
+		let mut timeline_value = 0;
+
+		// Rows are padded to 12 bytes
+		let pixels: Vec<u8> = (0..24).collect();
+		pixel_buffer.fill(&pixels, 12, 8, 2, false).unwrap();
+		gl_shared_image
+			.unpack_pixels(&pixel_buffer, &mut timeline_value)
+			.unwrap();
+
+		gl_shared_image
+			.pack_pixels(&mut pixel_buffer, true, &mut timeline_value)
+			.unwrap();
+		assert!(pixel_buffer.is_read_pending());
+
+		let mut read_pixels = [0; 16];
+		assert!(pixel_buffer.read(&mut read_pixels, u64::MAX).unwrap());
+		assert_eq!(&read_pixels[..8], &pixels[12..20]);
+		assert_eq!(&read_pixels[8..], &pixels[..8]);
+		assert!(!pixel_buffer.is_read_pending());
+
+		assert!(pixel_buffer.fill(&pixels[..16], 12, 8, 2, false).is_err());
+	}
 }
@@ -8,5 +8,7 @@ mod bindings;
 // cbindgen:ignore
 pub mod platform;
 
+pub use platform::aligned_ram_buffer::AlignedRamBuffer;
+pub use platform::ipc_ram_image::RamImage;
 pub use platform::ipc_shmem::IpcShmem;
 pub use platform::ipc_unix_socket::{IpcConnection, IpcSocket};
@@ -1,14 +1,19 @@
+pub mod aligned_ram_buffer;
 pub mod daemon_launch;
 pub mod img_data;
 pub mod ipc_commands;
 pub mod ipc_shmem;
+pub mod ipc_wire;
 
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
 pub(crate) use linux::*;
 
-pub use ipc_shmem_lock::{LockGuard, ReadLockGuard, RwLockInternalData};
+pub use ipc_shmem_lock::{LockGuard, LockInternalData, ReadLockGuard};
 
-pub use ipc_shmem::ShmemDataInternal;
 pub use ipc_shmem::Timeout;
+pub use ipc_shmem::{
+	monotonic_time_ns, FrameInfo, ShmemDataInternal, TimelineReservation, MAX_BUFFER_COUNT,
+	MAX_TIMELINE_RESERVATIONS,
+};
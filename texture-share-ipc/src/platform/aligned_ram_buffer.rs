@@ -0,0 +1,40 @@
+use libc::c_void;
+use std::{alloc, ptr};
+
+// Host memory with the alignment required to import it into GPU memory. The server copies frames
+// between GPUs through it
+pub struct AlignedRamBuffer {
+	pub layout: alloc::Layout,
+	pub ptr: *mut c_void,
+}
+
+impl Drop for AlignedRamBuffer {
+	fn drop(&mut self) {
+		if !self.ptr.is_null() {
+			unsafe { alloc::dealloc(self.ptr as *mut _, self.layout) };
+			self.ptr = ptr::null_mut();
+		}
+	}
+}
+
+impl Default for AlignedRamBuffer {
+	fn default() -> Self {
+		Self {
+			layout: alloc::Layout::new::<u8>(),
+			ptr: ptr::null_mut(),
+		}
+	}
+}
+
+impl AlignedRamBuffer {
+	pub fn new(min_size: usize, align: usize) -> AlignedRamBuffer {
+		let layout = alloc::Layout::from_size_align(min_size, align)
+			.expect("Unable to create memory layout");
+		let ptr = unsafe { alloc::alloc(layout) };
+
+		AlignedRamBuffer {
+			layout,
+			ptr: ptr as *mut _,
+		}
+	}
+}
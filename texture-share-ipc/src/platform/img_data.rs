@@ -1,3 +1,5 @@
+use std::fmt::Display;
+use std::io::{Error, ErrorKind};
 use std::mem::size_of;
 
 use super::ShmemDataInternal;
@@ -5,6 +7,41 @@ use super::ShmemDataInternal;
 pub(crate) type ImgName = [u8; 1024];
 pub(crate) type ShmemName = [u8; 1024];
 
+// Names are stored NUL terminated in shared memory, so they must leave room for the terminator
+pub const MAX_NAME_LEN: usize = size_of::<ImgName>() - 1;
+
+// POSIX shm names are limited to NAME_MAX bytes, including the leading '/'
+const MAX_SHMEM_NAME_LEN: usize = 250;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameError {
+	Empty,
+	TooLong(usize),
+	ContainsNul,
+}
+
+impl Display for NameError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			NameError::Empty => write!(f, "Image name is empty"),
+			NameError::TooLong(len) => write!(
+				f,
+				"Image name of {} bytes exceeds limit of {} bytes",
+				len, MAX_NAME_LEN
+			),
+			NameError::ContainsNul => write!(f, "Image name contains a NUL character"),
+		}
+	}
+}
+
+impl std::error::Error for NameError {}
+
+impl From<NameError> for Error {
+	fn from(value: NameError) -> Self {
+		Error::new(ErrorKind::InvalidInput, value)
+	}
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ImgFormat {
@@ -13,6 +50,18 @@ pub enum ImgFormat {
 	B8G8R8A8,
 	B8G8R8,
 	Undefined,
+	// Single channel, e.g. for masks and depth data
+	R8,
+	R16,
+	R32Sfloat,
+	// 16 bit and HDR formats
+	R16G16B16A16,
+	R16G16B16A16Sfloat,
+	R32G32B32A32Sfloat,
+	A2B10G10R10,
+	// sRGB encoded 8 bit formats, as used by swapchains
+	R8G8B8A8Srgb,
+	B8G8R8A8Srgb,
 }
 
 #[repr(C)]
@@ -56,29 +105,62 @@ impl ImgData {
 		}
 	}
 
-	pub fn convert_shmem_str_to_array(shmem_name: &str) -> ShmemName {
-		// Generate ResultMsg data
-		let shmem_name_array = shmem_name.as_bytes().to_owned();
-		let shmem_name_len = shmem_name_array.len();
-		if shmem_name_len >= size_of::<ShmemName>() {
-			panic!("Shmem Name '{}' too long. Stopping", shmem_name);
+	pub fn validate_name(name: &str) -> Result<(), NameError> {
+		if name.is_empty() {
+			return Err(NameError::Empty);
+		}
+		if name.len() > MAX_NAME_LEN {
+			return Err(NameError::TooLong(name.len()));
+		}
+		if name.contains('\0') {
+			return Err(NameError::ContainsNul);
 		}
 
+		Ok(())
+	}
+
+	// Image names may contain any character, but shm identifiers may not contain '/' and are
+	// limited in length. Replace everything outside of a portable set and append a hash of the
+	// original name, so that different names can't collide even if one of them looks like the
+	// altered form of the other
+	pub fn shmem_name_from_image_name(shmem_prefix: &str, image_name: &str) -> String {
+		let name = shmem_prefix.to_owned() + image_name;
+		let mut shmem_name: String = name
+			.chars()
+			.map(
+				|c| match c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' {
+					true => c,
+					false => '_',
+				},
+			)
+			.collect();
+
+		// FNV-1a, which is stable across builds unlike the std hashers
+		let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, b| {
+			(hash ^ b as u64).wrapping_mul(0x100000001b3)
+		});
+		shmem_name.truncate(MAX_SHMEM_NAME_LEN - 17);
+		shmem_name += &format!("_{:016x}", hash);
+
+		shmem_name
+	}
+
+	pub fn convert_shmem_str_to_array(shmem_name: &str) -> Result<ShmemName, NameError> {
+		ImgData::validate_name(shmem_name)?;
+
 		let mut buf = [0 as u8; size_of::<ShmemName>()];
-		buf[0..shmem_name_len].copy_from_slice(&shmem_name_array[0..shmem_name_len]);
-		buf
+		buf[0..shmem_name.len()].copy_from_slice(shmem_name.as_bytes());
+		Ok(buf)
 	}
 
+	// Buffers without a terminator are read in full instead of failing
 	pub fn convert_shmem_array_to_str(shmem_name: &ShmemName) -> String {
-		let end = shmem_name.iter().position(|it| *it == 0 as u8);
-		if end.is_none() {
-			panic!(
-				"Shmem Name '{}' contains no end",
-				String::from_utf8_lossy(shmem_name)
-			);
-		}
+		let end = shmem_name
+			.iter()
+			.position(|it| *it == 0 as u8)
+			.unwrap_or(shmem_name.len());
 
-		String::from_utf8_lossy(&shmem_name[0..end.unwrap()]).to_string()
+		String::from_utf8_lossy(&shmem_name[0..end]).to_string()
 	}
 }
 
@@ -88,6 +170,26 @@ impl Default for ImgFormat {
 	}
 }
 
+impl ImgFormat {
+	// Size of a pixel in tightly packed RAM images, 0 if the format is undefined
+	pub fn bytes_per_pixel(&self) -> usize {
+		match self {
+			ImgFormat::R8 => 1,
+			ImgFormat::R16 => 2,
+			ImgFormat::R8G8B8 | ImgFormat::B8G8R8 => 3,
+			ImgFormat::R8G8B8A8
+			| ImgFormat::B8G8R8A8
+			| ImgFormat::R8G8B8A8Srgb
+			| ImgFormat::B8G8R8A8Srgb
+			| ImgFormat::A2B10G10R10
+			| ImgFormat::R32Sfloat => 4,
+			ImgFormat::R16G16B16A16 | ImgFormat::R16G16B16A16Sfloat => 8,
+			ImgFormat::R32G32B32A32Sfloat => 16,
+			ImgFormat::Undefined => 0,
+		}
+	}
+}
+
 impl Default for ImgData {
 	fn default() -> Self {
 		Self {
@@ -96,3 +198,64 @@ impl Default for ImgData {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn name_validation() {
+		assert_eq!(ImgData::validate_name("scene: main/left"), Ok(()));
+		assert_eq!(ImgData::validate_name(""), Err(NameError::Empty));
+		assert_eq!(ImgData::validate_name("a\0b"), Err(NameError::ContainsNul));
+
+		let long_name = "a".repeat(MAX_NAME_LEN + 1);
+		assert_eq!(
+			ImgData::validate_name(&long_name),
+			Err(NameError::TooLong(MAX_NAME_LEN + 1))
+		);
+		assert!(ImgData::convert_shmem_str_to_array(&long_name).is_err());
+
+		let name = ImgData::convert_shmem_str_to_array(&long_name[1..]).unwrap();
+		assert_eq!(ImgData::convert_shmem_array_to_str(&name), long_name[1..]);
+
+		// Unterminated buffers don't panic
+		let name = [b'a'; size_of::<ShmemName>()];
+		assert_eq!(
+			ImgData::convert_shmem_array_to_str(&name).len(),
+			size_of::<ShmemName>()
+		);
+	}
+
+	#[test]
+	fn shmem_name_sanitized() {
+		let name = ImgData::shmem_name_from_image_name("shared_images_", "img-0.a");
+		assert!(name.starts_with("shared_images_img-0.a_"));
+		assert_eq!(name.len(), "shared_images_img-0.a".len() + 17);
+
+		let name = ImgData::shmem_name_from_image_name("shared_images_", "a/b");
+		assert!(name.starts_with("shared_images_a_b_"));
+		assert!(!name.contains('/'));
+
+		// Names that look like the sanitized form of another name get a different hash
+		assert_ne!(
+			ImgData::shmem_name_from_image_name("shared_images_", &name["shared_images_".len()..]),
+			name
+		);
+		assert_ne!(
+			name,
+			ImgData::shmem_name_from_image_name("shared_images_", "a_b")
+		);
+		assert_ne!(
+			name,
+			ImgData::shmem_name_from_image_name("shared_images_", "a b")
+		);
+
+		let name = ImgData::shmem_name_from_image_name("shared_images_", &"a".repeat(MAX_NAME_LEN));
+		assert_eq!(name.len(), MAX_SHMEM_NAME_LEN);
+		assert_ne!(
+			name,
+			ImgData::shmem_name_from_image_name("shared_images_", &"a".repeat(MAX_NAME_LEN - 1))
+		);
+	}
+}
@@ -1,49 +1,103 @@
-use crate::platform::img_data::{ImgData, ImgFormat, ImgName, ShmemName};
+use crate::platform::img_data::{ImgData, ImgFormat};
+use crate::platform::ipc_wire::{DecodeError, WireMessage, WireReader, WireWriter};
+use crate::platform::ShmemDataInternal;
 
-use std::mem::{size_of, ManuallyDrop};
+use std::fmt::Display;
 
-#[repr(C)]
-pub struct CommandMsg {
-	pub tag: CommandTag,
-	pub data: CommandData,
+// Must be bumped whenever the encoding of any message changes incompatibly. Fields appended to a
+// message are decoded with a default if the peer omits them
+pub const PROTOCOL_VERSION: u32 = 5;
+
+// Oldest client protocol the server accepts. Older clients expect a single image per name and its
+// timeline value in the shared memory
+pub const MIN_PROTOCOL_VERSION: u32 = 4;
+
+// Optional capabilities negotiated during the Hello exchange
+pub mod features {
+	pub const NONE: u64 = 0;
+
+	// Server pushes image events to connections that subscribed to them
+	pub const EVENTS: u64 = 1 << 0;
+
+	// Server sends the image's timeline semaphore fd after its memory fd
+	pub const TIMELINE_SEMAPHORE: u64 = 1 << 1;
+
+	// Images requested with a nil GPU uuid are sent as a memfd holding their pixels, which
+	// clients synchronize with the GPU images through RamTransfer commands
+	pub const RAM_IMAGES: u64 = 1 << 2;
+
+	// Server sends the image's IpcShmem fd before its other handles. Without it, clients open the
+	// IpcShmem by ImgData::shmem_name, which requires the server to use named segments
+	pub const SHMEM_HANDLES: u64 = 1 << 3;
+
+	// All features supported by this build
+	pub const SUPPORTED: u64 = EVENTS | TIMELINE_SEMAPHORE | RAM_IMAGES | SHMEM_HANDLES;
 }
 
-#[repr(C)]
-pub struct ResultMsg {
-	pub tag: CommandTag,
-	pub data: ResultData,
+pub enum CommandMsg {
+	InitImage(CommInitImage),
+	FindImage(CommFindImage),
+	CopyImage(CommCopyImage),
+	Hello(CommHello),
+	RenameImage(CommRenameImage),
+	DeleteImage(CommDeleteImage),
+	ListImages(CommListImages),
+	Subscribe(CommSubscribe),
+	RamTransfer(CommRamTransfer),
 }
 
-#[repr(u32)]
-#[derive(Debug, PartialEq)]
-pub enum CommandTag {
-	InitImage,
-	//RenameImage,
-	FindImage,
-	CopyImage,
+pub enum ResultMsg {
+	InitImage(ResultInitImage),
+	FindImage(ResultFindImage),
+	Hello(ResultHello),
+	Error(ResultError),
+	RenameImage(ResultRenameImage),
+	DeleteImage(ResultDeleteImage),
+	ListImages(ResultListImages),
+	CopyImage(ResultCopyImage),
+	Subscribe(ResultSubscribe),
+	RamTransfer(ResultRamTransfer),
+	// Pushed by the server at any time in between replies
+	Event(ImageEvent),
 }
 
-#[repr(C)]
-pub union CommandData {
-	pub init_img: ManuallyDrop<CommInitImage>,
-	pub find_img: ManuallyDrop<CommFindImage>,
-	pub copy_img: ManuallyDrop<CommCopyImage>,
+// Tag values are part of the wire format. Never reorder, only append
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandTag {
+	InitImage = 0,
+	FindImage = 1,
+	CopyImage = 2,
+	Hello = 3,
+	// Only sent as result
+	Error = 4,
+	RenameImage = 5,
+	DeleteImage = 6,
+	ListImages = 7,
+	Subscribe = 8,
+	// Only sent as result
+	Event = 9,
+	RamTransfer = 10,
 }
 
-#[repr(C)]
-pub union ResultData {
-	pub init_img: ManuallyDrop<ResultInitImage>,
-	pub find_img: ManuallyDrop<ResultFindImage>,
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientKind {
+	Vk,
+	Gl,
+	Other,
 }
 
 pub struct CommInitImage {
-	pub image_name: ImgName,
-	pub shmem_name: ShmemName,
+	pub image_name: String,
+	pub shmem_name: String,
 	pub width: u32,
 	pub height: u32,
 	pub format: ImgFormat,
 	pub overwrite_existing: bool,
 	pub gpu_device_uuid: u128,
+	// Number of images in the ring, between 1 and MAX_BUFFER_COUNT
+	pub buffer_count: u32,
 }
 
 pub struct ResultInitImage {
@@ -52,8 +106,9 @@ pub struct ResultInitImage {
 }
 
 pub struct CommRenameImage {
-	pub old_image_name: ImgName,
-	pub new_image_name: ImgName,
+	pub old_image_name: String,
+	pub new_image_name: String,
+	pub gpu_device_uuid: u128,
 }
 
 pub struct ResultRenameImage {
@@ -61,8 +116,34 @@ pub struct ResultRenameImage {
 	pub img_data: ImgData,
 }
 
+pub struct CommDeleteImage {
+	pub image_name: String,
+}
+
+pub struct ResultDeleteImage {
+	pub image_found: bool,
+}
+
+#[derive(Default)]
+pub struct CommListImages {}
+
+pub struct ImageListEntry {
+	pub image_name: String,
+	pub handle_id: u32,
+	pub width: u32,
+	pub height: u32,
+	pub format: ImgFormat,
+	// GPUs that currently hold a copy of the image. A nil uuid marks a copy in RAM
+	pub gpu_device_uuids: Vec<u128>,
+}
+
+#[derive(Default)]
+pub struct ResultListImages {
+	pub images: Vec<ImageListEntry>,
+}
+
 pub struct CommFindImage {
-	pub image_name: ImgName,
+	pub image_name: String,
 	pub gpu_device_uuid: u128,
 }
 
@@ -72,31 +153,807 @@ pub struct ResultFindImage {
 }
 
 pub struct CommCopyImage {
-	pub image_name: ImgName,
+	pub image_name: String,
 	pub gpu_device_uuid: u128,
 }
 
-impl Default for CommandMsg {
-	fn default() -> Self {
-		Self {
-			tag: CommandTag::FindImage,
-			data: CommandData {
-				find_img: ManuallyDrop::new(CommFindImage::default()),
+pub struct ResultCopyImage {
+	pub success: bool,
+	pub destinations_written: u32,
+	// Reason for the failure if success is false
+	pub error: Option<ResultError>,
+}
+
+// No image name (un)subscribes from events of all images
+pub struct CommSubscribe {
+	pub image_name: Option<String>,
+	pub subscribe: bool,
+}
+
+pub struct ResultSubscribe {
+	pub subscribed: bool,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RamTransferDirection {
+	// Write the RAM image to the image on every GPU
+	Upload,
+	// Read the latest frame into the RAM image
+	Download,
+}
+
+pub struct CommRamTransfer {
+	pub image_name: String,
+	pub direction: RamTransferDirection,
+}
+
+pub struct ResultRamTransfer {
+	pub success: bool,
+	// Reason for the failure if success is false
+	pub error: Option<ResultError>,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageEventKind {
+	Created,
+	Resized,
+	Deleted,
+	FrameAvailable,
+}
+
+#[derive(Clone)]
+pub struct ImageEvent {
+	pub kind: ImageEventKind,
+	pub image_name: String,
+	pub handle_id: u32,
+	pub width: u32,
+	pub height: u32,
+	pub format: ImgFormat,
+}
+
+pub struct CommHello {
+	pub protocol_version: u32,
+	pub client_kind: ClientKind,
+	pub pid: u32,
+	pub features: u64,
+}
+
+pub struct ResultHello {
+	pub accepted: bool,
+	pub protocol_version: u32,
+	pub features: u64,
+	// Id the server knows the connection by, which clients record their timeline reservations
+	// with. 0 if the server doesn't track reservations
+	pub connection_id: u32,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+	Unknown,
+	InvalidMessage,
+	ImageExists,
+	DeviceUnavailable,
+	SharedMemory,
+	Allocation,
+	LockTimeout,
+	ImageNotFound,
+	CopyFailed,
+	InvalidName,
+	InvalidBufferCount,
+	UnsupportedFormat,
+	InvalidSlot,
+}
+
+// Sent by the server if a command could not be processed. Clients return it as their error type,
+// so callers can downcast to it to inspect the code
+#[derive(Debug, Clone)]
+pub struct ResultError {
+	pub code: ErrorCode,
+	pub message: String,
+}
+
+impl ResultError {
+	pub fn new(code: ErrorCode, message: impl Display) -> ResultError {
+		ResultError {
+			code,
+			message: message.to_string(),
+		}
+	}
+}
+
+impl Display for ResultError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Server error {:?}: {}", self.code, self.message)
+	}
+}
+
+impl std::error::Error for ResultError {}
+
+impl TryFrom<u32> for CommandTag {
+	type Error = DecodeError;
+
+	fn try_from(value: u32) -> Result<Self, DecodeError> {
+		match value {
+			0 => Ok(CommandTag::InitImage),
+			1 => Ok(CommandTag::FindImage),
+			2 => Ok(CommandTag::CopyImage),
+			3 => Ok(CommandTag::Hello),
+			4 => Ok(CommandTag::Error),
+			5 => Ok(CommandTag::RenameImage),
+			6 => Ok(CommandTag::DeleteImage),
+			7 => Ok(CommandTag::ListImages),
+			8 => Ok(CommandTag::Subscribe),
+			9 => Ok(CommandTag::Event),
+			10 => Ok(CommandTag::RamTransfer),
+			_ => Err(DecodeError::UnknownTag(value)),
+		}
+	}
+}
+
+impl CommandMsg {
+	pub fn tag(&self) -> CommandTag {
+		match self {
+			CommandMsg::InitImage(_) => CommandTag::InitImage,
+			CommandMsg::FindImage(_) => CommandTag::FindImage,
+			CommandMsg::CopyImage(_) => CommandTag::CopyImage,
+			CommandMsg::Hello(_) => CommandTag::Hello,
+			CommandMsg::RenameImage(_) => CommandTag::RenameImage,
+			CommandMsg::DeleteImage(_) => CommandTag::DeleteImage,
+			CommandMsg::ListImages(_) => CommandTag::ListImages,
+			CommandMsg::Subscribe(_) => CommandTag::Subscribe,
+			CommandMsg::RamTransfer(_) => CommandTag::RamTransfer,
+		}
+	}
+
+	pub fn encode_payload(&self) -> Vec<u8> {
+		let mut writer = WireWriter::new();
+		match self {
+			CommandMsg::InitImage(c) => c.encode(&mut writer),
+			CommandMsg::FindImage(c) => c.encode(&mut writer),
+			CommandMsg::CopyImage(c) => c.encode(&mut writer),
+			CommandMsg::Hello(c) => c.encode(&mut writer),
+			CommandMsg::RenameImage(c) => c.encode(&mut writer),
+			CommandMsg::DeleteImage(c) => c.encode(&mut writer),
+			CommandMsg::ListImages(c) => c.encode(&mut writer),
+			CommandMsg::Subscribe(c) => c.encode(&mut writer),
+			CommandMsg::RamTransfer(c) => c.encode(&mut writer),
+		};
+		writer.into_inner()
+	}
+
+	pub fn decode(tag: u32, payload: &[u8]) -> Result<CommandMsg, DecodeError> {
+		let mut reader = WireReader::new(payload);
+		let msg = match CommandTag::try_from(tag)? {
+			CommandTag::InitImage => CommandMsg::InitImage(CommInitImage::decode(&mut reader)?),
+			CommandTag::FindImage => CommandMsg::FindImage(CommFindImage::decode(&mut reader)?),
+			CommandTag::CopyImage => CommandMsg::CopyImage(CommCopyImage::decode(&mut reader)?),
+			CommandTag::Hello => CommandMsg::Hello(CommHello::decode(&mut reader)?),
+			CommandTag::RenameImage => {
+				CommandMsg::RenameImage(CommRenameImage::decode(&mut reader)?)
+			}
+			CommandTag::DeleteImage => {
+				CommandMsg::DeleteImage(CommDeleteImage::decode(&mut reader)?)
+			}
+			CommandTag::ListImages => CommandMsg::ListImages(CommListImages::decode(&mut reader)?),
+			CommandTag::Subscribe => CommandMsg::Subscribe(CommSubscribe::decode(&mut reader)?),
+			CommandTag::RamTransfer => {
+				CommandMsg::RamTransfer(CommRamTransfer::decode(&mut reader)?)
+			}
+			CommandTag::Error | CommandTag::Event => return Err(DecodeError::UnknownTag(tag)),
+		};
+		Ok(msg)
+	}
+}
+
+impl ResultMsg {
+	pub fn tag(&self) -> CommandTag {
+		match self {
+			ResultMsg::InitImage(_) => CommandTag::InitImage,
+			ResultMsg::FindImage(_) => CommandTag::FindImage,
+			ResultMsg::Hello(_) => CommandTag::Hello,
+			ResultMsg::Error(_) => CommandTag::Error,
+			ResultMsg::RenameImage(_) => CommandTag::RenameImage,
+			ResultMsg::DeleteImage(_) => CommandTag::DeleteImage,
+			ResultMsg::ListImages(_) => CommandTag::ListImages,
+			ResultMsg::CopyImage(_) => CommandTag::CopyImage,
+			ResultMsg::Subscribe(_) => CommandTag::Subscribe,
+			ResultMsg::RamTransfer(_) => CommandTag::RamTransfer,
+			ResultMsg::Event(_) => CommandTag::Event,
+		}
+	}
+
+	pub fn encode_payload(&self) -> Vec<u8> {
+		let mut writer = WireWriter::new();
+		match self {
+			ResultMsg::InitImage(r) => r.encode(&mut writer),
+			ResultMsg::FindImage(r) => r.encode(&mut writer),
+			ResultMsg::Hello(r) => r.encode(&mut writer),
+			ResultMsg::Error(r) => r.encode(&mut writer),
+			ResultMsg::RenameImage(r) => r.encode(&mut writer),
+			ResultMsg::DeleteImage(r) => r.encode(&mut writer),
+			ResultMsg::ListImages(r) => r.encode(&mut writer),
+			ResultMsg::CopyImage(r) => r.encode(&mut writer),
+			ResultMsg::Subscribe(r) => r.encode(&mut writer),
+			ResultMsg::RamTransfer(r) => r.encode(&mut writer),
+			ResultMsg::Event(r) => r.encode(&mut writer),
+		};
+		writer.into_inner()
+	}
+
+	pub fn decode(tag: u32, payload: &[u8]) -> Result<ResultMsg, DecodeError> {
+		let mut reader = WireReader::new(payload);
+		let msg = match CommandTag::try_from(tag)? {
+			CommandTag::InitImage => ResultMsg::InitImage(ResultInitImage::decode(&mut reader)?),
+			CommandTag::FindImage => ResultMsg::FindImage(ResultFindImage::decode(&mut reader)?),
+			CommandTag::Hello => ResultMsg::Hello(ResultHello::decode(&mut reader)?),
+			CommandTag::Error => ResultMsg::Error(ResultError::decode(&mut reader)?),
+			CommandTag::RenameImage => {
+				ResultMsg::RenameImage(ResultRenameImage::decode(&mut reader)?)
+			}
+			CommandTag::DeleteImage => {
+				ResultMsg::DeleteImage(ResultDeleteImage::decode(&mut reader)?)
+			}
+			CommandTag::ListImages => ResultMsg::ListImages(ResultListImages::decode(&mut reader)?),
+			CommandTag::CopyImage => ResultMsg::CopyImage(ResultCopyImage::decode(&mut reader)?),
+			CommandTag::Subscribe => ResultMsg::Subscribe(ResultSubscribe::decode(&mut reader)?),
+			CommandTag::RamTransfer => {
+				ResultMsg::RamTransfer(ResultRamTransfer::decode(&mut reader)?)
+			}
+			CommandTag::Event => ResultMsg::Event(ImageEvent::decode(&mut reader)?),
+		};
+		Ok(msg)
+	}
+}
+
+impl WireMessage for ImgFormat {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(*self as u32);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let format = match reader.read_u32()? {
+			0 => ImgFormat::R8G8B8A8,
+			1 => ImgFormat::R8G8B8,
+			2 => ImgFormat::B8G8R8A8,
+			3 => ImgFormat::B8G8R8,
+			4 => ImgFormat::Undefined,
+			5 => ImgFormat::R8,
+			6 => ImgFormat::R16,
+			7 => ImgFormat::R32Sfloat,
+			8 => ImgFormat::R16G16B16A16,
+			9 => ImgFormat::R16G16B16A16Sfloat,
+			10 => ImgFormat::R32G32B32A32Sfloat,
+			11 => ImgFormat::A2B10G10R10,
+			12 => ImgFormat::R8G8B8A8Srgb,
+			13 => ImgFormat::B8G8R8A8Srgb,
+			f => {
+				return Err(DecodeError::InvalidValue {
+					field: "format",
+					value: f as u64,
+				})
+			}
+		};
+		Ok(format)
+	}
+}
+
+impl WireMessage for ClientKind {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(*self as u32);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		// Unknown client kinds are not an error, they may come from newer peers
+		let kind = match reader.read_u32()? {
+			0 => ClientKind::Vk,
+			1 => ClientKind::Gl,
+			_ => ClientKind::Other,
+		};
+		Ok(kind)
+	}
+}
+
+impl WireMessage for ErrorCode {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(*self as u32);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		// Error codes added by newer servers are reported as unknown
+		let code = match reader.read_u32()? {
+			1 => ErrorCode::InvalidMessage,
+			2 => ErrorCode::ImageExists,
+			3 => ErrorCode::DeviceUnavailable,
+			4 => ErrorCode::SharedMemory,
+			5 => ErrorCode::Allocation,
+			6 => ErrorCode::LockTimeout,
+			7 => ErrorCode::ImageNotFound,
+			8 => ErrorCode::CopyFailed,
+			9 => ErrorCode::InvalidName,
+			10 => ErrorCode::InvalidBufferCount,
+			11 => ErrorCode::UnsupportedFormat,
+			12 => ErrorCode::InvalidSlot,
+			_ => ErrorCode::Unknown,
+		};
+		Ok(code)
+	}
+}
+
+impl WireMessage for ImgData {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_name(&self.shmem_name);
+		writer.write_name(&self.data.name);
+		writer.write_u32(self.data.handle_id);
+		writer.write_u32(self.data.width);
+		writer.write_u32(self.data.height);
+		self.data.format.encode(writer);
+		writer.write_u64(self.data.allocation_size);
+		writer.write_u64(self.data.gpu_device_uuid_0);
+		writer.write_u64(self.data.gpu_device_uuid_1);
+		writer.write_u32(self.data.buffer_count);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let shmem_name = reader.read_name()?;
+		let data = ShmemDataInternal {
+			name: reader.read_name()?,
+			handle_id: reader.read_u32()?,
+			width: reader.read_u32()?,
+			height: reader.read_u32()?,
+			format: ImgFormat::decode(reader)?,
+			allocation_size: reader.read_u64()?,
+			gpu_device_uuid_0: reader.read_u64()?,
+			gpu_device_uuid_1: reader.read_u64()?,
+			buffer_count: match reader.is_empty() {
+				true => 1,
+				false => reader.read_u32()?,
+			},
+			..Default::default()
+		};
+		Ok(ImgData::from_shmem_data_internal(shmem_name, data))
+	}
+}
+
+impl WireMessage for CommInitImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.image_name);
+		writer.write_str(&self.shmem_name);
+		writer.write_u32(self.width);
+		writer.write_u32(self.height);
+		self.format.encode(writer);
+		writer.write_bool(self.overwrite_existing);
+		writer.write_u128(self.gpu_device_uuid);
+		writer.write_u32(self.buffer_count);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommInitImage {
+			image_name: reader.read_image_name()?,
+			// Only kept for compatibility, the server derives the shmem name itself
+			shmem_name: reader.read_string()?,
+			width: reader.read_u32()?,
+			height: reader.read_u32()?,
+			format: ImgFormat::decode(reader)?,
+			overwrite_existing: reader.read_bool()?,
+			gpu_device_uuid: reader.read_u128()?,
+			buffer_count: match reader.is_empty() {
+				true => 1,
+				false => reader.read_u32()?,
 			},
+		})
+	}
+}
+
+impl WireMessage for ResultInitImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.image_created);
+		self.img_data.encode(writer);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultInitImage {
+			image_created: reader.read_bool()?,
+			img_data: ImgData::decode(reader)?,
+		})
+	}
+}
+
+impl WireMessage for CommRenameImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.old_image_name);
+		writer.write_str(&self.new_image_name);
+		writer.write_u128(self.gpu_device_uuid);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommRenameImage {
+			old_image_name: reader.read_image_name()?,
+			new_image_name: reader.read_image_name()?,
+			gpu_device_uuid: reader.read_u128()?,
+		})
+	}
+}
+
+impl WireMessage for ResultRenameImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.image_found);
+		self.img_data.encode(writer);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultRenameImage {
+			image_found: reader.read_bool()?,
+			img_data: ImgData::decode(reader)?,
+		})
+	}
+}
+
+impl WireMessage for CommDeleteImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.image_name);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommDeleteImage {
+			image_name: reader.read_image_name()?,
+		})
+	}
+}
+
+impl WireMessage for ResultDeleteImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.image_found);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultDeleteImage {
+			image_found: reader.read_bool()?,
+		})
+	}
+}
+
+impl WireMessage for CommListImages {
+	fn encode(&self, _writer: &mut WireWriter) {}
+
+	fn decode(_reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommListImages {})
+	}
+}
+
+impl WireMessage for ImageListEntry {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.image_name);
+		writer.write_u32(self.handle_id);
+		writer.write_u32(self.width);
+		writer.write_u32(self.height);
+		self.format.encode(writer);
+		writer.write_u32(self.gpu_device_uuids.len() as u32);
+		for gpu_device_uuid in self.gpu_device_uuids.iter() {
+			writer.write_u128(*gpu_device_uuid);
 		}
 	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let image_name = reader.read_image_name()?;
+		let handle_id = reader.read_u32()?;
+		let width = reader.read_u32()?;
+		let height = reader.read_u32()?;
+		let format = ImgFormat::decode(reader)?;
+
+		// Don't preallocate from the received count, a corrupt count runs into Truncated instead
+		let gpu_count = reader.read_u32()?;
+		let mut gpu_device_uuids = Vec::new();
+		for _ in 0..gpu_count {
+			gpu_device_uuids.push(reader.read_u128()?);
+		}
+
+		Ok(ImageListEntry {
+			image_name,
+			handle_id,
+			width,
+			height,
+			format,
+			gpu_device_uuids,
+		})
+	}
 }
 
-impl Default for ResultMsg {
-	fn default() -> Self {
-		Self {
-			tag: CommandTag::FindImage,
-			data: ResultData {
-				find_img: ManuallyDrop::new(ResultFindImage {
-					image_found: false,
-					img_data: ImgData::default(),
-				}),
+impl WireMessage for ResultListImages {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(self.images.len() as u32);
+		for image in self.images.iter() {
+			image.encode(writer);
+		}
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let image_count = reader.read_u32()?;
+		let mut images = Vec::new();
+		for _ in 0..image_count {
+			images.push(ImageListEntry::decode(reader)?);
+		}
+
+		Ok(ResultListImages { images })
+	}
+}
+
+impl WireMessage for CommFindImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.image_name);
+		writer.write_u128(self.gpu_device_uuid);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommFindImage {
+			image_name: reader.read_image_name()?,
+			gpu_device_uuid: reader.read_u128()?,
+		})
+	}
+}
+
+impl WireMessage for ResultFindImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.image_found);
+		self.img_data.encode(writer);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultFindImage {
+			image_found: reader.read_bool()?,
+			img_data: ImgData::decode(reader)?,
+		})
+	}
+}
+
+impl WireMessage for CommCopyImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.image_name);
+		writer.write_u128(self.gpu_device_uuid);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommCopyImage {
+			image_name: reader.read_image_name()?,
+			gpu_device_uuid: reader.read_u128()?,
+		})
+	}
+}
+
+// The Hello messages must stay decodable by every protocol version
+impl WireMessage for CommHello {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(self.protocol_version);
+		self.client_kind.encode(writer);
+		writer.write_u32(self.pid);
+		writer.write_u64(self.features);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommHello {
+			protocol_version: reader.read_u32()?,
+			client_kind: ClientKind::decode(reader)?,
+			pid: reader.read_u32()?,
+			features: reader.read_u64()?,
+		})
+	}
+}
+
+impl WireMessage for ResultHello {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.accepted);
+		writer.write_u32(self.protocol_version);
+		writer.write_u64(self.features);
+		writer.write_u32(self.connection_id);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultHello {
+			accepted: reader.read_bool()?,
+			protocol_version: reader.read_u32()?,
+			features: reader.read_u64()?,
+			connection_id: match reader.is_empty() {
+				true => 0,
+				false => reader.read_u32()?,
 			},
+		})
+	}
+}
+
+impl WireMessage for ResultError {
+	fn encode(&self, writer: &mut WireWriter) {
+		self.code.encode(writer);
+		writer.write_str(&self.message);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultError {
+			code: ErrorCode::decode(reader)?,
+			message: reader.read_string()?,
+		})
+	}
+}
+
+impl WireMessage for ResultCopyImage {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.success);
+		writer.write_u32(self.destinations_written);
+		writer.write_bool(self.error.is_some());
+		if let Some(error) = &self.error {
+			error.encode(writer);
+		}
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let success = reader.read_bool()?;
+		let destinations_written = reader.read_u32()?;
+		let error = match reader.read_bool()? {
+			true => Some(ResultError::decode(reader)?),
+			false => None,
+		};
+
+		Ok(ResultCopyImage {
+			success,
+			destinations_written,
+			error,
+		})
+	}
+}
+
+impl WireMessage for CommSubscribe {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(self.image_name.as_deref().unwrap_or(""));
+		writer.write_bool(self.subscribe);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let image_name = match reader.read_string()? {
+			image_name if image_name.is_empty() => None,
+			image_name => {
+				ImgData::validate_name(&image_name).map_err(DecodeError::InvalidName)?;
+				Some(image_name)
+			}
+		};
+
+		Ok(CommSubscribe {
+			image_name,
+			subscribe: reader.read_bool()?,
+		})
+	}
+}
+
+impl WireMessage for ResultSubscribe {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.subscribed);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ResultSubscribe {
+			subscribed: reader.read_bool()?,
+		})
+	}
+}
+
+impl WireMessage for RamTransferDirection {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(*self as u32);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let direction = match reader.read_u32()? {
+			0 => RamTransferDirection::Upload,
+			1 => RamTransferDirection::Download,
+			d => {
+				return Err(DecodeError::InvalidValue {
+					field: "direction",
+					value: d as u64,
+				})
+			}
+		};
+		Ok(direction)
+	}
+}
+
+impl WireMessage for CommRamTransfer {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_str(&self.image_name);
+		self.direction.encode(writer);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(CommRamTransfer {
+			image_name: reader.read_image_name()?,
+			direction: RamTransferDirection::decode(reader)?,
+		})
+	}
+}
+
+impl WireMessage for ResultRamTransfer {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_bool(self.success);
+		writer.write_bool(self.error.is_some());
+		if let Some(error) = &self.error {
+			error.encode(writer);
+		}
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let success = reader.read_bool()?;
+		let error = match reader.read_bool()? {
+			true => Some(ResultError::decode(reader)?),
+			false => None,
+		};
+
+		Ok(ResultRamTransfer { success, error })
+	}
+}
+
+impl WireMessage for ImageEventKind {
+	fn encode(&self, writer: &mut WireWriter) {
+		writer.write_u32(*self as u32);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		let kind = match reader.read_u32()? {
+			0 => ImageEventKind::Created,
+			1 => ImageEventKind::Resized,
+			2 => ImageEventKind::Deleted,
+			3 => ImageEventKind::FrameAvailable,
+			k => {
+				return Err(DecodeError::InvalidValue {
+					field: "event_kind",
+					value: k as u64,
+				})
+			}
+		};
+		Ok(kind)
+	}
+}
+
+impl WireMessage for ImageEvent {
+	fn encode(&self, writer: &mut WireWriter) {
+		self.kind.encode(writer);
+		writer.write_str(&self.image_name);
+		writer.write_u32(self.handle_id);
+		writer.write_u32(self.width);
+		writer.write_u32(self.height);
+		self.format.encode(writer);
+	}
+
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError> {
+		Ok(ImageEvent {
+			kind: ImageEventKind::decode(reader)?,
+			image_name: reader.read_image_name()?,
+			handle_id: reader.read_u32()?,
+			width: reader.read_u32()?,
+			height: reader.read_u32()?,
+			format: ImgFormat::decode(reader)?,
+		})
+	}
+}
+
+impl ImageEvent {
+	pub fn new(kind: ImageEventKind, image_name: &str, data: &ShmemDataInternal) -> ImageEvent {
+		ImageEvent {
+			kind,
+			image_name: image_name.to_string(),
+			handle_id: data.handle_id,
+			width: data.width,
+			height: data.height,
+			format: data.format,
+		}
+	}
+}
+
+impl CommHello {
+	pub fn new(client_kind: ClientKind, features: u64) -> CommHello {
+		CommHello {
+			protocol_version: PROTOCOL_VERSION,
+			client_kind,
+			pid: std::process::id(),
+			features,
 		}
 	}
 }
@@ -104,13 +961,14 @@ impl Default for ResultMsg {
 impl Default for CommInitImage {
 	fn default() -> Self {
 		CommInitImage {
-			image_name: [0 as u8; size_of::<ImgName>()],
-			shmem_name: [0 as u8; size_of::<ShmemName>()],
+			image_name: String::new(),
+			shmem_name: String::new(),
 			format: ImgFormat::default(),
 			width: 0,
 			height: 0,
 			overwrite_existing: false,
 			gpu_device_uuid: uuid::Uuid::nil().as_u128(),
+			buffer_count: 1,
 		}
 	}
 }
@@ -118,17 +976,244 @@ impl Default for CommInitImage {
 impl Default for CommFindImage {
 	fn default() -> Self {
 		Self {
-			image_name: [0 as u8; size_of::<ImgName>()],
+			image_name: String::new(),
 			gpu_device_uuid: uuid::Uuid::nil().as_u128(),
 		}
 	}
 }
 
+impl Default for ResultFindImage {
+	fn default() -> Self {
+		Self {
+			image_found: false,
+			img_data: ImgData::default(),
+		}
+	}
+}
+
 impl Default for CommCopyImage {
 	fn default() -> Self {
 		Self {
-			image_name: [0 as u8; size_of::<ImgName>()],
+			image_name: String::new(),
 			gpu_device_uuid: uuid::Uuid::nil().as_u128(),
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn list_images_roundtrip() {
+		let msg = ResultMsg::ListImages(ResultListImages {
+			images: vec![
+				ImageListEntry {
+					image_name: "img_0".to_string(),
+					handle_id: 3,
+					width: 640,
+					height: 480,
+					format: ImgFormat::B8G8R8A8,
+					gpu_device_uuids: vec![1, u128::MAX],
+				},
+				ImageListEntry {
+					image_name: "img_1".to_string(),
+					handle_id: 0,
+					width: 1,
+					height: 1,
+					format: ImgFormat::R8G8B8A8,
+					gpu_device_uuids: Vec::new(),
+				},
+			],
+		});
+
+		let payload = msg.encode_payload();
+		let images = match ResultMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			ResultMsg::ListImages(res) => res.images,
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+
+		assert_eq!(images.len(), 2);
+		assert_eq!(images[0].image_name, "img_0");
+		assert_eq!(images[0].handle_id, 3);
+		assert_eq!(images[0].width, 640);
+		assert_eq!(images[0].format, ImgFormat::B8G8R8A8);
+		assert_eq!(images[0].gpu_device_uuids, vec![1, u128::MAX]);
+		assert!(images[1].gpu_device_uuids.is_empty());
+
+		// A count that doesn't match the payload is rejected
+		let err = ResultMsg::decode(msg.tag() as u32, &payload[0..payload.len() - 1])
+			.err()
+			.expect("Decoding truncated payload should fail");
+		assert!(matches!(err, DecodeError::Truncated { .. }));
+	}
+
+	#[test]
+	fn img_format_roundtrip() {
+		let formats = [
+			ImgFormat::R8G8B8A8,
+			ImgFormat::R8G8B8,
+			ImgFormat::B8G8R8A8,
+			ImgFormat::B8G8R8,
+			ImgFormat::Undefined,
+			ImgFormat::R8,
+			ImgFormat::R16,
+			ImgFormat::R32Sfloat,
+			ImgFormat::R16G16B16A16,
+			ImgFormat::R16G16B16A16Sfloat,
+			ImgFormat::R32G32B32A32Sfloat,
+			ImgFormat::A2B10G10R10,
+			ImgFormat::R8G8B8A8Srgb,
+			ImgFormat::B8G8R8A8Srgb,
+		];
+
+		for format in formats {
+			let msg = CommandMsg::InitImage(CommInitImage {
+				image_name: "img_0".to_string(),
+				format,
+				..Default::default()
+			});
+
+			let payload = msg.encode_payload();
+			match CommandMsg::decode(msg.tag() as u32, &payload).unwrap() {
+				CommandMsg::InitImage(cmd) => assert_eq!(cmd.format, format),
+				_ => panic!("Decoded wrong command"),
+			};
+		}
+	}
+
+	#[test]
+	fn init_image_roundtrip() {
+		let msg = CommandMsg::InitImage(CommInitImage {
+			image_name: "img_0".to_string(),
+			width: 640,
+			height: 480,
+			buffer_count: 3,
+			..Default::default()
+		});
+
+		let payload = msg.encode_payload();
+		let cmd = match CommandMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			CommandMsg::InitImage(cmd) => cmd,
+			_ => panic!("Decoded wrong command"),
+		};
+
+		assert_eq!(cmd.image_name, "img_0");
+		assert_eq!(cmd.width, 640);
+		assert_eq!(cmd.buffer_count, 3);
+
+		// Clients that don't know rings omit the buffer count, which stands for a single image
+		match CommandMsg::decode(msg.tag() as u32, &payload[..payload.len() - 4]).unwrap() {
+			CommandMsg::InitImage(cmd) => assert_eq!(cmd.buffer_count, 1),
+			_ => panic!("Decoded wrong command"),
+		};
+
+		let mut img_data = ImgData::default();
+		img_data.data.buffer_count = 3;
+		img_data.data.timeline_values[0] = 5;
+		let msg = ResultMsg::InitImage(ResultInitImage {
+			image_created: true,
+			img_data,
+		});
+
+		let payload = msg.encode_payload();
+		let img_data = match ResultMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			ResultMsg::InitImage(res) => res.img_data,
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+
+		// Slot state only lives in shared memory
+		assert_eq!(img_data.data.buffer_count, 3);
+		assert_eq!(img_data.data.timeline_values[0], 0);
+
+		// Servers that don't know rings omit it as well
+		match ResultMsg::decode(msg.tag() as u32, &payload[..payload.len() - 4]).unwrap() {
+			ResultMsg::InitImage(res) => assert_eq!(res.img_data.data.buffer_count, 1),
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+	}
+
+	#[test]
+	fn ram_transfer_roundtrip() {
+		let msg = CommandMsg::RamTransfer(CommRamTransfer {
+			image_name: "img_0".to_string(),
+			direction: RamTransferDirection::Download,
+		});
+
+		let payload = msg.encode_payload();
+		let cmd = match CommandMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			CommandMsg::RamTransfer(cmd) => cmd,
+			_ => panic!("Decoded wrong command"),
+		};
+
+		assert_eq!(cmd.image_name, "img_0");
+		assert_eq!(cmd.direction, RamTransferDirection::Download);
+
+		let msg = ResultMsg::RamTransfer(ResultRamTransfer {
+			success: false,
+			error: Some(ResultError::new(ErrorCode::ImageNotFound, "missing")),
+		});
+
+		let payload = msg.encode_payload();
+		let res = match ResultMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			ResultMsg::RamTransfer(res) => res,
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+
+		assert!(!res.success);
+		assert_eq!(res.error.unwrap().code, ErrorCode::ImageNotFound);
+	}
+
+	#[test]
+	fn image_event_roundtrip() {
+		let msg = ResultMsg::Event(ImageEvent {
+			kind: ImageEventKind::Resized,
+			image_name: "img_0".to_string(),
+			handle_id: 2,
+			width: 640,
+			height: 480,
+			format: ImgFormat::B8G8R8A8,
+		});
+
+		let payload = msg.encode_payload();
+		let event = match ResultMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			ResultMsg::Event(event) => event,
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+
+		assert_eq!(event.kind, ImageEventKind::Resized);
+		assert_eq!(event.image_name, "img_0");
+		assert_eq!(event.handle_id, 2);
+		assert_eq!(event.width, 640);
+		assert_eq!(event.height, 480);
+		assert_eq!(event.format, ImgFormat::B8G8R8A8);
+
+		// Events are only sent by the server
+		let err = CommandMsg::decode(msg.tag() as u32, &payload)
+			.err()
+			.expect("Decoding event as command should fail");
+		assert!(matches!(err, DecodeError::UnknownTag(_)));
+	}
+
+	#[test]
+	fn hello_connection_id() {
+		let msg = ResultMsg::Hello(ResultHello {
+			accepted: true,
+			protocol_version: PROTOCOL_VERSION,
+			features: features::SUPPORTED,
+			connection_id: 9,
+		});
+
+		let payload = msg.encode_payload();
+		match ResultMsg::decode(msg.tag() as u32, &payload).unwrap() {
+			ResultMsg::Hello(hello) => assert_eq!(hello.connection_id, 9),
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+
+		// Servers that don't assign ids omit the field
+		match ResultMsg::decode(msg.tag() as u32, &payload[..payload.len() - 4]).unwrap() {
+			ResultMsg::Hello(hello) => assert_eq!(hello.connection_id, 0),
+			res => panic!("Decoded wrong result {:?}", res.tag()),
+		};
+	}
+}
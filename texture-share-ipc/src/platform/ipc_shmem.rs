@@ -1,18 +1,42 @@
 use memoffset::offset_of;
-use raw_sync::locks::LockImpl;
 pub use raw_sync::Timeout;
 use shared_memory::Shmem;
 use shared_memory::ShmemConf;
 use shared_memory::ShmemError;
 use std::cell::UnsafeCell;
 
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::mem::size_of;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::AtomicU32;
+use std::time::Duration;
 
 use crate::platform::img_data::ImgData;
 use crate::platform::img_data::ImgFormat;
 use crate::platform::img_data::ImgName;
-use crate::platform::RwLockInternalData;
+use crate::platform::img_data::NameError;
+use crate::platform::img_data::ShmemName;
+use crate::platform::ipc_shmem_memfd::MemfdMapping;
+use crate::platform::ipc_shmem_lock::ShmemLock;
+use crate::platform::LockInternalData;
+
+// Upper limit of images in the ring allocated per image name
+pub const MAX_BUFFER_COUNT: usize = 8;
+
+// Upper limit of accesses that reserved a timeline value but weren't submitted yet
+pub const MAX_TIMELINE_RESERVATIONS: usize = 16;
+
+// Timeline value reserved by a client, see ShmemDataInternal::reserve_timeline_value
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimelineReservation {
+	// Id the server assigned to the client's connection. 0 if the entry is unused
+	pub owner: u32,
+	pub slot: u32,
+	pub value: u64,
+	// Whether the slot was acquired for writing
+	pub write: bool,
+}
 
 #[repr(C)]
 #[derive(Clone)]
@@ -25,17 +49,62 @@ pub struct ShmemDataInternal {
 	pub allocation_size: u64,
 	pub gpu_device_uuid_0: u64,
 	pub gpu_device_uuid_1: u64,
+	// Number of images in the ring. Producers write to a free slot and consumers read the latest
+	pub buffer_count: u32,
+	// Slot that holds the most recently completed frame
+	pub latest_slot: u32,
+	// Bitmask of slots that producers are writing to
+	pub writing_slots: u32,
+	// Number of consumers reading each slot
+	pub reader_counts: [u32; MAX_BUFFER_COUNT],
+	// Last value of each slot's timeline semaphore that accesses were submitted up to
+	pub timeline_values: [u64; MAX_BUFFER_COUNT],
+	// Number of frames published so far. Never reset, so that consumers can detect new frames
+	pub frame_number: u64,
+	// CLOCK_MONOTONIC time in nanoseconds at which the producer sent the latest frame
+	pub frame_timestamp_ns: u64,
+	// Reserved values that weren't submitted yet. The server completes them if their owner's
+	// connection closes, as later accesses would wait for them forever
+	pub timeline_reservations: [TimelineReservation; MAX_TIMELINE_RESERVATIONS],
+}
+
+// Sequence number and producer timestamp of a published frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameInfo {
+	pub number: u64,
+	pub timestamp_ns: u64,
 }
 
 #[repr(C)]
 pub(super) struct ShmemData {
-	pub(super) rwlock_data: RwLockInternalData,
+	pub(super) lock_data: LockInternalData,
+	// Odd while a writer holds the lock, see IpcShmem::snapshot
+	pub(super) seq: AtomicU32,
+	// Futex word that is incremented whenever a frame was published, see IpcShmem::wait_for_frame
+	pub(super) frame_futex: AtomicU32,
 	pub(super) data: UnsafeCell<ShmemDataInternal>,
 }
 
+// The lock points into the mapping, so it must be dropped first
 pub struct IpcShmem {
-	pub(super) lock: Box<dyn LockImpl>,
-	shmem: Shmem,
+	pub(super) lock: ShmemLock,
+	backing: ShmemBacking,
+}
+
+pub(super) enum ShmemBacking {
+	// Globally named segment that clients open by ImgData::shmem_name
+	Named(Shmem),
+	// Sealed memfd that is only passed to clients over their connection
+	Memfd(MemfdMapping),
+}
+
+impl ShmemBacking {
+	pub(super) fn as_ptr(&self) -> *mut u8 {
+		match self {
+			ShmemBacking::Named(shmem) => shmem.as_ptr(),
+			ShmemBacking::Memfd(mapping) => mapping.as_ptr(),
+		}
+	}
 }
 
 impl Default for ShmemDataInternal {
@@ -50,11 +119,28 @@ impl Default for ShmemDataInternal {
 			allocation_size: 0,
 			gpu_device_uuid_0: nil.0,
 			gpu_device_uuid_1: nil.1,
+			buffer_count: 1,
+			latest_slot: 0,
+			writing_slots: 0,
+			reader_counts: [0; MAX_BUFFER_COUNT],
+			timeline_values: [0; MAX_BUFFER_COUNT],
+			frame_number: 0,
+			frame_timestamp_ns: 0,
+			timeline_reservations: [TimelineReservation::default(); MAX_TIMELINE_RESERVATIONS],
 		}
 	}
 }
 
+// Current CLOCK_MONOTONIC time in nanoseconds, which is the same for all processes on the machine
+pub fn monotonic_time_ns() -> u64 {
+	let mut now: libc::timespec = unsafe { std::mem::zeroed() };
+	unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+	now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+}
+
 impl<'a> IpcShmem {
+	// Opens or creates a named segment. Prefer new_memfd, which doesn't leave segments behind if
+	// the server crashes and can't be opened by unrelated processes
 	pub fn new(
 		name: &str,
 		img_name: &str,
@@ -81,33 +167,72 @@ impl<'a> IpcShmem {
 		//     false => conf.open().map_err(|e| Box::new(e))?,
 		// };
 
-		let lock = IpcShmem::init_rw_lock(&shmem, !create)?;
+		IpcShmem::from_backing(ShmemBacking::Named(shmem), img_name, create)
+	}
+
+	// Creates the segment as a memfd. Clients receive it through export_handle
+	pub fn new_memfd(img_name: &str) -> Result<IpcShmem, Box<dyn std::error::Error>> {
+		let mapping = MemfdMapping::new(size_of::<ShmemData>())?;
+		IpcShmem::from_backing(ShmemBacking::Memfd(mapping), img_name, true)
+	}
+
+	// Maps a segment received from the server. Works for both memfds and named segments
+	pub fn import_handle(fd: OwnedFd) -> Result<IpcShmem, Box<dyn std::error::Error>> {
+		let mapping = MemfdMapping::import(fd, size_of::<ShmemData>())?;
+		IpcShmem::from_backing(ShmemBacking::Memfd(mapping), "", false)
+	}
+
+	fn from_backing(
+		backing: ShmemBacking,
+		img_name: &str,
+		create: bool,
+	) -> Result<IpcShmem, Box<dyn std::error::Error>> {
+		let lock = IpcShmem::init_lock(backing.as_ptr(), !create)?;
 
 		if create {
-			let _rw_lock = lock.lock()?;
+			let _lock = lock.lock(Timeout::Infinite, true)?;
 			unsafe {
-				let raw_data_ptr = shmem.as_ptr().add(offset_of!(ShmemData, data));
+				let raw_data_ptr = backing.as_ptr().add(offset_of!(ShmemData, data));
 				*(raw_data_ptr.cast::<UnsafeCell<ShmemDataInternal>>()) = UnsafeCell::new(
 					ShmemDataInternal::new_empty(img_name).map_err(|e| Box::new(e))?,
 				);
 			}
 		}
 
-		Ok(IpcShmem { lock, shmem })
+		Ok(IpcShmem { lock, backing })
 	}
 
 	// Get Id without acquiring lock. Should be sufficient for checking if surface image has changed
-	pub fn get_id_unchecked(&self) -> u32 {
-		unsafe {
-			(self.shmem.as_ptr().add(offset_of!(ShmemData, data)) as *const ShmemDataInternal)
-				.as_ref()
-				.unwrap()
-		}
-		.handle_id
+	pub fn get_id_unchecked(&self, timeout: Duration) -> Result<u32, Box<dyn std::error::Error>> {
+		Ok(self.snapshot(timeout)?.handle_id)
 	}
 
+	// Name that clients open the segment with. Empty for memfds
 	pub fn get_name(&self) -> &str {
-		self.shmem.get_os_id()
+		match &self.backing {
+			ShmemBacking::Named(shmem) => shmem.get_os_id(),
+			ShmemBacking::Memfd(_) => "",
+		}
+	}
+
+	// Name sent to clients in ImgData::shmem_name
+	pub fn get_name_array(&self) -> Result<ShmemName, NameError> {
+		match &self.backing {
+			ShmemBacking::Named(shmem) => ImgData::convert_shmem_str_to_array(shmem.get_os_id()),
+			ShmemBacking::Memfd(_) => Ok([0 as u8; size_of::<ShmemName>()]),
+		}
+	}
+
+	pub fn is_named(&self) -> bool {
+		matches!(self.backing, ShmemBacking::Named(_))
+	}
+
+	// Returns a new fd of the segment to send to a client
+	pub fn export_handle(&self) -> Result<OwnedFd, Error> {
+		match &self.backing {
+			ShmemBacking::Named(shmem) => MemfdMapping::open_named(shmem.get_os_id()),
+			ShmemBacking::Memfd(mapping) => mapping.export_handle(),
+		}
 	}
 
 	fn delete_shmem(shmem_name: &str) {
@@ -144,66 +269,204 @@ impl ShmemDataInternal {
 			allocation_size,
 			gpu_device_uuid_0: gpu_uuid_pair.0,
 			gpu_device_uuid_1: gpu_uuid_pair.1,
+			..Default::default()
 		}
 	}
 
 	fn new_empty(img_name: &str) -> Result<ShmemDataInternal, Error> {
-		if img_name.as_bytes().len() > size_of::<ImgName>() {
-			Err(Error::new(
-				ErrorKind::OutOfMemory,
-				format!(
-					"Image name '{img_name:?}' too long. Should be less than {:?}bytes",
-					size_of::<ImgName>()
-				),
-			))
-		} else {
-			let shmem_internal = ShmemDataInternal {
-				name: ImgData::convert_shmem_str_to_array(img_name),
-				handle_id: 0,
-				width: 0,
-				height: 0,
-				format: ImgFormat::Undefined,
-				allocation_size: 0,
-				..Default::default()
+		let shmem_internal = ShmemDataInternal {
+			name: ImgData::convert_shmem_str_to_array(img_name)?,
+			handle_id: 0,
+			width: 0,
+			height: 0,
+			format: ImgFormat::Undefined,
+			allocation_size: 0,
+			..Default::default()
+		};
+		Ok(shmem_internal)
+	}
+
+	// Clears the state of all slots, e.g. after the images were recreated
+	pub fn reset_slots(&mut self, buffer_count: u32) {
+		self.buffer_count = buffer_count;
+		self.latest_slot = 0;
+		self.writing_slots = 0;
+		self.reader_counts = [0; MAX_BUFFER_COUNT];
+		self.timeline_values = [0; MAX_BUFFER_COUNT];
+		self.timeline_reservations = [TimelineReservation::default(); MAX_TIMELINE_RESERVATIONS];
+	}
+
+	// Returns the latest slot, or None if a client stored a slot outside of the ring
+	pub fn latest_slot_checked(&self) -> Option<usize> {
+		let count = self.buffer_count.min(MAX_BUFFER_COUNT as u32);
+		match self.latest_slot < count {
+			true => Some(self.latest_slot as usize),
+			false => None,
+		}
+	}
+
+	// Picks the slot after the latest one that nobody accesses. If all are busy, the oldest slot
+	// is reused and its accesses are ordered by the slot's timeline
+	pub fn acquire_write_slot(&mut self) -> usize {
+		let count = self.buffer_count.clamp(1, MAX_BUFFER_COUNT as u32) as usize;
+		let latest = self.latest_slot as usize % count;
+
+		let candidates = (1..count).map(|i| (latest + i) % count);
+		let slot = candidates
+			.clone()
+			.find(|&i| self.writing_slots & (1 << i) == 0 && self.reader_counts[i] == 0)
+			.or_else(|| candidates.clone().next())
+			.unwrap_or(latest);
+
+		self.writing_slots |= 1 << slot;
+		slot
+	}
+
+	// Makes a written slot the one consumers read from. Without publishing, the previous frame stays
+	// the latest one
+	pub fn release_write_slot(&mut self, slot: usize, publish: bool) {
+		self.writing_slots &= !(1 << slot);
+		if publish {
+			self.latest_slot = slot as u32;
+		}
+	}
+
+	// Counts a new frame. The timestamp is passed on unchanged when the server copies frames
+	// between images
+	pub fn publish_frame(&mut self, timestamp_ns: u64) {
+		self.frame_number += 1;
+		self.frame_timestamp_ns = timestamp_ns;
+	}
+
+	pub fn frame(&self) -> FrameInfo {
+		FrameInfo {
+			number: self.frame_number,
+			timestamp_ns: self.frame_timestamp_ns,
+		}
+	}
+
+	pub fn acquire_read_slot(&mut self) -> usize {
+		let slot = self.latest_slot as usize
+			% self.buffer_count.clamp(1, MAX_BUFFER_COUNT as u32) as usize;
+		self.reader_counts[slot] += 1;
+		slot
+	}
+
+	pub fn release_read_slot(&mut self, slot: usize) {
+		self.reader_counts[slot] = self.reader_counts[slot].saturating_sub(1);
+	}
+
+	// Reserves the next value of the slot's timeline, so that the access can be submitted without
+	// holding the lock. Returns the value to wait for. The reservation is recorded for the
+	// connection with the id owner until it is completed or cancelled. Without an owner, or if too
+	// many accesses are pending, it can't be recovered if the client dies
+	pub fn reserve_timeline_value(&mut self, slot: usize, write: bool, owner: u32) -> u64 {
+		let value = self.timeline_values[slot];
+		self.timeline_values[slot] = value + 1;
+
+		let free_entry = self.timeline_reservations.iter_mut().find(|r| r.owner == 0);
+		if let (true, Some(entry)) = (owner != 0, free_entry) {
+			*entry = TimelineReservation {
+				owner,
+				slot: slot as u32,
+				value,
+				write,
 			};
-			Ok(shmem_internal)
 		}
+		value
+	}
+
+	// Forgets a reservation once its access was submitted, which signals value + 1
+	pub fn complete_timeline_value(&mut self, slot: usize, value: u64) {
+		let entry = self
+			.timeline_reservations
+			.iter_mut()
+			.find(|r| r.owner != 0 && r.slot == slot as u32 && r.value == value);
+		if let Some(entry) = entry {
+			*entry = TimelineReservation::default();
+		}
+	}
+
+	// Returns a reserved value if the access was never submitted and no access was reserved after
+	// it. Otherwise value + 1 still has to be signalled and the reservation is kept
+	pub fn cancel_timeline_value(&mut self, slot: usize, value: u64) -> bool {
+		if self.timeline_values[slot] != value + 1 {
+			return false;
+		}
+
+		self.timeline_values[slot] = value;
+		self.complete_timeline_value(slot, value);
+		true
+	}
+
+	// Removes the reservations of a connection that closed and releases their slots. Returns the
+	// slots and values, ordered by value, which have to be signalled on the client's behalf
+	pub fn take_timeline_reservations(&mut self, owner: u32) -> Vec<(usize, u64)> {
+		let mut taken = Vec::new();
+		for i in 0..MAX_TIMELINE_RESERVATIONS {
+			let reservation = self.timeline_reservations[i];
+			if owner == 0 || reservation.owner != owner {
+				continue;
+			}
+
+			let slot = reservation.slot as usize;
+			self.timeline_reservations[i] = TimelineReservation::default();
+			if slot >= MAX_BUFFER_COUNT {
+				continue;
+			}
+			match reservation.write {
+				true => self.release_write_slot(slot, false),
+				false => self.release_read_slot(slot),
+			}
+			taken.push((slot, reservation.value));
+		}
+
+		taken.sort_by_key(|&(_, value)| value);
+		taken
 	}
 }
 
 #[cfg(test)]
 mod tests {
+	use std::os::fd::AsRawFd;
 	use std::time::Duration;
 
 	use raw_sync::Timeout;
 
 	use crate::platform::img_data::ImgData;
 
-	use super::IpcShmem;
+	use super::{monotonic_time_ns, FrameInfo, IpcShmem, MAX_BUFFER_COUNT};
 
-	const SHMEM_NAME: &str = "shmem_name";
 	const TIMEOUT: Timeout = Timeout::Val(Duration::from_secs(10));
+	const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
 
 	fn img_name() -> String {
 		"img_name".to_string()
 	}
 
+	// Tests run in parallel, each one needs its own segment
+	fn unique_shmem_name(test: &str) -> String {
+		format!("{}_{}", test, std::process::id())
+	}
+
 	#[test]
 	fn shmem_create() {
-		IpcShmem::new(SHMEM_NAME, &img_name(), true).expect("Failed to create shmem");
+		let shmem_name = unique_shmem_name("shmem_create");
+		IpcShmem::new(&shmem_name, &img_name(), true).expect("Failed to create shmem");
 	}
 
-	fn _shmem_share() -> (IpcShmem, IpcShmem) {
-		let created_shmem = IpcShmem::new(SHMEM_NAME, &img_name(), true).unwrap();
+	fn _shmem_share(shmem_name: &str) -> (IpcShmem, IpcShmem) {
+		let created_shmem = IpcShmem::new(shmem_name, &img_name(), true).unwrap();
 		let shared_shmem =
-			IpcShmem::new(SHMEM_NAME, &img_name(), false).expect("Failed to share shmem");
+			IpcShmem::new(shmem_name, &img_name(), false).expect("Failed to share shmem");
 
 		(created_shmem, shared_shmem)
 	}
 
 	#[test]
 	fn shmem_name() {
-		let shmem = IpcShmem::new(SHMEM_NAME, &img_name(), true).expect("Failed to create shmem");
+		let shmem_name = unique_shmem_name("shmem_name");
+		let shmem = IpcShmem::new(&shmem_name, &img_name(), true).expect("Failed to create shmem");
 
 		let rlock = shmem.acquire_rlock(TIMEOUT).unwrap();
 		let rdata = IpcShmem::acquire_rdata(&rlock);
@@ -216,14 +479,337 @@ mod tests {
 		let _ = shmem_create();
 	}
 
+	#[test]
+	fn shmem_memfd_share() {
+		let created_shmem = IpcShmem::new_memfd(&img_name()).expect("Failed to create memfd");
+		assert_eq!(created_shmem.get_name(), "");
+
+		let shared_shmem = IpcShmem::import_handle(created_shmem.export_handle().unwrap())
+			.expect("Failed to import memfd");
+		{
+			let lock = shared_shmem.acquire_lock(TIMEOUT).unwrap();
+			IpcShmem::acquire_data(&lock).width = 7;
+		}
+
+		let rlock = created_shmem.acquire_rlock(TIMEOUT).unwrap();
+		let rdata = IpcShmem::acquire_rdata(&rlock);
+		assert_eq!(ImgData::convert_shmem_array_to_str(&rdata.name), img_name());
+		assert_eq!(rdata.width, 7);
+
+		// The size is sealed
+		let fd = created_shmem.export_handle().unwrap();
+		assert!(unsafe { libc::ftruncate(fd.as_raw_fd(), 0) } < 0);
+	}
+
+	#[test]
+	fn shmem_named_export() {
+		let shmem_name = unique_shmem_name("shmem_named_export");
+		let created_shmem = IpcShmem::new(&shmem_name, &img_name(), true).unwrap();
+		let shared_shmem = IpcShmem::import_handle(created_shmem.export_handle().unwrap())
+			.expect("Failed to import named shmem");
+
+		let rlock = shared_shmem.acquire_rlock(TIMEOUT).unwrap();
+		let rdata = IpcShmem::acquire_rdata(&rlock);
+		assert_eq!(ImgData::convert_shmem_array_to_str(&rdata.name), img_name());
+	}
+
+	#[test]
+	fn shmem_slots() {
+		let shmem_name = unique_shmem_name("shmem_slots");
+		let shmem = IpcShmem::new(&shmem_name, &img_name(), true).unwrap();
+		let lock = shmem.acquire_lock(TIMEOUT).unwrap();
+		let data = IpcShmem::acquire_data(&lock);
+
+		// Single buffered images always use the same slot
+		assert_eq!(data.buffer_count, 1);
+		assert_eq!(data.acquire_write_slot(), 0);
+		data.release_write_slot(0, true);
+		assert_eq!(data.acquire_read_slot(), 0);
+		data.release_read_slot(0);
+
+		data.reset_slots(3);
+
+		// The producer writes the slot after the latest one, while consumers keep reading the latest
+		let read_slot = data.acquire_read_slot();
+		assert_eq!(read_slot, 0);
+		let write_slot = data.acquire_write_slot();
+		assert_eq!(write_slot, 1);
+		assert_eq!(data.acquire_read_slot(), 0);
+		data.release_read_slot(0);
+
+		// Slots in use are skipped by other producers
+		assert_eq!(data.acquire_write_slot(), 2);
+		data.release_write_slot(2, false);
+
+		data.release_write_slot(write_slot, true);
+		assert_eq!(data.latest_slot, 1);
+
+		// Slot 0 is still being read
+		assert_eq!(data.acquire_write_slot(), 2);
+		data.release_write_slot(2, true);
+		data.release_read_slot(read_slot);
+		assert_eq!(data.acquire_write_slot(), 0);
+
+		// The shared memory is writable by clients, slots outside of the ring are rejected
+		assert_eq!(data.latest_slot_checked(), Some(2));
+		data.latest_slot = 3;
+		assert_eq!(data.latest_slot_checked(), None);
+		data.buffer_count = MAX_BUFFER_COUNT as u32 + 1;
+		data.latest_slot = MAX_BUFFER_COUNT as u32;
+		assert_eq!(data.latest_slot_checked(), None);
+	}
+
+	#[test]
+	fn shmem_timeline_values() {
+		let shmem_name = unique_shmem_name("shmem_timeline_values");
+		let shmem = IpcShmem::new(&shmem_name, &img_name(), true).unwrap();
+		let lock = shmem.acquire_lock(TIMEOUT).unwrap();
+		let data = IpcShmem::acquire_data(&lock);
+		data.reset_slots(2);
+
+		assert_eq!(data.reserve_timeline_value(1, true, 0), 0);
+		assert_eq!(data.reserve_timeline_value(1, false, 0), 1);
+		assert_eq!(data.timeline_values[0], 0);
+
+		// Only the last reservation can be returned
+		assert!(!data.cancel_timeline_value(1, 0));
+		assert_eq!(data.timeline_values[1], 2);
+		assert!(data.cancel_timeline_value(1, 1));
+		assert_eq!(data.timeline_values[1], 1);
+	}
+
+	// Forks a process that reserves timeline values of slot 1 for the given owners, without
+	// completing them, and kills it
+	fn kill_timeline_reserver(shmem: &IpcShmem, owners: &[u32]) {
+		let mut pipe_fds = [0; 2];
+		assert_eq!(unsafe { libc::pipe(pipe_fds.as_mut_ptr()) }, 0);
+
+		let pid = unsafe { libc::fork() };
+		assert!(pid >= 0);
+		if pid == 0 {
+			if let Ok(lock) = shmem.acquire_lock(Timeout::Infinite) {
+				let data = IpcShmem::acquire_data(&lock);
+				for &owner in owners {
+					let slot = data.acquire_write_slot();
+					data.reserve_timeline_value(slot, true, owner);
+				}
+				drop(lock);
+				unsafe { libc::write(pipe_fds[1], [1 as u8].as_ptr().cast(), 1) };
+				loop {
+					unsafe { libc::pause() };
+				}
+			}
+			unsafe { libc::_exit(1) };
+		}
+
+		let mut buf = [0 as u8; 1];
+		assert_eq!(unsafe { libc::read(pipe_fds[0], buf.as_mut_ptr().cast(), 1) }, 1);
+		unsafe {
+			libc::kill(pid, libc::SIGKILL);
+			libc::waitpid(pid, std::ptr::null_mut(), 0);
+			libc::close(pipe_fds[0]);
+			libc::close(pipe_fds[1]);
+		}
+	}
+
+	#[test]
+	fn shmem_timeline_reservations() {
+		let shmem = IpcShmem::new_memfd(&img_name()).unwrap();
+		let shared_shmem = IpcShmem::import_handle(shmem.export_handle().unwrap()).unwrap();
+		{
+			let lock = shmem.acquire_lock(TIMEOUT).unwrap();
+			let data = IpcShmem::acquire_data(&lock);
+			data.reset_slots(2);
+
+			// Completed and cancelled reservations are forgotten
+			let value = data.reserve_timeline_value(0, false, 3);
+			data.complete_timeline_value(0, value);
+			let value = data.reserve_timeline_value(0, false, 3);
+			assert!(data.cancel_timeline_value(0, value));
+			assert!(data.take_timeline_reservations(3).is_empty());
+		}
+
+		// A killed client leaves its reservations behind, while another client reserves after it
+		kill_timeline_reserver(&shared_shmem, &[7, 8, 7]);
+
+		let lock = shmem.acquire_lock(TIMEOUT).unwrap();
+		let data = IpcShmem::acquire_data(&lock);
+		assert_eq!(data.timeline_values[1], 3);
+		assert_eq!(data.take_timeline_reservations(7), vec![(1, 0), (1, 2)]);
+		assert!(data.take_timeline_reservations(7).is_empty());
+		assert_eq!(data.take_timeline_reservations(8), vec![(1, 1)]);
+
+		// The slot is free again
+		assert_eq!(data.writing_slots, 0);
+	}
+
+	#[test]
+	fn shmem_frames() {
+		let shmem = IpcShmem::new_memfd(&img_name()).unwrap();
+		assert_eq!(
+			shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap().frame(),
+			FrameInfo::default()
+		);
+
+		let before = monotonic_time_ns();
+		{
+			let lock = shmem.acquire_lock(TIMEOUT).unwrap();
+			let data = IpcShmem::acquire_data(&lock);
+			data.publish_frame(monotonic_time_ns());
+			data.publish_frame(monotonic_time_ns());
+
+			// Recreating the images keeps counting
+			data.reset_slots(2);
+		}
+
+		let frame = shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap().frame();
+		assert_eq!(frame.number, 2);
+		assert!(frame.timestamp_ns >= before && frame.timestamp_ns <= monotonic_time_ns());
+	}
+
+	#[test]
+	fn shmem_wait_for_frame() {
+		let shmem = IpcShmem::new_memfd(&img_name()).unwrap();
+		let fd = shmem.export_handle().unwrap();
+
+		// Times out without a producer
+		let timeout = Duration::from_millis(10);
+		assert!(!shmem.wait_for_frame(0, timeout).unwrap());
+
+		let producer = std::thread::spawn(move || {
+			let shared_shmem = IpcShmem::import_handle(fd).unwrap();
+			std::thread::sleep(Duration::from_millis(50));
+			let lock = shared_shmem.acquire_lock(TIMEOUT).unwrap();
+			IpcShmem::acquire_data(&lock).publish_frame(monotonic_time_ns());
+		});
+		assert!(shmem.wait_for_frame(0, Duration::from_secs(10)).unwrap());
+		assert_eq!(shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap().frame_number, 1);
+		producer.join().unwrap();
+
+		// Frames that were already published return immediately
+		assert!(shmem.wait_for_frame(0, Duration::ZERO).unwrap());
+		assert!(!shmem.wait_for_frame(1, timeout).unwrap());
+	}
+
+	// Forks a process that takes the lock, writes the width and holds the lock until it is killed
+	fn kill_lock_holder(shmem: &IpcShmem, width: u32) {
+		let mut pipe_fds = [0; 2];
+		assert_eq!(unsafe { libc::pipe(pipe_fds.as_mut_ptr()) }, 0);
+
+		let pid = unsafe { libc::fork() };
+		assert!(pid >= 0);
+		if pid == 0 {
+			// The test harness runs other threads, so the child must not allocate
+			if let Ok(lock) = shmem.acquire_lock(Timeout::Infinite) {
+				IpcShmem::acquire_data(&lock).width = width;
+				unsafe { libc::write(pipe_fds[1], [1 as u8].as_ptr().cast(), 1) };
+				loop {
+					unsafe { libc::pause() };
+				}
+			}
+			unsafe { libc::_exit(1) };
+		}
+
+		let mut buf = [0 as u8; 1];
+		assert_eq!(unsafe { libc::read(pipe_fds[0], buf.as_mut_ptr().cast(), 1) }, 1);
+		unsafe {
+			libc::kill(pid, libc::SIGKILL);
+			libc::waitpid(pid, std::ptr::null_mut(), 0);
+			libc::close(pipe_fds[0]);
+			libc::close(pipe_fds[1]);
+		}
+	}
+
+	#[test]
+	fn shmem_lock_owner_died() {
+		let created_shmem = IpcShmem::new_memfd(&img_name()).unwrap();
+		let shared_shmem = IpcShmem::import_handle(created_shmem.export_handle().unwrap()).unwrap();
+
+		// The lock is taken over along with whatever the dead process wrote
+		kill_lock_holder(&shared_shmem, 12);
+		{
+			let rlock = created_shmem.acquire_rlock(TIMEOUT).unwrap();
+			assert_eq!(IpcShmem::acquire_rdata(&rlock).width, 12);
+		}
+
+		// The lock stays usable by all processes afterwards
+		kill_lock_holder(&created_shmem, 34);
+		{
+			let lock = shared_shmem.acquire_lock(TIMEOUT).unwrap();
+			IpcShmem::acquire_data(&lock).height = 56;
+		}
+		let rlock = created_shmem.acquire_rlock(TIMEOUT).unwrap();
+		let rdata = IpcShmem::acquire_rdata(&rlock);
+		assert_eq!((rdata.width, rdata.height), (34, 56));
+	}
+
+	#[test]
+	fn shmem_snapshot() {
+		let shmem = IpcShmem::new_memfd(&img_name()).unwrap();
+		let fd = shmem.export_handle().unwrap();
+
+		// Snapshots never observe a partial update
+		let writer = std::thread::spawn(move || {
+			let shared_shmem = IpcShmem::import_handle(fd).unwrap();
+			for i in 1..=10000 {
+				let lock = shared_shmem.acquire_lock(TIMEOUT).unwrap();
+				let data = IpcShmem::acquire_data(&lock);
+				data.width = i;
+				data.height = i;
+			}
+		});
+		while !writer.is_finished() {
+			let data = shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap();
+			assert_eq!(data.width, data.height);
+		}
+		writer.join().unwrap();
+		let shared_shmem = IpcShmem::import_handle(shmem.export_handle().unwrap()).unwrap();
+		assert_eq!(shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap().width, 10000);
+
+		// Read locks don't invalidate snapshots, so they can be taken meanwhile
+		{
+			let _rlock = shared_shmem.acquire_rlock(TIMEOUT).unwrap();
+			assert_eq!(shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap().height, 10000);
+		}
+
+		// A writer that died leaves the sequence number odd until the lock is taken over
+		kill_lock_holder(&shared_shmem, 78);
+		assert_eq!(shmem.snapshot(SNAPSHOT_TIMEOUT).unwrap().width, 78);
+		assert_eq!(shmem.get_id_unchecked(SNAPSHOT_TIMEOUT).unwrap(), 0);
+	}
+
+	#[test]
+	fn shmem_lock_timeout() {
+		let shmem = IpcShmem::new_memfd(&img_name()).unwrap();
+		let shared_shmem = IpcShmem::import_handle(shmem.export_handle().unwrap()).unwrap();
+
+		let _lock = shmem.acquire_lock(TIMEOUT).unwrap();
+		let err = shared_shmem
+			.acquire_rlock(Timeout::Val(Duration::from_millis(10)))
+			.err()
+			.unwrap();
+		assert_eq!(
+			err.downcast_ref::<std::io::Error>().unwrap().kind(),
+			std::io::ErrorKind::TimedOut
+		);
+
+		// Snapshots only wait as long as requested for a writer that holds the lock
+		let err = shared_shmem
+			.snapshot(Duration::from_millis(10))
+			.err()
+			.unwrap();
+		assert_eq!(
+			err.downcast_ref::<std::io::Error>().unwrap().kind(),
+			std::io::ErrorKind::TimedOut
+		);
+	}
+
 	#[test]
 	fn shmem_set_width() {
 		const TEST_ORIG_VAL: u32 = 0;
 		const TEST_CH_VAL: u32 = 12345;
 
-		//let shared_shmem = IpcShmem::new(SHMEM_NAME, &img_name(), true).unwrap();
-
-		let (created_shmem, shared_shmem) = _shmem_share();
+		let (created_shmem, shared_shmem) = _shmem_share(&unique_shmem_name("shmem_set_width"));
 		{
 			let lock = shared_shmem.acquire_lock(TIMEOUT).unwrap();
 			let data = IpcShmem::acquire_data(&lock);
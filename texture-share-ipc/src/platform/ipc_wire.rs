@@ -0,0 +1,295 @@
+use std::fmt::Display;
+use std::io::{Error, ErrorKind};
+use std::mem::size_of;
+
+use crate::platform::img_data::{ImgData, ImgName, NameError};
+
+// Every message starts with a header containing the message tag and the payload length.
+// All integers are little endian, strings are prefixed with their length
+pub const HEADER_LEN: usize = 2 * size_of::<u32>();
+
+// Upper bound on a single payload. Anything larger is treated as a corrupt stream
+pub const MAX_PAYLOAD_LEN: usize = 1 << 20;
+
+// Reasons why a received message could not be decoded. Returned wrapped in an
+// io::Error of kind InvalidData by the IpcConnection recv functions
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+	UnknownTag(u32),
+	PayloadTooLarge(usize),
+	Truncated { expected: usize, remaining: usize },
+	InvalidValue { field: &'static str, value: u64 },
+	InvalidName(NameError),
+	InvalidUtf8,
+}
+
+impl Display for DecodeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DecodeError::UnknownTag(tag) => write!(f, "Unknown message tag {}", tag),
+			DecodeError::PayloadTooLarge(len) => write!(
+				f,
+				"Message payload of {} bytes exceeds limit of {} bytes",
+				len, MAX_PAYLOAD_LEN
+			),
+			DecodeError::Truncated {
+				expected,
+				remaining,
+			} => write!(
+				f,
+				"Message truncated: expected {} more bytes, got {}",
+				expected, remaining
+			),
+			DecodeError::InvalidValue { field, value } => {
+				write!(f, "Invalid value {} for field '{}'", value, field)
+			}
+			DecodeError::InvalidName(err) => write!(f, "Invalid name: {}", err),
+			DecodeError::InvalidUtf8 => write!(f, "String is not valid UTF-8"),
+		}
+	}
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for Error {
+	fn from(value: DecodeError) -> Self {
+		Error::new(ErrorKind::InvalidData, value)
+	}
+}
+
+impl DecodeError {
+	// Extract the DecodeError from an io::Error returned by IpcConnection, if any
+	pub fn from_io_error(err: &Error) -> Option<&DecodeError> {
+		err.get_ref().and_then(|e| e.downcast_ref::<DecodeError>())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WireHeader {
+	pub tag: u32,
+	pub payload_len: u32,
+}
+
+impl WireHeader {
+	pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+		let mut buf = [0 as u8; HEADER_LEN];
+		buf[0..4].copy_from_slice(&self.tag.to_le_bytes());
+		buf[4..8].copy_from_slice(&self.payload_len.to_le_bytes());
+		buf
+	}
+
+	pub fn from_bytes(buf: &[u8; HEADER_LEN]) -> Result<WireHeader, DecodeError> {
+		let header = WireHeader {
+			tag: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+			payload_len: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+		};
+
+		if header.payload_len as usize > MAX_PAYLOAD_LEN {
+			return Err(DecodeError::PayloadTooLarge(header.payload_len as usize));
+		}
+
+		Ok(header)
+	}
+}
+
+// Types that can be written to and read from a message payload
+pub trait WireMessage: Sized {
+	fn encode(&self, writer: &mut WireWriter);
+	fn decode(reader: &mut WireReader) -> Result<Self, DecodeError>;
+}
+
+#[derive(Default)]
+pub struct WireWriter {
+	buf: Vec<u8>,
+}
+
+impl WireWriter {
+	pub fn new() -> WireWriter {
+		WireWriter::default()
+	}
+
+	pub fn into_inner(self) -> Vec<u8> {
+		self.buf
+	}
+
+	pub fn write_bool(&mut self, val: bool) {
+		self.buf.push(val as u8);
+	}
+
+	pub fn write_u32(&mut self, val: u32) {
+		self.buf.extend_from_slice(&val.to_le_bytes());
+	}
+
+	pub fn write_u64(&mut self, val: u64) {
+		self.buf.extend_from_slice(&val.to_le_bytes());
+	}
+
+	pub fn write_u128(&mut self, val: u128) {
+		self.buf.extend_from_slice(&val.to_le_bytes());
+	}
+
+	pub fn write_bytes(&mut self, val: &[u8]) {
+		self.write_u32(val.len() as u32);
+		self.buf.extend_from_slice(val);
+	}
+
+	pub fn write_str(&mut self, val: &str) {
+		self.write_bytes(val.as_bytes());
+	}
+
+	// Fixed size name buffers are sent without their trailing zeros
+	pub fn write_name(&mut self, val: &ImgName) {
+		let end = val.iter().position(|c| *c == 0).unwrap_or(val.len());
+		self.write_bytes(&val[0..end]);
+	}
+}
+
+pub struct WireReader<'a> {
+	buf: &'a [u8],
+}
+
+impl<'a> WireReader<'a> {
+	pub fn new(buf: &'a [u8]) -> WireReader<'a> {
+		WireReader { buf }
+	}
+
+	// Fields appended in newer protocol revisions should only be read if data remains,
+	// so that messages from older peers can still be decoded
+	pub fn is_empty(&self) -> bool {
+		self.buf.is_empty()
+	}
+
+	fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+		if self.buf.len() < len {
+			return Err(DecodeError::Truncated {
+				expected: len,
+				remaining: self.buf.len(),
+			});
+		}
+
+		let (val, rest) = self.buf.split_at(len);
+		self.buf = rest;
+		Ok(val)
+	}
+
+	pub fn read_bool(&mut self) -> Result<bool, DecodeError> {
+		Ok(self.take(1)?[0] != 0)
+	}
+
+	pub fn read_u32(&mut self) -> Result<u32, DecodeError> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	pub fn read_u64(&mut self) -> Result<u64, DecodeError> {
+		Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+	}
+
+	pub fn read_u128(&mut self) -> Result<u128, DecodeError> {
+		Ok(u128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+	}
+
+	pub fn read_bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+		let len = self.read_u32()? as usize;
+		self.take(len)
+	}
+
+	pub fn read_string(&mut self) -> Result<String, DecodeError> {
+		let bytes = self.read_bytes()?;
+		String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+	}
+
+	// Reads a name into a fixed size buffer. Empty names are allowed for unset fields
+	pub fn read_name(&mut self) -> Result<ImgName, DecodeError> {
+		let name = self.read_string()?;
+		if name.is_empty() {
+			return Ok([0 as u8; size_of::<ImgName>()]);
+		}
+
+		ImgData::convert_shmem_str_to_array(&name).map_err(DecodeError::InvalidName)
+	}
+
+	pub fn read_image_name(&mut self) -> Result<String, DecodeError> {
+		let name = self.read_string()?;
+		ImgData::validate_name(&name).map_err(DecodeError::InvalidName)?;
+		Ok(name)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wire_roundtrip() {
+		let mut name = [0 as u8; size_of::<ImgName>()];
+		name[0..4].copy_from_slice(b"test");
+
+		let mut writer = WireWriter::new();
+		writer.write_bool(true);
+		writer.write_u32(42);
+		writer.write_u64(u64::MAX - 1);
+		writer.write_u128(u128::MAX - 2);
+		writer.write_str("image");
+		writer.write_name(&name);
+		let buf = writer.into_inner();
+
+		let mut reader = WireReader::new(&buf);
+		assert_eq!(reader.read_bool().unwrap(), true);
+		assert_eq!(reader.read_u32().unwrap(), 42);
+		assert_eq!(reader.read_u64().unwrap(), u64::MAX - 1);
+		assert_eq!(reader.read_u128().unwrap(), u128::MAX - 2);
+		assert_eq!(reader.read_string().unwrap(), "image");
+		assert_eq!(reader.read_name().unwrap(), name);
+		assert!(reader.is_empty());
+
+		// Names are sent without padding
+		assert_eq!(buf.len(), 1 + 4 + 8 + 16 + (4 + 5) + (4 + 4));
+	}
+
+	#[test]
+	fn wire_truncated() {
+		let mut reader = WireReader::new(&[1, 0]);
+		assert_eq!(
+			reader.read_u32().unwrap_err(),
+			DecodeError::Truncated {
+				expected: 4,
+				remaining: 2
+			}
+		);
+	}
+
+	#[test]
+	fn wire_invalid_names() {
+		let mut writer = WireWriter::new();
+		writer.write_str("");
+		writer.write_str(&"a".repeat(size_of::<ImgName>()));
+		writer.write_str("a\0b");
+		let buf = writer.into_inner();
+
+		let mut reader = WireReader::new(&buf);
+		assert_eq!(
+			reader.read_image_name().unwrap_err(),
+			DecodeError::InvalidName(NameError::Empty)
+		);
+		assert_eq!(
+			reader.read_image_name().unwrap_err(),
+			DecodeError::InvalidName(NameError::TooLong(size_of::<ImgName>()))
+		);
+		assert_eq!(
+			reader.read_name().unwrap_err(),
+			DecodeError::InvalidName(NameError::ContainsNul)
+		);
+	}
+
+	#[test]
+	fn wire_header_limit() {
+		let header = WireHeader {
+			tag: 0,
+			payload_len: MAX_PAYLOAD_LEN as u32 + 1,
+		};
+		assert_eq!(
+			WireHeader::from_bytes(&header.to_bytes()).unwrap_err(),
+			DecodeError::PayloadTooLarge(MAX_PAYLOAD_LEN + 1)
+		);
+	}
+}
@@ -1,2 +1,4 @@
+pub mod ipc_ram_image;
 pub(super) mod ipc_shmem_lock;
+pub(super) mod ipc_shmem_memfd;
 pub mod ipc_unix_socket;
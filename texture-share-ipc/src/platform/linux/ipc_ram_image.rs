@@ -0,0 +1,155 @@
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+
+use crate::platform::img_data::ImgFormat;
+use crate::platform::ShmemDataInternal;
+
+// Pixels of an image, stored tightly packed in a memfd. Used instead of GPU memory if a client
+// can't import the server's images
+pub struct RamImage {
+	fd: OwnedFd,
+	ptr: *mut u8,
+	size: usize,
+	pub handle_id: u32,
+	pub width: u32,
+	pub height: u32,
+	pub format: ImgFormat,
+}
+
+impl Drop for RamImage {
+	fn drop(&mut self) {
+		if !self.ptr.is_null() {
+			unsafe { libc::munmap(self.ptr as *mut _, self.size) };
+			self.ptr = ptr::null_mut();
+		}
+	}
+}
+
+impl RamImage {
+	// Allocates a memfd for the image described by data
+	pub fn new(data: &ShmemDataInternal) -> Result<RamImage, Error> {
+		let size = RamImage::image_size(data)?;
+
+		let name = c"texture_share_ram_image";
+		let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+		if fd < 0 {
+			return Err(Error::last_os_error());
+		}
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+		if unsafe { libc::ftruncate(fd.as_raw_fd(), size as libc::off_t) } < 0 {
+			return Err(Error::last_os_error());
+		}
+
+		RamImage::map(fd, size, data)
+	}
+
+	// Maps a memfd received from the server. Fails if it is too small for the image
+	pub fn import_handle(fd: OwnedFd, data: &ShmemDataInternal) -> Result<RamImage, Error> {
+		let size = RamImage::image_size(data)?;
+
+		let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+		if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } < 0 {
+			return Err(Error::last_os_error());
+		}
+		if (stat.st_size as usize) < size {
+			return Err(Error::new(
+				ErrorKind::InvalidData,
+				format!(
+					"RAM image of {} bytes is too small for {}x{} {:?} image",
+					stat.st_size, data.width, data.height, data.format
+				),
+			));
+		}
+
+		RamImage::map(fd, size, data)
+	}
+
+	fn map(fd: OwnedFd, size: usize, data: &ShmemDataInternal) -> Result<RamImage, Error> {
+		let ptr = unsafe {
+			libc::mmap(
+				ptr::null_mut(),
+				size,
+				libc::PROT_READ | libc::PROT_WRITE,
+				libc::MAP_SHARED,
+				fd.as_raw_fd(),
+				0,
+			)
+		};
+		if ptr == libc::MAP_FAILED {
+			return Err(Error::last_os_error());
+		}
+
+		Ok(RamImage {
+			fd,
+			ptr: ptr as *mut u8,
+			size,
+			handle_id: data.handle_id,
+			width: data.width,
+			height: data.height,
+			format: data.format,
+		})
+	}
+
+	pub fn image_size(data: &ShmemDataInternal) -> Result<usize, Error> {
+		let size = data.width as usize * data.height as usize * data.format.bytes_per_pixel();
+		match size {
+			0 => Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!(
+					"Can't store {}x{} {:?} image in RAM",
+					data.width, data.height, data.format
+				),
+			)),
+			s => Ok(s),
+		}
+	}
+
+	pub fn export_handle(&self) -> Result<OwnedFd, Error> {
+		self.fd.try_clone()
+	}
+
+	pub fn row_size(&self) -> usize {
+		self.width as usize * self.format.bytes_per_pixel()
+	}
+
+	// Accesses must be synchronized with the image's IpcShmem lock
+	pub fn as_slice(&self) -> &[u8] {
+		unsafe { std::slice::from_raw_parts(self.ptr, self.size) }
+	}
+
+	pub fn as_mut_slice(&mut self) -> &mut [u8] {
+		unsafe { std::slice::from_raw_parts_mut(self.ptr, self.size) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ram_image_shared() {
+		let mut data = ShmemDataInternal::default();
+		data.width = 3;
+		data.height = 2;
+		data.format = ImgFormat::R8G8B8;
+		data.handle_id = 5;
+
+		let mut image = RamImage::new(&data).unwrap();
+		assert_eq!(image.as_slice().len(), 18);
+		assert_eq!(image.row_size(), 9);
+		image.as_mut_slice()[17] = 42;
+
+		let imported = RamImage::import_handle(image.export_handle().unwrap(), &data).unwrap();
+		assert_eq!(imported.handle_id, 5);
+		assert_eq!(imported.as_slice()[17], 42);
+
+		// Larger images don't fit into the memfd
+		data.height = 3;
+		assert!(RamImage::import_handle(image.export_handle().unwrap(), &data).is_err());
+
+		data.format = ImgFormat::Undefined;
+		assert!(RamImage::new(&data).is_err());
+	}
+}
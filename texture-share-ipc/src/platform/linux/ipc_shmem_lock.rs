@@ -1,46 +1,247 @@
 use memoffset::offset_of;
-use raw_sync::{
-	locks::{LockImpl, LockInit, RwLock},
-	Timeout,
-};
-use shared_memory::Shmem;
+use raw_sync::Timeout;
 use std::cell::UnsafeCell;
+use std::io::{Error, ErrorKind};
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 
 use crate::{
 	platform::{ipc_shmem::ShmemData, ShmemDataInternal},
 	IpcShmem,
 };
 
-pub type RwLockInternalData = libc::pthread_rwlock_t;
+// A robust mutex, so that a process dying while holding the lock doesn't block all others. A
+// rwlock can't detect that its owner died
+pub type LockInternalData = libc::pthread_mutex_t;
+
+// Number of lock-free attempts of IpcShmem::snapshot before it waits for the lock
+const SNAPSHOT_ATTEMPTS: usize = 64;
+
+// Pointers into the shared memory, which may be mapped at a different address in each process.
+// Writers make the sequence number odd while they hold the lock, so that snapshots can detect
+// concurrent changes
+pub(crate) struct ShmemLock {
+	mutex: *mut LockInternalData,
+	seq: *const AtomicU32,
+	frame_futex: *const AtomicU32,
+	data: *mut u8,
+}
+
+// Dereferences to the locked data. Unlocks when dropped
+pub struct LockGuard<'a> {
+	lock: &'a ShmemLock,
+	write: bool,
+	// Frame number when the lock was taken. Waiters are woken if a writer published a frame
+	frame_number: u64,
+}
+
+// Readers take the same lock as writers, but don't invalidate snapshots
+pub type ReadLockGuard<'a> = LockGuard<'a>;
+
+impl<'a> Deref for LockGuard<'a> {
+	type Target = *mut u8;
+
+	fn deref(&self) -> &Self::Target {
+		&self.lock.data
+	}
+}
+
+impl<'a> Drop for LockGuard<'a> {
+	fn drop(&mut self) {
+		let published = self.write && self.lock.frame_number() != self.frame_number;
+		if self.write {
+			self.lock.seq().fetch_add(1, Ordering::Release);
+		}
+		unsafe { libc::pthread_mutex_unlock(self.lock.mutex) };
+
+		if published {
+			self.lock.frame_futex().fetch_add(1, Ordering::Release);
+			ShmemLock::futex_wake(self.lock.frame_futex);
+		}
+	}
+}
+
+impl ShmemLock {
+	fn init(mutex: *mut LockInternalData) -> Result<(), Error> {
+		let check = |res: libc::c_int| match res {
+			0 => Ok(()),
+			e => Err(Error::from_raw_os_error(e)),
+		};
 
-pub type ReadLockGuard<'a> = raw_sync::locks::ReadLockGuard<'a>;
-pub type LockGuard<'a> = raw_sync::locks::LockGuard<'a>;
+		unsafe {
+			let mut attr: libc::pthread_mutexattr_t = std::mem::zeroed();
+			check(libc::pthread_mutexattr_init(&mut attr))?;
+			let res = check(libc::pthread_mutexattr_setpshared(
+				&mut attr,
+				libc::PTHREAD_PROCESS_SHARED,
+			))
+			.and_then(|_| {
+				check(libc::pthread_mutexattr_setrobust(
+					&mut attr,
+					libc::PTHREAD_MUTEX_ROBUST,
+				))
+			})
+			.and_then(|_| check(libc::pthread_mutex_init(mutex, &attr)));
+			libc::pthread_mutexattr_destroy(&mut attr);
+			res
+		}
+	}
+
+	fn seq(&self) -> &AtomicU32 {
+		unsafe { &*self.seq }
+	}
+
+	fn frame_futex(&self) -> &AtomicU32 {
+		unsafe { &*self.frame_futex }
+	}
+
+	// Must only be called while holding the lock
+	fn frame_number(&self) -> u64 {
+		unsafe { (*self.data.cast::<ShmemDataInternal>()).frame_number }
+	}
+
+	// If the previous owner died, the lock is taken over. The data may then be partially updated,
+	// which the slot bookkeeping tolerates like a client that crashed between two locks
+	pub(crate) fn lock(&self, timeout: Timeout, write: bool) -> Result<LockGuard<'_>, Error> {
+		let res = match timeout {
+			Timeout::Infinite => unsafe { libc::pthread_mutex_lock(self.mutex) },
+			Timeout::Val(duration) => {
+				let deadline = ShmemLock::deadline(duration)?;
+				unsafe { libc::pthread_mutex_timedlock(self.mutex, &deadline) }
+			}
+		};
+
+		match res {
+			0 => {}
+			libc::EOWNERDEAD => {
+				unsafe { libc::pthread_mutex_consistent(self.mutex) };
+				// A writer died, so its sequence number was never completed
+				if self.seq().load(Ordering::Relaxed) & 1 != 0 {
+					self.seq().fetch_add(1, Ordering::Release);
+				}
+			}
+			libc::ETIMEDOUT => {
+				return Err(Error::new(
+					ErrorKind::TimedOut,
+					"Timed out waiting for shared memory lock",
+				))
+			}
+			e => return Err(Error::from_raw_os_error(e)),
+		};
+
+		if write {
+			self.seq().fetch_add(1, Ordering::Acquire);
+			fence(Ordering::Release);
+		}
+		Ok(LockGuard {
+			lock: self,
+			write,
+			frame_number: self.frame_number(),
+		})
+	}
+
+	// Copies the data if no writer changed it meanwhile
+	fn try_snapshot(&self) -> Option<ShmemDataInternal> {
+		let seq = self.seq().load(Ordering::Acquire);
+		if seq & 1 != 0 {
+			return None;
+		}
+
+		// The copy may be torn, so it is only interpreted once the sequence number confirmed it
+		let data: MaybeUninit<ShmemDataInternal> =
+			unsafe { ptr::read_volatile(self.data.cast_const().cast()) };
+		fence(Ordering::Acquire);
+
+		match self.seq().load(Ordering::Relaxed) == seq {
+			true => Some(unsafe { data.assume_init() }),
+			false => None,
+		}
+	}
+
+	// The segment is mapped by several processes, so the futex must not be process private
+	fn futex_wait(futex: *const AtomicU32, value: u32, timeout: Duration) -> Result<(), Error> {
+		let timeout = libc::timespec {
+			tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+			tv_nsec: timeout.subsec_nanos() as libc::c_long,
+		};
+		let res = unsafe {
+			libc::syscall(
+				libc::SYS_futex,
+				futex,
+				libc::FUTEX_WAIT,
+				value,
+				&timeout,
+				ptr::null::<u32>(),
+				0,
+			)
+		};
+		match res {
+			0 => Ok(()),
+			_ => Err(Error::last_os_error()),
+		}
+	}
+
+	fn futex_wake(futex: *const AtomicU32) {
+		unsafe {
+			libc::syscall(
+				libc::SYS_futex,
+				futex,
+				libc::FUTEX_WAKE,
+				libc::c_int::MAX,
+				ptr::null::<libc::timespec>(),
+				ptr::null::<u32>(),
+				0,
+			)
+		};
+	}
+
+	// pthread_mutex_timedlock expects an absolute CLOCK_REALTIME time
+	fn deadline(duration: Duration) -> Result<libc::timespec, Error> {
+		let mut now: libc::timespec = unsafe { std::mem::zeroed() };
+		if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut now) } < 0 {
+			return Err(Error::last_os_error());
+		}
+
+		let nsec = now.tv_nsec as u64 + duration.subsec_nanos() as u64;
+		let sec = duration.as_secs() + nsec / 1_000_000_000;
+		Ok(libc::timespec {
+			tv_sec: now.tv_sec.saturating_add(sec as libc::time_t),
+			tv_nsec: (nsec % 1_000_000_000) as libc::c_long,
+		})
+	}
+}
 
 impl IpcShmem {
-	pub(crate) fn init_rw_lock(
-		shmem: &Shmem,
+	pub(crate) fn init_lock(
+		shmem_ptr: *mut u8,
 		from_existing: bool,
-	) -> Result<Box<dyn LockImpl>, Box<dyn std::error::Error>> {
-		let raw_rwlock_ptr = unsafe { shmem.as_ptr().add(offset_of!(ShmemData, rwlock_data)) };
-		let raw_data_ptr = unsafe { shmem.as_ptr().add(offset_of!(ShmemData, data)) };
+	) -> Result<ShmemLock, Box<dyn std::error::Error>> {
+		let mutex = unsafe { shmem_ptr.add(offset_of!(ShmemData, lock_data)) };
+		let seq = unsafe { shmem_ptr.add(offset_of!(ShmemData, seq)) };
+		let frame_futex = unsafe { shmem_ptr.add(offset_of!(ShmemData, frame_futex)) };
+		let data = unsafe { shmem_ptr.add(offset_of!(ShmemData, data)) };
 
-		let res = unsafe {
-			if !from_existing {
-				RwLock::new(raw_rwlock_ptr, raw_data_ptr)
-			} else {
-				RwLock::from_existing(raw_rwlock_ptr, raw_data_ptr)
-			}
-		}?;
-		assert!(res.1 <= offset_of!(ShmemData, data) - offset_of!(ShmemData, rwlock_data));
+		let lock = ShmemLock {
+			mutex: mutex.cast(),
+			seq: seq.cast(),
+			frame_futex: frame_futex.cast(),
+			data,
+		};
+		if !from_existing {
+			ShmemLock::init(lock.mutex)?;
+		}
 
-		Ok(res.0)
+		Ok(lock)
 	}
 
 	pub fn acquire_rlock<'a>(
 		&'a self,
 		timeout: Timeout,
 	) -> Result<ReadLockGuard<'a>, Box<dyn std::error::Error>> {
-		self.lock.try_rlock(timeout)
+		Ok(self.lock.lock(timeout, false)?)
 	}
 
 	pub fn acquire_rdata<'a>(lock: &ReadLockGuard<'a>) -> &'a ShmemDataInternal {
@@ -54,23 +255,60 @@ impl IpcShmem {
 		}
 	}
 
-	// fn read_lock(
-	// 	&self,
-	// 	timeout: Timeout,
-	// ) -> Result<(&ShmemDataInternal, ReadLockGuard), Box<dyn std::error::Error>> {
-	// 	let raw_ptr = self.shmem.as_ptr().cast::<ShmemData>();
-
-	// 	let lock = unsafe { raw_ptr.as_ref().unwrap().lock.try_rlock(timeout)? };
-	// 	let data = unsafe { raw_ptr.as_ref().unwrap().data.get().as_ref().unwrap() };
-
-	// 	Ok((data, lock))
-	// }
-
 	pub fn acquire_lock<'a>(
 		&'a self,
 		timeout: Timeout,
 	) -> Result<LockGuard<'a>, Box<dyn std::error::Error>> {
-		self.lock.try_lock(timeout)
+		Ok(self.lock.lock(timeout, true)?)
+	}
+
+	// Consistent copy of the data that usually doesn't take the lock or make any syscalls. Waits up
+	// to timeout for the lock if writers keep changing the data, so it must not be called while
+	// holding the lock
+	pub fn snapshot(
+		&self,
+		timeout: Duration,
+	) -> Result<ShmemDataInternal, Box<dyn std::error::Error>> {
+		for _ in 0..SNAPSHOT_ATTEMPTS {
+			if let Some(data) = self.lock.try_snapshot() {
+				return Ok(data);
+			}
+			std::hint::spin_loop();
+		}
+
+		let rlock = self.acquire_rlock(Timeout::Val(timeout))?;
+		Ok(IpcShmem::acquire_rdata(&rlock).clone())
+	}
+
+	// Sleeps until a frame newer than frame_number was published or the timeout expired. Returns
+	// whether a new frame is available. Must not be called while holding the lock
+	pub fn wait_for_frame(
+		&self,
+		frame_number: u64,
+		timeout: Duration,
+	) -> Result<bool, Box<dyn std::error::Error>> {
+		let deadline = Instant::now() + timeout;
+		loop {
+			// Publishing increments the futex word after the frame number, so a frame published
+			// after this load makes the wait return immediately
+			let futex_value = self.lock.frame_futex().load(Ordering::Acquire);
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			if self.snapshot(remaining)?.frame_number != frame_number {
+				return Ok(true);
+			}
+
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			if remaining.is_zero() {
+				return Ok(false);
+			}
+			// The frame number is checked again after wakeups, timeouts and interruptions
+			if let Err(e) = ShmemLock::futex_wait(self.lock.frame_futex, futex_value, remaining) {
+				match e.raw_os_error() {
+					Some(libc::ETIMEDOUT | libc::EAGAIN | libc::EINTR) => {}
+					_ => return Err(Box::new(e)),
+				}
+			}
+		}
 	}
 
 	pub fn acquire_data<'a>(lock: &'a LockGuard<'a>) -> &'a mut ShmemDataInternal {
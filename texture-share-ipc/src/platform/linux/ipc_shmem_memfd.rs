@@ -0,0 +1,105 @@
+use std::ffi::CString;
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+
+// Mapping of an IpcShmem segment that is shared as a file descriptor instead of a global name
+pub(crate) struct MemfdMapping {
+	fd: OwnedFd,
+	ptr: *mut u8,
+	size: usize,
+}
+
+impl Drop for MemfdMapping {
+	fn drop(&mut self) {
+		if !self.ptr.is_null() {
+			unsafe { libc::munmap(self.ptr as *mut _, self.size) };
+			self.ptr = ptr::null_mut();
+		}
+	}
+}
+
+impl MemfdMapping {
+	// The size is sealed, so clients can't make the server or each other fault by truncating it
+	pub(crate) fn new(size: usize) -> Result<MemfdMapping, Error> {
+		let name = c"texture_share_shmem";
+		let fd = unsafe {
+			libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+		};
+		if fd < 0 {
+			return Err(Error::last_os_error());
+		}
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+		if unsafe { libc::ftruncate(fd.as_raw_fd(), size as libc::off_t) } < 0 {
+			return Err(Error::last_os_error());
+		}
+
+		let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_SEAL;
+		if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+			return Err(Error::last_os_error());
+		}
+
+		MemfdMapping::map(fd, size)
+	}
+
+	// Maps a segment received from the server. Seals aren't checked, since named segments can't be
+	// sealed
+	pub(crate) fn import(fd: OwnedFd, size: usize) -> Result<MemfdMapping, Error> {
+		let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+		if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } < 0 {
+			return Err(Error::last_os_error());
+		}
+		if (stat.st_size as usize) < size {
+			return Err(Error::new(
+				ErrorKind::InvalidData,
+				format!(
+					"Shared memory of {} bytes is smaller than the expected {} bytes",
+					stat.st_size, size
+				),
+			));
+		}
+
+		MemfdMapping::map(fd, size)
+	}
+
+	// Opens a named segment as a file descriptor, so that it can be sent like a memfd
+	pub(crate) fn open_named(name: &str) -> Result<OwnedFd, Error> {
+		let name = CString::new(name).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+		let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC, 0) };
+		if fd < 0 {
+			return Err(Error::last_os_error());
+		}
+		Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+	}
+
+	fn map(fd: OwnedFd, size: usize) -> Result<MemfdMapping, Error> {
+		let ptr = unsafe {
+			libc::mmap(
+				ptr::null_mut(),
+				size,
+				libc::PROT_READ | libc::PROT_WRITE,
+				libc::MAP_SHARED,
+				fd.as_raw_fd(),
+				0,
+			)
+		};
+		if ptr == libc::MAP_FAILED {
+			return Err(Error::last_os_error());
+		}
+
+		Ok(MemfdMapping {
+			fd,
+			ptr: ptr as *mut u8,
+			size,
+		})
+	}
+
+	pub(crate) fn as_ptr(&self) -> *mut u8 {
+		self.ptr
+	}
+
+	pub(crate) fn export_handle(&self) -> Result<OwnedFd, Error> {
+		self.fd.try_clone()
+	}
+}
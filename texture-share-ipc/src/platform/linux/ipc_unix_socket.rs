@@ -1,23 +1,43 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashSet, VecDeque};
 use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Read, Write};
 use std::mem::size_of;
 use std::os::fd::{FromRawFd, OwnedFd, RawFd};
 use std::os::unix::net::{AncillaryData, SocketAncillary, UnixListener, UnixStream};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
-use crate::platform::ipc_commands::{CommandMsg, ResultMsg};
+use crate::platform::ipc_commands::{
+	ClientKind, CommHello, CommandMsg, ResultMsg, PROTOCOL_VERSION,
+};
+use crate::platform::ipc_wire::{WireHeader, HEADER_LEN, MAX_PAYLOAD_LEN};
+
+// Results queued for a peer that doesn't read them. The peer is dropped once the queue is full
+pub const MAX_QUEUED_RESULTS: usize = 64;
 
 pub struct IpcConnection {
 	conn: RefCell<UnixStream>,
 	//proc_id: i32,
 	timeout: Duration,
+	// Feature bits agreed upon during the Hello exchange. None until the handshake succeeded
+	features: Cell<Option<u64>>,
+	// Id the server assigned to the connection. Clients receive it during the Hello exchange
+	id: Cell<u32>,
+	// Image names this connection receives events for. An empty name matches all images
+	subscriptions: RefCell<HashSet<String>>,
+	// Encoded results that couldn't be written without blocking, see queue_result
+	send_queue: RefCell<VecDeque<Vec<u8>>>,
+	// Bytes of the first queued result that were already written
+	send_queue_offset: Cell<usize>,
 }
 
 pub struct IpcSocket {
 	listener_socket: UnixListener,
 	pub connections: Arc<Mutex<Vec<RefCell<IpcConnection>>>>,
 	pub timeout: Duration,
+	// Id of the next accepted connection
+	next_connection_id: AtomicU32,
 }
 
 impl IpcConnection {
@@ -32,6 +52,11 @@ impl IpcConnection {
 			conn: RefCell::new(conn),
 			//proc_id,
 			timeout,
+			features: Cell::new(None),
+			id: Cell::new(0),
+			subscriptions: RefCell::new(HashSet::default()),
+			send_queue: RefCell::new(VecDeque::new()),
+			send_queue_offset: Cell::new(0),
 		}
 	}
 
@@ -62,6 +87,69 @@ impl IpcConnection {
 		)
 	}
 
+	pub fn get_features(&self) -> Option<u64> {
+		self.features.get()
+	}
+
+	pub fn set_features(&self, features: Option<u64>) {
+		self.features.set(features)
+	}
+
+	pub fn get_id(&self) -> u32 {
+		self.id.get()
+	}
+
+	pub fn set_id(&self, id: u32) {
+		self.id.set(id)
+	}
+
+	pub fn subscribe(&self, image_name: &str) {
+		self.subscriptions
+			.borrow_mut()
+			.insert(image_name.to_string());
+	}
+
+	pub fn unsubscribe(&self, image_name: &str) {
+		self.subscriptions.borrow_mut().remove(image_name);
+	}
+
+	pub fn is_subscribed(&self, image_name: &str) -> bool {
+		let subscriptions = self.subscriptions.borrow();
+		subscriptions.contains("") || subscriptions.contains(image_name)
+	}
+
+	// Client side of the Hello exchange. Returns the feature bits accepted by the server
+	pub fn handshake(&self, client_kind: ClientKind, features: u64) -> Result<u64, Error> {
+		self.send_command(CommandMsg::Hello(CommHello::new(client_kind, features)))?;
+
+		let res_msg = self.recv_result()?.ok_or(Error::new(
+			ErrorKind::TimedOut,
+			"Server did not respond to Hello",
+		))?;
+		let res_data = match res_msg {
+			ResultMsg::Hello(data) => data,
+			_ => {
+				return Err(Error::new(
+					ErrorKind::InvalidData,
+					"Received invalid data from server",
+				))
+			}
+		};
+		if !res_data.accepted {
+			return Err(Error::new(
+				ErrorKind::ConnectionRefused,
+				format!(
+					"Server rejected connection: client protocol version {}, server protocol version {}",
+					PROTOCOL_VERSION, res_data.protocol_version
+				),
+			));
+		}
+
+		self.features.set(Some(res_data.features));
+		self.id.set(res_data.connection_id);
+		Ok(res_data.features)
+	}
+
 	fn compute_cmsg_header_size() -> usize {
 		#[cfg(target_pointer_width = "64")]
 		return 8 + 3 * 4;
@@ -133,32 +221,53 @@ impl IpcConnection {
 	}
 
 	pub fn send_command(&self, command_msg: CommandMsg) -> Result<(), Error> {
-		const MSG_LEN: usize = size_of::<CommandMsg>();
-		let raw_ptr: *const CommandMsg = &(command_msg);
-		let msg: &[u8; MSG_LEN] = unsafe { raw_ptr.cast::<[u8; MSG_LEN]>().as_ref().unwrap() };
-		self.conn.borrow_mut().write_all(msg)
+		self.send_message(command_msg.tag() as u32, &command_msg.encode_payload())
 	}
 
 	pub fn send_result(&self, result_msg: ResultMsg) -> Result<(), Error> {
-		const MSG_LEN: usize = size_of::<ResultMsg>();
-		let raw_ptr: *const ResultMsg = &(result_msg);
-		let msg: &[u8; MSG_LEN] = unsafe { raw_ptr.cast::<[u8; MSG_LEN]>().as_ref().unwrap() };
-		self.conn.borrow_mut().write_all(msg)
+		self.send_message(result_msg.tag() as u32, &result_msg.encode_payload())
 	}
 
-	pub fn recv_command_if_available(&self) -> Result<Option<CommandMsg>, Error> {
-		let mut msg = CommandMsg::default();
+	// Sends a result without blocking, e.g. an event the peer didn't wait for. Whatever can't be
+	// written yet stays queued until flush_queued_results or the next message
+	pub fn queue_result(&self, result_msg: ResultMsg) -> Result<(), Error> {
+		if self.send_queue.borrow().len() >= MAX_QUEUED_RESULTS {
+			return Err(Error::new(
+				ErrorKind::WouldBlock,
+				"Peer stopped reading, send queue is full",
+			));
+		}
 
-		const MSG_LEN: usize = size_of::<CommandMsg>();
-		let buf: &mut [u8; MSG_LEN] = unsafe {
-			(&mut msg as *mut CommandMsg)
-				.cast::<[u8; MSG_LEN]>()
-				.as_mut()
-				.unwrap()
-		};
+		let msg =
+			IpcConnection::encode_message(result_msg.tag() as u32, &result_msg.encode_payload())?;
+		self.send_queue.borrow_mut().push_back(msg);
+		self.flush_queued_results()?;
+		Ok(())
+	}
+
+	// Writes queued results without blocking. Returns true once the queue is empty
+	pub fn flush_queued_results(&self) -> Result<bool, Error> {
+		let conn = self.conn.borrow();
+		conn.set_nonblocking(true)?;
+		let res = self.write_queued_results(&conn);
+		conn.set_nonblocking(false)?;
+
+		match res {
+			Ok(()) => Ok(true),
+			Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+			Err(e) => Err(e),
+		}
+	}
+
+	pub fn has_queued_results(&self) -> bool {
+		!self.send_queue.borrow().is_empty()
+	}
+
+	pub fn recv_command_if_available(&self) -> Result<Option<CommandMsg>, Error> {
+		let mut header_buf = [0 as u8; HEADER_LEN];
 
 		// Check if a message is waiting
-		let first_read = match self.conn.borrow_mut().read(buf) {
+		let first_read = match self.conn.borrow_mut().read(&mut header_buf) {
 			Err(e) => match e.kind() {
 				ErrorKind::WouldBlock => Ok(0 as usize),
 				_ => Err(e),
@@ -169,84 +278,165 @@ impl IpcConnection {
 			return Ok(None);
 		}
 
-		let mut rec_bytes: usize = first_read;
-		let recv_res = IpcConnection::try_fcn_timeout(
-			|| {
-				let rec_buf: &mut [u8] = buf.split_at_mut(rec_bytes).1;
-				self.conn.borrow_mut().read_exact(rec_buf)?;
-				rec_bytes += rec_buf.len();
-
-				if rec_bytes >= MSG_LEN {
-					Ok::<Option<()>, Error>(Some(()))
-				} else {
-					Ok(None)
-				}
-			},
-			&self.timeout,
-		)?;
-
-		Ok(recv_res.and_then(|_| Some(msg)))
+		match self.recv_message(header_buf, first_read)? {
+			Some((header, payload)) => Ok(Some(CommandMsg::decode(header.tag, &payload)?)),
+			None => Ok(None),
+		}
 	}
 
 	#[allow(dead_code)]
 	pub fn recv_command(&self) -> Result<Option<CommandMsg>, Error> {
-		let mut msg = CommandMsg::default();
+		match self.recv_message([0 as u8; HEADER_LEN], 0)? {
+			Some((header, payload)) => Ok(Some(CommandMsg::decode(header.tag, &payload)?)),
+			None => Ok(None),
+		}
+	}
 
-		const MSG_LEN: usize = size_of::<CommandMsg>();
-		let buf: &mut [u8; MSG_LEN] = unsafe {
-			(&mut msg as *mut CommandMsg)
-				.cast::<[u8; MSG_LEN]>()
-				.as_mut()
-				.unwrap()
+	pub fn recv_result(&self) -> Result<Option<ResultMsg>, Error> {
+		match self.recv_message([0 as u8; HEADER_LEN], 0)? {
+			Some((header, payload)) => Ok(Some(ResultMsg::decode(header.tag, &payload)?)),
+			None => Ok(None),
+		}
+	}
+
+	// Non-blocking variant of recv_result. Returns None if no result has arrived yet
+	pub fn recv_result_if_available(&self) -> Result<Option<ResultMsg>, Error> {
+		let mut header_buf = [0 as u8; HEADER_LEN];
+
+		let first_read = {
+			let conn = self.conn.borrow_mut();
+			conn.set_nonblocking(true)?;
+			let res = (&*conn).read(&mut header_buf);
+			conn.set_nonblocking(false)?;
+			res
 		};
 
-		let mut rec_bytes: usize = 0;
-		let recv_res = IpcConnection::try_fcn_timeout(
-			|| {
-				let rec_buf: &mut [u8] = buf.split_at_mut(rec_bytes).1;
-				self.conn.borrow_mut().read_exact(rec_buf)?;
-				rec_bytes += rec_buf.len();
+		let first_read = match first_read {
+			Ok(0) => Err(Error::new(
+				ErrorKind::UnexpectedEof,
+				"Connection closed by peer",
+			)),
+			Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+			s => s,
+		}?;
 
-				if rec_bytes >= MSG_LEN {
-					Ok::<Option<()>, Error>(Some(()))
-				} else {
-					Ok(None)
+		match self.recv_message(header_buf, first_read)? {
+			Some((header, payload)) => Ok(Some(ResultMsg::decode(header.tag, &payload)?)),
+			None => Ok(None),
+		}
+	}
+
+	fn send_message(&self, tag: u32, payload: &[u8]) -> Result<(), Error> {
+		let msg = IpcConnection::encode_message(tag, payload)?;
+
+		// Queued results go first, a partially written one would otherwise corrupt the stream
+		let conn = self.conn.borrow();
+		self.write_queued_results(&conn)?;
+		(&*conn).write_all(&msg)
+	}
+
+	// Writes the queued results until done or until the socket would block
+	fn write_queued_results(&self, mut conn: &UnixStream) -> Result<(), Error> {
+		let mut send_queue = self.send_queue.borrow_mut();
+		while let Some(msg) = send_queue.front() {
+			let offset = self.send_queue_offset.get();
+			match conn.write(&msg[offset..]) {
+				Ok(0) => {
+					return Err(Error::new(
+						ErrorKind::WriteZero,
+						"Failed to write queued result",
+					))
 				}
-			},
-			&self.timeout,
-		)?;
+				Ok(len) if offset + len < msg.len() => self.send_queue_offset.set(offset + len),
+				Ok(_) => {
+					send_queue.pop_front();
+					self.send_queue_offset.set(0);
+				}
+				Err(e) if e.kind() == ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
 
-		Ok(recv_res.and_then(|_| Some(msg)))
+		Ok(())
 	}
 
-	pub fn recv_result(&self) -> Result<Option<ResultMsg>, Error> {
-		let mut msg = ResultMsg::default();
+	fn encode_message(tag: u32, payload: &[u8]) -> Result<Vec<u8>, Error> {
+		if payload.len() > MAX_PAYLOAD_LEN {
+			return Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!(
+					"Message payload of {} bytes exceeds limit of {} bytes",
+					payload.len(),
+					MAX_PAYLOAD_LEN
+				),
+			));
+		}
 
-		const MSG_LEN: usize = size_of::<ResultMsg>();
-		let buf: &mut [u8; MSG_LEN] = unsafe {
-			(&mut msg as *mut ResultMsg)
-				.cast::<[u8; MSG_LEN]>()
-				.as_mut()
-				.unwrap()
+		let header = WireHeader {
+			tag,
+			payload_len: payload.len() as u32,
 		};
 
-		let mut rec_bytes: usize = 0;
-		let recv_res = IpcConnection::try_fcn_timeout(
+		// Header and payload are sent with a single write
+		let mut msg = Vec::with_capacity(HEADER_LEN + payload.len());
+		msg.extend_from_slice(&header.to_bytes());
+		msg.extend_from_slice(payload);
+		Ok(msg)
+	}
+
+	// Receive a single frame. `header_bytes` of the header were already read into `header_buf`
+	fn recv_message(
+		&self,
+		mut header_buf: [u8; HEADER_LEN],
+		header_bytes: usize,
+	) -> Result<Option<(WireHeader, Vec<u8>)>, Error> {
+		if self.read_timeout(&mut header_buf, header_bytes)?.is_none() {
+			if header_bytes == 0 {
+				return Ok(None);
+			}
+
+			return Err(Error::new(
+				ErrorKind::TimedOut,
+				"Timed out while receiving message header",
+			));
+		}
+
+		let header = WireHeader::from_bytes(&header_buf)?;
+
+		// Once the header arrived, the payload must follow. Otherwise the stream is out of sync
+		let mut payload = vec![0 as u8; header.payload_len as usize];
+		if self.read_timeout(&mut payload, 0)?.is_none() {
+			return Err(Error::new(
+				ErrorKind::TimedOut,
+				"Timed out while receiving message payload",
+			));
+		}
+
+		Ok(Some((header, payload)))
+	}
+
+	fn read_timeout(&self, buf: &mut [u8], mut rec_bytes: usize) -> Result<Option<()>, Error> {
+		IpcConnection::try_fcn_timeout(
 			|| {
-				let rec_buf = buf.split_at_mut(rec_bytes).1;
-				self.conn.borrow_mut().read_exact(rec_buf)?;
-				rec_bytes += rec_buf.len();
+				if rec_bytes < buf.len() {
+					let len = self.conn.borrow_mut().read(&mut buf[rec_bytes..])?;
+					if len == 0 {
+						return Err(Error::new(
+							ErrorKind::UnexpectedEof,
+							"Connection closed by peer",
+						));
+					}
+					rec_bytes += len;
+				}
 
-				if rec_bytes >= MSG_LEN {
-					Ok::<Option<()>, Error>(Some(()))
+				if rec_bytes >= buf.len() {
+					Ok(Some(()))
 				} else {
 					Ok(None)
 				}
 			},
 			&self.timeout,
-		)?;
-
-		Ok(recv_res.and_then(|_| Some(msg)))
+		)
 	}
 
 	pub fn send_ack(&self) -> Result<(), Error> {
@@ -316,6 +506,7 @@ impl IpcSocket {
 			listener_socket,
 			connections: Arc::new(Mutex::new(Vec::new())),
 			timeout,
+			next_connection_id: AtomicU32::new(1),
 		})
 	}
 
@@ -334,6 +525,7 @@ impl IpcSocket {
 					},
 					Ok(c) => {
 						let ipc_conn = IpcConnection::new(c.0, self.timeout);
+						ipc_conn.set_id(self.next_connection_id.fetch_add(1, Ordering::Relaxed));
 						self.connections
 							.lock()
 							.unwrap()
@@ -358,6 +550,13 @@ mod tests {
 	use std::thread;
 	use std::{fs, os::fd::AsRawFd};
 
+	use crate::platform::ipc_commands::{
+		CommCopyImage, CommFindImage, CommandTag, ImageEvent, ImageEventKind, ResultCopyImage,
+		ResultFindImage,
+	};
+	use crate::platform::ipc_wire::DecodeError;
+	use crate::platform::ShmemDataInternal;
+
 	use super::*;
 
 	const TIMEOUT: Duration = Duration::from_millis(10000);
@@ -436,15 +635,15 @@ mod tests {
 
 		let conn_vector = listener.connections.clone();
 		let send_thread = move || {
-			let mut msg = CommandMsg::default();
-			unsafe { (*msg.data.find_img).image_name.fill(1) };
+			let mut msg = CommFindImage::default();
+			msg.image_name = "test".to_string();
 			conn_vector
 				.lock()
 				.unwrap()
 				.last()
 				.unwrap()
 				.borrow()
-				.send_command(msg)
+				.send_command(CommandMsg::FindImage(msg))
 		};
 
 		let recv_thread = move || conn.recv_command();
@@ -458,14 +657,15 @@ mod tests {
 		//assert!(s_res, size_of::<CommandMsg>());
 		assert!(r_res.is_some());
 
-		let mut cmp_msg = CommandMsg::default();
-		unsafe { (*cmp_msg.data.find_img).image_name.fill(1) };
-		let rec_msg = r_res.unwrap();
+		let mut cmp_msg = CommFindImage::default();
+		cmp_msg.image_name = "test".to_string();
+		let rec_msg = match r_res.unwrap() {
+			CommandMsg::FindImage(msg) => msg,
+			msg => panic!("Received wrong command {:?}", msg.tag()),
+		};
 
-		assert_eq!(cmp_msg.tag, rec_msg.tag);
-		assert_eq!(unsafe { cmp_msg.data.find_img.image_name }, unsafe {
-			rec_msg.data.find_img.image_name
-		});
+		assert_eq!(cmp_msg.image_name, rec_msg.image_name);
+		assert_eq!(cmp_msg.gpu_device_uuid, rec_msg.gpu_device_uuid);
 	}
 
 	#[test]
@@ -474,8 +674,8 @@ mod tests {
 		let (listener, conn) = _ipc_stream_create();
 
 		let send_thread = move || {
-			let mut msg = ResultMsg::default();
-			unsafe { (*msg.data.find_img).img_data.data.height = 1024 };
+			let mut msg = ResultFindImage::default();
+			msg.img_data.data.height = 1024;
 			listener
 				.connections
 				.lock()
@@ -483,7 +683,7 @@ mod tests {
 				.last()
 				.unwrap()
 				.borrow()
-				.send_result(msg)
+				.send_result(ResultMsg::FindImage(msg))
 		};
 
 		let recv_thread = move || conn.recv_result();
@@ -497,14 +697,175 @@ mod tests {
 		//assert_eq!(s_res, size_of::<ResultMsg>());
 		assert!(r_res.is_some());
 
-		let mut cmp_msg = ResultMsg::default();
-		unsafe { (*cmp_msg.data.find_img).img_data.data.height = 1024 };
-		let rec_msg = r_res.unwrap();
+		let rec_msg = match r_res.unwrap() {
+			ResultMsg::FindImage(msg) => msg,
+			msg => panic!("Received wrong result {:?}", msg.tag()),
+		};
+
+		assert_eq!(rec_msg.image_found, false);
+		assert_eq!(rec_msg.img_data.data.height, 1024);
+	}
+
+	#[test]
+	fn ipc_result_if_available() {
+		let _ = fs::remove_file(SOCK_PATH);
+		let (listener, conn) = _ipc_stream_create();
+
+		// Nothing was sent yet, so this must return immediately
+		assert!(conn.recv_result_if_available().unwrap().is_none());
+
+		listener
+			.connections
+			.lock()
+			.unwrap()
+			.last()
+			.unwrap()
+			.borrow()
+			.send_result(ResultMsg::CopyImage(ResultCopyImage {
+				success: true,
+				destinations_written: 2,
+				error: None,
+			}))
+			.expect("Failed to send res");
+
+		let rec_msg = IpcConnection::try_fcn_timeout(|| conn.recv_result_if_available(), &TIMEOUT)
+			.expect("Failed to recv res")
+			.expect("Result did not arrive");
+		match rec_msg {
+			ResultMsg::CopyImage(msg) => {
+				assert!(msg.success);
+				assert_eq!(msg.destinations_written, 2);
+				assert!(msg.error.is_none());
+			}
+			msg => panic!("Received wrong result {:?}", msg.tag()),
+		};
+	}
+
+	#[test]
+	fn ipc_queue_result() {
+		let (server_stream, client_stream) = UnixStream::pair().unwrap();
+		let server_conn = IpcConnection::new(server_stream, TIMEOUT);
+		let client_conn = IpcConnection::new(client_stream, TIMEOUT);
+
+		let event = |i: usize| {
+			ResultMsg::Event(ImageEvent::new(
+				ImageEventKind::FrameAvailable,
+				&format!("{:0>8}", i),
+				&ShmemDataInternal::default(),
+			))
+		};
+
+		// Queueing doesn't block if the peer stops reading, it fails once the queue is full
+		let mut queued = 0;
+		while server_conn.queue_result(event(queued)).is_ok() {
+			queued += 1;
+		}
+		assert!(queued >= MAX_QUEUED_RESULTS);
+		assert!(server_conn.has_queued_results());
+		assert!(!server_conn.flush_queued_results().unwrap());
+
+		// Queued results arrive complete and in order, before any later result
+		let recv_thread = thread::spawn(move || {
+			for i in 0..queued {
+				match client_conn.recv_result().unwrap().unwrap() {
+					ResultMsg::Event(ev) => assert_eq!(ev.image_name, format!("{:0>8}", i)),
+					msg => panic!("Received wrong result {:?}", msg.tag()),
+				};
+			}
+			match client_conn.recv_result().unwrap().unwrap() {
+				ResultMsg::CopyImage(msg) => assert_eq!(msg.destinations_written, 1),
+				msg => panic!("Received wrong result {:?}", msg.tag()),
+			};
+		});
+
+		server_conn
+			.send_result(ResultMsg::CopyImage(ResultCopyImage {
+				success: true,
+				destinations_written: 1,
+				error: None,
+			}))
+			.unwrap();
+		assert!(!server_conn.has_queued_results());
+
+		recv_thread.join().unwrap();
+	}
+
+	#[test]
+	fn ipc_msg_extra_fields() {
+		let _ = fs::remove_file(SOCK_PATH);
+		let (listener, conn) = _ipc_stream_create();
+
+		// Newer peers may append fields to a message. These must be ignored
+		let send_thread = move || {
+			let mut payload = CommandMsg::CopyImage(CommCopyImage {
+				image_name: "test".to_string(),
+				..Default::default()
+			})
+			.encode_payload();
+			payload.extend_from_slice(&[0xAB; 16]);
+			let header = WireHeader {
+				tag: CommandTag::CopyImage as u32,
+				payload_len: payload.len() as u32,
+			};
+
+			let conn_lock = listener.connections.lock().unwrap();
+			let conn = conn_lock.last().unwrap().borrow();
+			{
+				let socket = conn.get_socket();
+				(&*socket).write_all(&header.to_bytes())?;
+				(&*socket).write_all(&payload)?;
+			}
+			conn.send_command(CommandMsg::Hello(CommHello::new(ClientKind::Other, 0)))
+		};
+
+		let recv_thread = move || {
+			let first = conn.recv_command()?;
+			let second = conn.recv_command()?;
+			Ok::<_, Error>((first, second))
+		};
+
+		let s_handle = thread::spawn(send_thread);
+		let r_handle = thread::spawn(recv_thread);
+
+		s_handle.join().unwrap().expect("Failed to send cmd");
+		let (first, second) = r_handle.join().unwrap().expect("Failed to recv cmd");
+
+		assert_eq!(first.unwrap().tag(), CommandTag::CopyImage);
+		assert_eq!(second.unwrap().tag(), CommandTag::Hello);
+	}
+
+	#[test]
+	fn ipc_msg_invalid() {
+		let _ = fs::remove_file(SOCK_PATH);
+		let (listener, conn) = _ipc_stream_create();
+
+		let send_thread = move || {
+			let header = WireHeader {
+				tag: u32::MAX,
+				payload_len: 0,
+			};
+			let conn_lock = listener.connections.lock().unwrap();
+			let conn = conn_lock.last().unwrap().borrow();
+			let res = (&*conn.get_socket()).write_all(&header.to_bytes());
+			res
+		};
+
+		let recv_thread = move || conn.recv_command();
+
+		let s_handle = thread::spawn(send_thread);
+		let r_handle = thread::spawn(recv_thread);
+
+		s_handle.join().unwrap().expect("Failed to send cmd");
+		let err = r_handle
+			.join()
+			.unwrap()
+			.err()
+			.expect("Invalid message was accepted");
 
-		assert_eq!(cmp_msg.tag, rec_msg.tag);
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
 		assert_eq!(
-			unsafe { cmp_msg.data.find_img.img_data.data.height },
-			unsafe { rec_msg.data.find_img.img_data.data.height }
+			DecodeError::from_io_error(&err),
+			Some(&DecodeError::UnknownTag(u32::MAX))
 		);
 	}
 
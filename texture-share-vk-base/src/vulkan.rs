@@ -1,4 +1,4 @@
-mod vk_cpu_buffer;
+pub mod vk_cpu_buffer;
 pub mod vk_cpu_shared_image;
 pub mod vk_device;
 pub mod vk_entry;
@@ -7,7 +7,7 @@ use std::{
 use ash::vk::{self};
 
 use crate::{
-	vk_device::{VkBuffer, VkDevice},
+	vk_device::{TimelineSync, VkBuffer, VkDevice},
 	vk_instance::VkInstance,
 	vk_shared_image::VkSharedImage,
 };
@@ -174,6 +174,26 @@ impl VkCpuBuffer {
 		image_layout: vk::ImageLayout,
 		image_width: u32,
 		image_height: u32,
+	) -> Result<(), vk::Result> {
+		self.read_image_to_cpu_synchronized(
+			vk_device,
+			image,
+			image_layout,
+			image_width,
+			image_height,
+			&[],
+		)
+	}
+
+	// Waits on and signals timeline_syncs, e.g. the semaphore of a shared image
+	pub fn read_image_to_cpu_synchronized(
+		&self,
+		vk_device: &VkDevice,
+		image: vk::Image,
+		image_layout: vk::ImageLayout,
+		image_width: u32,
+		image_height: u32,
+		timeline_syncs: &[TimelineSync],
 	) -> Result<(), vk::Result> {
 		let img_copy_fcn = |cmd_buf: vk::CommandBuffer| {
 			// Ensure that image is ready to send and buffer is ready for receive
@@ -278,7 +298,7 @@ impl VkCpuBuffer {
 			Ok(())
 		};
 
-		vk_device.immediate_submit(vk_device.command_buffer, img_copy_fcn, &[], &[])?;
+		VkCpuBuffer::immediate_submit(vk_device, img_copy_fcn, timeline_syncs)?;
 		self.sync_memory_to_cpu(vk_device)?;
 
 		Ok(())
@@ -291,6 +311,26 @@ impl VkCpuBuffer {
 		image_layout: vk::ImageLayout,
 		image_width: u32,
 		image_height: u32,
+	) -> Result<(), vk::Result> {
+		self.write_image_from_cpu_synchronized(
+			vk_device,
+			image,
+			image_layout,
+			image_width,
+			image_height,
+			&[],
+		)
+	}
+
+	// Waits on and signals timeline_syncs, e.g. the semaphore of a shared image
+	pub fn write_image_from_cpu_synchronized(
+		&self,
+		vk_device: &VkDevice,
+		image: vk::Image,
+		image_layout: vk::ImageLayout,
+		image_width: u32,
+		image_height: u32,
+		timeline_syncs: &[TimelineSync],
 	) -> Result<(), vk::Result> {
 		let img_copy_fcn = |cmd_buf: vk::CommandBuffer| {
 			// Read from host. Not sure if this is required, but it works so I'll keep it
@@ -397,11 +437,27 @@ impl VkCpuBuffer {
 		};
 
 		self.sync_memory_from_cpu(vk_device)?;
-		vk_device.immediate_submit(vk_device.command_buffer, img_copy_fcn, &[], &[])?;
+		VkCpuBuffer::immediate_submit(vk_device, img_copy_fcn, timeline_syncs)?;
 
 		Ok(())
 	}
 
+	// Submissions without semaphores don't require timeline semaphore support
+	fn immediate_submit<F: FnOnce(vk::CommandBuffer) -> Result<(), vk::Result>>(
+		vk_device: &VkDevice,
+		fill_cmd_buf_fcn: F,
+		timeline_syncs: &[TimelineSync],
+	) -> Result<(), vk::Result> {
+		match timeline_syncs.is_empty() {
+			true => vk_device.immediate_submit(vk_device.command_buffer, fill_cmd_buf_fcn, &[], &[]),
+			false => vk_device.immediate_submit_with_timeline(
+				vk_device.command_buffer,
+				fill_cmd_buf_fcn,
+				timeline_syncs,
+			),
+		}
+	}
+
 	pub fn read_from_buffer(
 		&self,
 		vk_device: &VkDevice,
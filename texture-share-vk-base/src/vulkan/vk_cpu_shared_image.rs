@@ -1,7 +1,5 @@
 use ash::vk;
-use libc::c_void;
 use std::ptr::NonNull;
-use std::{alloc, ptr};
 
 use super::vk_cpu_buffer::VkCpuBuffer;
 use super::vk_shared_image::VkSharedImage;
@@ -9,53 +7,19 @@ use crate::vk_device::VkDevice;
 use crate::vk_instance::VkInstance;
 use crate::vk_shared_image::ImageBlit;
 
+pub use texture_share_ipc::AlignedRamBuffer;
+
 pub struct VkCpuSharedImage {
 	pub image: VkSharedImage,
 	pub cpu_buffer: VkCpuBuffer,
 }
 
-pub struct AlignedRamBuffer {
-	pub layout: alloc::Layout,
-	pub ptr: *mut c_void,
-}
-
 impl Drop for VkCpuSharedImage {
 	fn drop(&mut self) {
 		println!("Warning: VkCpuSharedImage should be manually destroyed, not dropped");
 	}
 }
 
-impl Drop for AlignedRamBuffer {
-	fn drop(&mut self) {
-		if !self.ptr.is_null() {
-			unsafe { alloc::dealloc(self.ptr as *mut _, self.layout) };
-			self.ptr = ptr::null_mut();
-		}
-	}
-}
-
-impl Default for AlignedRamBuffer {
-	fn default() -> Self {
-		Self {
-			layout: alloc::Layout::new::<u8>(),
-			ptr: ptr::null_mut(),
-		}
-	}
-}
-
-impl AlignedRamBuffer {
-	pub fn new(min_size: usize, align: usize) -> AlignedRamBuffer {
-		let layout = alloc::Layout::from_size_align(min_size, align)
-			.expect("Unable to create memory layout");
-		let ptr = unsafe { alloc::alloc(layout) };
-
-		AlignedRamBuffer {
-			layout,
-			ptr: ptr as *mut _,
-		}
-	}
-}
-
 impl VkCpuSharedImage {
 	pub fn new(
 		vk_instance: &VkInstance,
@@ -96,9 +60,12 @@ impl VkCpuSharedImage {
 			VkDevice::get_external_memory_host_properties(&vk_instance.instance, physical_device)
 				.min_imported_host_pointer_alignment as usize;
 
-		AlignedRamBuffer::new(min_size, align)
+		// Imported host memory must span a multiple of the alignment
+		AlignedRamBuffer::new(min_size.next_multiple_of(align), align)
 	}
 
+	// Grows ram_buffer if the resized image doesn't fit. Other images that import ram_buffer must
+	// import it again afterwards
 	pub fn resize_image(
 		&mut self,
 		vk_instance: &VkInstance,
@@ -111,18 +78,25 @@ impl VkCpuSharedImage {
 	) -> Result<(), vk::Result> {
 		self.image
 			.resize_image(vk_instance, vk_device, width, height, format, id)?;
-		if self.image.data.allocation_size as usize > ram_buffer.layout.align() {
-			*ram_buffer = VkCpuSharedImage::gen_device_aligned_ram_buffer(
-				self.image.data.allocation_size as usize,
-				vk_instance,
-				vk_device.physical_device,
-			);
-		}
+		// The old buffer stays alive until the CPU buffer stopped importing it
+		let allocation_size = self.image.data.allocation_size as usize;
+		let _old_ram_buffer = if allocation_size > ram_buffer.layout.size() {
+			Some(std::mem::replace(
+				ram_buffer,
+				VkCpuSharedImage::gen_device_aligned_ram_buffer(
+					allocation_size,
+					vk_instance,
+					vk_device.physical_device,
+				),
+			))
+		} else {
+			None
+		};
 
 		self.cpu_buffer.resize(
 			vk_instance,
 			vk_device,
-			ram_buffer.layout.align() as u64,
+			ram_buffer.layout.size() as u64,
 			Some(NonNull::new(ram_buffer.ptr).unwrap()),
 		)?;
 
@@ -145,6 +119,7 @@ impl ImageBlit for VkCpuSharedImage {
 		orig_dst_image_layout: vk::ImageLayout,
 		target_dst_image_layout: vk::ImageLayout,
 		dst_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let src_image_extent = [
@@ -322,13 +297,9 @@ impl ImageBlit for VkCpuSharedImage {
 		};
 
 		self.cpu_buffer.sync_memory_from_cpu(vk_device)?;
-		vk_device.immediate_submit_with_fence(
-			vk_device.command_buffer,
-			send_image_cmd_fcn,
-			&[],
-			&[],
-			fence,
-		)?;
+		self.image
+			.submit_synchronized(vk_device, send_image_cmd_fcn, timeline_value, fence)?;
+		self.image.wait_timeline(vk_device, *timeline_value)?;
 
 		Ok(())
 	}
@@ -339,6 +310,7 @@ impl ImageBlit for VkCpuSharedImage {
 		dst_image: &vk::Image,
 		orig_dst_image_layout: vk::ImageLayout,
 		target_dst_image_layout: vk::ImageLayout,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let dst_image_extent = [
@@ -356,6 +328,7 @@ impl ImageBlit for VkCpuSharedImage {
 			orig_dst_image_layout,
 			target_dst_image_layout,
 			&dst_image_extent,
+			timeline_value,
 			fence,
 		)
 	}
@@ -367,6 +340,7 @@ impl ImageBlit for VkCpuSharedImage {
 		orig_src_image_layout: vk::ImageLayout,
 		target_src_image_layout: vk::ImageLayout,
 		src_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let dst_image_extent = [
@@ -540,13 +514,9 @@ impl ImageBlit for VkCpuSharedImage {
 			Ok(())
 		};
 
-		vk_device.immediate_submit_with_fence(
-			vk_device.command_buffer,
-			recv_image_cmd_fcn,
-			&[],
-			&[],
-			fence,
-		)?;
+		self.image
+			.submit_synchronized(vk_device, recv_image_cmd_fcn, timeline_value, fence)?;
+		self.image.wait_timeline(vk_device, *timeline_value)?;
 		self.cpu_buffer.sync_memory_to_cpu(vk_device)?;
 
 		Ok(())
@@ -558,6 +528,7 @@ impl ImageBlit for VkCpuSharedImage {
 		src_image: &vk::Image,
 		orig_src_image_layout: vk::ImageLayout,
 		target_src_image_layout: vk::ImageLayout,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let src_image_extent = [
@@ -575,6 +546,7 @@ impl ImageBlit for VkCpuSharedImage {
 			orig_src_image_layout,
 			target_src_image_layout,
 			&src_image_extent,
+			timeline_value,
 			fence,
 		)
 	}
@@ -657,6 +629,8 @@ mod tests {
 		};
 
 		let fence = vk_device.create_fence(None).unwrap();
+		let mut in_timeline_value = 0;
+		let mut out_timeline_value = 0;
 
 		let test_val = 5;
 		let fake_val = 31;
@@ -682,9 +656,17 @@ mod tests {
 				&vk_cpu_shared_image_in.image.image,
 				vk_cpu_shared_image_in.image.image_layout,
 				vk_cpu_shared_image_in.image.image_layout,
+				&mut out_timeline_value,
 				fence,
 			)
 			.expect("Unable to recv_image_blit");
+		unsafe {
+			vk_device
+				.device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap();
+			vk_device.device.reset_fences(&[fence]).unwrap();
+		}
 
 		assert_eq!(ram_in[0], test_val);
 		assert_eq!(ram_out[0], test_val);
@@ -698,6 +680,7 @@ mod tests {
 				&vk_cpu_shared_image_out.image.image,
 				vk_cpu_shared_image_out.image.image_layout,
 				vk_cpu_shared_image_out.image.image_layout,
+				&mut in_timeline_value,
 				fence,
 			)
 			.expect("Unable to send_image_blit");
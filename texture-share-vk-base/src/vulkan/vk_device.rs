@@ -1,5 +1,7 @@
 use ash::{vk, Device, Instance};
 use std::ffi::{CStr, CString};
+#[cfg(target_os = "linux")]
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
 use texture_share_ipc::uuid;
 
 use super::vk_entry::VkEntry;
@@ -20,6 +22,16 @@ pub struct VkDevice {
 
 	#[cfg(target_os = "linux")]
 	pub external_memory_fd: ash::extensions::khr::ExternalMemoryFd,
+	#[cfg(target_os = "linux")]
+	pub external_semaphore_fd: ash::extensions::khr::ExternalSemaphoreFd,
+}
+
+// Timeline semaphore that a submission waits on and signals
+#[derive(Clone, Copy)]
+pub struct TimelineSync {
+	pub semaphore: vk::Semaphore,
+	pub wait_value: u64,
+	pub signal_value: u64,
 }
 
 pub struct VkPhysicalDeviceOptions {
@@ -173,6 +185,9 @@ impl VkDevice {
 		#[cfg(target_os = "linux")]
 		let external_memory_fd =
 			ash::extensions::khr::ExternalMemoryFd::new(&vk_instance.instance, &vk_device);
+		#[cfg(target_os = "linux")]
+		let external_semaphore_fd =
+			ash::extensions::khr::ExternalSemaphoreFd::new(&vk_instance.instance, &vk_device);
 
 		Ok(VkDevice {
 			device: vk_device,
@@ -184,6 +199,7 @@ impl VkDevice {
 			command_pool: vk_command_pool,
 			command_buffer: vk_command_buffer,
 			external_memory_fd,
+			external_semaphore_fd,
 		})
 	}
 
@@ -209,6 +225,9 @@ impl VkDevice {
 		#[cfg(target_os = "linux")]
 		let external_memory_fd =
 			ash::extensions::khr::ExternalMemoryFd::new(&vk_instance.instance, &vk_device);
+		#[cfg(target_os = "linux")]
+		let external_semaphore_fd =
+			ash::extensions::khr::ExternalSemaphoreFd::new(&vk_instance.instance, &vk_device);
 
 		Ok(VkDevice {
 			device: vk_device,
@@ -219,6 +238,7 @@ impl VkDevice {
 			command_pool: vk_command_pool,
 			command_buffer: vk_command_buffer,
 			external_memory_fd,
+			external_semaphore_fd,
 			import_only,
 		})
 	}
@@ -395,6 +415,18 @@ impl VkDevice {
 		};
 	}
 
+	pub fn allocate_command_buffer(&self) -> Result<vk::CommandBuffer, vk::Result> {
+		Self::_allocate_command_buffer(
+			&self.device,
+			self.command_pool,
+			vk::CommandBufferLevel::PRIMARY,
+		)
+	}
+
+	pub fn free_command_buffer(&self, command_buffer: vk::CommandBuffer) {
+		self._free_command_buffer(&self.command_pool, command_buffer)
+	}
+
 	pub fn create_fence(
 		&self,
 		fence_info: Option<vk::FenceCreateInfo>,
@@ -411,6 +443,96 @@ impl VkDevice {
 		unsafe { self.device.destroy_fence(fence, None) };
 	}
 
+	pub fn create_timeline_semaphore(
+		&self,
+		initial_value: u64,
+		exportable: bool,
+	) -> Result<vk::Semaphore, vk::Result> {
+		let mut export_info = vk::ExportSemaphoreCreateInfo::builder()
+			.handle_types(Self::SEMAPHORE_HANDLE_TYPE_FLAG)
+			.build();
+		let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+			.semaphore_type(vk::SemaphoreType::TIMELINE)
+			.initial_value(initial_value)
+			.build();
+
+		let mut create_info = vk::SemaphoreCreateInfo::builder().push_next(&mut type_info);
+		if exportable {
+			create_info = create_info.push_next(&mut export_info);
+		}
+
+		unsafe { self.device.create_semaphore(&create_info, None) }
+	}
+
+	pub fn destroy_semaphore(&self, semaphore: vk::Semaphore) {
+		unsafe { self.device.destroy_semaphore(semaphore, None) };
+	}
+
+	#[cfg(target_os = "linux")]
+	const SEMAPHORE_HANDLE_TYPE_FLAG: vk::ExternalSemaphoreHandleTypeFlags =
+		vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD;
+
+	#[cfg(target_os = "linux")]
+	pub fn export_semaphore_handle(&self, semaphore: vk::Semaphore) -> Result<OwnedFd, vk::Result> {
+		let get_info = vk::SemaphoreGetFdInfoKHR::builder()
+			.semaphore(semaphore)
+			.handle_type(Self::SEMAPHORE_HANDLE_TYPE_FLAG)
+			.build();
+
+		let fd = unsafe { self.external_semaphore_fd.get_semaphore_fd(&get_info) }?;
+		Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+	}
+
+	#[cfg(target_os = "linux")]
+	pub fn import_semaphore_handle(&self, fd: OwnedFd) -> Result<vk::Semaphore, vk::Result> {
+		let semaphore = self.create_timeline_semaphore(0, false)?;
+
+		let import_info = vk::ImportSemaphoreFdInfoKHR::builder()
+			.semaphore(semaphore)
+			.handle_type(Self::SEMAPHORE_HANDLE_TYPE_FLAG)
+			.fd(fd.into_raw_fd())
+			.build();
+
+		// On success, the semaphore takes ownership of the fd
+		if let Err(e) = unsafe { self.external_semaphore_fd.import_semaphore_fd(&import_info) } {
+			unsafe { drop(OwnedFd::from_raw_fd(import_info.fd)) };
+			self.destroy_semaphore(semaphore);
+			return Err(e);
+		}
+
+		Ok(semaphore)
+	}
+
+	pub fn wait_semaphore(
+		&self,
+		semaphore: vk::Semaphore,
+		value: u64,
+		timeout_ns: u64,
+	) -> Result<(), vk::Result> {
+		let semaphores = [semaphore];
+		let values = [value];
+		let wait_info = vk::SemaphoreWaitInfo::builder()
+			.semaphores(&semaphores)
+			.values(&values)
+			.build();
+
+		unsafe { self.device.wait_semaphores(&wait_info, timeout_ns) }
+	}
+
+	// Signals a timeline semaphore from the host. The value must be larger than the current one
+	pub fn signal_semaphore(&self, semaphore: vk::Semaphore, value: u64) -> Result<(), vk::Result> {
+		let signal_info = vk::SemaphoreSignalInfo::builder()
+			.semaphore(semaphore)
+			.value(value)
+			.build();
+
+		unsafe { self.device.signal_semaphore(&signal_info) }
+	}
+
+	pub fn get_semaphore_value(&self, semaphore: vk::Semaphore) -> Result<u64, vk::Result> {
+		unsafe { self.device.get_semaphore_counter_value(semaphore) }
+	}
+
 	fn _create_buffer(
 		vk_device: &Device,
 		create_info: &vk::BufferCreateInfo,
@@ -478,6 +600,90 @@ impl VkDevice {
 		Ok(())
 	}
 
+	// Submits without waiting for completion. The fence may be null
+	pub fn submit_with_timeline<F: FnOnce(vk::CommandBuffer) -> Result<(), vk::Result>>(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		fill_cmd_buf_fcn: F,
+		timeline_syncs: &[TimelineSync],
+		fence: vk::Fence,
+	) -> Result<(), vk::Result> {
+		let cmd_begin_info = vk::CommandBufferBeginInfo::builder()
+			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+			.build();
+		unsafe {
+			self.device
+				.begin_command_buffer(command_buffer, &cmd_begin_info)
+		}?;
+
+		fill_cmd_buf_fcn(command_buffer)?;
+
+		unsafe { self.device.end_command_buffer(command_buffer) }?;
+
+		let semaphores = timeline_syncs
+			.iter()
+			.map(|x| x.semaphore)
+			.collect::<Vec<_>>();
+		let wait_values = timeline_syncs
+			.iter()
+			.map(|x| x.wait_value)
+			.collect::<Vec<_>>();
+		let signal_values = timeline_syncs
+			.iter()
+			.map(|x| x.signal_value)
+			.collect::<Vec<_>>();
+		let wait_stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; timeline_syncs.len()];
+
+		// Built manually for the same reason as in immediate_submit_with_fence
+		let timeline_info = vk::TimelineSemaphoreSubmitInfo {
+			wait_semaphore_value_count: wait_values.len() as u32,
+			p_wait_semaphore_values: wait_values.as_ptr(),
+			signal_semaphore_value_count: signal_values.len() as u32,
+			p_signal_semaphore_values: signal_values.as_ptr(),
+			..Default::default()
+		};
+		let submit_info = vk::SubmitInfo {
+			p_next: &timeline_info as *const _ as *const _,
+			command_buffer_count: 1,
+			p_command_buffers: &command_buffer as *const _,
+			wait_semaphore_count: semaphores.len() as u32,
+			p_wait_semaphores: semaphores.as_ptr(),
+			p_wait_dst_stage_mask: wait_stages.as_ptr(),
+			signal_semaphore_count: semaphores.len() as u32,
+			p_signal_semaphores: semaphores.as_ptr(),
+			..Default::default()
+		};
+
+		unsafe {
+			self.device
+				.queue_submit(self.graphics_queue, &[submit_info], fence)
+		}?;
+
+		Ok(())
+	}
+
+	pub fn immediate_submit_with_timeline<
+		F: FnOnce(vk::CommandBuffer) -> Result<(), vk::Result>,
+	>(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		fill_cmd_buf_fcn: F,
+		timeline_syncs: &[TimelineSync],
+	) -> Result<(), vk::Result> {
+		let fence = self.create_fence(None)?;
+
+		let res = self
+			.submit_with_timeline(command_buffer, fill_cmd_buf_fcn, timeline_syncs, fence)
+			.and_then(|_| unsafe {
+				self.device
+					.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+			});
+
+		self.destroy_fence(fence);
+
+		res
+	}
+
 	pub fn immediate_submit<F: FnOnce(vk::CommandBuffer) -> Result<(), vk::Result>>(
 		&self,
 		command_buffer: vk::CommandBuffer,
@@ -509,7 +715,7 @@ mod tests {
 
 	use std::ffi::CStr;
 
-	use super::VkDevice;
+	use super::{TimelineSync, VkDevice};
 	use crate::vk_instance::VkInstance;
 
 	fn _init_vk_instance() -> VkInstance {
@@ -535,6 +741,34 @@ mod tests {
 		vk_device.destroy_fence(fence);
 	}
 
+	#[test]
+	fn vk_timeline_semaphore_submit() {
+		let vk_instance = _init_vk_instance();
+		let vk_device = _init_vk_device(&vk_instance);
+
+		let semaphore = vk_device.create_timeline_semaphore(1, true).unwrap();
+		let sync = TimelineSync {
+			semaphore,
+			wait_value: 1,
+			signal_value: 2,
+		};
+		vk_device
+			.immediate_submit_with_timeline(vk_device.command_buffer, |_x| Ok(()), &[sync])
+			.unwrap();
+		vk_device
+			.wait_semaphore(semaphore, 2, 1000 * 1000 * 1000)
+			.unwrap();
+
+		let fd = vk_device.export_semaphore_handle(semaphore).unwrap();
+		let imported = vk_device.import_semaphore_handle(fd).unwrap();
+		vk_device
+			.wait_semaphore(imported, 2, 1000 * 1000 * 1000)
+			.unwrap();
+
+		vk_device.destroy_semaphore(imported);
+		vk_device.destroy_semaphore(semaphore);
+	}
+
 	#[test]
 	fn vk_immediate_submit() {
 		let vk_instance = _init_vk_instance();
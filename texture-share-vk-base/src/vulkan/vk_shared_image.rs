@@ -1,9 +1,14 @@
+use std::cell::Cell;
 use std::os::fd::{AsRawFd, OwnedFd};
 
 use ash::vk;
 use texture_share_ipc::platform::{img_data::ImgFormat, ShmemDataInternal};
 
-use crate::{vk_device::VkDevice, vk_instance::VkInstance};
+use crate::{
+	vk_cpu_buffer::VkCpuBuffer,
+	vk_device::{TimelineSync, VkDevice},
+	vk_instance::VkInstance,
+};
 
 #[derive(Clone)]
 #[repr(C)]
@@ -21,7 +26,7 @@ impl SharedImageData {
 			id: data.handle_id,
 			width: data.width,
 			height: data.height,
-			format: vk::Format::R8G8B8A8_UNORM, //TODO: Change
+			format: VkSharedImage::get_vk_format(data.format),
 			allocation_size: data.allocation_size,
 		}
 	}
@@ -32,6 +37,13 @@ pub struct VkSharedImage {
 	pub image_layout: vk::ImageLayout,
 	memory: vk::DeviceMemory,
 
+	// Timeline semaphore shared with all processes accessing the image. Null if the image was
+	// imported without one, in which case blits wait for completion on the CPU
+	pub semaphore: vk::Semaphore,
+	command_buffer: vk::CommandBuffer,
+	// Value signalled by the last submission of command_buffer
+	pending_value: Cell<u64>,
+
 	pub(crate) data: SharedImageData,
 	//_phantom_dev: PhantomData<&'a VkSetup>,
 }
@@ -39,6 +51,14 @@ pub struct VkSharedImage {
 #[cfg(target_os = "linux")]
 type VkMemoryHandle = OwnedFd;
 
+// One side of a blit, moved from orig_layout to target_layout around the transfer
+struct BlitImage<'a> {
+	image: &'a vk::Image,
+	orig_layout: vk::ImageLayout,
+	target_layout: vk::ImageLayout,
+	extent: &'a [vk::Offset3D; 2],
+}
+
 impl Drop for VkSharedImage {
 	fn drop(&mut self) {
 		if self.image_layout != vk::ImageLayout::UNDEFINED {
@@ -47,6 +67,8 @@ impl Drop for VkSharedImage {
 	}
 }
 
+// timeline_value is the shared timeline value of the image, read from and stored back to its
+// shared memory while holding the write lock. Blits wait for it and increment it on submission
 pub trait ImageBlit {
 	fn send_image_blit_with_extents(
 		&self,
@@ -55,6 +77,7 @@ pub trait ImageBlit {
 		orig_dst_image_layout: vk::ImageLayout,
 		target_dst_image_layout: vk::ImageLayout,
 		dst_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result>;
 
@@ -64,6 +87,7 @@ pub trait ImageBlit {
 		dst_image: &vk::Image,
 		orig_dst_image_layout: vk::ImageLayout,
 		target_dst_image_layout: vk::ImageLayout,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result>;
 
@@ -74,6 +98,7 @@ pub trait ImageBlit {
 		orig_src_image_layout: vk::ImageLayout,
 		target_src_image_layout: vk::ImageLayout,
 		src_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result>;
 
@@ -83,12 +108,14 @@ pub trait ImageBlit {
 		src_image: &vk::Image,
 		orig_src_image_layout: vk::ImageLayout,
 		target_src_image_layout: vk::ImageLayout,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result>;
 }
 
 impl VkSharedImage {
 	const DEFAULT_IMAGE_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+	const SEMAPHORE_TIMEOUT_NS: u64 = 1000 * 1000 * 1000;
 
 	pub fn get_vk_format(format: ImgFormat) -> vk::Format {
 		match format {
@@ -96,6 +123,15 @@ impl VkSharedImage {
 			ImgFormat::B8G8R8A8 => vk::Format::B8G8R8A8_UNORM,
 			ImgFormat::R8G8B8 => vk::Format::R8G8B8_UNORM,
 			ImgFormat::R8G8B8A8 => vk::Format::R8G8B8A8_UNORM,
+			ImgFormat::R8 => vk::Format::R8_UNORM,
+			ImgFormat::R16 => vk::Format::R16_UNORM,
+			ImgFormat::R32Sfloat => vk::Format::R32_SFLOAT,
+			ImgFormat::R16G16B16A16 => vk::Format::R16G16B16A16_UNORM,
+			ImgFormat::R16G16B16A16Sfloat => vk::Format::R16G16B16A16_SFLOAT,
+			ImgFormat::R32G32B32A32Sfloat => vk::Format::R32G32B32A32_SFLOAT,
+			ImgFormat::A2B10G10R10 => vk::Format::A2B10G10R10_UNORM_PACK32,
+			ImgFormat::R8G8B8A8Srgb => vk::Format::R8G8B8A8_SRGB,
+			ImgFormat::B8G8R8A8Srgb => vk::Format::B8G8R8A8_SRGB,
 			ImgFormat::Undefined => vk::Format::UNDEFINED,
 		}
 	}
@@ -106,8 +142,67 @@ impl VkSharedImage {
 			vk::Format::B8G8R8A8_UNORM => ImgFormat::B8G8R8A8,
 			vk::Format::R8G8B8_UNORM => ImgFormat::R8G8B8,
 			vk::Format::R8G8B8A8_UNORM => ImgFormat::R8G8B8A8,
-			vk::Format::UNDEFINED => ImgFormat::Undefined,
-			_ => panic!("VkFormat {:?} not implemented", format),
+			vk::Format::R8_UNORM => ImgFormat::R8,
+			vk::Format::R16_UNORM => ImgFormat::R16,
+			vk::Format::R32_SFLOAT => ImgFormat::R32Sfloat,
+			vk::Format::R16G16B16A16_UNORM => ImgFormat::R16G16B16A16,
+			vk::Format::R16G16B16A16_SFLOAT => ImgFormat::R16G16B16A16Sfloat,
+			vk::Format::R32G32B32A32_SFLOAT => ImgFormat::R32G32B32A32Sfloat,
+			vk::Format::A2B10G10R10_UNORM_PACK32 => ImgFormat::A2B10G10R10,
+			vk::Format::R8G8B8A8_SRGB => ImgFormat::R8G8B8A8Srgb,
+			vk::Format::B8G8R8A8_SRGB => ImgFormat::B8G8R8A8Srgb,
+			// Formats that can't be shared
+			_ => ImgFormat::Undefined,
+		}
+	}
+
+	// Checks that images of format can be created with the usage of imported images, and that
+	// their memory handles support the required features, e.g. EXPORTABLE on the server
+	pub fn check_format_support(
+		vk_instance: &VkInstance,
+		vk_device: &VkDevice,
+		format: vk::Format,
+		required: vk::ExternalMemoryFeatureFlags,
+	) -> Result<(), vk::Result> {
+		if format == vk::Format::UNDEFINED {
+			return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+		}
+
+		let mut external_format_info = vk::PhysicalDeviceExternalImageFormatInfo::builder()
+			.handle_type(Self::MEMORY_HANDLE_TYPE_FLAG);
+		let format_info = vk::PhysicalDeviceImageFormatInfo2::builder()
+			.format(format)
+			.ty(vk::ImageType::TYPE_2D)
+			.tiling(vk::ImageTiling::OPTIMAL)
+			.usage(
+				vk::ImageUsageFlags::COLOR_ATTACHMENT
+					| vk::ImageUsageFlags::SAMPLED
+					| vk::ImageUsageFlags::TRANSFER_SRC
+					| vk::ImageUsageFlags::TRANSFER_DST,
+			)
+			.push_next(&mut external_format_info);
+
+		let mut external_props = vk::ExternalImageFormatProperties::default();
+		{
+			let mut props = vk::ImageFormatProperties2::builder().push_next(&mut external_props);
+			unsafe {
+				vk_instance
+					.instance
+					.get_physical_device_image_format_properties2(
+						vk_device.physical_device,
+						&format_info,
+						&mut props,
+					)
+			}?;
+		}
+
+		match external_props
+			.external_memory_properties
+			.external_memory_features
+			.contains(required)
+		{
+			true => Ok(()),
+			false => Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED),
 		}
 	}
 
@@ -190,10 +285,16 @@ impl VkSharedImage {
 			allocation_size: mem_allocate_info.allocation_size,
 		};
 
+		let semaphore = vk_device.create_timeline_semaphore(0, true)?;
+		let command_buffer = vk_device.allocate_command_buffer()?;
+
 		Ok(VkSharedImage {
 			image,
 			image_layout,
 			memory,
+			semaphore,
+			command_buffer,
+			pending_value: Cell::new(0),
 			data,
 			//_phantom_dev: PhantomData,
 		})
@@ -215,8 +316,18 @@ impl VkSharedImage {
 	}
 
 	pub(crate) fn _destroy(&self, vk_device: &VkDevice) {
+		// Only submissions of command_buffer may still be in flight
+		if self.semaphore != vk::Semaphore::null() {
+			let _ = vk_device.wait_semaphore(
+				self.semaphore,
+				self.pending_value.get(),
+				Self::SEMAPHORE_TIMEOUT_NS,
+			);
+			vk_device.destroy_semaphore(self.semaphore);
+		}
+		vk_device.free_command_buffer(self.command_buffer);
+
 		unsafe {
-			vk_device.device.device_wait_idle().unwrap();
 			vk_device.device.free_memory(self.memory, None);
 			vk_device.device.destroy_image(self.image, None);
 		}
@@ -231,15 +342,24 @@ impl VkSharedImage {
 		vk_instance: &VkInstance,
 		vk_device: &VkDevice,
 		mem_fd: VkMemoryHandle,
+		semaphore_fd: Option<VkMemoryHandle>,
 		image_data: SharedImageData,
 	) -> Result<VkSharedImage, vk::Result> {
+		// The image must be created with the format the memory was exported with
+		Self::check_format_support(
+			vk_instance,
+			vk_device,
+			image_data.format,
+			vk::ExternalMemoryFeatureFlags::IMPORTABLE,
+		)?;
+
 		// Create and allocate image memory
 		let mut external_memory_image_info = vk::ExternalMemoryImageCreateInfo::builder()
 			.handle_types(Self::MEMORY_HANDLE_TYPE_FLAG);
 		let image_create_info = vk::ImageCreateInfo::builder()
 			.push_next(&mut external_memory_image_info)
 			.image_type(vk::ImageType::TYPE_2D)
-			.format(vk::Format::R8G8B8A8_UNORM) // TODO: Use image_data.format
+			.format(image_data.format)
 			.mip_levels(1)
 			.array_layers(1)
 			.samples(vk::SampleCountFlags::TYPE_1)
@@ -261,6 +381,12 @@ impl VkSharedImage {
 
 		let memory_requirements = unsafe { vk_device.device.get_image_memory_requirements(image) };
 
+		// An image that needs more memory than was exported doesn't match the exported one
+		if memory_requirements.size > image_data.allocation_size {
+			unsafe { vk_device.device.destroy_image(image, None) };
+			return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE);
+		}
+
 		#[cfg(target_os = "linux")]
 		let mut import_memory_info = vk::ImportMemoryFdInfoKHR::builder()
 			.fd(mem_fd.as_raw_fd())
@@ -303,10 +429,19 @@ impl VkSharedImage {
 			vk::AccessFlags::MEMORY_WRITE,
 		)?;
 
+		let semaphore = match semaphore_fd {
+			Some(fd) => vk_device.import_semaphore_handle(fd)?,
+			None => vk::Semaphore::null(),
+		};
+		let command_buffer = vk_device.allocate_command_buffer()?;
+
 		Ok(VkSharedImage {
 			image,
 			image_layout,
 			memory,
+			semaphore,
+			command_buffer,
+			pending_value: Cell::new(0),
 			data: image_data,
 			//_phantom_dev: PhantomData,
 		})
@@ -387,6 +522,93 @@ impl VkSharedImage {
 		Ok(fd)
 	}
 
+	#[cfg(target_os = "linux")]
+	pub fn export_semaphore_handle(&self, vk_device: &VkDevice) -> Result<OwnedFd, vk::Result> {
+		if self.semaphore == vk::Semaphore::null() {
+			return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+		}
+
+		vk_device.export_semaphore_handle(self.semaphore)
+	}
+
+	// Waits on the CPU until the shared timeline reaches timeline_value
+	pub fn wait_timeline(
+		&self,
+		vk_device: &VkDevice,
+		timeline_value: u64,
+	) -> Result<(), vk::Result> {
+		if self.semaphore == vk::Semaphore::null() {
+			return Ok(());
+		}
+
+		vk_device.wait_semaphore(self.semaphore, timeline_value, Self::SEMAPHORE_TIMEOUT_NS)
+	}
+
+	// Completes a reserved timeline value whose access was never submitted, so that later accesses
+	// don't wait for it forever. Waits for the accesses before it first
+	pub fn skip_timeline_value(
+		&self,
+		vk_device: &VkDevice,
+		timeline_value: u64,
+	) -> Result<(), vk::Result> {
+		if self.semaphore == vk::Semaphore::null() {
+			return Ok(());
+		}
+
+		vk_device.wait_semaphore(self.semaphore, timeline_value, Self::SEMAPHORE_TIMEOUT_NS)?;
+		if vk_device.get_semaphore_value(self.semaphore)? > timeline_value {
+			return Ok(());
+		}
+		vk_device.signal_semaphore(self.semaphore, timeline_value + 1)
+	}
+
+	// Submits commands that are ordered after all previous accesses to the shared image. Without
+	// a semaphore, waits for completion instead
+	pub(crate) fn submit_synchronized<F: FnOnce(vk::CommandBuffer) -> Result<(), vk::Result>>(
+		&self,
+		vk_device: &VkDevice,
+		fill_cmd_buf_fcn: F,
+		timeline_value: &mut u64,
+		fence: vk::Fence,
+	) -> Result<(), vk::Result> {
+		if self.semaphore == vk::Semaphore::null() {
+			vk_device.immediate_submit_with_timeline(
+				vk_device.command_buffer,
+				fill_cmd_buf_fcn,
+				&[],
+			)?;
+
+			// Still signal the caller's fence, same as with a semaphore
+			if fence != vk::Fence::null() {
+				unsafe {
+					vk_device
+						.device
+						.queue_submit(vk_device.graphics_queue, &[], fence)
+				}?;
+			}
+			return Ok(());
+		}
+
+		// The command buffer can only be re-recorded once its previous submission finished
+		vk_device.wait_semaphore(
+			self.semaphore,
+			self.pending_value.get(),
+			Self::SEMAPHORE_TIMEOUT_NS,
+		)?;
+
+		let sync = TimelineSync {
+			semaphore: self.semaphore,
+			wait_value: *timeline_value,
+			signal_value: *timeline_value + 1,
+		};
+		vk_device.submit_with_timeline(self.command_buffer, fill_cmd_buf_fcn, &[sync], fence)?;
+
+		*timeline_value = sync.signal_value;
+		self.pending_value.set(sync.signal_value);
+
+		Ok(())
+	}
+
 	pub fn gen_img_mem_barrier(
 		image: vk::Image,
 		orig_layout: vk::ImageLayout,
@@ -413,123 +635,272 @@ impl VkSharedImage {
 			.build()
 	}
 
-	pub(crate) fn image_blit(
+	// Records barriers and the blit into cmd_buf, leaving both images in their target layouts
+	fn record_image_blit(
 		vk_device: &VkDevice,
-		src_image: &vk::Image,
-		orig_src_image_layout: vk::ImageLayout,
-		target_src_image_layout: vk::ImageLayout,
-		src_image_extent: &[vk::Offset3D; 2],
-		dst_image: &vk::Image,
-		orig_dst_image_layout: vk::ImageLayout,
-		target_dst_image_layout: vk::ImageLayout,
-		dst_image_extent: &[vk::Offset3D; 2],
+		cmd_buf: vk::CommandBuffer,
+		src: &BlitImage,
+		dst: &BlitImage,
+	) {
+		const SRC_BLIT_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+		const DST_BLIT_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+
+		// Image memory barrier that prepares image transfer
+		// Sets src_image to TRANSFER_SRC_OPTIMAL layout
+		// Sets dst_image to TRANSFER_DST_OPTIMAL layout
+		// Ensures that dst access masks are set to TRANSFER_READ and TRANSFER_WRITE respectively
+		let src_img_mem_barrier = Self::gen_img_mem_barrier(
+			*src.image,
+			src.orig_layout,
+			SRC_BLIT_LAYOUT,
+			vk::AccessFlags::NONE,
+			vk::AccessFlags::TRANSFER_READ,
+		);
+		let dst_img_mem_barrier = Self::gen_img_mem_barrier(
+			*dst.image,
+			dst.orig_layout,
+			DST_BLIT_LAYOUT,
+			vk::AccessFlags::NONE,
+			vk::AccessFlags::TRANSFER_WRITE,
+		);
+
+		// Push pipeline barrier
+		unsafe {
+			vk_device.device.cmd_pipeline_barrier(
+				cmd_buf,
+				vk::PipelineStageFlags::TOP_OF_PIPE,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::DependencyFlags::default(),
+				&[],
+				&[],
+				&[src_img_mem_barrier, dst_img_mem_barrier],
+			)
+		};
+
+		// Blit image
+		let image_subresource_layer = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_array_layer(0)
+			.layer_count(1)
+			.mip_level(0)
+			.build();
+		let image_blit = vk::ImageBlit::builder()
+			.src_subresource(image_subresource_layer)
+			.src_offsets(*src.extent)
+			.dst_subresource(image_subresource_layer)
+			.dst_offsets(*dst.extent)
+			.build();
+		unsafe {
+			vk_device.device.cmd_blit_image(
+				cmd_buf,
+				*src.image,
+				SRC_BLIT_LAYOUT,
+				*dst.image,
+				DST_BLIT_LAYOUT,
+				&[image_blit],
+				vk::Filter::NEAREST,
+			)
+		};
+
+		// Image memory barrier that waits for image transfer
+		// Sets src_image to target_src_image_layout layout
+		// Sets dst_image to target_dst_image_layout layout
+		// Ensures that src access masks are set to TRANSFER_READ and TRANSFER_WRITE respectively
+		let src_img_mem_barrier = Self::gen_img_mem_barrier(
+			*src.image,
+			SRC_BLIT_LAYOUT,
+			src.target_layout,
+			vk::AccessFlags::TRANSFER_READ,
+			vk::AccessFlags::NONE,
+		);
+		let dst_img_mem_barrier = Self::gen_img_mem_barrier(
+			*dst.image,
+			DST_BLIT_LAYOUT,
+			dst.target_layout,
+			vk::AccessFlags::TRANSFER_WRITE,
+			vk::AccessFlags::NONE,
+		);
+
+		// Push pipeline barrier
+		unsafe {
+			vk_device.device.cmd_pipeline_barrier(
+				cmd_buf,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+				vk::DependencyFlags::default(),
+				&[],
+				&[],
+				&[src_img_mem_barrier, dst_img_mem_barrier],
+			)
+		};
+	}
+
+	fn image_blit(
+		&self,
+		vk_device: &VkDevice,
+		src: &BlitImage,
+		dst: &BlitImage,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let blit_fcn = |cmd_buf: vk::CommandBuffer| -> Result<(), vk::Result> {
-			const SRC_BLIT_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-			const DST_BLIT_LAYOUT: vk::ImageLayout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-
-			// Image memory barrier that prepares image transfer
-			// Sets src_image to TRANSFER_SRC_OPTIMAL layout
-			// Sets dst_image to TRANSFER_DST_OPTIMAL layout
-			// Ensures that dst access masks are set to TRANSFER_READ and TRANSFER_WRITE respectively
-			let src_img_mem_barrier = Self::gen_img_mem_barrier(
-				*src_image,
-				orig_src_image_layout,
-				SRC_BLIT_LAYOUT,
-				vk::AccessFlags::NONE,
-				vk::AccessFlags::TRANSFER_READ,
-			);
-			let dst_img_mem_barrier = Self::gen_img_mem_barrier(
-				*dst_image,
-				orig_dst_image_layout,
-				DST_BLIT_LAYOUT,
-				vk::AccessFlags::NONE,
-				vk::AccessFlags::TRANSFER_WRITE,
-			);
+			Self::record_image_blit(vk_device, cmd_buf, src, dst);
+			Ok(())
+		};
 
-			// Push pipeline barrier
-			unsafe {
-				vk_device.device.cmd_pipeline_barrier(
-					cmd_buf,
-					vk::PipelineStageFlags::TOP_OF_PIPE,
-					vk::PipelineStageFlags::TRANSFER,
-					vk::DependencyFlags::default(),
-					&[],
-					&[],
-					&[src_img_mem_barrier, dst_img_mem_barrier],
-				)
-			};
+		self.submit_synchronized(vk_device, blit_fcn, timeline_value, fence)
+	}
 
-			// Blit image
-			let image_subresource_layer = vk::ImageSubresourceLayers::builder()
-				.aspect_mask(vk::ImageAspectFlags::COLOR)
-				.base_array_layer(0)
-				.layer_count(1)
-				.mip_level(0)
-				.build();
-			let image_blit = vk::ImageBlit::builder()
-				.src_subresource(image_subresource_layer)
-				.src_offsets(*src_image_extent)
-				.dst_subresource(image_subresource_layer)
-				.dst_offsets(*dst_image_extent)
-				.build();
-			unsafe {
-				vk_device.device.cmd_blit_image(
-					cmd_buf,
-					*src_image,
-					SRC_BLIT_LAYOUT,
-					*dst_image,
-					DST_BLIT_LAYOUT,
-					&[image_blit],
-					vk::Filter::NEAREST,
-				)
-			};
+	// Copies the image into cpu_buffer, which must hold the tightly packed pixels. Ordered after
+	// all previous accesses to the shared image, completes before returning
+	pub fn read_to_cpu_buffer(
+		&self,
+		vk_device: &VkDevice,
+		cpu_buffer: &VkCpuBuffer,
+		timeline_value: &mut u64,
+	) -> Result<(), vk::Result> {
+		let timeline_syncs = self.immediate_timeline_syncs(timeline_value)?;
+		cpu_buffer.read_image_to_cpu_synchronized(
+			vk_device,
+			self.image,
+			self.image_layout,
+			self.data.width,
+			self.data.height,
+			&timeline_syncs,
+		)
+	}
 
-			// Image memory barrier that waits for image transfer
-			// Sets src_image to target_src_image_layout layout
-			// Sets dst_image to target_dst_image_layout layout
-			// Ensures that src access masks are set to TRANSFER_READ and TRANSFER_WRITE respectively
-			let src_img_mem_barrier = Self::gen_img_mem_barrier(
-				*src_image,
-				SRC_BLIT_LAYOUT,
-				target_src_image_layout,
-				vk::AccessFlags::TRANSFER_READ,
-				vk::AccessFlags::NONE,
-			);
-			let dst_img_mem_barrier = Self::gen_img_mem_barrier(
-				*dst_image,
-				DST_BLIT_LAYOUT,
-				target_dst_image_layout,
-				vk::AccessFlags::TRANSFER_WRITE,
-				vk::AccessFlags::NONE,
-			);
+	// Copies the tightly packed pixels in cpu_buffer into the image, see read_to_cpu_buffer
+	pub fn write_from_cpu_buffer(
+		&self,
+		vk_device: &VkDevice,
+		cpu_buffer: &VkCpuBuffer,
+		timeline_value: &mut u64,
+	) -> Result<(), vk::Result> {
+		let timeline_syncs = self.immediate_timeline_syncs(timeline_value)?;
+		cpu_buffer.write_image_from_cpu_synchronized(
+			vk_device,
+			self.image,
+			self.image_layout,
+			self.data.width,
+			self.data.height,
+			&timeline_syncs,
+		)
+	}
 
-			// Push pipeline barrier
-			unsafe {
-				vk_device.device.cmd_pipeline_barrier(
-					cmd_buf,
-					vk::PipelineStageFlags::TRANSFER,
-					vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-					vk::DependencyFlags::default(),
-					&[],
-					&[],
-					&[src_img_mem_barrier, dst_img_mem_barrier],
-				)
-			};
+	// Without a semaphore, submissions are ordered by waiting for their completion
+	fn immediate_timeline_syncs(
+		&self,
+		timeline_value: &mut u64,
+	) -> Result<Vec<TimelineSync>, vk::Result> {
+		match self.semaphore == vk::Semaphore::null() {
+			true => Ok(Vec::new()),
+			false => Ok(vec![self.reserve_timeline(timeline_value)?]),
+		}
+	}
 
-			Ok(())
+	// Reserves the next value of the shared timeline for a submission made by the caller
+	fn reserve_timeline(&self, timeline_value: &mut u64) -> Result<TimelineSync, vk::Result> {
+		if self.semaphore == vk::Semaphore::null() {
+			return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+		}
+
+		let sync = TimelineSync {
+			semaphore: self.semaphore,
+			wait_value: *timeline_value,
+			signal_value: *timeline_value + 1,
 		};
 
-		vk_device.immediate_submit_with_fence(
-			vk_device.command_buffer,
-			blit_fcn,
-			&[],
-			&[],
-			fence,
-		)?;
+		*timeline_value = sync.signal_value;
+		self.pending_value.set(sync.signal_value);
 
-		Ok(())
+		Ok(sync)
+	}
+
+	// Records a blit from the shared image into dst_image without submitting it. The caller's
+	// submission of cmd_buf must wait on and signal the returned values of the image's semaphore
+	pub fn cmd_send_image_blit(
+		&self,
+		vk_device: &VkDevice,
+		cmd_buf: vk::CommandBuffer,
+		dst_image: &vk::Image,
+		orig_dst_image_layout: vk::ImageLayout,
+		target_dst_image_layout: vk::ImageLayout,
+		dst_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
+	) -> Result<TimelineSync, vk::Result> {
+		let sync = self.reserve_timeline(timeline_value)?;
+
+		let src_image_extent = [
+			vk::Offset3D { x: 0, y: 0, z: 0 },
+			vk::Offset3D {
+				x: self.data.width as i32,
+				y: self.data.height as i32,
+				z: 1,
+			},
+		];
+
+		Self::record_image_blit(
+			vk_device,
+			cmd_buf,
+			&BlitImage {
+				image: &self.image,
+				orig_layout: self.image_layout,
+				target_layout: self.image_layout,
+				extent: &src_image_extent,
+			},
+			&BlitImage {
+				image: dst_image,
+				orig_layout: orig_dst_image_layout,
+				target_layout: target_dst_image_layout,
+				extent: dst_image_extent,
+			},
+		);
+
+		Ok(sync)
+	}
+
+	// Records a blit from src_image into the shared image without submitting it. The caller's
+	// submission of cmd_buf must wait on and signal the returned values of the image's semaphore
+	pub fn cmd_recv_image_blit(
+		&self,
+		vk_device: &VkDevice,
+		cmd_buf: vk::CommandBuffer,
+		src_image: &vk::Image,
+		orig_src_image_layout: vk::ImageLayout,
+		target_src_image_layout: vk::ImageLayout,
+		src_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
+	) -> Result<TimelineSync, vk::Result> {
+		let sync = self.reserve_timeline(timeline_value)?;
+
+		let dst_image_extent = [
+			vk::Offset3D { x: 0, y: 0, z: 0 },
+			vk::Offset3D {
+				x: self.data.width as i32,
+				y: self.data.height as i32,
+				z: 1,
+			},
+		];
+
+		Self::record_image_blit(
+			vk_device,
+			cmd_buf,
+			&BlitImage {
+				image: src_image,
+				orig_layout: orig_src_image_layout,
+				target_layout: target_src_image_layout,
+				extent: src_image_extent,
+			},
+			&BlitImage {
+				image: &self.image,
+				orig_layout: self.image_layout,
+				target_layout: self.image_layout,
+				extent: &dst_image_extent,
+			},
+		);
+
+		Ok(sync)
 	}
 }
 
@@ -541,6 +912,7 @@ impl ImageBlit for VkSharedImage {
 		orig_dst_image_layout: vk::ImageLayout,
 		target_dst_image_layout: vk::ImageLayout,
 		dst_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let src_image_extent = [
@@ -552,16 +924,21 @@ impl ImageBlit for VkSharedImage {
 			},
 		];
 
-		Self::image_blit(
+		self.image_blit(
 			vk_device,
-			&self.image,
-			self.image_layout,
-			self.image_layout,
-			&src_image_extent,
-			dst_image,
-			orig_dst_image_layout,
-			target_dst_image_layout,
-			dst_image_extent,
+			&BlitImage {
+				image: &self.image,
+				orig_layout: self.image_layout,
+				target_layout: self.image_layout,
+				extent: &src_image_extent,
+			},
+			&BlitImage {
+				image: dst_image,
+				orig_layout: orig_dst_image_layout,
+				target_layout: target_dst_image_layout,
+				extent: dst_image_extent,
+			},
+			timeline_value,
 			fence,
 		)
 	}
@@ -572,6 +949,7 @@ impl ImageBlit for VkSharedImage {
 		dst_image: &vk::Image,
 		orig_dst_image_layout: vk::ImageLayout,
 		target_dst_image_layout: vk::ImageLayout,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let dst_image_extent = [
@@ -589,6 +967,7 @@ impl ImageBlit for VkSharedImage {
 			orig_dst_image_layout,
 			target_dst_image_layout,
 			&dst_image_extent,
+			timeline_value,
 			fence,
 		)
 	}
@@ -600,6 +979,7 @@ impl ImageBlit for VkSharedImage {
 		orig_src_image_layout: vk::ImageLayout,
 		target_src_image_layout: vk::ImageLayout,
 		src_image_extent: &[vk::Offset3D; 2],
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let dst_image_extent = [
@@ -611,16 +991,21 @@ impl ImageBlit for VkSharedImage {
 			},
 		];
 
-		Self::image_blit(
+		self.image_blit(
 			vk_device,
-			src_image,
-			orig_src_image_layout,
-			target_src_image_layout,
-			&src_image_extent,
-			&self.image,
-			self.image_layout,
-			self.image_layout,
-			&dst_image_extent,
+			&BlitImage {
+				image: src_image,
+				orig_layout: orig_src_image_layout,
+				target_layout: target_src_image_layout,
+				extent: src_image_extent,
+			},
+			&BlitImage {
+				image: &self.image,
+				orig_layout: self.image_layout,
+				target_layout: self.image_layout,
+				extent: &dst_image_extent,
+			},
+			timeline_value,
 			fence,
 		)
 	}
@@ -631,6 +1016,7 @@ impl ImageBlit for VkSharedImage {
 		src_image: &vk::Image,
 		orig_src_image_layout: vk::ImageLayout,
 		target_src_image_layout: vk::ImageLayout,
+		timeline_value: &mut u64,
 		fence: vk::Fence,
 	) -> Result<(), vk::Result> {
 		let src_image_extent = [
@@ -648,6 +1034,7 @@ impl ImageBlit for VkSharedImage {
 			orig_src_image_layout,
 			target_src_image_layout,
 			&src_image_extent,
+			timeline_value,
 			fence,
 		)
 	}
@@ -658,8 +1045,12 @@ mod tests {
 	use ash::vk;
 	use std::ffi::CStr;
 
-	use super::{ImageBlit, VkSharedImage};
-	use crate::{vk_device::VkDevice, vk_instance::VkInstance};
+	use super::{ImageBlit, SharedImageData, VkSharedImage};
+	use crate::{
+		ipc::platform::{img_data::ImgFormat, ShmemDataInternal},
+		vk_device::{TimelineSync, VkDevice},
+		vk_instance::VkInstance,
+	};
 
 	fn _init_vk_device() -> (VkInstance, VkDevice) {
 		let vk_instance =
@@ -716,13 +1107,59 @@ mod tests {
 			VkSharedImage::new(&vk_instance, &vk_device, width, height, format, 0).unwrap();
 
 		let share_handle = original_image.export_handle(&vk_device).unwrap();
+		let semaphore_handle = original_image.export_semaphore_handle(&vk_device).unwrap();
 		let import_img = VkSharedImage::import_from_handle(
 			&vk_instance,
 			&vk_device,
 			share_handle,
+			Some(semaphore_handle),
 			original_image.get_image_data().clone(),
 		)
 		.unwrap();
+		assert_ne!(import_img.semaphore, vk::Semaphore::null());
+
+		import_img.destroy(&vk_device);
+		original_image.destroy(&vk_device);
+	}
+
+	#[test]
+	fn vk_shared_image_import_format() {
+		let (vk_instance, vk_device) = _init_vk_device();
+
+		let format = vk::Format::B8G8R8A8_UNORM;
+		let original_image =
+			VkSharedImage::new(&vk_instance, &vk_device, 16, 16, format, 0).unwrap();
+
+		let mut img_data = ShmemDataInternal::default();
+		img_data.width = 16;
+		img_data.height = 16;
+		img_data.format = ImgFormat::B8G8R8A8;
+		img_data.allocation_size = original_image.get_image_data().allocation_size;
+		let image_data = SharedImageData::from_shmem_img_data(&img_data);
+		assert_eq!(image_data.format, format);
+
+		let import_img = VkSharedImage::import_from_handle(
+			&vk_instance,
+			&vk_device,
+			original_image.export_handle(&vk_device).unwrap(),
+			None,
+			image_data.clone(),
+		)
+		.unwrap();
+		assert_eq!(import_img.get_image_data().format, format);
+
+		// Images that don't fit into the exported memory are rejected
+		let mut large_data = image_data;
+		large_data.width = 4096;
+		large_data.height = 4096;
+		let res = VkSharedImage::import_from_handle(
+			&vk_instance,
+			&vk_device,
+			original_image.export_handle(&vk_device).unwrap(),
+			None,
+			large_data,
+		);
+		assert_eq!(res.err(), Some(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE));
 
 		import_img.destroy(&vk_device);
 		original_image.destroy(&vk_device);
@@ -741,20 +1178,86 @@ mod tests {
 			VkSharedImage::new(&vk_instance, &vk_device, width, height, format, 0).unwrap();
 
 		let fence = vk_device.create_fence(None).unwrap();
+		let mut timeline_value = 0;
 		src_image
 			.send_image_blit(
 				&vk_device,
 				&dst_image.image,
 				dst_image.image_layout,
 				dst_image.image_layout,
+				&mut timeline_value,
 				fence,
 			)
 			.unwrap();
+		assert_eq!(timeline_value, 1);
+		src_image.wait_timeline(&vk_device, timeline_value).unwrap();
+		unsafe {
+			vk_device
+				.device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap()
+		};
 		vk_device.destroy_fence(fence);
 
 		dst_image.destroy(&vk_device);
 		src_image.destroy(&vk_device);
 	}
+
+	#[test]
+	fn vk_shared_image_cmd_blit() {
+		let (vk_instance, vk_device) = _init_vk_device();
+
+		let width: u32 = 1;
+		let height: u32 = 2;
+		let format = vk::Format::R8G8B8A8_UNORM;
+		let src_image =
+			VkSharedImage::new(&vk_instance, &vk_device, width, height, format, 0).unwrap();
+		let dst_image =
+			VkSharedImage::new(&vk_instance, &vk_device, width, height, format, 0).unwrap();
+
+		let expected_sync = TimelineSync {
+			semaphore: src_image.semaphore,
+			wait_value: 0,
+			signal_value: 1,
+		};
+		let dst_image_extent = [
+			vk::Offset3D { x: 0, y: 0, z: 0 },
+			vk::Offset3D {
+				x: width as i32,
+				y: height as i32,
+				z: 1,
+			},
+		];
+
+		let cmd_buf = vk_device.allocate_command_buffer().unwrap();
+		let mut timeline_value = 0;
+		vk_device
+			.immediate_submit_with_timeline(
+				cmd_buf,
+				|cmd_buf| {
+					let sync = src_image.cmd_send_image_blit(
+						&vk_device,
+						cmd_buf,
+						&dst_image.image,
+						dst_image.image_layout,
+						dst_image.image_layout,
+						&dst_image_extent,
+						&mut timeline_value,
+					)?;
+					assert_eq!(sync.wait_value, expected_sync.wait_value);
+					assert_eq!(sync.signal_value, expected_sync.signal_value);
+					Ok(())
+				},
+				&[expected_sync],
+			)
+			.unwrap();
+		assert_eq!(timeline_value, 1);
+		src_image.wait_timeline(&vk_device, timeline_value).unwrap();
+		vk_device.free_command_buffer(cmd_buf);
+
+		dst_image.destroy(&vk_device);
+		src_image.destroy(&vk_device);
+	}
 }
 
 // #[cfg(test)]
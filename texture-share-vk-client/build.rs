@@ -19,6 +19,7 @@ fn main() {
 		"VkImage",
 		"VkImageLayout",
 		"VkOffset3D",
+		"VkSemaphore",
 	]
 	.into_iter()
 	.map(|x| x.to_string())
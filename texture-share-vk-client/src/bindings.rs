@@ -7,8 +7,15 @@ use std::{
 use texture_share_vk_base::{
 	ash::vk,
 	bindings::vk_setup_from_c,
-	ipc::platform::{img_data::ImgFormat, ReadLockGuard, ShmemDataInternal},
-	vk_device::VkDevice,
+	ipc::{
+		platform::{
+			img_data::{ImgData, ImgFormat},
+			ipc_commands::{ImageEvent, ImageEventKind, ImageListEntry},
+			FrameInfo, ReadLockGuard, ShmemDataInternal,
+		},
+		uuid,
+	},
+	vk_device::{TimelineSync, VkDevice},
 	vk_instance::VkInstance,
 	vk_setup::VkSetup,
 };
@@ -19,6 +26,8 @@ type VkFence = vk::Fence;
 type VkOffset3D = vk::Offset3D;
 type VkImage = vk::Image;
 type VkImageLayout = vk::ImageLayout;
+type VkCommandBuffer = vk::CommandBuffer;
+type VkSemaphore = vk::Semaphore;
 
 #[repr(C)]
 enum ImageLookupResult {
@@ -47,6 +56,133 @@ impl<'a> ClientImageDataGuard<'a> {
 	}
 }
 
+// Entry returned by the image list iterator. gpu_device_uuids points to gpu_device_count
+// uuid pairs and stays valid until the list is destroyed
+#[repr(C)]
+struct ClientImageListEntry {
+	image_name: [u8; 1024],
+	handle_id: u32,
+	width: u32,
+	height: u32,
+	format: ImgFormat,
+	gpu_device_count: u32,
+	gpu_device_uuids: *const [u64; 2],
+}
+
+#[repr(C)]
+enum ClientImageEventKind {
+	Created = 0,
+	Resized = 1,
+	Deleted = 2,
+	FrameAvailable = 3,
+}
+
+#[repr(C)]
+struct ClientImageEvent {
+	kind: ClientImageEventKind,
+	image_name: [u8; 1024],
+	handle_id: u32,
+	width: u32,
+	height: u32,
+	format: ImgFormat,
+}
+
+impl From<ImageEvent> for ClientImageEvent {
+	fn from(event: ImageEvent) -> Self {
+		ClientImageEvent {
+			kind: match event.kind {
+				ImageEventKind::Created => ClientImageEventKind::Created,
+				ImageEventKind::Resized => ClientImageEventKind::Resized,
+				ImageEventKind::Deleted => ClientImageEventKind::Deleted,
+				ImageEventKind::FrameAvailable => ClientImageEventKind::FrameAvailable,
+			},
+			image_name: ImgData::convert_shmem_str_to_array(&event.image_name).unwrap_or([0; 1024]),
+			handle_id: event.handle_id,
+			width: event.width,
+			height: event.height,
+			format: event.format,
+		}
+	}
+}
+
+// Sequence number and CLOCK_MONOTONIC timestamp in nanoseconds of a frame
+#[repr(C)]
+struct ClientFrameInfo {
+	number: u64,
+	timestamp_ns: u64,
+}
+
+impl From<FrameInfo> for ClientFrameInfo {
+	fn from(frame: FrameInfo) -> Self {
+		ClientFrameInfo {
+			number: frame.number,
+			timestamp_ns: frame.timestamp_ns,
+		}
+	}
+}
+
+// Timeline semaphore values that the submission of a recorded command buffer must wait on and
+// signal
+#[repr(C)]
+struct ClientTimelineSync {
+	semaphore: VkSemaphore,
+	wait_value: u64,
+	signal_value: u64,
+}
+
+impl From<TimelineSync> for ClientTimelineSync {
+	fn from(sync: TimelineSync) -> Self {
+		ClientTimelineSync {
+			semaphore: sync.semaphore,
+			wait_value: sync.wait_value,
+			signal_value: sync.signal_value,
+		}
+	}
+}
+
+struct ClientImageList {
+	entries: Vec<ClientImageListEntry>,
+	_gpu_device_uuids: Vec<Vec<[u64; 2]>>,
+	next: usize,
+}
+
+impl ClientImageList {
+	fn new(images: Vec<ImageListEntry>) -> ClientImageList {
+		let gpu_device_uuids: Vec<Vec<[u64; 2]>> = images
+			.iter()
+			.map(|x| {
+				x.gpu_device_uuids
+					.iter()
+					.map(|uuid| {
+						let pair = uuid::Uuid::from_u128(*uuid).as_u64_pair();
+						[pair.0, pair.1]
+					})
+					.collect()
+			})
+			.collect();
+
+		let entries = images
+			.iter()
+			.zip(gpu_device_uuids.iter())
+			.map(|(x, uuids)| ClientImageListEntry {
+				image_name: ImgData::convert_shmem_str_to_array(&x.image_name).unwrap_or([0; 1024]),
+				handle_id: x.handle_id,
+				width: x.width,
+				height: x.height,
+				format: x.format,
+				gpu_device_count: uuids.len() as u32,
+				gpu_device_uuids: uuids.as_ptr(),
+			})
+			.collect();
+
+		ClientImageList {
+			entries,
+			_gpu_device_uuids: gpu_device_uuids,
+			next: 0,
+		}
+	}
+}
+
 #[no_mangle]
 extern "C" fn vk_client_new(
 	socket_path: *const c_char,
@@ -187,6 +323,36 @@ extern "C" fn vk_client_init_image(
 	}
 }
 
+#[no_mangle]
+extern "C" fn vk_client_init_image_with_buffer_count(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	width: u32,
+	height: u32,
+	format: ImgFormat,
+	overwrite_existing: bool,
+	buffer_count: u32,
+) -> ImageLookupResult {
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.init_image_with_buffer_count(
+			&get_str(&image_name),
+			width,
+			height,
+			format,
+			overwrite_existing,
+			buffer_count,
+		) {
+		Ok(Some(true)) => return ImageLookupResult::RequiresUpdate,
+		Ok(Some(false)) => return ImageLookupResult::Found,
+		Ok(None) => return ImageLookupResult::NotFound,
+		Err(e) => {
+			println!("Failed to init image with err '{:}'", e);
+			return ImageLookupResult::Error;
+		}
+	}
+}
+
 #[no_mangle]
 extern "C" fn vk_client_find_image(
 	vk_client: *mut VkClient,
@@ -208,6 +374,78 @@ extern "C" fn vk_client_find_image(
 	}
 }
 
+#[no_mangle]
+extern "C" fn vk_client_rename_image(
+	vk_client: *mut VkClient,
+	old_image_name: *const c_char,
+	new_image_name: *const c_char,
+) -> ImageLookupResult {
+	let res = unsafe { vk_client.as_mut() }
+		.unwrap()
+		.rename_image(&get_str(&old_image_name), &get_str(&new_image_name));
+
+	match res {
+		Ok(Some(())) => return ImageLookupResult::Found,
+		Ok(None) => return ImageLookupResult::NotFound,
+		Err(e) => {
+			println!("Failed to rename image with err '{:}'", e);
+			return ImageLookupResult::Error;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_delete_image(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+) -> ImageLookupResult {
+	let res = unsafe { vk_client.as_mut() }
+		.unwrap()
+		.delete_image(&get_str(&image_name));
+
+	match res {
+		Ok(Some(())) => return ImageLookupResult::Found,
+		Ok(None) => return ImageLookupResult::NotFound,
+		Err(e) => {
+			println!("Failed to delete image with err '{:}'", e);
+			return ImageLookupResult::Error;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_list_images(vk_client: *mut VkClient) -> *mut ClientImageList {
+	match unsafe { vk_client.as_mut() }.unwrap().list_images() {
+		Ok(Some(images)) => Box::into_raw(Box::new(ClientImageList::new(images))),
+		Ok(None) => null_mut(),
+		Err(e) => {
+			println!("Failed to list images with err '{:}'", e);
+			null_mut()
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_image_list_next(
+	image_list: *mut ClientImageList,
+) -> *const ClientImageListEntry {
+	let image_list = unsafe { image_list.as_mut() }.unwrap();
+	match image_list.entries.get(image_list.next) {
+		Some(entry) => {
+			image_list.next += 1;
+			entry
+		}
+		None => ptr::null(),
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_image_list_destroy(image_list: Option<NonNull<ClientImageList>>) {
+	if let Some(image_list) = image_list {
+		drop(unsafe { Box::from_raw(image_list.as_ptr()) });
+	}
+}
+
 #[no_mangle]
 extern "C" fn vk_client_find_image_data<'a>(
 	vk_client: *mut VkClient,
@@ -325,3 +563,346 @@ extern "C" fn vk_client_recv_image(
 		}
 	}
 }
+
+#[no_mangle]
+extern "C" fn vk_client_cmd_send_image(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	cmd_buf: VkCommandBuffer,
+	image: VkImage,
+	orig_layout: VkImageLayout,
+	target_layout: VkImageLayout,
+	extents: Option<NonNull<VkOffset3D>>,
+	timeline_sync: *mut ClientTimelineSync,
+) -> c_int {
+	let vk_client = unsafe { vk_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+	let extents = extents.map(|s| unsafe { &*(s.as_ptr() as *const [VkOffset3D; 2]) });
+
+	match vk_client.cmd_send_image(
+		image_name,
+		cmd_buf,
+		image,
+		orig_layout,
+		target_layout,
+		extents,
+	) {
+		Ok(Some(sync)) => {
+			unsafe { *timeline_sync = sync.into() };
+			return 1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to record image send with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Publishes the frame recorded by vk_client_cmd_send_image once its command buffer was submitted.
+// Returns 1 on success, 0 if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_cmd_send_image_submitted(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+) -> c_int {
+	let vk_client = unsafe { vk_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+
+	match vk_client.cmd_send_image_submitted(image_name) {
+		Ok(Some(_)) => return 1,
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to publish recorded image send with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Releases the slot reserved by vk_client_cmd_send_image if its command buffer won't be submitted.
+// Returns 1 on success, 0 if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_cmd_send_image_discarded(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+) -> c_int {
+	let vk_client = unsafe { vk_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+
+	match vk_client.cmd_send_image_discarded(image_name) {
+		Ok(Some(_)) => return 1,
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to discard recorded image send with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_cmd_recv_image(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	cmd_buf: VkCommandBuffer,
+	image: VkImage,
+	orig_layout: VkImageLayout,
+	target_layout: VkImageLayout,
+	extents: Option<NonNull<VkOffset3D>>,
+	timeline_sync: *mut ClientTimelineSync,
+) -> c_int {
+	let vk_client = unsafe { vk_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+	let extents = extents.map(|s| unsafe { &*(s.as_ptr() as *const [VkOffset3D; 2]) });
+
+	match vk_client.cmd_recv_image(
+		image_name,
+		cmd_buf,
+		image,
+		orig_layout,
+		target_layout,
+		extents,
+	) {
+		Ok(Some(sync)) => {
+			unsafe { *timeline_sync = sync.into() };
+			return 1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to record image receive with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Copies the tightly packed pixels of image_name into pixels, which holds pixels_size bytes.
+// Returns 1 on success, 0 if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_read_pixels(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	pixels: *mut u8,
+	pixels_size: usize,
+) -> c_int {
+	let vk_client = unsafe { vk_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+
+	match vk_client.read_pixels(image_name) {
+		Ok(Some(data)) if data.len() <= pixels_size => {
+			unsafe { ptr::copy_nonoverlapping(data.as_ptr(), pixels, data.len()) };
+			return 1;
+		}
+		Ok(Some(data)) => {
+			println!(
+				"Failed to read pixels, {} bytes are required but only {} are available",
+				data.len(),
+				pixels_size
+			);
+			return -1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to read pixels with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Writes pixels_size bytes with rows that are stride bytes apart into image_name. A stride of 0
+// means the rows are tightly packed. Returns 1 on success, 0 if the image wasn't found, -1 on
+// failure
+#[no_mangle]
+extern "C" fn vk_client_write_pixels(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	pixels: *const u8,
+	pixels_size: usize,
+	stride: usize,
+) -> c_int {
+	let vk_client = unsafe { vk_client.as_mut() }.unwrap();
+	let image_name = &get_str(&image_name);
+	let pixels = match pixels.is_null() {
+		true => &[],
+		false => unsafe { std::slice::from_raw_parts(pixels, pixels_size) },
+	};
+
+	match vk_client.write_pixels(image_name, pixels, stride) {
+		Ok(Some(_)) => return 1,
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to write pixels with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_set_wait_for_copy(vk_client: *mut VkClient, wait_for_copy: bool) {
+	unsafe { vk_client.as_mut() }
+		.unwrap()
+		.set_wait_for_copy(wait_for_copy);
+}
+
+// Share images through RAM if GPU memory can't be shared with the server.
+// Returns 1 on success, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_set_use_ram_images(vk_client: *mut VkClient, use_ram_images: bool) -> c_int {
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.set_use_ram_images(use_ram_images)
+	{
+		Ok(_) => 1,
+		Err(e) => {
+			println!("Failed to set RAM image mode with error '{:}'", e);
+			-1
+		}
+	}
+}
+
+// Returns 1 if an outstanding copy completed, 0 if none completed yet, -1 if it failed
+#[no_mangle]
+extern "C" fn vk_client_poll_copy_image(vk_client: *mut VkClient) -> c_int {
+	match unsafe { vk_client.as_mut() }.unwrap().poll_copy_image() {
+		Ok(Some(res)) if res.success => return 1,
+		Ok(Some(res)) => {
+			if let Some(err) = res.error {
+				println!("Failed to copy image with error '{:}'", err);
+			}
+			return -1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to poll image copy with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Subscribes to events of image_name, or of all images if image_name is NULL.
+// Returns 1 on success, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_subscribe(vk_client: *mut VkClient, image_name: *const c_char) -> c_int {
+	let image_name = match image_name.is_null() {
+		true => None,
+		false => Some(get_str(&image_name)),
+	};
+
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.subscribe(image_name.as_deref())
+	{
+		Ok(Some(())) => return 1,
+		Ok(None) => return -1,
+		Err(e) => {
+			println!("Failed to subscribe with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+#[no_mangle]
+extern "C" fn vk_client_unsubscribe(vk_client: *mut VkClient, image_name: *const c_char) -> c_int {
+	let image_name = match image_name.is_null() {
+		true => None,
+		false => Some(get_str(&image_name)),
+	};
+
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.unsubscribe(image_name.as_deref())
+	{
+		Ok(Some(())) => return 1,
+		Ok(None) => return -1,
+		Err(e) => {
+			println!("Failed to unsubscribe with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Returns 1 if an event was written to event, 0 if no event is queued, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_poll_event(
+	vk_client: *mut VkClient,
+	event: *mut ClientImageEvent,
+) -> c_int {
+	match unsafe { vk_client.as_mut() }.unwrap().poll_event() {
+		Ok(Some(res)) => {
+			unsafe { event.write(res.into()) };
+			return 1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to poll image events with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Writes the latest frame of image_name to frame. Returns 1 on success, 0 if the image wasn't
+// found, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_last_frame(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	frame: *mut ClientFrameInfo,
+) -> c_int {
+	let image_name = &get_str(&image_name);
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.last_frame(image_name)
+	{
+		Ok(Some(res)) => {
+			unsafe { frame.write(res.into()) };
+			return 1;
+		}
+		Ok(None) => return 0,
+		Err(e) => {
+			println!("Failed to get last frame with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Returns 1 if a frame was published since the client last sent or received image_name, 0 if
+// not or if the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_has_new_frame(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+) -> c_int {
+	let image_name = &get_str(&image_name);
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.has_new_frame(image_name)
+	{
+		Ok(Some(true)) => return 1,
+		Ok(_) => return 0,
+		Err(e) => {
+			println!("Failed to check for new frames with error '{:}'", e);
+			return -1;
+		}
+	}
+}
+
+// Sleeps until a frame was published that the client didn't send or receive yet. Returns 1 if a
+// new frame is available, 0 if the timeout expired or the image wasn't found, -1 on failure
+#[no_mangle]
+extern "C" fn vk_client_wait_for_frame(
+	vk_client: *mut VkClient,
+	image_name: *const c_char,
+	timeout_in_millis: u64,
+) -> c_int {
+	let image_name = &get_str(&image_name);
+	let timeout = Duration::from_millis(timeout_in_millis);
+	match unsafe { vk_client.as_mut() }
+		.unwrap()
+		.wait_for_frame(image_name, timeout)
+	{
+		Ok(Some(true)) => return 1,
+		Ok(_) => return 0,
+		Err(e) => {
+			println!("Failed to wait for frame with error '{:}'", e);
+			return -1;
+		}
+	}
+}
@@ -1,25 +1,213 @@
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
 use std::io::{Error, ErrorKind};
-use std::{mem::ManuallyDrop, os::fd::OwnedFd, time::Duration};
+use std::{os::fd::OwnedFd, time::Duration};
 
 use texture_share_vk_base::ash::vk;
 use texture_share_vk_base::ipc::platform::daemon_launch::server_connect_and_daemon_launch;
 use texture_share_vk_base::ipc::platform::img_data::{ImgData, ImgFormat};
 use texture_share_vk_base::ipc::platform::ipc_commands::{
-	CommCopyImage, CommFindImage, CommInitImage, CommandData, CommandMsg, CommandTag,
+	features, ClientKind, CommCopyImage, CommDeleteImage, CommFindImage, CommInitImage,
+	CommListImages, CommRamTransfer, CommRenameImage, CommSubscribe, CommandMsg, ErrorCode,
+	ImageEvent, ImageListEntry, RamTransferDirection, ResultCopyImage, ResultError, ResultMsg,
 };
-use texture_share_vk_base::ipc::platform::ShmemDataInternal;
+use texture_share_vk_base::ipc::platform::{monotonic_time_ns, FrameInfo, ShmemDataInternal};
 use texture_share_vk_base::ipc::platform::{ReadLockGuard, Timeout};
-use texture_share_vk_base::ipc::{IpcConnection, IpcShmem};
+use texture_share_vk_base::ipc::{IpcConnection, IpcShmem, RamImage};
 
-use texture_share_vk_base::vk_device::VkDevice;
+use texture_share_vk_base::uuid;
+use texture_share_vk_base::vk_cpu_buffer::VkCpuBuffer;
+use texture_share_vk_base::vk_device::{TimelineSync, VkDevice};
 use texture_share_vk_base::vk_setup::VkSetup;
 use texture_share_vk_base::vk_shared_image::VkSharedImage;
 use texture_share_vk_base::vk_shared_image::{ImageBlit, SharedImageData};
 
 pub struct ImageData {
 	pub ipc_info: IpcShmem,
-	pub vk_shared_image: VkSharedImage,
+	// Ring of images, indexed by the slots in the shared memory. Empty for images shared through RAM
+	pub vk_shared_images: Vec<VkSharedImage>,
+	pub ram_image: Option<RamImageData>,
+	// Number of the latest frame this client sent or received
+	received_frame: Cell<u64>,
+	// Slot recorded by VkClient::cmd_send_image that wasn't submitted yet
+	pending_send: Cell<Option<SlotAccess>>,
+}
+
+// Slot reserved by ImageData::reserve_slot
+#[derive(Clone, Copy)]
+struct SlotAccess {
+	slot: usize,
+	write: bool,
+	reserved_value: u64,
+	// Frame number at the time a slot was reserved for reading
+	read_frame: u64,
+}
+
+// Image shared through RAM, see VkClient::set_use_ram_images
+pub struct RamImageData {
+	pub ram_image: RamImage,
+	// Host visible buffer that local images are copied from and to
+	pub staging_buffer: VkCpuBuffer,
+}
+
+impl ImageData {
+	pub fn get_image_data(&self) -> &SharedImageData {
+		self.vk_shared_images[0].get_image_data()
+	}
+
+	pub fn get_image_id(&self) -> u32 {
+		match &self.ram_image {
+			Some(ram_image) => ram_image.ram_image.handle_id,
+			None => self.get_image_data().id,
+		}
+	}
+
+	pub(crate) fn destroy(self, vk_device: &VkDevice) {
+		self.vk_shared_images
+			.into_iter()
+			.for_each(|img| img.destroy(vk_device));
+		if let Some(ram_image) = self.ram_image {
+			ram_image.staging_buffer.destroy(vk_device);
+		}
+	}
+
+	// Runs blit_fcn on the slot that should be written or read next. The lock is only held to
+	// reserve and release the slot, so other clients can access the remaining slots meanwhile.
+	// The reservation is recorded for the connection with the id owner, so the server can complete
+	// it if the client dies before submitting
+	fn blit_slot<R, F: FnOnce(&VkSharedImage, &mut u64) -> Result<R, vk::Result>>(
+		&self,
+		vk_device: &VkDevice,
+		owner: u32,
+		write: bool,
+		blit_fcn: F,
+	) -> Result<R, Box<dyn std::error::Error>> {
+		let access = self.reserve_slot(owner, write)?;
+		let mut timeline_value = access.reserved_value;
+		let res = self.blit_reserved_slot(&access, &mut timeline_value, blit_fcn);
+		let released = self.release_slot(vk_device, &access, timeline_value, res.is_ok());
+		res.and_then(|r| released.map(|_| r))
+	}
+
+	fn reserve_slot(
+		&self,
+		owner: u32,
+		write: bool,
+	) -> Result<SlotAccess, Box<dyn std::error::Error>> {
+		let lock = self
+			.ipc_info
+			.acquire_lock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+		let data = IpcShmem::acquire_data(&lock);
+		let slot = match write {
+			true => data.acquire_write_slot(),
+			false => data.acquire_read_slot(),
+		};
+		Ok(SlotAccess {
+			slot,
+			write,
+			reserved_value: data.reserve_timeline_value(slot, write, owner),
+			read_frame: data.frame_number,
+		})
+	}
+
+	fn blit_reserved_slot<R, F: FnOnce(&VkSharedImage, &mut u64) -> Result<R, vk::Result>>(
+		&self,
+		access: &SlotAccess,
+		timeline_value: &mut u64,
+		blit_fcn: F,
+	) -> Result<R, Box<dyn std::error::Error>> {
+		match self.vk_shared_images.get(access.slot) {
+			Some(shared_image) => blit_fcn(shared_image, timeline_value).map_err(|e| e.into()),
+			None => Err(Error::new(
+				ErrorKind::InvalidData,
+				"Image was resized on the server, find it again",
+			)
+			.into()),
+		}
+	}
+
+	// Releases a reserved slot. timeline_value is the value the access signals, which equals the
+	// reserved value if nothing was submitted. Writes are only published if success is set
+	fn release_slot(
+		&self,
+		vk_device: &VkDevice,
+		access: &SlotAccess,
+		timeline_value: u64,
+		success: bool,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let (slot, reserved_value) = (access.slot, access.reserved_value);
+
+		// Accesses that weren't submitted return their value. If later accesses were reserved
+		// meanwhile, the value is signalled instead so they don't wait for it forever
+		let mut res = Ok(());
+		if timeline_value == reserved_value {
+			let cancelled = {
+				let lock = self
+					.ipc_info
+					.acquire_lock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+				IpcShmem::acquire_data(&lock).cancel_timeline_value(slot, reserved_value)
+			};
+			if let (false, Some(shared_image)) = (cancelled, self.vk_shared_images.get(slot)) {
+				res = shared_image.skip_timeline_value(vk_device, reserved_value);
+			}
+		}
+		let success = success && res.is_ok();
+
+		let lock = self
+			.ipc_info
+			.acquire_lock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+		let data = IpcShmem::acquire_data(&lock);
+		data.complete_timeline_value(slot, reserved_value);
+		match access.write {
+			true => data.release_write_slot(slot, success),
+			false => data.release_read_slot(slot),
+		}
+		if success {
+			match access.write {
+				true => self.publish_frame(data),
+				false => self.received_frame.set(access.read_frame),
+			}
+		}
+
+		Ok(res?)
+	}
+
+	// Must be called while holding the lock
+	fn publish_frame(&self, data: &mut ShmemDataInternal) {
+		data.publish_frame(monotonic_time_ns());
+		self.received_frame.set(data.frame_number);
+	}
+
+	fn has_new_frame(&self) -> Result<bool, Box<dyn std::error::Error>> {
+		let data = self.ipc_info.snapshot(VkClient::IPC_TIMEOUT)?;
+		Ok(data.frame_number != self.received_frame.get())
+	}
+}
+
+impl RamImageData {
+	fn copy_from_staging(&mut self) {
+		let pixels = self.ram_image.as_mut_slice();
+		let len = pixels.len().min(self.staging_buffer.buffer_size as usize);
+		unsafe {
+			std::ptr::copy_nonoverlapping(
+				self.staging_buffer.ram_memory as *const u8,
+				pixels.as_mut_ptr(),
+				len,
+			)
+		};
+	}
+
+	fn copy_to_staging(&self) {
+		let pixels = self.ram_image.as_slice();
+		let len = pixels.len().min(self.staging_buffer.buffer_size as usize);
+		unsafe {
+			std::ptr::copy_nonoverlapping(
+				pixels.as_ptr(),
+				self.staging_buffer.ram_memory as *mut u8,
+				len,
+			)
+		};
+	}
 }
 
 pub struct VkClient {
@@ -27,6 +215,13 @@ pub struct VkClient {
 	vk_setup: Box<VkSetup>,
 	shared_images: HashMap<String, ImageData>,
 	gpu_device_uuid: u128,
+	wait_for_copy: bool,
+	// Number of copy commands whose result wasn't received yet
+	pending_copies: usize,
+	// Copy results received while polling for events
+	copy_results: VecDeque<ResultMsg>,
+	events: VecDeque<ImageEvent>,
+	use_ram_images: bool,
 }
 
 impl Drop for VkClient {
@@ -34,7 +229,7 @@ impl Drop for VkClient {
 		// Ensure that images are cleared before destroying vulkan instance
 		self.shared_images
 			.drain()
-			.for_each(|x| x.1.vk_shared_image.destroy(&self.vk_setup.device));
+			.for_each(|x| x.1.destroy(&self.vk_setup.device));
 	}
 }
 
@@ -54,6 +249,9 @@ impl VkClient {
 			));
 		}
 
+		let connection = connection.unwrap();
+		connection.handshake(ClientKind::Vk, features::SUPPORTED)?;
+
 		let shared_images = HashMap::default();
 
 		let gpu_device_uuid = VkDevice::get_gpu_device_uuid(
@@ -63,10 +261,15 @@ impl VkClient {
 		.as_u128();
 
 		Ok(VkClient {
-			connection: connection.unwrap(),
+			connection,
 			vk_setup,
 			shared_images,
 			gpu_device_uuid,
+			wait_for_copy: true,
+			pending_copies: 0,
+			copy_results: VecDeque::new(),
+			events: VecDeque::new(),
+			use_ram_images: false,
 		})
 	}
 
@@ -119,11 +322,18 @@ impl VkClient {
 		)?;
 
 		if let Some(connection) = res {
+			connection.handshake(ClientKind::Vk, features::SUPPORTED)?;
+
 			return Ok(VkClient {
 				connection,
 				vk_setup,
 				shared_images: HashMap::default(),
 				gpu_device_uuid: gpu_device_uuid.as_u128(),
+				wait_for_copy: true,
+				pending_copies: 0,
+				copy_results: VecDeque::new(),
+				events: VecDeque::new(),
+				use_ram_images: false,
 			});
 		} else {
 			return Err(Error::new(
@@ -141,8 +351,41 @@ impl VkClient {
 		&mut self.vk_setup
 	}
 
-	fn is_update_available(image_data: &ImageData) -> bool {
-		image_data.ipc_info.get_id_unchecked() != image_data.vk_shared_image.get_image_data().id
+	// Share images through RAM instead of GPU memory. Use this if the server can't access this
+	// client's GPU, e.g. in VMs or if producer and consumer run on incompatible GPUs. Local images
+	// must then have the same format as the shared images. Images found before are dropped
+	pub fn set_use_ram_images(&mut self, use_ram_images: bool) -> Result<(), Error> {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		if use_ram_images && features & features::RAM_IMAGES == 0 {
+			return Err(Error::new(
+				ErrorKind::Unsupported,
+				"Server does not support sharing images through RAM",
+			));
+		}
+
+		if use_ram_images != self.use_ram_images {
+			self.shared_images
+				.drain()
+				.for_each(|x| x.1.destroy(&self.vk_setup.device));
+		}
+		self.use_ram_images = use_ram_images;
+
+		Ok(())
+	}
+
+	// Images shared through RAM are requested with a nil GPU uuid
+	fn requested_device_uuid(&self) -> u128 {
+		match self.use_ram_images {
+			true => uuid::Uuid::nil().as_u128(),
+			false => self.gpu_device_uuid,
+		}
+	}
+
+	fn is_update_available(image_data: &ImageData) -> Result<bool, Box<dyn std::error::Error>> {
+		let id = image_data
+			.ipc_info
+			.get_id_unchecked(VkClient::IPC_TIMEOUT)?;
+		Ok(id != image_data.get_image_id())
 	}
 
 	pub fn init_image(
@@ -153,37 +396,47 @@ impl VkClient {
 		format: ImgFormat,
 		overwrite_existing: bool,
 	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
-		let image_name_buf = ImgData::convert_shmem_str_to_array(image_name);
-		let cmd_msg = CommandMsg {
-			tag: CommandTag::InitImage,
-			data: CommandData {
-				init_img: ManuallyDrop::new(CommInitImage {
-					image_name: image_name_buf,
-					shmem_name: image_name_buf,
-					width,
-					height,
-					format,
-					overwrite_existing,
-					gpu_device_uuid: self.gpu_device_uuid,
-				}),
-			},
-		};
+		self.init_image_with_buffer_count(image_name, width, height, format, overwrite_existing, 1)
+	}
 
-		self.connection.send_command(cmd_msg)?;
+	// Allocates a ring of buffer_count images, so that producers and consumers don't access the
+	// same image at once
+	pub fn init_image_with_buffer_count(
+		&mut self,
+		image_name: &str,
+		width: u32,
+		height: u32,
+		format: ImgFormat,
+		overwrite_existing: bool,
+		buffer_count: u32,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		ImgData::validate_name(image_name)?;
+		let cmd_msg = CommandMsg::InitImage(CommInitImage {
+			image_name: image_name.to_string(),
+			shmem_name: image_name.to_string(),
+			width,
+			height,
+			format,
+			overwrite_existing,
+			gpu_device_uuid: self.requested_device_uuid(),
+			buffer_count,
+		});
+
+		self.send_command(cmd_msg)?;
 
 		// Receive message and check for validity
-		let res_msg = self.connection.recv_result()?;
+		let res_msg = self.recv_result(true)?;
 		let res_data: Option<&ImgData> = match &res_msg {
 			None => Ok(None),
-			Some(msg) => match msg.tag {
-				CommandTag::InitImage => {
-					let data = unsafe { &msg.data.init_img };
+			Some(msg) => match msg {
+				ResultMsg::InitImage(data) => {
 					if data.image_created {
 						Ok(Some(&data.img_data))
 					} else {
 						Ok(None)
 					}
 				}
+				ResultMsg::Error(err) => return Err(Box::new(err.clone())),
 				_ => Err(Box::new(Error::new(
 					ErrorKind::InvalidData,
 					"Received invalid data from server",
@@ -198,14 +451,16 @@ impl VkClient {
 
 		let res_data = res_data.unwrap();
 
-		let mut share_handles = self.connection.recv_ancillary(1)?;
+		let share_handles = self
+			.connection
+			.recv_ancillary(self.share_handle_count(res_data))?;
 
 		self.connection.send_ack()?;
 
-		let res = self.add_new_image(&res_data, &mut share_handles)?;
+		let res = self.add_new_image(&res_data, share_handles)?;
 
 		let res = match res {
-			Some(r) => Some(VkClient::is_update_available(r)),
+			Some(r) => Some(VkClient::is_update_available(r)?),
 			None => None,
 		};
 		Ok(res)
@@ -218,7 +473,7 @@ impl VkClient {
 	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
 		let res = self.find_image_internal(image_name, force_update)?;
 		let res = match res {
-			Some(r) => Some(VkClient::is_update_available(r)),
+			Some(r) => Some(VkClient::is_update_available(r)?),
 			None => None,
 		};
 		Ok(res)
@@ -243,6 +498,55 @@ impl VkClient {
 		Ok(res)
 	}
 
+	// Sequence number and producer timestamp of the latest frame, read without taking the lock.
+	// Images shared through RAM only count frames of producers on a GPU once they were received
+	pub fn last_frame(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<FrameInfo>, Box<dyn std::error::Error>> {
+		let res = self.find_image_internal(image_name, false)?;
+		let res = match res {
+			Some(image_data) => Some(image_data.ipc_info.snapshot(VkClient::IPC_TIMEOUT)?.frame()),
+			None => None,
+		};
+		Ok(res)
+	}
+
+	// Whether a frame was published since this client last sent or received the image
+	pub fn has_new_frame(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		let res = self.find_image_internal(image_name, false)?;
+		let res = match res {
+			Some(image_data) => Some(image_data.has_new_frame()?),
+			None => None,
+		};
+		Ok(res)
+	}
+
+	// Sleeps until a frame was published that this client didn't send or receive yet, so that
+	// consumers don't need to poll. Returns false if the timeout expired first. Images shared
+	// through RAM only see frames of producers on a GPU once they were received, see last_frame
+	pub fn wait_for_frame(
+		&mut self,
+		image_name: &str,
+		timeout: Duration,
+	) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+		let res = self.find_image_internal(image_name, false)?;
+		let res = match res {
+			Some(image_data) => Some(
+				image_data
+					.ipc_info
+					.wait_for_frame(image_data.received_frame.get(), timeout)?,
+			),
+			None => None,
+		};
+		Ok(res)
+	}
+
+	// The fence is signalled once the copy finished, the client doesn't wait for it. Accesses from
+	// other processes are ordered through the image's timeline semaphore
 	pub fn send_image(
 		&mut self,
 		image_name: &str,
@@ -251,6 +555,10 @@ impl VkClient {
 		target_layout: vk::ImageLayout,
 		fence: vk::Fence,
 	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		if self.is_ram_image(image_name) {
+			return self.ram_send_image(image_name, image, orig_layout, target_layout, fence);
+		}
+
 		let remote_image = self.shared_images.get_mut(image_name);
 		if remote_image.is_none() {
 			return Ok(None);
@@ -258,14 +566,24 @@ impl VkClient {
 
 		// Send image
 		let remote_image = remote_image.unwrap();
-		remote_image.vk_shared_image.recv_image_blit(
+		remote_image.blit_slot(
 			&self.vk_setup.device,
-			&image,
-			orig_layout,
-			target_layout,
-			fence,
+			self.connection.get_id(),
+			true,
+			|shared_image, timeline_value| {
+				shared_image.recv_image_blit(
+					&self.vk_setup.device,
+					&image,
+					orig_layout,
+					target_layout,
+					timeline_value,
+					fence,
+				)
+			},
 		)?;
 
+		self.copy_image_cmd(image_name)?;
+
 		Ok(Some(()))
 	}
 
@@ -299,6 +617,7 @@ impl VkClient {
 		fence: vk::Fence,
 		extents: *const vk::Offset3D,
 	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		VkClient::check_gpu_image(self.shared_images.get(image_name))?;
 		let remote_image = self.shared_images.get_mut(image_name);
 		if remote_image.is_none() {
 			return Ok(None);
@@ -311,13 +630,21 @@ impl VkClient {
 		};
 
 		let remote_image = remote_image.unwrap();
-		remote_image.vk_shared_image.recv_image_blit_with_extents(
+		remote_image.blit_slot(
 			&self.vk_setup.device,
-			&image,
-			orig_layout,
-			target_layout,
-			extents,
-			fence,
+			self.connection.get_id(),
+			true,
+			|shared_image, timeline_value| {
+				shared_image.recv_image_blit_with_extents(
+					&self.vk_setup.device,
+					&image,
+					orig_layout,
+					target_layout,
+					extents,
+					timeline_value,
+					fence,
+				)
+			},
 		)?;
 
 		self.copy_image_cmd(image_name)?;
@@ -333,18 +660,30 @@ impl VkClient {
 		target_layout: vk::ImageLayout,
 		fence: vk::Fence,
 	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		if self.is_ram_image(image_name) {
+			return self.ram_recv_image(image_name, image, orig_layout, target_layout, fence);
+		}
+
 		let remote_image = self.shared_images.get_mut(image_name);
 		if remote_image.is_none() {
 			return Ok(None);
 		}
 
 		let remote_image = remote_image.unwrap();
-		remote_image.vk_shared_image.send_image_blit(
+		remote_image.blit_slot(
 			&self.vk_setup.device,
-			&image,
-			orig_layout,
-			target_layout,
-			fence,
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.send_image_blit(
+					&self.vk_setup.device,
+					&image,
+					orig_layout,
+					target_layout,
+					timeline_value,
+					fence,
+				)
+			},
 		)?;
 
 		Ok(Some(()))
@@ -380,6 +719,7 @@ impl VkClient {
 		fence: vk::Fence,
 		extents: *const vk::Offset3D,
 	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		VkClient::check_gpu_image(self.shared_images.get(image_name))?;
 		let remote_image = self.shared_images.get_mut(image_name);
 		if remote_image.is_none() {
 			return Ok(None);
@@ -392,62 +732,860 @@ impl VkClient {
 		};
 
 		let remote_image = remote_image.unwrap();
-		remote_image.vk_shared_image.send_image_blit_with_extents(
+		remote_image.blit_slot(
+			&self.vk_setup.device,
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.send_image_blit_with_extents(
+					&self.vk_setup.device,
+					&image,
+					orig_layout,
+					target_layout,
+					extents,
+					timeline_value,
+					fence,
+				)
+			},
+		)?;
+		Ok(Some(()))
+	}
+
+	// Records the blit into cmd_buf instead of submitting it. The application's submission of
+	// cmd_buf must wait on and signal the returned timeline values. Extents default to the whole
+	// image. The frame is only published once cmd_send_image_submitted is called after the
+	// submission, or dropped with cmd_send_image_discarded if cmd_buf is never submitted
+	pub fn cmd_send_image(
+		&mut self,
+		image_name: &str,
+		cmd_buf: vk::CommandBuffer,
+		image: vk::Image,
+		orig_layout: vk::ImageLayout,
+		target_layout: vk::ImageLayout,
+		extents: Option<&[vk::Offset3D; 2]>,
+	) -> Result<Option<TimelineSync>, Box<dyn std::error::Error>> {
+		VkClient::check_gpu_image(self.shared_images.get(image_name))?;
+		let remote_image = self.shared_images.get_mut(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let remote_image = remote_image.unwrap();
+		if remote_image.pending_send.get().is_some() {
+			return Err(Box::new(Error::new(
+				ErrorKind::InvalidInput,
+				"A recorded send of the image wasn't submitted or discarded yet",
+			)));
+		}
+
+		let extents = extents
+			.copied()
+			.unwrap_or_else(|| VkClient::image_extents(remote_image.get_image_data()));
+		let access = remote_image.reserve_slot(self.connection.get_id(), true)?;
+		let mut timeline_value = access.reserved_value;
+		let res = remote_image.blit_reserved_slot(
+			&access,
+			&mut timeline_value,
+			|shared_image, timeline_value| {
+				shared_image.cmd_recv_image_blit(
+					&self.vk_setup.device,
+					cmd_buf,
+					&image,
+					orig_layout,
+					target_layout,
+					&extents,
+					timeline_value,
+				)
+			},
+		);
+
+		// The slot stays reserved until the application reports what happened to cmd_buf
+		match res {
+			Ok(sync) => {
+				remote_image.pending_send.set(Some(access));
+				Ok(Some(sync))
+			}
+			Err(e) => {
+				remote_image.release_slot(&self.vk_setup.device, &access, timeline_value, false)?;
+				Err(e)
+			}
+		}
+	}
+
+	// Publishes the frame recorded by cmd_send_image once its command buffer was submitted
+	pub fn cmd_send_image_submitted(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		let access = match self.take_pending_send(image_name)? {
+			Some(access) => access,
+			None => return Ok(None),
+		};
+
+		let remote_image = self.shared_images.get(image_name).unwrap();
+		remote_image.release_slot(
+			&self.vk_setup.device,
+			&access,
+			access.reserved_value + 1,
+			true,
+		)?;
+
+		self.copy_image_cmd(image_name)?;
+
+		Ok(Some(()))
+	}
+
+	// Releases the slot reserved by cmd_send_image if its command buffer won't be submitted
+	pub fn cmd_send_image_discarded(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		let access = match self.take_pending_send(image_name)? {
+			Some(access) => access,
+			None => return Ok(None),
+		};
+
+		let remote_image = self.shared_images.get(image_name).unwrap();
+		remote_image.release_slot(&self.vk_setup.device, &access, access.reserved_value, false)?;
+
+		Ok(Some(()))
+	}
+
+	fn take_pending_send(
+		&self,
+		image_name: &str,
+	) -> Result<Option<SlotAccess>, Box<dyn std::error::Error>> {
+		let remote_image = match self.shared_images.get(image_name) {
+			Some(remote_image) => remote_image,
+			None => return Ok(None),
+		};
+
+		match remote_image.pending_send.take() {
+			Some(access) => Ok(Some(access)),
+			None => Err(Box::new(Error::new(
+				ErrorKind::InvalidInput,
+				"No send of the image was recorded",
+			))),
+		}
+	}
+
+	pub fn cmd_recv_image(
+		&mut self,
+		image_name: &str,
+		cmd_buf: vk::CommandBuffer,
+		image: vk::Image,
+		orig_layout: vk::ImageLayout,
+		target_layout: vk::ImageLayout,
+		extents: Option<&[vk::Offset3D; 2]>,
+	) -> Result<Option<TimelineSync>, Box<dyn std::error::Error>> {
+		VkClient::check_gpu_image(self.shared_images.get(image_name))?;
+		let remote_image = self.shared_images.get_mut(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let remote_image = remote_image.unwrap();
+		let extents = extents
+			.copied()
+			.unwrap_or_else(|| VkClient::image_extents(remote_image.get_image_data()));
+		let sync = remote_image.blit_slot(
+			&self.vk_setup.device,
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.cmd_send_image_blit(
+					&self.vk_setup.device,
+					cmd_buf,
+					&image,
+					orig_layout,
+					target_layout,
+					&extents,
+					timeline_value,
+				)
+			},
+		)?;
+
+		Ok(Some(sync))
+	}
+
+	// Copies the latest frame into a tightly packed buffer, so that no local image is required
+	pub fn read_pixels(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+		if self.is_ram_image(image_name) {
+			return self.ram_read_pixels(image_name);
+		}
+
+		let remote_image = self.shared_images.get(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let remote_image = remote_image.unwrap();
+		let vk_device = &self.vk_setup.device;
+		let (staging_buffer, pixels_size) =
+			VkClient::create_staging_buffer(&self.vk_setup, remote_image.get_image_data())?;
+		let res = remote_image.blit_slot(
 			&self.vk_setup.device,
-			&image,
+			self.connection.get_id(),
+			false,
+			|shared_image, timeline_value| {
+				shared_image.read_to_cpu_buffer(vk_device, &staging_buffer, timeline_value)
+			},
+		);
+		let pixels = res.map(|_| {
+			unsafe {
+				std::slice::from_raw_parts(staging_buffer.ram_memory as *const u8, pixels_size)
+			}
+			.to_vec()
+		});
+		staging_buffer.destroy(vk_device);
+
+		Ok(Some(pixels?))
+	}
+
+	// Writes pixels into the image and copies them to other GPUs. stride is the number of bytes
+	// per row in pixels, or 0 if the rows are tightly packed
+	pub fn write_pixels(
+		&mut self,
+		image_name: &str,
+		pixels: &[u8],
+		stride: usize,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		if self.is_ram_image(image_name) {
+			return self.ram_write_pixels(image_name, pixels, stride);
+		}
+
+		let remote_image = self.shared_images.get(image_name);
+		if remote_image.is_none() {
+			return Ok(None);
+		}
+
+		let remote_image = remote_image.unwrap();
+		let vk_device = &self.vk_setup.device;
+		let image_data = remote_image.get_image_data();
+		let (staging_buffer, pixels_size) =
+			VkClient::create_staging_buffer(&self.vk_setup, image_data)?;
+		let staging_pixels = unsafe {
+			std::slice::from_raw_parts_mut(staging_buffer.ram_memory as *mut u8, pixels_size)
+		};
+		let res = VkClient::pack_rows(pixels, stride, staging_pixels, image_data.height as usize)
+			.map_err(|e| e.into())
+			.and_then(|_| {
+				remote_image.blit_slot(
+					&self.vk_setup.device,
+					self.connection.get_id(),
+					true,
+					|shared_image, timeline_value| {
+						shared_image.write_from_cpu_buffer(
+							vk_device,
+							&staging_buffer,
+							timeline_value,
+						)
+					},
+				)
+			});
+		staging_buffer.destroy(vk_device);
+		res?;
+
+		self.copy_image_cmd(image_name)?;
+
+		Ok(Some(()))
+	}
+
+	pub fn rename_image(
+		&mut self,
+		old_image_name: &str,
+		new_image_name: &str,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		ImgData::validate_name(old_image_name)?;
+		ImgData::validate_name(new_image_name)?;
+		let cmd_msg = CommandMsg::RenameImage(CommRenameImage {
+			old_image_name: old_image_name.to_string(),
+			new_image_name: new_image_name.to_string(),
+			gpu_device_uuid: self.requested_device_uuid(),
+		});
+		self.send_command(cmd_msg)?;
+
+		let res_msg = self.recv_result(true)?;
+		let res_data = match res_msg {
+			None => return Ok(None),
+			Some(ResultMsg::RenameImage(data)) => data,
+			Some(ResultMsg::Error(err)) => return Err(Box::new(err)),
+			Some(_) => {
+				return Err(Box::new(Error::new(
+					ErrorKind::InvalidData,
+					"Received invalid data from server",
+				)))
+			}
+		};
+
+		if !res_data.image_found {
+			return Ok(None);
+		}
+
+		// The image memory is unchanged, so the local image can be kept if it is still up to date
+		if let Some(mut image_data) = self.shared_images.remove(old_image_name) {
+			let shmem_name = ImgData::convert_shmem_array_to_str(&res_data.img_data.shmem_name);
+			if res_data.img_data.data.handle_id == image_data.get_image_id() {
+				// A memfd is kept across renames, only named segments are recreated
+				if !shmem_name.is_empty() {
+					image_data.ipc_info = match IpcShmem::new(&shmem_name, new_image_name, false) {
+						Ok(ipc_info) => ipc_info,
+						Err(e) => {
+							image_data.destroy(&self.vk_setup.device);
+							return Err(e);
+						}
+					};
+				}
+				self.shared_images
+					.insert(new_image_name.to_string(), image_data)
+					.map(|x| x.destroy(&self.vk_setup.device));
+			} else {
+				image_data.destroy(&self.vk_setup.device);
+			}
+		}
+
+		Ok(Some(()))
+	}
+
+	pub fn delete_image(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		ImgData::validate_name(image_name)?;
+		let cmd_msg = CommandMsg::DeleteImage(CommDeleteImage {
+			image_name: image_name.to_string(),
+		});
+		self.send_command(cmd_msg)?;
+
+		let res_msg = self.recv_result(true)?;
+		let res_data = match res_msg {
+			None => return Ok(None),
+			Some(ResultMsg::DeleteImage(data)) => data,
+			Some(ResultMsg::Error(err)) => return Err(Box::new(err)),
+			Some(_) => {
+				return Err(Box::new(Error::new(
+					ErrorKind::InvalidData,
+					"Received invalid data from server",
+				)))
+			}
+		};
+
+		self.shared_images
+			.remove(image_name)
+			.map(|x| x.destroy(&self.vk_setup.device));
+
+		match res_data.image_found {
+			true => Ok(Some(())),
+			false => Ok(None),
+		}
+	}
+
+	pub fn list_images(
+		&mut self,
+	) -> Result<Option<Vec<ImageListEntry>>, Box<dyn std::error::Error>> {
+		self.send_command(CommandMsg::ListImages(CommListImages {}))?;
+
+		let res_msg = self.recv_result(true)?;
+		match res_msg {
+			None => Ok(None),
+			Some(ResultMsg::ListImages(data)) => Ok(Some(data.images)),
+			Some(ResultMsg::Error(err)) => Err(Box::new(err)),
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	// Receive events for the given image, or for all images if image_name is None
+	pub fn subscribe(
+		&mut self,
+		image_name: Option<&str>,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		self.subscribe_cmd(image_name, true)
+	}
+
+	pub fn unsubscribe(
+		&mut self,
+		image_name: Option<&str>,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		self.subscribe_cmd(image_name, false)
+	}
+
+	// Returns the oldest queued event without blocking. Events that arrive while waiting for
+	// other replies are queued as well
+	pub fn poll_event(&mut self) -> Result<Option<ImageEvent>, Box<dyn std::error::Error>> {
+		while self.events.is_empty() {
+			match self.connection.recv_result_if_available()? {
+				None => break,
+				Some(ResultMsg::Event(event)) => self.events.push_back(event),
+				Some(res_msg @ (ResultMsg::CopyImage(_) | ResultMsg::Error(_)))
+					if self.pending_copies > self.copy_results.len() =>
+				{
+					self.copy_results.push_back(res_msg)
+				}
+				Some(_) => {
+					return Err(Box::new(Error::new(
+						ErrorKind::InvalidData,
+						"Received invalid data from server",
+					)))
+				}
+			}
+		}
+
+		Ok(self.events.pop_front())
+	}
+
+	// If disabled, send_image returns as soon as the copy to other GPUs was requested. Completion
+	// can then be checked with poll_copy_image or wait_copy_image. Any other command waits for
+	// outstanding copies first
+	pub fn set_wait_for_copy(&mut self, wait_for_copy: bool) {
+		self.wait_for_copy = wait_for_copy;
+	}
+
+	// Returns the result of the oldest outstanding copy if it has completed
+	pub fn poll_copy_image(
+		&mut self,
+	) -> Result<Option<ResultCopyImage>, Box<dyn std::error::Error>> {
+		self.recv_copy_result(false)
+	}
+
+	pub fn wait_copy_image(
+		&mut self,
+	) -> Result<Option<ResultCopyImage>, Box<dyn std::error::Error>> {
+		self.recv_copy_result(true)
+	}
+
+	fn is_ram_image(&self, image_name: &str) -> bool {
+		self.shared_images
+			.get(image_name)
+			.map_or(false, |x| x.ram_image.is_some())
+	}
+
+	// Extents and recording into command buffers require GPU memory
+	fn check_gpu_image(image_data: Option<&ImageData>) -> Result<(), Error> {
+		match image_data {
+			Some(ImageData {
+				ram_image: Some(_), ..
+			}) => Err(Error::new(
+				ErrorKind::Unsupported,
+				"Operation is not supported for images shared through RAM",
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	// Copies the local image into RAM, then has the server upload it to the GPU images
+	fn ram_send_image(
+		&mut self,
+		image_name: &str,
+		image: vk::Image,
+		orig_layout: vk::ImageLayout,
+		target_layout: vk::ImageLayout,
+		fence: vk::Fence,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		let vk_device = &self.vk_setup.device;
+		let remote_image = self.shared_images.get_mut(image_name).unwrap();
+		let ram_image = remote_image.ram_image.as_mut().unwrap();
+
+		ram_image.staging_buffer.read_image_to_cpu(
+			vk_device,
+			image,
 			orig_layout,
-			target_layout,
-			extents,
-			fence,
+			ram_image.ram_image.width,
+			ram_image.ram_image.height,
 		)?;
+		{
+			let lock = remote_image
+				.ipc_info
+				.acquire_lock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+			let data = IpcShmem::acquire_data(&lock);
+			ram_image.copy_from_staging();
+			remote_image.publish_frame(data);
+		}
+		VkClient::finish_ram_blit(vk_device, image, orig_layout, target_layout, fence)?;
+
+		self.ram_transfer_cmd(image_name, RamTransferDirection::Upload)?;
+
 		Ok(Some(()))
 	}
 
-	fn add_new_image(
+	// Has the server download the latest frame into RAM, then copies it into the local image
+	fn ram_recv_image(
+		&mut self,
+		image_name: &str,
+		image: vk::Image,
+		orig_layout: vk::ImageLayout,
+		target_layout: vk::ImageLayout,
+		fence: vk::Fence,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		self.ram_transfer_cmd(image_name, RamTransferDirection::Download)?;
+
+		let vk_device = &self.vk_setup.device;
+		let remote_image = self.shared_images.get(image_name).unwrap();
+		let ram_image = remote_image.ram_image.as_ref().unwrap();
+		{
+			let rlock = remote_image
+				.ipc_info
+				.acquire_rlock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+			let rdata = IpcShmem::acquire_rdata(&rlock);
+			ram_image.copy_to_staging();
+			remote_image.received_frame.set(rdata.frame_number);
+		}
+
+		ram_image.staging_buffer.write_image_from_cpu(
+			vk_device,
+			image,
+			orig_layout,
+			ram_image.ram_image.width,
+			ram_image.ram_image.height,
+		)?;
+		VkClient::finish_ram_blit(vk_device, image, orig_layout, target_layout, fence)?;
+
+		Ok(Some(()))
+	}
+
+	fn ram_read_pixels(
+		&mut self,
+		image_name: &str,
+	) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+		self.ram_transfer_cmd(image_name, RamTransferDirection::Download)?;
+
+		let remote_image = self.shared_images.get(image_name).unwrap();
+		let rlock = remote_image
+			.ipc_info
+			.acquire_rlock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+		let rdata = IpcShmem::acquire_rdata(&rlock);
+		let ram_image = &remote_image.ram_image.as_ref().unwrap().ram_image;
+		remote_image.received_frame.set(rdata.frame_number);
+
+		Ok(Some(ram_image.as_slice().to_vec()))
+	}
+
+	fn ram_write_pixels(
+		&mut self,
+		image_name: &str,
+		pixels: &[u8],
+		stride: usize,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		{
+			let remote_image = self.shared_images.get_mut(image_name).unwrap();
+			let lock = remote_image
+				.ipc_info
+				.acquire_lock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
+			let data = IpcShmem::acquire_data(&lock);
+			let ram_image = &mut remote_image.ram_image.as_mut().unwrap().ram_image;
+			let height = ram_image.height as usize;
+			VkClient::pack_rows(pixels, stride, ram_image.as_mut_slice(), height)?;
+			remote_image.publish_frame(data);
+		}
+
+		self.ram_transfer_cmd(image_name, RamTransferDirection::Upload)?;
+
+		Ok(Some(()))
+	}
+
+	// Host visible buffer for the tightly packed pixels of an image, along with their size
+	fn create_staging_buffer(
+		vk_setup: &VkSetup,
+		image_data: &SharedImageData,
+	) -> Result<(VkCpuBuffer, usize), Box<dyn std::error::Error>> {
+		let format = VkSharedImage::get_img_format(image_data.format);
+		let pixels_size =
+			image_data.width as usize * image_data.height as usize * format.bytes_per_pixel();
+		if pixels_size == 0 {
+			return Err(Box::new(Error::new(
+				ErrorKind::Unsupported,
+				format!("Can't access pixels of {:?} image", format),
+			)));
+		}
+
+		let staging_buffer = VkCpuBuffer::new(
+			&vk_setup.instance,
+			&vk_setup.device,
+			pixels_size as u64,
+			None,
+		)?;
+		Ok((staging_buffer, pixels_size))
+	}
+
+	// Copies rows of stride bytes into the tightly packed dst
+	fn pack_rows(src: &[u8], stride: usize, dst: &mut [u8], height: usize) -> Result<(), Error> {
+		let row_size = match height {
+			0 => 0,
+			_ => dst.len() / height,
+		};
+		let stride = match stride {
+			0 => row_size,
+			_ => stride,
+		};
+		if stride < row_size || (height > 0 && src.len() < stride * (height - 1) + row_size) {
+			return Err(Error::new(
+				ErrorKind::InvalidInput,
+				format!(
+					"{} bytes with a stride of {} are too few for {} rows of {} bytes",
+					src.len(),
+					stride,
+					height,
+					row_size
+				),
+			));
+		}
+
+		for (row, dst_row) in dst.chunks_exact_mut(row_size.max(1)).take(height).enumerate() {
+			dst_row.copy_from_slice(&src[row * stride..row * stride + dst_row.len()]);
+		}
+
+		Ok(())
+	}
+
+	// RAM copies complete before returning, so only the layout transition and the fence are left
+	fn finish_ram_blit(
+		vk_device: &VkDevice,
+		image: vk::Image,
+		orig_layout: vk::ImageLayout,
+		target_layout: vk::ImageLayout,
+		fence: vk::Fence,
+	) -> Result<(), vk::Result> {
+		if orig_layout != target_layout {
+			let transition_fcn = |cmd_buf: vk::CommandBuffer| {
+				let img_mem_barrier = VkSharedImage::gen_img_mem_barrier(
+					image,
+					orig_layout,
+					target_layout,
+					vk::AccessFlags::NONE,
+					vk::AccessFlags::NONE,
+				);
+				unsafe {
+					vk_device.device.cmd_pipeline_barrier(
+						cmd_buf,
+						vk::PipelineStageFlags::TOP_OF_PIPE,
+						vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+						vk::DependencyFlags::default(),
+						&[],
+						&[],
+						&[img_mem_barrier],
+					)
+				};
+				Ok(())
+			};
+			vk_device.immediate_submit(vk_device.command_buffer, transition_fcn, &[], &[])?;
+		}
+
+		if fence != vk::Fence::null() {
+			unsafe {
+				vk_device
+					.device
+					.queue_submit(vk_device.graphics_queue, &[], fence)
+			}?;
+		}
+
+		Ok(())
+	}
+
+	fn ram_transfer_cmd(
 		&mut self,
+		image_name: &str,
+		direction: RamTransferDirection,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		self.send_command(CommandMsg::RamTransfer(CommRamTransfer {
+			image_name: image_name.to_string(),
+			direction,
+		}))?;
+
+		let res_msg = self.recv_result(true)?;
+		match res_msg {
+			None => Err(Box::new(Error::new(
+				ErrorKind::TimedOut,
+				"Timed out while waiting for RAM transfer",
+			))),
+			Some(ResultMsg::RamTransfer(data)) => match data.success {
+				true => Ok(()),
+				false => Err(Box::new(data.error.unwrap_or_else(|| {
+					ResultError::new(ErrorCode::Unknown, "RAM transfer failed")
+				}))),
+			},
+			Some(ResultMsg::Error(err)) => Err(Box::new(err)),
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	fn image_extents(data: &SharedImageData) -> [vk::Offset3D; 2] {
+		[
+			vk::Offset3D { x: 0, y: 0, z: 0 },
+			vk::Offset3D {
+				x: data.width as i32,
+				y: data.height as i32,
+				z: 1,
+			},
+		]
+	}
+
+	// Per slot, the memory fd followed by the timeline semaphore fd if that feature was negotiated
+	fn slot_handle_count(&self) -> usize {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		match features & features::TIMELINE_SEMAPHORE {
+			0 => 1,
+			_ => 2,
+		}
+	}
+
+	// Images shared through RAM come with a single memfd. The IpcShmem fd may precede the image's
+	// handles, see open_ipc_info
+	fn share_handle_count(&self, img_data: &ImgData) -> usize {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		let shmem_handle_count = match features & features::SHMEM_HANDLES {
+			0 => 0,
+			_ => 1,
+		};
+		shmem_handle_count
+			+ match self.use_ram_images {
+				true => 1,
+				false => self.slot_handle_count() * img_data.data.buffer_count.max(1) as usize,
+			}
+	}
+
+	// The server sends the IpcShmem fd ahead of the image's handles if features::SHMEM_HANDLES was
+	// negotiated, otherwise it is opened by name
+	fn open_ipc_info(
+		&self,
 		img_data: &ImgData,
 		share_handles: &mut Vec<OwnedFd>,
-	) -> Result<Option<&ImageData>, Box<dyn std::error::Error>> {
-		// TODO: Update if sharing more handles
-		debug_assert_eq!(share_handles.len(), 1);
-		let fd = share_handles.pop().unwrap();
+	) -> Result<IpcShmem, Box<dyn std::error::Error>> {
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		if features & features::SHMEM_HANDLES == 0 {
+			return IpcShmem::new(
+				&ImgData::convert_shmem_array_to_str(&img_data.shmem_name),
+				&ImgData::convert_shmem_array_to_str(&img_data.data.name),
+				false,
+			);
+		}
 
+		if share_handles.is_empty() {
+			return Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Server sent no shared memory handle",
+			)));
+		}
+		IpcShmem::import_handle(share_handles.remove(0))
+	}
+
+	fn add_new_image(
+		&mut self,
+		img_data: &ImgData,
+		mut share_handles: Vec<OwnedFd>,
+	) -> Result<Option<&ImageData>, Box<dyn std::error::Error>> {
 		let image_name = ImgData::convert_shmem_array_to_str(&img_data.data.name);
-		let image_data = Self::create_local_image(&self.vk_setup, img_data, fd)?;
+		let shmem = self.open_ipc_info(img_data, &mut share_handles)?;
+		let image_data = match self.use_ram_images {
+			true => Self::create_ram_image(&self.vk_setup, shmem, img_data, share_handles)?,
+			false => Self::create_local_image(
+				&self.vk_setup,
+				shmem,
+				img_data,
+				share_handles,
+				self.slot_handle_count(),
+			)?,
+		};
 		self.shared_images
 			.insert(image_name.to_string(), image_data)
-			.map(|x| x.vk_shared_image.destroy(&self.vk_setup.device));
+			.map(|x| x.destroy(&self.vk_setup.device));
 
 		Ok(Some(self.shared_images.get(&image_name).unwrap()))
 	}
 
 	fn create_local_image(
 		vk_setup: &VkSetup,
+		shmem: IpcShmem,
 		img_data: &ImgData,
-		img_mem_fd: OwnedFd,
+		share_handles: Vec<OwnedFd>,
+		slot_handle_count: usize,
 	) -> Result<ImageData, Box<dyn std::error::Error>> {
-		let shmem = IpcShmem::new(
-			&ImgData::convert_shmem_array_to_str(&img_data.shmem_name),
-			&ImgData::convert_shmem_array_to_str(&img_data.data.name),
-			false,
-		)?;
+		if share_handles.is_empty() {
+			return Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Server sent no memory handle",
+			)));
+		}
 
-		let vk_shared_image = {
-			let rlock = shmem.acquire_rlock(Timeout::Val(VkClient::IPC_TIMEOUT))?;
-			let _rdata = IpcShmem::acquire_rdata(&rlock);
-
-			let vk_shared_image = VkSharedImage::import_from_handle(
-				&vk_setup.instance,
-				&vk_setup.device,
-				img_mem_fd,
-				SharedImageData::from_shmem_img_data(&img_data.data),
-			)?;
-			vk_shared_image
+		// Resizes while importing are detected through the handle id afterwards
+		let vk_shared_images = {
+			let mut vk_shared_images = Vec::new();
+			let mut share_handles = share_handles.into_iter();
+			while let Some(img_mem_fd) = share_handles.next() {
+				let semaphore_fd = match slot_handle_count {
+					1 => None,
+					_ => share_handles.next(),
+				};
+
+				match VkSharedImage::import_from_handle(
+					&vk_setup.instance,
+					&vk_setup.device,
+					img_mem_fd,
+					semaphore_fd,
+					SharedImageData::from_shmem_img_data(&img_data.data),
+				) {
+					Ok(vk_shared_image) => vk_shared_images.push(vk_shared_image),
+					Err(e) => {
+						vk_shared_images
+							.into_iter()
+							.for_each(|img: VkSharedImage| img.destroy(&vk_setup.device));
+						return Err(Box::new(Error::new(
+							ErrorKind::Unsupported,
+							format!(
+								"Failed to import {:?} image of size {}x{}: {}",
+								img_data.data.format, img_data.data.width, img_data.data.height, e
+							),
+						)));
+					}
+				}
+			}
+			vk_shared_images
 		};
 
 		Ok(ImageData {
 			ipc_info: shmem,
-			vk_shared_image,
+			vk_shared_images,
+			ram_image: None,
+			received_frame: Cell::new(0),
+			pending_send: Cell::new(None),
+		})
+	}
+
+	fn create_ram_image(
+		vk_setup: &VkSetup,
+		shmem: IpcShmem,
+		img_data: &ImgData,
+		share_handles: Vec<OwnedFd>,
+	) -> Result<ImageData, Box<dyn std::error::Error>> {
+		let ram_fd = share_handles
+			.into_iter()
+			.next()
+			.ok_or_else(|| Error::new(ErrorKind::InvalidData, "Server sent no RAM image handle"))?;
+
+		let ram_image = RamImage::import_handle(ram_fd, &img_data.data)?;
+		let staging_buffer = VkCpuBuffer::new(
+			&vk_setup.instance,
+			&vk_setup.device,
+			ram_image.as_slice().len() as u64,
+			None,
+		)?;
+
+		Ok(ImageData {
+			ipc_info: shmem,
+			vk_shared_images: Vec::new(),
+			ram_image: Some(RamImageData {
+				ram_image,
+				staging_buffer,
+			}),
+			received_frame: Cell::new(0),
+			pending_send: Cell::new(None),
 		})
 	}
 
@@ -473,28 +1611,25 @@ impl VkClient {
 		&mut self,
 		image_name: &str,
 	) -> Result<Option<&ImageData>, Box<dyn std::error::Error>> {
-		let cmd_dat = ManuallyDrop::new(CommFindImage {
-			image_name: ImgData::convert_shmem_str_to_array(image_name),
-			gpu_device_uuid: self.gpu_device_uuid,
+		ImgData::validate_name(image_name)?;
+		let cmd_msg = CommandMsg::FindImage(CommFindImage {
+			image_name: image_name.to_string(),
+			gpu_device_uuid: self.requested_device_uuid(),
 		});
-		let cmd_msg = CommandMsg {
-			tag: CommandTag::FindImage,
-			data: CommandData { find_img: cmd_dat },
-		};
-		self.connection.send_command(cmd_msg)?;
+		self.send_command(cmd_msg)?;
 
-		let res_msg = self.connection.recv_result()?;
+		let res_msg = self.recv_result(true)?;
 		let res_data: Option<&ImgData> = match &res_msg {
 			None => Ok(None),
-			Some(msg) => match msg.tag {
-				CommandTag::FindImage => {
-					let data = unsafe { &msg.data.find_img };
+			Some(msg) => match msg {
+				ResultMsg::FindImage(data) => {
 					if data.image_found {
 						Ok(Some(&data.img_data))
 					} else {
 						Ok(None)
 					}
 				}
+				ResultMsg::Error(err) => return Err(Box::new(err.clone())),
 				_ => Err(Box::new(Error::new(
 					ErrorKind::InvalidData,
 					"Received invalid data from server",
@@ -503,36 +1638,169 @@ impl VkClient {
 		}?;
 
 		if res_data.is_none() {
+			// The image was deleted on the server, so drop any stale local copy
+			if res_msg.is_some() {
+				self.shared_images
+					.remove(image_name)
+					.map(|x| x.destroy(&self.vk_setup.device));
+			}
 			return Ok(None);
 		}
 
 		let res_data = res_data.unwrap();
 
-		let mut share_handles = self.connection.recv_ancillary(1)?;
+		let mut share_handles = self
+			.connection
+			.recv_ancillary(self.share_handle_count(res_data))?;
 
 		self.connection.send_ack()?;
 
-		let fd = share_handles.pop().unwrap();
-
-		let image_data = Self::create_local_image(&self.vk_setup, &res_data, fd)?;
+		let shmem = self.open_ipc_info(res_data, &mut share_handles)?;
+		let image_data = match self.use_ram_images {
+			true => Self::create_ram_image(&self.vk_setup, shmem, &res_data, share_handles)?,
+			false => Self::create_local_image(
+				&self.vk_setup,
+				shmem,
+				&res_data,
+				share_handles,
+				self.slot_handle_count(),
+			)?,
+		};
 		self.shared_images
 			.insert(image_name.to_string(), image_data)
-			.map(|x| x.vk_shared_image.destroy(&self.vk_setup.device));
+			.map(|x| x.destroy(&self.vk_setup.device));
 
 		Ok(Some(&self.shared_images.get(image_name).unwrap()))
 	}
 
 	fn copy_image_cmd(&mut self, image_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-		let cmd_dat = ManuallyDrop::new(CommCopyImage {
-			image_name: ImgData::convert_shmem_str_to_array(image_name),
+		let cmd_msg = CommandMsg::CopyImage(CommCopyImage {
+			image_name: image_name.to_string(),
 			gpu_device_uuid: self.gpu_device_uuid,
 		});
-		let cmd_msg = CommandMsg {
-			tag: CommandTag::CopyImage,
-			data: CommandData { copy_img: cmd_dat },
+
+		if !self.wait_for_copy {
+			self.connection.send_command(cmd_msg)?;
+			self.pending_copies += 1;
+			return Ok(());
+		}
+
+		self.send_command(cmd_msg)?;
+		self.pending_copies += 1;
+
+		let res = match self.wait_copy_image()? {
+			Some(res) => res,
+			None => {
+				return Err(Box::new(Error::new(
+					ErrorKind::TimedOut,
+					"Timed out while waiting for image copy",
+				)))
+			}
 		};
-		self.connection.send_command(cmd_msg)?;
 
+		if !res.success {
+			return Err(Box::new(res.error.unwrap_or_else(|| {
+				ResultError::new(ErrorCode::Unknown, "Image copy failed")
+			})));
+		}
+
+		Ok(())
+	}
+
+	fn recv_copy_result(
+		&mut self,
+		blocking: bool,
+	) -> Result<Option<ResultCopyImage>, Box<dyn std::error::Error>> {
+		if self.pending_copies == 0 {
+			return Ok(None);
+		}
+
+		let res_msg = match self.copy_results.pop_front() {
+			Some(res_msg) => Some(res_msg),
+			None => self.recv_result(blocking)?,
+		};
+
+		match res_msg {
+			None => Ok(None),
+			Some(ResultMsg::CopyImage(data)) => {
+				self.pending_copies -= 1;
+				Ok(Some(data))
+			}
+			Some(ResultMsg::Error(err)) => {
+				self.pending_copies -= 1;
+				Err(Box::new(err))
+			}
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	// Queues events pushed by the server and returns the next reply
+	fn recv_result(
+		&mut self,
+		blocking: bool,
+	) -> Result<Option<ResultMsg>, Box<dyn std::error::Error>> {
+		loop {
+			let res_msg = match blocking {
+				true => self.connection.recv_result()?,
+				false => self.connection.recv_result_if_available()?,
+			};
+
+			match res_msg {
+				Some(ResultMsg::Event(event)) => self.events.push_back(event),
+				res_msg => return Ok(res_msg),
+			}
+		}
+	}
+
+	fn subscribe_cmd(
+		&mut self,
+		image_name: Option<&str>,
+		subscribe: bool,
+	) -> Result<Option<()>, Box<dyn std::error::Error>> {
+		if let Some(image_name) = image_name {
+			ImgData::validate_name(image_name)?;
+		}
+
+		let features = self.connection.get_features().unwrap_or(features::NONE);
+		if features & features::EVENTS == 0 {
+			return Err(Box::new(Error::new(
+				ErrorKind::Unsupported,
+				"Server does not support image events",
+			)));
+		}
+
+		self.send_command(CommandMsg::Subscribe(CommSubscribe {
+			image_name: image_name.map(|x| x.to_string()),
+			subscribe,
+		}))?;
+
+		let res_msg = self.recv_result(true)?;
+		match res_msg {
+			None => Ok(None),
+			Some(ResultMsg::Subscribe(_)) => Ok(Some(())),
+			Some(ResultMsg::Error(err)) => Err(Box::new(err)),
+			Some(_) => Err(Box::new(Error::new(
+				ErrorKind::InvalidData,
+				"Received invalid data from server",
+			))),
+		}
+	}
+
+	fn send_command(&mut self, cmd_msg: CommandMsg) -> Result<(), Box<dyn std::error::Error>> {
+		// Results of outstanding copies arrive before the reply to this command
+		while self.pending_copies > 0 {
+			if self.wait_copy_image()?.is_none() {
+				return Err(Box::new(Error::new(
+					ErrorKind::TimedOut,
+					"Timed out while waiting for pending image copies",
+				)));
+			}
+		}
+
+		self.connection.send_command(cmd_msg)?;
 		Ok(())
 	}
 }
@@ -540,13 +1808,22 @@ impl VkClient {
 #[cfg(test)]
 mod tests {
 	use std::ffi::CStr;
-	use std::time::Duration;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Arc;
+	use std::time::{Duration, Instant};
 	use std::{fs, thread};
 
+	use texture_share_vk_base::ash::vk;
+	use texture_share_vk_base::ipc::platform::img_data::ImgFormat;
+	use texture_share_vk_base::ipc::platform::ipc_commands::{
+		features, CommandMsg, ImageEventKind, ResultHello, ResultMsg, PROTOCOL_VERSION,
+	};
 	use texture_share_vk_base::ipc::IpcSocket;
 	use texture_share_vk_base::vk_device::VkDevice;
 	use texture_share_vk_base::vk_instance::VkInstance;
 	use texture_share_vk_base::vk_setup::VkSetup;
+	use texture_share_vk_base::vk_shared_image::VkSharedImage;
+	use texture_share_vk_server::VkServer;
 
 	use super::VkClient;
 
@@ -563,7 +1840,24 @@ mod tests {
 
 		let server_socket_fcn = || {
 			let server_socket = _create_server_socket();
-			server_socket.try_accept().unwrap()
+			let res = server_socket.try_accept().unwrap();
+
+			// Answer the client's Hello
+			let conn_lock = server_socket.connections.lock().unwrap();
+			let conn = conn_lock.last().unwrap().borrow();
+			match conn.recv_command().unwrap().unwrap() {
+				CommandMsg::Hello(hello) => assert_eq!(hello.protocol_version, PROTOCOL_VERSION),
+				cmd => panic!("Expected Hello, received {:?}", cmd.tag()),
+			};
+			conn.send_result(ResultMsg::Hello(ResultHello {
+				accepted: true,
+				protocol_version: PROTOCOL_VERSION,
+				features: features::NONE,
+				connection_id: conn.get_id(),
+			}))
+			.unwrap();
+
+			res
 		};
 
 		let server_thread = thread::spawn(server_socket_fcn);
@@ -582,4 +1876,80 @@ mod tests {
 		let server_res = server_thread.join().unwrap();
 		assert!(server_res.is_some());
 	}
+
+	#[test]
+	fn client_send_image_event() {
+		const EVENT_SOCKET_PATH: &str = "test_event_socket.sock";
+		let _ = fs::remove_file(EVENT_SOCKET_PATH);
+		let stop_bit = Arc::new(AtomicBool::new(false));
+
+		let stop_clone = stop_bit.clone();
+		let server_thread = thread::spawn(move || {
+			let server = VkServer::new(
+				EVENT_SOCKET_PATH,
+				"test_event_images_",
+				TIMEOUT,
+				TIMEOUT,
+				TIMEOUT,
+				None,
+			)
+			.unwrap();
+			server.loop_server(stop_clone).expect("Server loop failed")
+		});
+
+		let vk_instance = VkInstance::new(
+			None,
+			CStr::from_bytes_with_nul(b"vk_setup\0").unwrap(),
+			true,
+		)
+		.unwrap();
+		let vk_device = VkDevice::new(&vk_instance, None).unwrap();
+		let vk_setup = Box::new(VkSetup::new(vk_instance, vk_device));
+
+		let mut client = VkClient::new(EVENT_SOCKET_PATH, vk_setup, TIMEOUT).unwrap();
+		client
+			.init_image("event_image", 4, 4, ImgFormat::R8G8B8A8, true)
+			.unwrap()
+			.unwrap();
+		client.subscribe(Some("event_image")).unwrap().unwrap();
+
+		// A plain send_image notifies subscribers about the new frame
+		let local_image = VkSharedImage::new(
+			&client.get_vk_setup().instance,
+			&client.get_vk_setup().device,
+			4,
+			4,
+			vk::Format::R8G8B8A8_UNORM,
+			0,
+		)
+		.unwrap();
+		client
+			.send_image(
+				"event_image",
+				local_image.image,
+				vk::ImageLayout::UNDEFINED,
+				vk::ImageLayout::GENERAL,
+				vk::Fence::null(),
+			)
+			.unwrap()
+			.unwrap();
+
+		let start = Instant::now();
+		let event = loop {
+			if let Some(event) = client.poll_event().unwrap() {
+				break event;
+			}
+			assert!(start.elapsed() < TIMEOUT, "No event received");
+			thread::sleep(Duration::from_millis(10));
+		};
+		assert_eq!(event.kind, ImageEventKind::FrameAvailable);
+		assert_eq!(event.image_name, "event_image");
+
+		unsafe { client.get_vk_setup().device.device.device_wait_idle() }.unwrap();
+		local_image.destroy(&client.get_vk_setup().device);
+		drop(client);
+
+		stop_bit.store(true, Ordering::Relaxed);
+		server_thread.join().unwrap();
+	}
 }
@@ -11,7 +11,8 @@ use std::{
 
 use texture_share_vk_base::{ash::vk, vk_device::VkDevice, vk_instance::VkInstance};
 use texture_share_vk_base::{
-	ipc::platform::img_data::ImgFormat, vk_setup::VkSetup, vk_shared_image::VkSharedImage,
+	ipc::platform::img_data::ImgFormat, ipc::platform::ipc_commands::ImageEventKind,
+	ipc::platform::MAX_BUFFER_COUNT, vk_setup::VkSetup, vk_shared_image::VkSharedImage,
 };
 use texture_share_vk_client::VkClient;
 use texture_share_vk_server::VkServer;
@@ -372,6 +373,15 @@ fn server_client_send_image() {
 		assert!(res.is_some(), "Failed to send image");
 		println!("Image sent");
 
+		// The fence is only signalled, not waited on by the client
+		unsafe {
+			let device = &client.get_vk_setup().device.device;
+			device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap();
+			device.reset_fences(&[fence]).unwrap();
+		}
+
 		let res = client
 			.recv_image(
 				IMAGE_NAME,
@@ -385,6 +395,289 @@ fn server_client_send_image() {
 		assert!(res.is_some(), "Failed to receive image");
 		println!("Image received");
 
+		unsafe {
+			client
+				.get_vk_setup()
+				.device
+				.device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap()
+		};
+		client.get_vk_setup().device.destroy_fence(fence);
+		local_image.destroy(&client.get_vk_setup().device);
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_rename_image() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+	const NEW_IMAGE_NAME: &str = "test_img_renamed";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		println!("Connection successful");
+
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+		println!("Image created");
+
+		let res = client.rename_image(IMAGE_NAME, NEW_IMAGE_NAME).unwrap();
+		assert!(res.is_some());
+		println!("Image renamed");
+
+		// Local image was kept under the new name
+		let res = client.find_image(NEW_IMAGE_NAME, false).unwrap();
+		assert_eq!(res, Some(false));
+
+		let res = client.find_image(NEW_IMAGE_NAME, true).unwrap();
+		assert!(res.is_some());
+
+		let res = client.find_image(IMAGE_NAME, true).unwrap();
+		assert!(res.is_none());
+
+		let res = client.rename_image(IMAGE_NAME, NEW_IMAGE_NAME).unwrap();
+		assert!(res.is_none());
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_delete_image() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		let mut client2 = _client_create();
+		println!("Connection successful");
+
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		let res = client2.find_image(IMAGE_NAME, false).unwrap();
+		assert!(res.is_some());
+		println!("Image created");
+
+		let res = client.delete_image(IMAGE_NAME).unwrap();
+		assert!(res.is_some());
+		println!("Image deleted");
+
+		// Second client drops its stale copy on the next lookup
+		let res = client2.find_image(IMAGE_NAME, true).unwrap();
+		assert!(res.is_none());
+
+		let res = client2.find_image(IMAGE_NAME, false).unwrap();
+		assert!(res.is_none());
+
+		let res = client.delete_image(IMAGE_NAME).unwrap();
+		assert!(res.is_none());
+
+		// Name can be reused after deletion
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_list_images() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAMES: [&str; 2] = ["test_img_b", "test_img_a"];
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		println!("Connection successful");
+
+		let res = client.list_images().unwrap().unwrap();
+		assert!(res.is_empty());
+
+		let res = client
+			.init_image(IMAGE_NAMES[0], 4, 2, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		let res = client
+			.init_image(IMAGE_NAMES[1], 1, 1, ImgFormat::B8G8R8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		// Lookups of missing images don't show up in the list
+		let res = client.find_image("missing_img", false).unwrap();
+		assert!(res.is_none());
+
+		let res = client.list_images().unwrap().unwrap();
+		let names: Vec<&str> = res.iter().map(|x| x.image_name.as_str()).collect();
+		assert_eq!(names, vec![IMAGE_NAMES[1], IMAGE_NAMES[0]]);
+
+		assert_eq!(res[1].width, 4);
+		assert_eq!(res[1].height, 2);
+		assert_eq!(res[1].format, ImgFormat::R8G8B8A8);
+		assert_eq!(res[1].gpu_device_uuids.len(), 1);
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_send_image_async() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		println!("Connection successful");
+
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		let local_image = VkSharedImage::new(
+			&client.get_vk_setup().instance,
+			&client.get_vk_setup().device,
+			1,
+			1,
+			vk::Format::R8G8B8A8_UNORM,
+			0,
+		)
+		.unwrap();
+
+		// Nothing to poll before an image was sent
+		assert!(client.poll_copy_image().unwrap().is_none());
+
+		client.set_wait_for_copy(false);
+		let fence = client.get_vk_setup().device.create_fence(None).unwrap();
+		let res = client
+			.send_image(
+				IMAGE_NAME,
+				local_image.image,
+				local_image.image_layout,
+				local_image.image_layout,
+				fence,
+			)
+			.unwrap();
+		assert!(res.is_some(), "Failed to send image");
+
+		let copy_res = loop {
+			if let Some(res) = client.poll_copy_image().unwrap() {
+				break res;
+			}
+			thread::sleep(Duration::from_millis(10));
+		};
+
+		// Only a single GPU holds the image, so there is nothing to copy to
+		assert!(copy_res.success);
+		assert_eq!(copy_res.destinations_written, 0);
+		assert!(copy_res.error.is_none());
+		assert!(client.poll_copy_image().unwrap().is_none());
+
+		unsafe {
+			client
+				.get_vk_setup()
+				.device
+				.device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap()
+		};
 		client.get_vk_setup().device.destroy_fence(fence);
 		local_image.destroy(&client.get_vk_setup().device);
 	};
@@ -404,3 +697,567 @@ fn server_client_send_image() {
 	client_thread.join().unwrap();
 	server_thread.join().unwrap();
 }
+
+#[test]
+fn server_client_subscribe() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		let mut client2 = _client_create();
+		println!("Connection successful");
+
+		let res = client2.subscribe(None).unwrap();
+		assert!(res.is_some());
+		assert!(client2.poll_event().unwrap().is_none());
+
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		// Events are pushed before the reply to the next command
+		client2.list_images().unwrap().unwrap();
+		let event = client2.poll_event().unwrap().unwrap();
+		assert_eq!(event.kind, ImageEventKind::Created);
+		assert_eq!(event.image_name, IMAGE_NAME);
+		assert_eq!(event.width, 1);
+		assert!(client2.poll_event().unwrap().is_none());
+
+		let res = client
+			.init_image(IMAGE_NAME, 2, 2, ImgFormat::R8G8B8A8, true)
+			.unwrap();
+		assert!(res.is_some());
+
+		let res = client.delete_image(IMAGE_NAME).unwrap();
+		assert!(res.is_some());
+
+		client2.list_images().unwrap().unwrap();
+		let event = client2.poll_event().unwrap().unwrap();
+		assert_eq!(event.kind, ImageEventKind::Resized);
+		assert_eq!(event.width, 2);
+		let event = client2.poll_event().unwrap().unwrap();
+		assert_eq!(event.kind, ImageEventKind::Deleted);
+
+		// Only subscribed names are reported
+		client2.unsubscribe(None).unwrap().unwrap();
+		client2.subscribe(Some("other_img")).unwrap().unwrap();
+
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		client2.list_images().unwrap().unwrap();
+		assert!(client2.poll_event().unwrap().is_none());
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_cmd_send_image() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		println!("Connection successful");
+
+		let res = client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+		println!("Image created");
+
+		let local_image = VkSharedImage::new(
+			&client.get_vk_setup().instance,
+			&client.get_vk_setup().device,
+			1,
+			1,
+			vk::Format::R8G8B8A8_UNORM,
+			0,
+		)
+		.unwrap();
+
+		// Record into an application owned command buffer, then submit it with the returned values
+		let cmd_buf = client
+			.get_vk_setup()
+			.device
+			.allocate_command_buffer()
+			.unwrap();
+		let device = client.get_vk_setup().device.device.clone();
+		unsafe {
+			device
+				.begin_command_buffer(cmd_buf, &vk::CommandBufferBeginInfo::default())
+				.unwrap()
+		};
+		let sync = client
+			.cmd_send_image(
+				IMAGE_NAME,
+				cmd_buf,
+				local_image.image,
+				local_image.image_layout,
+				local_image.image_layout,
+				None,
+			)
+			.unwrap()
+			.expect("Failed to record image send");
+		assert_eq!(sync.signal_value, sync.wait_value + 1);
+		unsafe { device.end_command_buffer(cmd_buf).unwrap() };
+
+		// Only one send per image can be pending
+		assert!(client
+			.cmd_send_image(
+				IMAGE_NAME,
+				cmd_buf,
+				local_image.image,
+				local_image.image_layout,
+				local_image.image_layout,
+				None,
+			)
+			.is_err());
+
+		let timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+			.wait_semaphore_values(&[sync.wait_value])
+			.signal_semaphore_values(&[sync.signal_value])
+			.build();
+		let submit_info = vk::SubmitInfo {
+			p_next: &timeline_info as *const _ as *const _,
+			command_buffer_count: 1,
+			p_command_buffers: &cmd_buf,
+			wait_semaphore_count: 1,
+			p_wait_semaphores: &sync.semaphore,
+			p_wait_dst_stage_mask: &vk::PipelineStageFlags::ALL_COMMANDS,
+			signal_semaphore_count: 1,
+			p_signal_semaphores: &sync.semaphore,
+			..Default::default()
+		};
+		let fence = client.get_vk_setup().device.create_fence(None).unwrap();
+		unsafe {
+			device
+				.queue_submit(
+					client.get_vk_setup().device.graphics_queue,
+					&[submit_info],
+					fence,
+				)
+				.unwrap();
+			device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap();
+		}
+		client
+			.cmd_send_image_submitted(IMAGE_NAME)
+			.unwrap()
+			.expect("Failed to publish image send");
+		println!("Image sent");
+
+		// Sends that are never submitted return their slot and timeline value
+		let discarded_cmd_buf = client
+			.get_vk_setup()
+			.device
+			.allocate_command_buffer()
+			.unwrap();
+		unsafe {
+			device
+				.begin_command_buffer(discarded_cmd_buf, &vk::CommandBufferBeginInfo::default())
+				.unwrap()
+		};
+		let discarded_sync = client
+			.cmd_send_image(
+				IMAGE_NAME,
+				discarded_cmd_buf,
+				local_image.image,
+				local_image.image_layout,
+				local_image.image_layout,
+				None,
+			)
+			.unwrap()
+			.unwrap();
+		client
+			.cmd_send_image_discarded(IMAGE_NAME)
+			.unwrap()
+			.unwrap();
+		assert!(client.cmd_send_image_discarded(IMAGE_NAME).is_err());
+		let sync = client
+			.cmd_send_image(
+				IMAGE_NAME,
+				discarded_cmd_buf,
+				local_image.image,
+				local_image.image_layout,
+				local_image.image_layout,
+				None,
+			)
+			.unwrap()
+			.unwrap();
+		assert_eq!(sync.wait_value, discarded_sync.wait_value);
+		client
+			.cmd_send_image_discarded(IMAGE_NAME)
+			.unwrap()
+			.unwrap();
+
+		unsafe { device.end_command_buffer(discarded_cmd_buf).unwrap() };
+		client
+			.get_vk_setup()
+			.device
+			.free_command_buffer(discarded_cmd_buf);
+
+		client.get_vk_setup().device.destroy_fence(fence);
+		client.get_vk_setup().device.free_command_buffer(cmd_buf);
+		local_image.destroy(&client.get_vk_setup().device);
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_send_image_ring() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+	const BUFFER_COUNT: u32 = 3;
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut client = _client_create();
+		println!("Connection successful");
+
+		let res = client.init_image_with_buffer_count(
+			IMAGE_NAME,
+			1,
+			1,
+			ImgFormat::R8G8B8A8,
+			false,
+			MAX_BUFFER_COUNT as u32 + 1,
+		);
+		assert!(res.is_err(), "Server accepted an invalid buffer count");
+
+		let res = client
+			.init_image_with_buffer_count(
+				IMAGE_NAME,
+				1,
+				1,
+				ImgFormat::R8G8B8A8,
+				false,
+				BUFFER_COUNT,
+			)
+			.unwrap();
+		assert!(res.is_some());
+		println!("Image ring created");
+
+		let local_image = VkSharedImage::new(
+			&client.get_vk_setup().instance,
+			&client.get_vk_setup().device,
+			1,
+			1,
+			vk::Format::R8G8B8A8_UNORM,
+			0,
+		)
+		.unwrap();
+
+		let fence = client.get_vk_setup().device.create_fence(None).unwrap();
+		for _ in 0..BUFFER_COUNT + 1 {
+			let res = client
+				.send_image(
+					IMAGE_NAME,
+					local_image.image,
+					local_image.image_layout,
+					local_image.image_layout,
+					fence,
+				)
+				.unwrap();
+			assert!(res.is_some(), "Failed to send image");
+
+			unsafe {
+				let device = &client.get_vk_setup().device.device;
+				device
+					.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+					.unwrap();
+				device.reset_fences(&[fence]).unwrap();
+			}
+
+			// Each write is published to the next slot of the ring
+			let (_lock, data) = client.find_image_data(IMAGE_NAME, false).unwrap().unwrap();
+			assert_eq!(data.buffer_count, BUFFER_COUNT);
+			assert!(data.latest_slot < BUFFER_COUNT);
+		}
+		println!("Image ring sent");
+
+		let res = client
+			.recv_image(
+				IMAGE_NAME,
+				local_image.image,
+				local_image.image_layout,
+				local_image.image_layout,
+				fence,
+			)
+			.unwrap();
+		assert!(res.is_some(), "Failed to receive image");
+
+		unsafe {
+			client
+				.get_vk_setup()
+				.device
+				.device
+				.wait_for_fences(&[fence], true, 1000 * 1000 * 1000)
+				.unwrap()
+		};
+		client.get_vk_setup().device.destroy_fence(fence);
+		local_image.destroy(&client.get_vk_setup().device);
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_send_ram_image() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut gpu_client = _client_create();
+		let mut ram_client = _client_create();
+		ram_client.set_use_ram_images(true).unwrap();
+		println!("Connection successful");
+
+		let res = gpu_client
+			.init_image(IMAGE_NAME, 1, 1, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+
+		// The RAM client gets its own copy of the image next to the GPU images
+		let res = ram_client.find_image(IMAGE_NAME, false).unwrap();
+		assert!(res.is_some(), "Failed to find RAM image");
+		let images = ram_client.list_images().unwrap().unwrap();
+		assert_eq!(images[0].gpu_device_uuids.len(), 2);
+		assert!(images[0].gpu_device_uuids.contains(&0));
+
+		let gpu_image = VkSharedImage::new(
+			&gpu_client.get_vk_setup().instance,
+			&gpu_client.get_vk_setup().device,
+			1,
+			1,
+			vk::Format::R8G8B8A8_UNORM,
+			0,
+		)
+		.unwrap();
+		let ram_image = VkSharedImage::new(
+			&ram_client.get_vk_setup().instance,
+			&ram_client.get_vk_setup().device,
+			1,
+			1,
+			vk::Format::R8G8B8A8_UNORM,
+			0,
+		)
+		.unwrap();
+
+		let res = gpu_client
+			.send_image(
+				IMAGE_NAME,
+				gpu_image.image,
+				gpu_image.image_layout,
+				gpu_image.image_layout,
+				vk::Fence::null(),
+			)
+			.unwrap();
+		assert!(res.is_some(), "Failed to send image");
+		println!("Image sent");
+
+		let res = ram_client
+			.recv_image(
+				IMAGE_NAME,
+				ram_image.image,
+				ram_image.image_layout,
+				ram_image.image_layout,
+				vk::Fence::null(),
+			)
+			.unwrap();
+		assert!(res.is_some(), "Failed to receive RAM image");
+
+		let res = ram_client
+			.send_image(
+				IMAGE_NAME,
+				ram_image.image,
+				ram_image.image_layout,
+				ram_image.image_layout,
+				vk::Fence::null(),
+			)
+			.unwrap();
+		assert!(res.is_some(), "Failed to send RAM image");
+		println!("RAM image sent");
+
+		// Recording into command buffers requires GPU memory
+		let res = ram_client.cmd_send_image(
+			IMAGE_NAME,
+			vk::CommandBuffer::null(),
+			ram_image.image,
+			ram_image.image_layout,
+			ram_image.image_layout,
+			None,
+		);
+		assert!(res.is_err());
+
+		gpu_image.destroy(&gpu_client.get_vk_setup().device);
+		ram_image.destroy(&ram_client.get_vk_setup().device);
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
+
+#[test]
+fn server_client_read_write_pixels() {
+	let _ = fs::remove_file(SOCKET_PATH);
+
+	const IMAGE_NAME: &str = "test_img";
+
+	let stop_bit = Arc::new(AtomicBool::new(false));
+
+	let stop_clone = stop_bit.clone();
+	let server_fcn = move || {
+		let server = _server_create();
+		server.loop_server(stop_clone).expect("Server loop failed")
+	};
+
+	let client_fcn = move || {
+		let mut gpu_client = _client_create();
+		let mut ram_client = _client_create();
+		ram_client.set_use_ram_images(true).unwrap();
+		println!("Connection successful");
+
+		let res = gpu_client
+			.init_image(IMAGE_NAME, 2, 2, ImgFormat::R8G8B8A8, false)
+			.unwrap();
+		assert!(res.is_some());
+		let res = ram_client.find_image(IMAGE_NAME, false).unwrap();
+		assert!(res.is_some(), "Failed to find RAM image");
+
+		let res = gpu_client.read_pixels("missing_img").unwrap();
+		assert!(res.is_none());
+
+		// Rows are padded to 12 bytes
+		let pixels: Vec<u8> = (0..24).collect();
+		let res = gpu_client.write_pixels(IMAGE_NAME, &pixels, 12).unwrap();
+		assert!(res.is_some(), "Failed to write pixels");
+
+		let mut packed = pixels[..8].to_vec();
+		packed.extend_from_slice(&pixels[12..20]);
+		let res = gpu_client.read_pixels(IMAGE_NAME).unwrap();
+		assert_eq!(res, Some(packed.clone()));
+
+		let res = ram_client.read_pixels(IMAGE_NAME).unwrap();
+		assert_eq!(res, Some(packed));
+
+		let pixels: Vec<u8> = (100..116).collect();
+		let res = ram_client.write_pixels(IMAGE_NAME, &pixels, 0).unwrap();
+		assert!(res.is_some(), "Failed to write RAM pixels");
+
+		let res = gpu_client.read_pixels(IMAGE_NAME).unwrap();
+		assert_eq!(res, Some(pixels));
+
+		// Too few bytes for the whole image
+		let res = gpu_client.write_pixels(IMAGE_NAME, &[0; 15], 0);
+		assert!(res.is_err());
+	};
+
+	let server_thread = thread::spawn(server_fcn);
+	let client_thread = thread::spawn(client_fcn);
+
+	thread::sleep(Duration::from_secs(2));
+	loop {
+		stop_bit.clone().store(true, Ordering::Relaxed);
+
+		if server_thread.is_finished() && client_thread.is_finished() {
+			break;
+		}
+	}
+
+	client_thread.join().unwrap();
+	server_thread.join().unwrap();
+}
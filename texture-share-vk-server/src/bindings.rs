@@ -6,6 +6,7 @@ use std::{
 	time::Duration,
 };
 
+#[cfg(feature = "vulkan")]
 use texture_share_vk_base::vk_device::VkPhysicalDeviceOptions;
 
 use crate::VkServer;
@@ -20,6 +21,7 @@ fn get_str<'a>(buf: &'a *const c_char) -> Cow<'a, str> {
 //     unsafe { Pin::new_unchecked(vk_server.as_mut().unwrap()) }
 // }
 
+#[cfg(feature = "vulkan")]
 #[no_mangle]
 extern "C" fn vk_server_new(
 	socket_path: *const c_char,
@@ -54,6 +56,30 @@ extern "C" fn vk_server_new(
 	}
 }
 
+// Server without Vulkan, clients can only share images through RAM
+#[no_mangle]
+extern "C" fn vk_server_new_headless(
+	socket_path: *const c_char,
+	shmem_prefix: *const c_char,
+	socket_timeout_in_millis: u64,
+	no_connection_timeout_in_millis: u64,
+	ipc_timeout_in_millis: u64,
+) -> *mut VkServer {
+	let socket_path = get_str(&socket_path);
+	let shmem_prefix = get_str(&shmem_prefix);
+
+	match VkServer::new_headless(
+		&socket_path,
+		&shmem_prefix,
+		Duration::from_millis(socket_timeout_in_millis),
+		Duration::from_millis(no_connection_timeout_in_millis),
+		Duration::from_millis(ipc_timeout_in_millis),
+	) {
+		Err(_) => null_mut(),
+		Ok(s) => Box::into_raw(Box::new(s)),
+	}
+}
+
 #[no_mangle]
 extern "C" fn vk_server_destroy(vk_server: Option<NonNull<VkServer>>) {
 	match vk_server {
@@ -1,22 +1,28 @@
 #![feature(cstr_count_bytes)]
 
 use std::{
-	ffi::CString,
 	fs::{self, OpenOptions},
 	path::Path,
-	str::FromStr,
 	sync::{atomic::AtomicBool, Arc},
 	time::{Duration, SystemTime},
 };
 
-use clap::{builder::TypedValueParser, Parser};
+use clap::Parser;
 use fs2::FileExt;
-use texture_share_vk_base::{uuid, vk_device::VkPhysicalDeviceOptions};
 use texture_share_vk_server::VkServer;
 
+// GPU selection is only available with Vulkan
+#[cfg(feature = "vulkan")]
+use {
+	clap::builder::TypedValueParser, std::ffi::CString, std::str::FromStr, texture_share_ipc::uuid,
+	texture_share_vk_base::vk_device::VkPhysicalDeviceOptions,
+};
+
+#[cfg(feature = "vulkan")]
 #[derive(Clone)]
 struct UuidParser;
 
+#[cfg(feature = "vulkan")]
 impl TypedValueParser for UuidParser {
 	type Value = uuid::Uuid;
 
@@ -73,17 +79,29 @@ struct Args {
 	#[arg(long, default_value_t = 2000)]
 	lockfile_timeout_millis: u64,
 
+	#[cfg(feature = "vulkan")]
 	#[arg(long, required = false)]
 	gpu_vendor_id: Option<u32>,
 
+	#[cfg(feature = "vulkan")]
 	#[arg(long, required = false)]
 	gpu_device_id: Option<u32>,
 
+	#[cfg(feature = "vulkan")]
 	#[arg(long, required = false)]
 	gpu_device_name: Option<String>,
 
+	#[cfg(feature = "vulkan")]
 	#[arg(long, required = false, value_parser=clap::builder::ValueParser::new(UuidParser{}))]
 	gpu_device_uuid: Option<uuid::Uuid>,
+
+	/// Run without Vulkan, images can only be shared through RAM
+	#[arg(long, default_value_t = false)]
+	headless: bool,
+
+	/// Share image metadata through named shared memory instead of passing memfds to clients
+	#[arg(long, default_value_t = false)]
+	named_shmem: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -118,6 +136,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 	let _ = fs::remove_file(&args.socket_file);
 
+	let mut vk_server = create_server(&args)?;
+	vk_server.set_named_shmem(args.named_shmem);
+
+	vk_server.loop_server(Arc::new(AtomicBool::new(false)))?;
+
+	// File cleanup
+	let _ = fs::remove_file(&args.socket_file);
+	let _ = fs::remove_file(lock_file_path);
+	lock_file.unlock()?;
+
+	Ok(())
+}
+
+#[cfg(feature = "vulkan")]
+fn create_server(args: &Args) -> Result<VkServer, Box<dyn std::error::Error>> {
 	// Check if GPU vendor and device ID's were submitted
 	let physical_device_properties = VkPhysicalDeviceOptions {
 		vendor_id: args.gpu_vendor_id,
@@ -125,25 +158,41 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		device_uuid: args.gpu_device_uuid,
 		device_name: args
 			.gpu_device_name
-			.map(|x| CString::new(x).expect("Failed to get GPU device name")),
+			.as_ref()
+			.map(|x| CString::new(x.as_str()).expect("Failed to get GPU device name")),
 		..Default::default()
 	};
 
-	let vk_server = VkServer::new(
+	match args.headless {
+		true => VkServer::new_headless(
+			&args.socket_file,
+			&args.shmem_prefix,
+			Duration::from_millis(args.socket_timeout_millis),
+			Duration::from_millis(args.connection_wait_timeout_millis),
+			Duration::from_millis(args.ipc_timeout_millis),
+		),
+		false => VkServer::new(
+			&args.socket_file,
+			&args.shmem_prefix,
+			Duration::from_millis(args.socket_timeout_millis),
+			Duration::from_millis(args.connection_wait_timeout_millis),
+			Duration::from_millis(args.ipc_timeout_millis),
+			Some(physical_device_properties),
+		),
+	}
+}
+
+#[cfg(not(feature = "vulkan"))]
+fn create_server(args: &Args) -> Result<VkServer, Box<dyn std::error::Error>> {
+	if !args.headless {
+		println!("Built without the vulkan feature, running headless");
+	}
+
+	VkServer::new_headless(
 		&args.socket_file,
 		&args.shmem_prefix,
 		Duration::from_millis(args.socket_timeout_millis),
 		Duration::from_millis(args.connection_wait_timeout_millis),
 		Duration::from_millis(args.ipc_timeout_millis),
-		Some(physical_device_properties),
-	)?;
-
-	vk_server.loop_server(Arc::new(AtomicBool::new(false)))?;
-
-	// File cleanup
-	let _ = fs::remove_file(&args.socket_file);
-	let _ = fs::remove_file(lock_file_path);
-	lock_file.unlock()?;
-
-	Ok(())
+	)
 }
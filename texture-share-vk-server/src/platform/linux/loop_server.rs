@@ -4,8 +4,6 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::SystemTime;
 
-
-
 use crate::VkServer;
 
 impl VkServer {
@@ -21,6 +19,7 @@ impl VkServer {
 		let poller = Poller::new()?;
 		let mut events = Events::new();
 		let mut connections_to_close = Vec::default();
+		let mut image_events = Vec::new();
 
 		// Add listener event request to poller
 		unsafe {
@@ -47,9 +46,18 @@ impl VkServer {
 
 					// Remove unused connections from both poller and connections vector
 					if !connections_to_close.is_empty() {
+						// A connection may have failed more than once
+						connections_to_close.sort_unstable();
+						connections_to_close.dedup();
+
 						// Remove connections that were closed by peer
 						for ci in connections_to_close.iter().rev() {
-							conn_lock.as_mut().unwrap().remove(*ci);
+							let conn = conn_lock.as_mut().unwrap().remove(*ci);
+							VkServer::release_timeline_reservations(
+								&self.images,
+								conn.borrow().get_id(),
+								self.ipc_timeout,
+							);
 						}
 
 						connections_to_close.clear();
@@ -65,13 +73,11 @@ impl VkServer {
 				// Add poll request for each connection
 				let conn_lock = self.socket.connections.lock();
 				for conn_id in 0..conn_lock.as_ref().unwrap().len() {
+					let conn = conn_lock.as_ref().unwrap()[conn_id].borrow();
 					unsafe {
 						poller.add(
-							conn_lock.as_ref().unwrap()[conn_id]
-								.borrow()
-								.get_socket()
-								.as_raw_fd(),
-							Event::readable(conn_id).with_interrupt(),
+							conn.get_socket().as_raw_fd(),
+							VkServer::connection_event(conn_id, conn.has_queued_results()),
 						)?;
 					}
 				}
@@ -90,20 +96,54 @@ impl VkServer {
 						continue;
 					} else {
 						let conn = &connections[ev.key];
-						if !VkServer::process_single_connection(
-							&conn.borrow(),
-							&self.vk_instance,
-							&mut self.vk_devices,
-							&self.shmem_prefix,
-							&mut self.images,
-							self.ipc_timeout,
-						)? {
+						let mut keep_connection = true;
+
+						// Queued events are written once the client reads again
+						if ev.writable {
+							keep_connection = conn
+								.borrow()
+								.flush_queued_results()
+								.map_err(|e| println!("Dropping connection after error: {}", e))
+								.is_ok();
+						}
+
+						if keep_connection && ev.readable {
+							keep_connection = VkServer::process_single_connection(
+								&conn.borrow(),
+								self.backend.as_mut(),
+								self.named_shmem.then_some(self.shmem_prefix.as_str()),
+								&mut self.images,
+								self.ipc_timeout,
+								&mut image_events,
+							)
+							.unwrap_or_else(|e| {
+								// A failing connection must not stop the server for other clients
+								println!("Dropping connection after error: {}", e);
+								false
+							});
+						}
+						if !keep_connection {
 							connections_to_close.push(ev.key);
 						}
 
+						connections_to_close
+							.extend(VkServer::send_events(connections, &mut image_events));
+
+						// Wait until clients can take the events that couldn't be written yet
+						for (conn_id, other_conn) in connections.iter().enumerate() {
+							let other_conn = other_conn.borrow();
+							if conn_id != ev.key && other_conn.has_queued_results() {
+								poller.modify(
+									other_conn.get_socket().as_fd(),
+									VkServer::connection_event(conn_id, true),
+								)?;
+							}
+						}
+
+						let conn = conn.borrow();
 						poller.modify(
-							conn.borrow().get_socket().as_fd(),
-							Event::readable(ev.key).with_interrupt(),
+							conn.get_socket().as_fd(),
+							VkServer::connection_event(ev.key, conn.has_queued_results()),
 						)?;
 					}
 				} else if ev.key == VkServer::LISTENER_EVENT_KEY {
@@ -135,4 +175,8 @@ impl VkServer {
 
 		Ok(())
 	}
+
+	fn connection_event(conn_id: usize, has_queued_results: bool) -> Event {
+		Event::new(conn_id, true, has_queued_results).with_interrupt()
+	}
 }
@@ -1,46 +1,53 @@
-mod vk_copy_images;
+mod image_backend;
+mod shm_image_backend;
+#[cfg(feature = "vulkan")]
+mod vk_image_backend;
 
-use std::borrow::{BorrowMut};
+use std::alloc::Layout;
+use std::cell::RefCell;
 
-use std::collections::hash_map::{Entry, OccupiedEntry};
 use std::collections::HashMap;
-use std::ffi::CStr;
 use std::fs;
 use std::io::{Error, ErrorKind};
-use std::mem::{ManuallyDrop, MaybeUninit};
 
-use std::os::fd::IntoRawFd;
+use std::os::fd::{IntoRawFd, OwnedFd};
 use std::time::Duration;
-use texture_share_vk_base::ipc::platform::img_data::{ImgData};
-use texture_share_vk_base::ipc::platform::ipc_commands::{
-	CommCopyImage, CommFindImage, CommInitImage, CommandTag, ResultData, ResultFindImage,
-	ResultInitImage, ResultMsg,
+use texture_share_ipc::platform::img_data::{ImgData};
+use texture_share_ipc::platform::ipc_commands::{
+	features, CommCopyImage, CommDeleteImage, CommFindImage, CommHello, CommInitImage,
+	CommRamTransfer, CommRenameImage, CommSubscribe, CommandMsg, ErrorCode, ImageEvent,
+	ImageEventKind, ImageListEntry, RamTransferDirection, ResultCopyImage, ResultDeleteImage,
+	ResultError, ResultFindImage, ResultHello, ResultInitImage, ResultListImages, ResultMsg,
+	ResultRamTransfer, ResultRenameImage, ResultSubscribe, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
-use texture_share_vk_base::ipc::platform::ShmemDataInternal;
-use texture_share_vk_base::ipc::platform::{ReadLockGuard, Timeout};
-use texture_share_vk_base::ipc::{IpcConnection, IpcShmem, IpcSocket};
-use texture_share_vk_base::vk_cpu_shared_image::{AlignedRamBuffer, VkCpuSharedImage};
-use texture_share_vk_base::vk_device::{VkDevice, VkPhysicalDeviceOptions};
-use texture_share_vk_base::vk_instance::VkInstance;
+use texture_share_ipc::platform::ipc_wire::DecodeError;
+use texture_share_ipc::platform::{ReadLockGuard, Timeout};
+use texture_share_ipc::platform::{ShmemDataInternal, MAX_BUFFER_COUNT};
+use texture_share_ipc::AlignedRamBuffer;
+use texture_share_ipc::{IpcConnection, IpcShmem, IpcSocket};
+#[cfg(feature = "vulkan")]
+use texture_share_vk_base::vk_device::VkPhysicalDeviceOptions;
 
-use texture_share_vk_base::vk_shared_image::VkSharedImage;
-use texture_share_vk_base::{uuid};
+use texture_share_ipc::{uuid};
 
-use self::vk_copy_images::VkCopyImages;
+use self::image_backend::{ImageBackend, ServerImage};
+use self::shm_image_backend::{ShmImage, ShmImageBackend};
+#[cfg(feature = "vulkan")]
+use self::vk_image_backend::VkImageBackend;
 
 pub(super) struct ServerImageData {
 	pub ipc_info: IpcShmem,
-	pub vk_shared_image: VkCpuSharedImage,
+	// Ring of images, the image stored under the nil GPU uuid is shared through RAM
+	pub image: Box<dyn ServerImage>,
 }
 
+// The images are dropped before the RAM buffer they import
 #[derive(Default)]
 pub(super) struct GpuImageData {
 	pub images: GpuImagesMap,
 	pub ram_buffer: AlignedRamBuffer,
 }
 
-type DevicesMap = HashMap<u128, VkDevice>;
-
 type GpuImagesMap = HashMap<u128, ServerImageData>;
 type NameImagesMap = HashMap<String, GpuImageData>;
 
@@ -48,35 +55,32 @@ pub struct VkServer {
 	pub(crate) socket: IpcSocket,
 	pub(crate) socket_path: String,
 	pub(crate) shmem_prefix: String,
+	// Share IpcShmem through named segments instead of memfds
+	pub(crate) named_shmem: bool,
 	pub(crate) images: NameImagesMap,
-	pub(crate) vk_instance: VkInstance,
-	pub(crate) vk_devices: DevicesMap,
+	pub(crate) backend: Box<dyn ImageBackend>,
 	pub(crate) connection_wait_timeout: Duration,
 	pub(crate) ipc_timeout: Duration,
 }
 
 impl Drop for VkServer {
 	fn drop(&mut self) {
-		// Ensure that images are cleared before vk_devices are destroyed
+		// Ensure that images are destroyed before the backend
 		self.images.drain().for_each(|mut map| {
 			map.1.images.drain().for_each(|x| {
+				// RAM images are unmapped when dropped
+				if x.1.image.is_ram_image() {
+					return;
+				}
+
 				let _rlock =
 					x.1.ipc_info
 						.acquire_rlock(Timeout::Val(self.ipc_timeout))
 						.expect("Failed to acquire lock on IpcData");
-				let uuid = uuid::Uuid::from_u128(x.0);
-				x.1.vk_shared_image.destroy(
-					&self
-						.vk_devices
-						.get(&uuid.as_u128())
-						.expect("Failed to find device for VkSharedImage"),
-				);
+				drop(x.1.image);
 			})
 		});
 
-		// Destroy devices before vk_instance
-		self.vk_devices.clear();
-
 		let _ = fs::remove_file(self.socket_path.to_owned());
 	}
 }
@@ -84,6 +88,7 @@ impl Drop for VkServer {
 impl VkServer {
 	pub(crate) const LISTENER_EVENT_KEY: usize = usize::MAX - 1;
 
+	#[cfg(feature = "vulkan")]
 	pub fn new(
 		socket_path: &str,
 		shmem_prefix: &str,
@@ -92,22 +97,48 @@ impl VkServer {
 		ipc_timeout: Duration,
 		physical_device_options: Option<VkPhysicalDeviceOptions>,
 	) -> Result<VkServer, Box<dyn std::error::Error>> {
-		let _ = fs::remove_file(socket_path.to_owned());
+		let backend = VkImageBackend::new(physical_device_options)?;
 
-		let socket = IpcSocket::new(socket_path, socket_timeout).map_err(|e| Box::new(e))?;
+		VkServer::with_backend(
+			socket_path,
+			shmem_prefix,
+			socket_timeout,
+			connection_wait_timeout,
+			ipc_timeout,
+			Box::new(backend),
+		)
+	}
 
-		let vk_instance = VkInstance::new(
-			None,
-			CStr::from_bytes_with_nul(b"VkServer\0").unwrap(),
-			false,
-		)?;
-		let vk_device = VkDevice::new(&vk_instance, physical_device_options)?;
+	// Creates a server that doesn't use Vulkan. Clients can only share images through RAM, see
+	// features::RAM_IMAGES
+	pub fn new_headless(
+		socket_path: &str,
+		shmem_prefix: &str,
+		socket_timeout: Duration,
+		connection_wait_timeout: Duration,
+		ipc_timeout: Duration,
+	) -> Result<VkServer, Box<dyn std::error::Error>> {
+		VkServer::with_backend(
+			socket_path,
+			shmem_prefix,
+			socket_timeout,
+			connection_wait_timeout,
+			ipc_timeout,
+			Box::new(ShmImageBackend),
+		)
+	}
 
-		let gpu_device_uuid =
-			VkDevice::get_gpu_device_uuid(&vk_instance.instance, vk_device.physical_device);
+	fn with_backend(
+		socket_path: &str,
+		shmem_prefix: &str,
+		socket_timeout: Duration,
+		connection_wait_timeout: Duration,
+		ipc_timeout: Duration,
+		backend: Box<dyn ImageBackend>,
+	) -> Result<VkServer, Box<dyn std::error::Error>> {
+		let _ = fs::remove_file(socket_path.to_owned());
 
-		let mut vk_devices = HashMap::default();
-		vk_devices.insert(gpu_device_uuid.as_u128(), vk_device);
+		let socket = IpcSocket::new(socket_path, socket_timeout).map_err(|e| Box::new(e))?;
 
 		let images = HashMap::default();
 
@@ -115,9 +146,9 @@ impl VkServer {
 			socket,
 			socket_path: socket_path.to_string(),
 			shmem_prefix: shmem_prefix.to_string(),
+			named_shmem: false,
 			images,
-			vk_instance,
-			vk_devices,
+			backend,
 			connection_wait_timeout,
 			ipc_timeout,
 		})
@@ -127,21 +158,49 @@ impl VkServer {
 		self.socket.timeout = connection_timeout;
 	}
 
+	// Creates IpcShmem segments named after shmem_prefix and the image, for clients that don't
+	// support features::SHMEM_HANDLES. Only affects images created afterwards
+	pub fn set_named_shmem(&mut self, named_shmem: bool) {
+		self.named_shmem = named_shmem;
+	}
+
 	pub(crate) fn process_single_connection(
 		conn: &IpcConnection,
-		vk_instance: &VkInstance,
-		vk_devices: &mut DevicesMap,
-		shmem_prefix: &str,
+		backend: &mut dyn ImageBackend,
+		shmem_prefix: Option<&str>,
 		images: &mut NameImagesMap,
 		ipc_timeout: Duration,
+		events: &mut Vec<ImageEvent>,
 	) -> Result<bool, Box<dyn std::error::Error>> {
 		// Try to receive command. If connection was closed by peer, remove this connection from vector
 		let cmd = match conn.recv_command_if_available() {
 			Err(e) => match e.kind() {
-				ErrorKind::BrokenPipe => {
+				ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof => {
 					return Ok(false);
 				}
-				_ => Err(e),
+				_ => match DecodeError::from_io_error(&e) {
+					// The message was received in full, so the connection can still be used
+					Some(DecodeError::InvalidName(name_err)) => {
+						conn.send_result(ResultMsg::Error(ResultError::new(
+							ErrorCode::InvalidName,
+							name_err,
+						)))?;
+						return Ok(true);
+					}
+					// Malformed messages only terminate the offending connection
+					Some(decode_err) => {
+						println!("Dropping connection after invalid message: {}", decode_err);
+						let _ = conn.send_result(ResultMsg::Error(ResultError {
+							code: ErrorCode::InvalidMessage,
+							message: decode_err.to_string(),
+						}));
+						return Ok(false);
+					}
+					None => {
+						println!("Dropping connection after receive error: {}", e);
+						return Ok(false);
+					}
+				},
 			},
 			o => o,
 		}?;
@@ -151,150 +210,293 @@ impl VkServer {
 		}
 
 		let cmd = cmd.unwrap();
-		let res = match cmd.tag {
-			CommandTag::InitImage => VkServer::process_cmd_init_image(
+
+		// Peers must complete the Hello exchange before sending any other command
+		if let CommandMsg::Hello(hello) = &cmd {
+			return VkServer::process_cmd_hello(conn, hello);
+		} else if conn.get_features().is_none() {
+			println!(
+				"Rejecting connection: received {:?} command before Hello. Client is likely older than protocol version {}",
+				cmd.tag(), PROTOCOL_VERSION
+			);
+			let _ = VkServer::send_hello_result(conn, false, features::NONE);
+			return Ok(false);
+		}
+
+		let res = match &cmd {
+			CommandMsg::InitImage(init_img) => VkServer::process_cmd_init_image(
 				conn,
-				unsafe { &cmd.data.init_img },
-				vk_instance,
-				vk_devices,
+				init_img,
+				backend,
 				shmem_prefix,
 				images,
 				ipc_timeout,
+				events,
 			),
-			CommandTag::FindImage => VkServer::process_cmd_find_image(
+			CommandMsg::FindImage(find_img) => VkServer::process_cmd_find_image(
 				conn,
-				unsafe { &cmd.data.find_img },
-				vk_instance,
-				vk_devices,
+				find_img,
+				backend,
+				shmem_prefix,
 				images,
 				ipc_timeout,
 			),
-			CommandTag::CopyImage => VkServer::process_cmd_copy_image(
+			CommandMsg::CopyImage(copy_img) => VkServer::process_cmd_copy_image(
 				conn,
-				unsafe { &cmd.data.copy_img },
-				vk_instance,
-				vk_devices,
+				copy_img,
 				images,
 				ipc_timeout,
+				events,
 			),
-			// CommandTag::RenameImage => Server::process_cmd_rename_image(
-			//     &conn.borrow(),
-			//     unsafe { &cmd.data.rename_img },
-			//     vk_setup,
-			//     images,
-			// ),
-			#[allow(unreachable_patterns)]
-			_ => Err::<(), Box<dyn std::error::Error>>(Box::new(Error::new(
-				ErrorKind::InvalidData,
-				"Unknown command received",
-			))),
+			CommandMsg::RenameImage(rename_img) => VkServer::process_cmd_rename_image(
+				conn,
+				rename_img,
+				shmem_prefix,
+				images,
+				ipc_timeout,
+				events,
+			),
+			CommandMsg::DeleteImage(delete_img) => VkServer::process_cmd_delete_image(
+				conn,
+				delete_img,
+				images,
+				ipc_timeout,
+				events,
+			),
+			CommandMsg::ListImages(_) => {
+				VkServer::process_cmd_list_images(conn, images, ipc_timeout)
+			}
+			CommandMsg::Subscribe(subscribe) => VkServer::process_cmd_subscribe(conn, subscribe),
+			CommandMsg::RamTransfer(ram_transfer) => VkServer::process_cmd_ram_transfer(
+				conn,
+				ram_transfer,
+				images,
+				ipc_timeout,
+				events,
+			),
+			// Handled above
+			CommandMsg::Hello(_) => Ok(()),
 		};
 
-		match res {
-			Err(e) => match e.downcast_ref::<Error>() {
-				None => Err(e),
-				Some(ioe) => match ioe.kind() {
-					ErrorKind::BrokenPipe => return Ok(false),
-					_ => Err(e),
-				},
-			},
-			s => s,
-		}?;
+		let e = match res {
+			Ok(_) => return Ok(true),
+			Err(e) => e,
+		};
+
+		// Failures that occurred before any reply was sent are reported to the client.
+		// Other errors leave the connection in an unknown state, so only that connection is dropped
+		if let Some(err) = e.downcast_ref::<ResultError>() {
+			println!("Failed to process {:?} command: {}", cmd.tag(), err);
+			return match conn.send_result(ResultMsg::Error(err.clone())) {
+				Ok(_) => Ok(true),
+				Err(_) => Ok(false),
+			};
+		}
+
+		if !matches!(e.downcast_ref::<Error>(), Some(ioe) if ioe.kind() == ErrorKind::BrokenPipe) {
+			println!(
+				"Dropping connection after failed {:?} command: {}",
+				cmd.tag(),
+				e
+			);
+		}
 
-		Ok(true)
+		Ok(false)
+	}
+
+	fn process_cmd_hello(
+		connection: &IpcConnection,
+		cmd: &CommHello,
+	) -> Result<bool, Box<dyn std::error::Error>> {
+		// Newer messages only append fields, so older clients can still be served
+		if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&cmd.protocol_version) {
+			println!(
+				"Rejecting {:?} client (pid {}): protocol version {} is not between {} and {}",
+				cmd.client_kind,
+				cmd.pid,
+				cmd.protocol_version,
+				MIN_PROTOCOL_VERSION,
+				PROTOCOL_VERSION
+			);
+			let _ = VkServer::send_hello_result(connection, false, features::NONE);
+			return Ok(false);
+		}
+
+		// Only enable features both sides support
+		let features = cmd.features & features::SUPPORTED;
+		connection.set_features(Some(features));
+
+		match VkServer::send_hello_result(connection, true, features) {
+			Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(false),
+			Err(e) => Err(Box::new(e)),
+			Ok(_) => Ok(true),
+		}
+	}
+
+	fn send_hello_result(
+		connection: &IpcConnection,
+		accepted: bool,
+		features: u64,
+	) -> Result<(), Error> {
+		connection.send_result(ResultMsg::Hello(ResultHello {
+			accepted,
+			protocol_version: PROTOCOL_VERSION,
+			features,
+			connection_id: connection.get_id(),
+		}))
 	}
 
 	fn process_cmd_init_image(
 		connection: &IpcConnection,
 		cmd: &CommInitImage,
-		vk_instance: &VkInstance,
-		vk_devices: &mut DevicesMap,
-		shmem_prefix: &str,
+		backend: &mut dyn ImageBackend,
+		shmem_prefix: Option<&str>,
 		images: &mut NameImagesMap,
 		ipc_timeout: Duration,
+		events: &mut Vec<ImageEvent>,
 	) -> Result<(), Box<dyn std::error::Error>> {
 		// Get or create correct device
-		let vk_device_entry =
-			Self::get_or_create_device(vk_devices, vk_instance, cmd.gpu_device_uuid)?;
-		let vk_device = vk_device_entry.get();
+		if !VkServer::is_ram_request(connection, cmd.gpu_device_uuid) {
+			backend
+				.open_device(cmd.gpu_device_uuid)
+				.map_err(|e| ResultError::new(ErrorCode::DeviceUnavailable, e))?;
+		}
+
+		if cmd.buffer_count == 0 || cmd.buffer_count as usize > MAX_BUFFER_COUNT {
+			return Err(Box::new(ResultError::new(
+				ErrorCode::InvalidBufferCount,
+				format!(
+					"Buffer count {} is not between 1 and {}",
+					cmd.buffer_count, MAX_BUFFER_COUNT
+				),
+			)));
+		}
 
-		let img_name_str = ImgData::convert_shmem_array_to_str(&cmd.image_name);
-		let shmem_name_str = shmem_prefix.to_owned() + &img_name_str;
+		// Every GPU that stores the image is resized to the new format
+		let existing_devices = images
+			.get(&cmd.image_name)
+			.map(|x| x.images.keys().copied().collect::<Vec<_>>())
+			.unwrap_or_default();
+		for gpu_device_uuid in std::iter::once(cmd.gpu_device_uuid).chain(existing_devices) {
+			backend
+				.check_format(gpu_device_uuid, cmd.format)
+				.map_err(|e| ResultError::new(ErrorCode::UnsupportedFormat, e))?;
+		}
+
+		let img_name_str = cmd.image_name.clone();
 
-		let gpu_images_map = images.entry(img_name_str.clone()).or_default();
+		let image_existed = images.contains_key(&img_name_str);
 
 		// Find image data
-		let img_loaded = gpu_images_map.images.contains_key(&cmd.gpu_device_uuid);
+		let img_loaded = images
+			.get(&img_name_str)
+			.is_some_and(|m| m.images.contains_key(&cmd.gpu_device_uuid));
 
 		// Process initialization
-		let (result_msg_data, vk_shared_image, _lock) = if !img_loaded || cmd.overwrite_existing {
+		let (result_msg_data, server_image, _lock) = if !img_loaded || cmd.overwrite_existing {
 			// Only initialize image if none exists or the cmd explicitly allows overriding an image
 			if !img_loaded {
 				// Create image if it doesn't exist yet
-				let ipc_info = IpcShmem::new(&shmem_name_str, &img_name_str, true)?;
-				let vk_shared_image = VkCpuSharedImage::new(
-					vk_instance,
-					vk_device,
-					cmd.width,
-					cmd.height,
-					VkSharedImage::get_vk_format(cmd.format),
-					0,
+				// The images are allocated along with the other GPUs' rings below, a new entry is
+				// removed again if that fails
+				let ipc_info = VkServer::create_ipc_info(
+					shmem_prefix,
+					&img_name_str,
+					cmd.gpu_device_uuid,
 				)?;
-				let _ = gpu_images_map
+				let _ = images
+					.entry(img_name_str.clone())
+					.or_default()
 					.images
 					.entry(cmd.gpu_device_uuid)
 					.insert_entry(ServerImageData {
 						ipc_info,
-						vk_shared_image,
+						image: backend.create_image(cmd.gpu_device_uuid),
 					});
 			};
+			let gpu_images_map = images.get_mut(&img_name_str).unwrap();
+
+			// Every GPU's image stays locked until all of them were resized, so clients never see
+			// a partially resized image
+			let GpuImageData {
+				images: gpu_images,
+				ram_buffer,
+			} = &mut *gpu_images_map;
+			let mut locked_images = Vec::with_capacity(gpu_images.len());
+			let mut resize_started = false;
+			let mut res = Ok(());
+			for (gpu_device_uuid, image) in gpu_images.iter_mut() {
+				let ServerImageData { ipc_info, image } = image;
+				let ipc_info = &*ipc_info;
+				let lock = match ipc_info.acquire_lock(Timeout::Val(ipc_timeout)) {
+					Ok(lock) => lock,
+					Err(e) => {
+						res = Err(ResultError::new(ErrorCode::LockTimeout, e));
+						break;
+					}
+				};
+				let handle_id = IpcShmem::acquire_data(&lock).handle_id + 1;
+
+				// A failed resize may already have released the old image memory
+				resize_started = true;
+				if let Err(e) = image.resize(cmd, handle_id) {
+					res = Err(ResultError::new(ErrorCode::Allocation, e));
+					break;
+				}
+				locked_images.push((*gpu_device_uuid, ipc_info, image, lock));
+			}
+			if res.is_ok() {
+				let mut resized_images = locked_images
+					.iter_mut()
+					.map(|(_, _, image, _)| &mut **image)
+					.collect::<Vec<_>>();
+				res = VkServer::import_ram_buffer(&mut resized_images, ram_buffer)
+					.map_err(|e| ResultError::new(ErrorCode::Allocation, e));
+			}
 
-			// Acquire write lock to image
-			// let lock = server_image_data
-			// 	.acquire_lock(Timeout::Val(ipc_timeout))
-			// 	.unwrap();
-			// let mut data = IpcShmem::acquire_data(&lock);
-
-			let format = VkSharedImage::get_vk_format(cmd.format);
-			let mut cur_img_lock = MaybeUninit::uninit();
-			let mut cur_img_data = MaybeUninit::uninit();
-			let _locks = gpu_images_map
-				.images
-				.iter_mut()
-				.map(|image| {
-					// Update all shared images with the new size
-					let lock = image.1.ipc_info.acquire_lock(Timeout::Val(ipc_timeout))?;
-					let data = IpcShmem::acquire_data(&lock);
+			if let Err(e) = res {
+				drop(locked_images);
+				if !image_existed {
+					images.remove(&img_name_str);
+				} else if resize_started {
+					// Clients can't use the image anymore, so it is removed entirely
+					let gpu_images_map = images.remove(&img_name_str).unwrap();
+					events.extend(VkServer::remove_gpu_images(
+						&img_name_str,
+						gpu_images_map,
+						ipc_timeout,
+					));
+				} else if !img_loaded {
+					images
+						.get_mut(&img_name_str)
+						.unwrap()
+						.images
+						.remove(&cmd.gpu_device_uuid);
+				}
+				return Err(Box::new(e));
+			}
 
-					image.1.vk_shared_image.borrow_mut().resize_image(
-						&vk_instance,
-						&vk_device,
-						cmd.width,
-						cmd.height,
-						format,
-						data.handle_id + 1,
-						&mut gpu_images_map.ram_buffer,
-					)?;
-
-					// Update Shmem data
-					VkServer::update_shmem_data(data, &image.1.vk_shared_image.image);
-
-					if *image.0 == cmd.gpu_device_uuid {
-						cur_img_lock = MaybeUninit::new(lock);
-						cur_img_data = MaybeUninit::new(&image.1.vk_shared_image);
-						Ok::<_, Box<dyn std::error::Error>>(None)
-					} else {
-						Ok::<_, Box<dyn std::error::Error>>(Some(lock))
-					}
-				})
-				.collect::<Result<Vec<_>, _>>()?;
+			// Only publish the new image data once all images were resized
+			for (_, _, image, lock) in locked_images.iter() {
+				VkServer::update_shmem_data(
+					IpcShmem::acquire_data(lock),
+					&***image,
+					cmd.buffer_count,
+				);
+			}
 
-			let data = IpcShmem::acquire_data(unsafe { cur_img_lock.assume_init_ref() });
+			// Keep the lock of the requested image until its handles were sent
+			let cur_img_pos = locked_images
+				.iter()
+				.position(|image| image.0 == cmd.gpu_device_uuid)
+				.unwrap();
+			let (_, ipc_info, image, lock) = locked_images.swap_remove(cur_img_pos);
+			drop(locked_images);
 
 			// Generate ResultMsg data
 			let img_data = ImgData::from_shmem_data_internal(
-				ImgData::convert_shmem_str_to_array(&shmem_name_str),
-				data.clone(),
+				ipc_info.get_name_array()?,
+				IpcShmem::acquire_data(&lock).clone(),
 			);
 
 			// Return result, vk_shared_img, and lock
@@ -303,8 +505,8 @@ impl VkServer {
 					image_created: true,
 					img_data,
 				},
-				Some(unsafe { cur_img_data.assume_init() }),
-				Some(unsafe { cur_img_lock.assume_init() }),
+				Some((ipc_info, &**image)),
+				Some(lock),
 			)
 		} else {
 			// If image not loaded or cmd.overwrite_existing is false, send empty result back
@@ -318,69 +520,86 @@ impl VkServer {
 			)
 		};
 
+		// Export handles before replying, so that a failure can still be reported to the client
+		let handles = server_image
+			.map(|(ipc_info, image)| VkServer::export_handles(connection, ipc_info, image))
+			.transpose()?;
+
+		let event = match (result_msg_data.image_created, image_existed) {
+			(false, _) => None,
+			(true, false) => Some(ImageEventKind::Created),
+			(true, true) => Some(ImageEventKind::Resized),
+		}
+		.map(|kind| ImageEvent::new(kind, &img_name_str, &result_msg_data.img_data.data));
+
 		// Send result to client
-		let res_msg = ResultMsg {
-			tag: CommandTag::InitImage,
-			data: ResultData {
-				init_img: ManuallyDrop::new(result_msg_data),
-			},
-		};
-		connection.send_result(res_msg)?;
+		connection.send_result(ResultMsg::InitImage(result_msg_data))?;
 
 		// If image was created/updated, send handles to client
-		if vk_shared_image.is_some() {
-			let handles = vk_shared_image.unwrap().image.export_handle(vk_device)?;
-			connection.send_anillary_handles(&[handles.into_raw_fd()])?;
+		if let Some(handles) = handles {
+			let handles = handles
+				.into_iter()
+				.map(|fd| fd.into_raw_fd())
+				.collect::<Vec<_>>();
+			connection.send_anillary_handles(&handles)?;
 
 			// Receive ack
 			connection.recv_ack()?;
 		}
 
+		events.extend(event);
+
 		Ok(())
 	}
 
 	fn process_cmd_find_image(
 		connection: &IpcConnection,
 		cmd: &CommFindImage,
-		vk_instance: &VkInstance,
-		vk_devices: &mut DevicesMap,
+		backend: &mut dyn ImageBackend,
+		shmem_prefix: Option<&str>,
 		images: &mut NameImagesMap,
 		ipc_timeout: Duration,
 	) -> Result<(), Box<dyn std::error::Error>> {
-		// Get or create correct device
-		let vk_device_entry =
-			Self::get_or_create_device(vk_devices, vk_instance, cmd.gpu_device_uuid)?;
-
-		let vk_device = vk_device_entry.get();
-
-		let img_name_str = ImgData::convert_shmem_array_to_str(&cmd.image_name);
+		let img_name_str = cmd.image_name.clone();
 
-		let gpu_images_map = images.entry(img_name_str).or_default();
-		let image_and_lock: Option<(ImgData, &mut VkCpuSharedImage, ReadLockGuard)> =
-			match gpu_images_map.images.entry(cmd.gpu_device_uuid) {
-				Entry::Occupied(e) => {
-					let entry = e.into_mut();
+		// Get or create correct device
+		if !VkServer::is_ram_request(connection, cmd.gpu_device_uuid) {
+			backend
+				.open_device(cmd.gpu_device_uuid)
+				.map_err(|e| ResultError::new(ErrorCode::DeviceUnavailable, e))?;
+		} else if let Some(gpu_images_map) = images
+			.get_mut(&img_name_str)
+			.filter(|m| !m.images.contains_key(&cmd.gpu_device_uuid))
+		{
+			VkServer::add_ram_image(gpu_images_map, shmem_prefix, &img_name_str, ipc_timeout)?;
+		}
+		let found_image = images
+			.get(&img_name_str)
+			.and_then(|m| m.images.get(&cmd.gpu_device_uuid));
+		let image_and_lock: Option<(ImgData, &ServerImageData, ReadLockGuard)> =
+			match found_image {
+				Some(entry) => {
 					let rlock = entry
 						.ipc_info
 						.acquire_rlock(Timeout::Val(ipc_timeout))
-						.unwrap();
+						.map_err(|e| ResultError::new(ErrorCode::LockTimeout, e))?;
 					let rdata = IpcShmem::acquire_rdata(&rlock);
 
 					Some((
 						ImgData::from_shmem_data_internal(
-							ImgData::convert_shmem_str_to_array(entry.ipc_info.get_name()),
+							entry.ipc_info.get_name_array()?,
 							rdata.clone(),
 						),
-						&mut entry.vk_shared_image,
+						entry,
 						rlock,
 					))
 				}
-				Entry::Vacant(_) => None,
+				None => None,
 			};
 
 		// Keep lock, extract image
-		let (image, vk_shared_image, _opt_lock) = match image_and_lock {
-			Some((image, vk_img, lock)) => (Some(image), Some(vk_img), Some(lock)),
+		let (image, server_image, _opt_lock) = match image_and_lock {
+			Some((image, server_image, lock)) => (Some(image), Some(server_image), Some(lock)),
 			_ => (None, None, None),
 		};
 
@@ -396,16 +615,19 @@ impl VkServer {
 			},
 		};
 
-		connection.send_result(ResultMsg {
-			tag: CommandTag::FindImage,
-			data: ResultData {
-				find_img: ManuallyDrop::new(res_data),
-			},
-		})?;
+		// Export handles before replying, so that a failure can still be reported to the client
+		let handles = server_image
+			.map(|image| VkServer::export_handles(connection, &image.ipc_info, &*image.image))
+			.transpose()?;
 
-		if vk_shared_image.is_some() {
-			let fd = vk_shared_image.unwrap().image.export_handle(vk_device)?;
-			connection.send_anillary_handles(&[fd.into_raw_fd()])?;
+		connection.send_result(ResultMsg::FindImage(res_data))?;
+
+		if let Some(handles) = handles {
+			let handles = handles
+				.into_iter()
+				.map(|fd| fd.into_raw_fd())
+				.collect::<Vec<_>>();
+			connection.send_anillary_handles(&handles)?;
 			connection.recv_ack()?;
 		}
 
@@ -413,63 +635,708 @@ impl VkServer {
 	}
 
 	fn process_cmd_copy_image(
-		_connection: &IpcConnection,
+		connection: &IpcConnection,
+		cmd: &CommCopyImage,
+		images: &mut NameImagesMap,
+		ipc_timeout: Duration,
+		events: &mut Vec<ImageEvent>,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let img_name_str = cmd.image_name.clone();
+
+		let mut destinations_written = 0;
+		let res = match images.get_mut(&img_name_str) {
+			Some(gpu_images_map) => VkServer::copy_gpu_images(
+				gpu_images_map,
+				cmd,
+				ipc_timeout,
+				&mut destinations_written,
+			),
+			_ => Err(ResultError::new(
+				ErrorCode::ImageNotFound,
+				format!("Image '{}' not found", img_name_str),
+			)),
+		};
+
+		// Clients request a copy after every frame they send, even if the image only exists on one
+		// GPU
+		let res = res.and_then(|_| {
+			let image = images
+				.get(&img_name_str)
+				.and_then(|m| m.images.get(&cmd.gpu_device_uuid));
+			if let Some(image) = image {
+				let data = image
+					.ipc_info
+					.snapshot(ipc_timeout)
+					.map_err(|e| ResultError::new(ErrorCode::LockTimeout, e))?;
+				events.push(ImageEvent::new(
+					ImageEventKind::FrameAvailable,
+					&img_name_str,
+					&data,
+				));
+			}
+			Ok(())
+		});
+		if let Err(e) = &res {
+			println!("Failed to copy image '{}': {}", img_name_str, e);
+		}
+
+		connection.send_result(ResultMsg::CopyImage(ResultCopyImage {
+			success: res.is_ok(),
+			destinations_written,
+			error: res.err(),
+		}))?;
+
+		Ok(())
+	}
+
+	fn copy_gpu_images(
+		gpu_images_map: &mut GpuImageData,
 		cmd: &CommCopyImage,
-		_vk_instance: &VkInstance,
-		vk_devices: &mut DevicesMap,
+		ipc_timeout: Duration,
+		destinations_written: &mut u32,
+	) -> Result<(), ResultError> {
+		let lock_err = |e| ResultError::new(ErrorCode::LockTimeout, e);
+		let copy_err = |e| ResultError::new(ErrorCode::CopyFailed, e);
+
+		// Images shared through RAM are updated by RamTransfer commands instead
+		let gpu_image_count = gpu_images_map
+			.images
+			.values()
+			.filter(|image| !image.image.is_ram_image())
+			.count();
+
+		// If only there's only one image in the map, no copy is necessary
+		if gpu_image_count <= 1 {
+			return Ok(());
+		}
+
+		// Read the latest frame into the RAM buffer shared by all images
+		let read_image = gpu_images_map
+			.images
+			.get(&cmd.gpu_device_uuid)
+			.filter(|image| !image.image.is_ram_image())
+			.ok_or_else(|| {
+				ResultError::new(
+					ErrorCode::ImageNotFound,
+					format!(
+						"Image has no copy on GPU {}",
+						uuid::Uuid::from_u128(cmd.gpu_device_uuid)
+					),
+				)
+			})?;
+		let timestamp_ns = {
+			let rlock = read_image
+				.ipc_info
+				.acquire_rlock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			let rdata = IpcShmem::acquire_rdata(&rlock);
+			let read_slot = VkServer::latest_slot(rdata)?;
+			read_image
+				.image
+				.read_slot(
+					read_slot,
+					rdata.timeline_values[read_slot],
+					&gpu_images_map.ram_buffer,
+				)
+				.map_err(copy_err)?;
+			rdata.frame_timestamp_ns
+		};
+
+		for (gpu_device_uuid, image) in gpu_images_map.images.iter_mut() {
+			if *gpu_device_uuid == cmd.gpu_device_uuid || image.image.is_ram_image() {
+				continue;
+			}
+
+			// The copy goes into a slot that no reader uses and only becomes the latest one once
+			// it was written. The lock is held until the copy completed
+			let write_lock = image
+				.ipc_info
+				.acquire_lock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			let write_data = IpcShmem::acquire_data(&write_lock);
+			let write_slot = write_data.acquire_write_slot();
+			let res = image
+				.image
+				.write_slot(
+					write_slot,
+					write_data.timeline_values[write_slot],
+					&gpu_images_map.ram_buffer,
+				)
+				.map_err(copy_err);
+			write_data.release_write_slot(write_slot, res.is_ok());
+			res?;
+			// Consumers see the time at which the producer sent the frame, not the copy's
+			write_data.publish_frame(timestamp_ns);
+			*destinations_written += 1;
+		}
+
+		Ok(())
+	}
+
+	fn process_cmd_rename_image(
+		connection: &IpcConnection,
+		cmd: &CommRenameImage,
+		shmem_prefix: Option<&str>,
+		images: &mut NameImagesMap,
+		ipc_timeout: Duration,
+		events: &mut Vec<ImageEvent>,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let old_name_str = cmd.old_image_name.clone();
+		let new_name_str = cmd.new_image_name.clone();
+
+		if !images.contains_key(&old_name_str) {
+			connection.send_result(ResultMsg::RenameImage(ResultRenameImage {
+				image_found: false,
+				img_data: ImgData::default(),
+			}))?;
+			return Ok(());
+		}
+
+		if old_name_str != new_name_str && images.contains_key(&new_name_str) {
+			return Err(Box::new(ResultError::new(
+				ErrorCode::ImageExists,
+				format!("Image '{}' already exists", new_name_str),
+			)));
+		}
+
+		let mut gpu_images_map = images.remove(&old_name_str).unwrap();
+
+		let img_data = match VkServer::rename_gpu_images(
+			&mut gpu_images_map,
+			cmd,
+			&old_name_str,
+			&new_name_str,
+			shmem_prefix,
+			ipc_timeout,
+		) {
+			Ok(img_data) => img_data,
+			Err(e) => {
+				// Keep the image available under its old name
+				images.insert(old_name_str, gpu_images_map);
+				return Err(e);
+			}
+		};
+
+		let img_data = img_data.unwrap_or_default();
+		if old_name_str != new_name_str {
+			events.push(ImageEvent::new(
+				ImageEventKind::Deleted,
+				&old_name_str,
+				&img_data.data,
+			));
+			events.push(ImageEvent::new(
+				ImageEventKind::Created,
+				&new_name_str,
+				&img_data.data,
+			));
+		}
+
+		images.insert(new_name_str, gpu_images_map);
+
+		connection.send_result(ResultMsg::RenameImage(ResultRenameImage {
+			image_found: true,
+			img_data,
+		}))?;
+
+		Ok(())
+	}
+
+	fn rename_gpu_images(
+		gpu_images_map: &mut GpuImageData,
+		cmd: &CommRenameImage,
+		old_name_str: &str,
+		new_name_str: &str,
+		shmem_prefix: Option<&str>,
+		ipc_timeout: Duration,
+	) -> Result<Option<ImgData>, Box<dyn std::error::Error>> {
+		// Replaced shared memory is kept until all images were renamed, so it can be restored
+		let mut renamed: Vec<(u128, Option<IpcShmem>)> = Vec::new();
+		let mut res = Ok(());
+		if old_name_str != new_name_str {
+			for image in gpu_images_map.images.iter_mut() {
+				match VkServer::rename_gpu_image(
+					image.1,
+					*image.0,
+					new_name_str,
+					shmem_prefix,
+					ipc_timeout,
+				) {
+					Ok(old_ipc_info) => renamed.push((*image.0, old_ipc_info)),
+					Err(e) => {
+						res = Err(e);
+						break;
+					}
+				}
+			}
+		}
+
+		let res = res.and_then(|_| {
+			VkServer::read_gpu_image_data(gpu_images_map, cmd.gpu_device_uuid, ipc_timeout)
+		});
+		if res.is_err() {
+			VkServer::restore_gpu_image_names(gpu_images_map, renamed, old_name_str, ipc_timeout);
+		}
+
+		// Dropping the old IpcShmems unlinks their segments
+		res
+	}
+
+	fn rename_gpu_image(
+		image: &mut ServerImageData,
+		gpu_device_uuid: u128,
+		new_name_str: &str,
+		shmem_prefix: Option<&str>,
+		ipc_timeout: Duration,
+	) -> Result<Option<IpcShmem>, Box<dyn std::error::Error>> {
+		let lock_err = |e| ResultError::new(ErrorCode::LockTimeout, e);
+
+		// Memfds aren't tied to the image name, so only the name stored in them changes
+		if !image.ipc_info.is_named() {
+			let name = ImgData::convert_shmem_str_to_array(new_name_str)?;
+			let lock = image
+				.ipc_info
+				.acquire_lock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			IpcShmem::acquire_data(&lock).name = name;
+			return Ok(None);
+		}
+
+		// The image memory stays the same, only the shared memory is recreated under the new name
+		let ipc_info = VkServer::create_ipc_info(shmem_prefix, new_name_str, gpu_device_uuid)?;
+		{
+			let old_lock = image
+				.ipc_info
+				.acquire_lock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			let old_data = IpcShmem::acquire_data(&old_lock);
+			let new_lock = ipc_info
+				.acquire_lock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			let new_data = IpcShmem::acquire_data(&new_lock);
+
+			let name = new_data.name;
+			*new_data = old_data.clone();
+			new_data.name = name;
+
+			// Clients still holding the old shared memory will notice that the image changed
+			old_data.handle_id = old_data.handle_id.wrapping_add(1);
+		}
+
+		Ok(Some(std::mem::replace(&mut image.ipc_info, ipc_info)))
+	}
+
+	fn read_gpu_image_data(
+		gpu_images_map: &GpuImageData,
+		gpu_device_uuid: u128,
+		ipc_timeout: Duration,
+	) -> Result<Option<ImgData>, Box<dyn std::error::Error>> {
+		let image = match gpu_images_map.images.get(&gpu_device_uuid) {
+			Some(image) => image,
+			None => return Ok(None),
+		};
+
+		let rlock = image
+			.ipc_info
+			.acquire_rlock(Timeout::Val(ipc_timeout))
+			.map_err(|e| ResultError::new(ErrorCode::LockTimeout, e))?;
+		Ok(Some(ImgData::from_shmem_data_internal(
+			image.ipc_info.get_name_array()?,
+			IpcShmem::acquire_rdata(&rlock).clone(),
+		)))
+	}
+
+	// Best effort, the image is already in an error state if this fails as well
+	fn restore_gpu_image_names(
+		gpu_images_map: &mut GpuImageData,
+		renamed: Vec<(u128, Option<IpcShmem>)>,
+		old_name_str: &str,
+		ipc_timeout: Duration,
+	) {
+		for (gpu_device_uuid, old_ipc_info) in renamed.into_iter().rev() {
+			let image = match gpu_images_map.images.get_mut(&gpu_device_uuid) {
+				Some(image) => image,
+				None => continue,
+			};
+
+			match old_ipc_info {
+				Some(old_ipc_info) => {
+					if let Ok(lock) = old_ipc_info.acquire_lock(Timeout::Val(ipc_timeout)) {
+						let data = IpcShmem::acquire_data(&lock);
+						data.handle_id = data.handle_id.wrapping_sub(1);
+					}
+					image.ipc_info = old_ipc_info;
+				}
+				None => {
+					let name = match ImgData::convert_shmem_str_to_array(old_name_str) {
+						Ok(name) => name,
+						Err(_) => continue,
+					};
+					if let Ok(lock) = image.ipc_info.acquire_lock(Timeout::Val(ipc_timeout)) {
+						IpcShmem::acquire_data(&lock).name = name;
+					}
+				}
+			}
+		}
+	}
+
+	fn process_cmd_delete_image(
+		connection: &IpcConnection,
+		cmd: &CommDeleteImage,
+		images: &mut NameImagesMap,
+		ipc_timeout: Duration,
+		events: &mut Vec<ImageEvent>,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let img_name_str = cmd.image_name.clone();
+
+		let image_found = match images.remove(&img_name_str) {
+			Some(gpu_images_map) => {
+				match VkServer::remove_gpu_images(&img_name_str, gpu_images_map, ipc_timeout) {
+					Some(event) => {
+						events.push(event);
+						true
+					}
+					None => false,
+				}
+			}
+			None => false,
+		};
+
+		connection.send_result(ResultMsg::DeleteImage(ResultDeleteImage { image_found }))?;
+
+		Ok(())
+	}
+
+	// Destroys the images of an entry that was removed from the map. Returns the event to send if
+	// the entry held any image
+	fn remove_gpu_images(
+		img_name_str: &str,
+		mut gpu_images_map: GpuImageData,
+		ipc_timeout: Duration,
+	) -> Option<ImageEvent> {
+		let image_found = !gpu_images_map.images.is_empty();
+		let mut event = None;
+		for (_, image) in gpu_images_map.images.drain() {
+			// Bump the handle id so that clients drop their cached copies on the next lookup.
+			// The image is already removed from the map, so destroy it even if that fails
+			match image.ipc_info.acquire_lock(Timeout::Val(ipc_timeout)) {
+				Ok(lock) => {
+					let data = IpcShmem::acquire_data(&lock);
+					event.get_or_insert_with(|| {
+						ImageEvent::new(ImageEventKind::Deleted, img_name_str, data)
+					});
+					data.handle_id = data.handle_id.wrapping_add(1);
+				}
+				Err(e) => println!("Failed to invalidate image '{}': {}", img_name_str, e),
+			}
+
+			// Dropping the IpcShmem unlinks its segment
+			drop(image.image);
+			drop(image.ipc_info);
+		}
+
+		if !image_found {
+			return None;
+		}
+		Some(event.unwrap_or_else(|| {
+			ImageEvent::new(
+				ImageEventKind::Deleted,
+				img_name_str,
+				&ShmemDataInternal::default(),
+			)
+		}))
+	}
+
+	fn process_cmd_list_images(
+		connection: &IpcConnection,
+		images: &NameImagesMap,
+		ipc_timeout: Duration,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let mut entries = Vec::new();
+		for (img_name, gpu_images_map) in images.iter() {
+			let image = match gpu_images_map.images.values().next() {
+				Some(image) => image,
+				None => continue,
+			};
+
+			let rdata = image
+				.ipc_info
+				.snapshot(ipc_timeout)
+				.map_err(|e| ResultError::new(ErrorCode::LockTimeout, e))?;
+
+			let mut gpu_device_uuids: Vec<u128> = gpu_images_map.images.keys().copied().collect();
+			gpu_device_uuids.sort();
+
+			entries.push(ImageListEntry {
+				image_name: img_name.clone(),
+				handle_id: rdata.handle_id,
+				width: rdata.width,
+				height: rdata.height,
+				format: rdata.format,
+				gpu_device_uuids,
+			});
+		}
+
+		entries.sort_by(|a, b| a.image_name.cmp(&b.image_name));
+
+		connection.send_result(ResultMsg::ListImages(ResultListImages { images: entries }))?;
+
+		Ok(())
+	}
+
+	fn process_cmd_subscribe(
+		connection: &IpcConnection,
+		cmd: &CommSubscribe,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		// The connection tracks subscriptions to all images under the empty name
+		let img_name_str = cmd.image_name.as_deref().unwrap_or("");
+		if cmd.subscribe {
+			connection.subscribe(img_name_str);
+		} else {
+			connection.unsubscribe(img_name_str);
+		}
+
+		connection.send_result(ResultMsg::Subscribe(ResultSubscribe {
+			subscribed: cmd.subscribe,
+		}))?;
+
+		Ok(())
+	}
+
+	fn process_cmd_ram_transfer(
+		connection: &IpcConnection,
+		cmd: &CommRamTransfer,
 		images: &mut NameImagesMap,
 		ipc_timeout: Duration,
+		events: &mut Vec<ImageEvent>,
 	) -> Result<(), Box<dyn std::error::Error>> {
-		let img_name_str = ImgData::convert_shmem_array_to_str(&cmd.image_name);
+		let img_name_str = cmd.image_name.clone();
+		let ram_device_uuid = uuid::Uuid::nil().as_u128();
+
+		let res = match images.get_mut(&img_name_str) {
+			Some(gpu_images_map) if gpu_images_map.images.contains_key(&ram_device_uuid) => {
+				match cmd.direction {
+					RamTransferDirection::Upload => {
+						VkServer::upload_ram_image(gpu_images_map, ipc_timeout)
+					}
+					RamTransferDirection::Download => {
+						VkServer::download_ram_image(gpu_images_map, ipc_timeout)
+					}
+				}
+			}
+			_ => Err(ResultError::new(
+				ErrorCode::ImageNotFound,
+				format!("Image '{}' is not shared through RAM", img_name_str),
+			)),
+		};
 
-		// Get gpu map
-		let gpu_images_map = images.get(&img_name_str);
-		if let Some(gpu_images_map) = gpu_images_map {
-			// If only there's only one image in the map, no copy is necessary
-			if gpu_images_map.images.len() <= 1 {
+		let res = res.and_then(|_| {
+			if cmd.direction != RamTransferDirection::Upload {
 				return Ok(());
 			}
+			let image = images
+				.get(&img_name_str)
+				.and_then(|m| m.images.get(&ram_device_uuid));
+			if let Some(image) = image {
+				let data = image
+					.ipc_info
+					.snapshot(ipc_timeout)
+					.map_err(|e| ResultError::new(ErrorCode::LockTimeout, e))?;
+				events.push(ImageEvent::new(
+					ImageEventKind::FrameAvailable,
+					&img_name_str,
+					&data,
+				));
+			}
+			Ok(())
+		});
+		if let Err(e) = &res {
+			println!("Failed to transfer RAM image '{}': {}", img_name_str, e);
+		}
 
-			// Get read and write images
-			let mut read_image = None;
-			let mut read_lock = None;
-			let mut write_images = Vec::new();
-			write_images.reserve(gpu_images_map.images.len() - 1);
-			let _write_locks = gpu_images_map
+		connection.send_result(ResultMsg::RamTransfer(ResultRamTransfer {
+			success: res.is_ok(),
+			error: res.err(),
+		}))?;
+
+		Ok(())
+	}
+
+	// Writes the RAM image into a free slot of every GPU's ring and publishes it
+	fn upload_ram_image(
+		gpu_images_map: &mut GpuImageData,
+		ipc_timeout: Duration,
+	) -> Result<(), ResultError> {
+		let lock_err = |e| ResultError::new(ErrorCode::LockTimeout, e);
+		let copy_err = |e| ResultError::new(ErrorCode::CopyFailed, e);
+
+		if !gpu_images_map
+			.images
+			.values()
+			.any(|image| !image.image.is_ram_image())
+		{
+			return Ok(());
+		}
+
+		// All GPU images share the same RAM buffer
+		let timestamp_ns = {
+			let ram_image = gpu_images_map
 				.images
-				.iter()
-				.map(|image| {
-					if *image.0 == cmd.gpu_device_uuid {
-						read_lock =
-							Some(image.1.ipc_info.acquire_rlock(Timeout::Val(ipc_timeout))?);
-						read_image =
-							Some((vk_devices.get(image.0).unwrap(), &image.1.vk_shared_image));
-						Ok::<_, Box<dyn std::error::Error>>(None)
-					} else {
-						let write_lock =
-							image.1.ipc_info.acquire_lock(Timeout::Val(ipc_timeout))?;
-						write_images
-							.push((vk_devices.get(image.0).unwrap(), &image.1.vk_shared_image));
-						Ok::<_, Box<dyn std::error::Error>>(Some(write_lock))
-					}
-				})
-				.collect::<Result<Vec<_>, _>>();
+				.get(&uuid::Uuid::nil().as_u128())
+				.unwrap();
+			let ram_rlock = ram_image
+				.ipc_info
+				.acquire_rlock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			ram_image
+				.image
+				.read_slot(0, 0, &gpu_images_map.ram_buffer)
+				.map_err(copy_err)?;
+			IpcShmem::acquire_rdata(&ram_rlock).frame_timestamp_ns
+		};
 
-			if let Some(read_image) = read_image {
-				VkCopyImages::copy_images(read_image, &write_images)?;
+		for image in gpu_images_map.images.iter_mut() {
+			if image.1.image.is_ram_image() {
+				continue;
 			}
+
+			let lock = image
+				.1
+				.ipc_info
+				.acquire_lock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			let data = IpcShmem::acquire_data(&lock);
+			let slot = data.acquire_write_slot();
+
+			let res = image
+				.1
+				.image
+				.write_slot(slot, data.timeline_values[slot], &gpu_images_map.ram_buffer)
+				.map_err(copy_err);
+			data.release_write_slot(slot, res.is_ok());
+			res?;
+			data.publish_frame(timestamp_ns);
 		}
 
 		Ok(())
 	}
 
-	// fn process_cmd_rename_image(
-	//     connection: &IpcConnection,
-	//     cmd: &CommRenameImage,
-	//     vk_setup: &VkSetup,
-	//     images: &mut Vec<ImageData>,
-	// ) {
-	// }
+	// Reads the latest frame of any GPU image into the RAM image. If only RAM clients use the
+	// image, the RAM image already holds the latest frame
+	fn download_ram_image(
+		gpu_images_map: &mut GpuImageData,
+		ipc_timeout: Duration,
+	) -> Result<(), ResultError> {
+		let lock_err = |e| ResultError::new(ErrorCode::LockTimeout, e);
+		let copy_err = |e| ResultError::new(ErrorCode::CopyFailed, e);
+
+		let gpu_image = gpu_images_map
+			.images
+			.values()
+			.find(|image| !image.image.is_ram_image());
+		let gpu_image = match gpu_image {
+			Some(image) => image,
+			None => return Ok(()),
+		};
+
+		let timestamp_ns = {
+			let rlock = gpu_image
+				.ipc_info
+				.acquire_rlock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			let rdata = IpcShmem::acquire_rdata(&rlock);
+			let slot = VkServer::latest_slot(rdata)?;
+
+			gpu_image
+				.image
+				.read_slot(slot, rdata.timeline_values[slot], &gpu_images_map.ram_buffer)
+				.map_err(copy_err)?;
+			rdata.frame_timestamp_ns
+		};
+
+		let ram_image = gpu_images_map
+			.images
+			.get_mut(&uuid::Uuid::nil().as_u128())
+			.unwrap();
+		let ram_lock = ram_image
+			.ipc_info
+			.acquire_lock(Timeout::Val(ipc_timeout))
+			.map_err(lock_err)?;
+		ram_image
+			.image
+			.write_slot(0, 0, &gpu_images_map.ram_buffer)
+			.map_err(copy_err)?;
+
+		// Frames that were uploaded from RAM, or downloaded before, are already counted
+		let ram_data = IpcShmem::acquire_data(&ram_lock);
+		if ram_data.frame_timestamp_ns != timestamp_ns {
+			ram_data.publish_frame(timestamp_ns);
+		}
+
+		Ok(())
+	}
+
+	// Completes the timeline values that a closed connection reserved but never submitted, as
+	// other clients would wait for them forever
+	pub(crate) fn release_timeline_reservations(
+		images: &NameImagesMap,
+		connection_id: u32,
+		ipc_timeout: Duration,
+	) {
+		if connection_id == 0 {
+			return;
+		}
+
+		for (img_name, gpu_images_map) in images.iter() {
+			for image in gpu_images_map.images.values() {
+				let reservations = match image.ipc_info.acquire_lock(Timeout::Val(ipc_timeout)) {
+					Ok(lock) => {
+						IpcShmem::acquire_data(&lock).take_timeline_reservations(connection_id)
+					}
+					Err(e) => {
+						println!(
+							"Failed to release reservations of image '{}': {}",
+							img_name, e
+						);
+						continue;
+					}
+				};
+
+				// Values are completed in order, each one waits for the accesses before it
+				for (slot, value) in reservations {
+					if let Err(e) = image.image.skip_timeline_value(slot, value) {
+						println!(
+							"Failed to release timeline value {} of image '{}': {}",
+							value, img_name, e
+						);
+					}
+				}
+			}
+		}
+	}
+
+	// Events are queued without blocking, so a subscriber that stops reading can't stall the
+	// server. Returns the connections that failed and have to be closed
+	pub(crate) fn send_events(
+		connections: &[RefCell<IpcConnection>],
+		events: &mut Vec<ImageEvent>,
+	) -> Vec<usize> {
+		let mut failed_connections = Vec::new();
+		for event in events.drain(..) {
+			for (conn_id, conn) in connections.iter().enumerate() {
+				let conn = conn.borrow();
+				if !conn.is_subscribed(&event.image_name) || failed_connections.contains(&conn_id) {
+					continue;
+				}
+
+				if let Err(e) = conn.queue_result(ResultMsg::Event(event.clone())) {
+					println!("Dropping subscriber after error: {}", e);
+					failed_connections.push(conn_id);
+				}
+			}
+		}
+
+		failed_connections
+	}
 
 	// fn update_shared_image<'a>(
 	// 	cmd: &CommInitImage,
@@ -560,37 +1427,178 @@ impl VkServer {
 	// 	));
 	// }
 
-	fn update_shmem_data(shmem_data: &mut ShmemDataInternal, vk_shared_image: &VkSharedImage) {
-		let vk_data = vk_shared_image.get_image_data();
+	// Images created by GPU clients get a copy in RAM once a RAM client looks them up. It holds the
+	// latest frame after the next Download
+	fn add_ram_image(
+		gpu_images_map: &mut GpuImageData,
+		shmem_prefix: Option<&str>,
+		img_name_str: &str,
+		ipc_timeout: Duration,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let lock_err = |e| ResultError::new(ErrorCode::LockTimeout, e);
+
+		let src_data = {
+			let image = gpu_images_map.images.values().next().unwrap();
+			let rlock = image
+				.ipc_info
+				.acquire_rlock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			IpcShmem::acquire_rdata(&rlock).clone()
+		};
+
+		let ram_image =
+			ShmImage::new(&src_data).map_err(|e| ResultError::new(ErrorCode::Allocation, e))?;
+		let ipc_info =
+			VkServer::create_ipc_info(shmem_prefix, img_name_str, uuid::Uuid::nil().as_u128())?;
 
-		shmem_data.width = vk_data.width;
-		shmem_data.height = vk_data.height;
-		shmem_data.format = VkSharedImage::get_img_format(vk_data.format);
-		shmem_data.allocation_size = vk_data.allocation_size;
-		shmem_data.handle_id = vk_data.id;
+		let image = ServerImageData {
+			ipc_info,
+			image: Box::new(ram_image),
+		};
+		{
+			let lock = image
+				.ipc_info
+				.acquire_lock(Timeout::Val(ipc_timeout))
+				.map_err(lock_err)?;
+			VkServer::update_shmem_data(
+				IpcShmem::acquire_data(&lock),
+				&*image.image,
+				src_data.buffer_count,
+			);
+		}
+
+		gpu_images_map
+			.images
+			.insert(uuid::Uuid::nil().as_u128(), image);
+		Ok(())
 	}
 
-	fn get_or_create_device<'a>(
-		vk_devices: &'a mut DevicesMap,
-		vk_instance: &VkInstance,
-		gpu_device_uuid: u128,
-	) -> Result<OccupiedEntry<'a, u128, VkDevice>, Box<dyn std::error::Error>> {
-		// Check that a device with the given uuid is initialized
-		let vk_device = match vk_devices.entry(gpu_device_uuid) {
-			Entry::Occupied(o) => o,
-			Entry::Vacant(v) => {
-				let new_vk_device = VkDevice::new(
-					&vk_instance,
-					Some(VkPhysicalDeviceOptions {
-						device_uuid: Some(uuid::Uuid::from_u128(gpu_device_uuid)),
-						..Default::default()
-					}),
-				)
-				.map_err(|err| err)?; // TODO: Handle wrong UUID
-				v.insert_entry(new_vk_device)
-			}
+	// Grows the RAM buffer to fit the slots of every GPU and imports it on all of them. The buffer
+	// is allocated once, so GPUs never import memory that was freed by another GPU's resize
+	fn import_ram_buffer(
+		images: &mut [&mut Box<dyn ServerImage>],
+		ram_buffer: &mut AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let (size, align) = images
+			.iter()
+			.filter_map(|image| image.ram_buffer_layout())
+			.fold((0, 1), |(size, align), layout| {
+				(size.max(layout.size()), align.max(layout.align()))
+			});
+		if size == 0 {
+			return Ok(());
+		}
+		let layout = Layout::from_size_align(size, align)?.pad_to_align();
+
+		// The old buffer is only freed once no image imports it anymore
+		let _old_ram_buffer = if ram_buffer.ptr.is_null()
+			|| layout.size() > ram_buffer.layout.size()
+			|| layout.align() > ram_buffer.layout.align()
+		{
+			Some(std::mem::replace(
+				ram_buffer,
+				AlignedRamBuffer::new(layout.size(), layout.align()),
+			))
+		} else {
+			None
 		};
-		Ok(vk_device)
+
+		// Every image is updated even if one fails, so that none keeps importing the old buffer
+		let mut res = Ok(());
+		for image in images.iter_mut() {
+			if let Err(e) = image.import_ram_buffer(ram_buffer) {
+				res = res.and(Err(e));
+			}
+		}
+		res
+	}
+
+	fn update_shmem_data(
+		shmem_data: &mut ShmemDataInternal,
+		image: &dyn ServerImage,
+		buffer_count: u32,
+	) {
+		image.update_shmem_data(shmem_data);
+		// The resized images come with new semaphores
+		shmem_data.reset_slots(buffer_count);
+	}
+
+	// The IpcShmem fd if the client supports it, followed by the memfd of images shared through RAM
+	// or the handles of the image ring
+	fn export_handles(
+		connection: &IpcConnection,
+		ipc_info: &IpcShmem,
+		image: &dyn ServerImage,
+	) -> Result<Vec<OwnedFd>, ResultError> {
+		let features = connection.get_features().unwrap_or(features::NONE);
+
+		let mut handles = Vec::new();
+		if features & features::SHMEM_HANDLES != 0 {
+			let handle = ipc_info
+				.export_handle()
+				.map_err(|e| ResultError::new(ErrorCode::SharedMemory, e))?;
+			handles.push(handle);
+		} else if !ipc_info.is_named() {
+			return Err(ResultError::new(
+				ErrorCode::SharedMemory,
+				"Client can't receive shared memory handles, the server must use named shared memory",
+			));
+		}
+
+		let image_handles = image
+			.export_handles(features)
+			.map_err(|e| ResultError::new(ErrorCode::Allocation, e))?;
+		handles.extend(image_handles);
+		Ok(handles)
+	}
+
+	// Clients can write any slot to the shared memory, it's checked before indexing the ring
+	fn latest_slot(shmem_data: &ShmemDataInternal) -> Result<usize, ResultError> {
+		shmem_data.latest_slot_checked().ok_or_else(|| {
+			ResultError::new(
+				ErrorCode::InvalidSlot,
+				format!(
+					"Latest slot {} is outside of the ring of {} images",
+					shmem_data.latest_slot, shmem_data.buffer_count
+				),
+			)
+		})
+	}
+
+	// Named segments if a prefix is given, otherwise a memfd
+	fn create_ipc_info(
+		shmem_prefix: Option<&str>,
+		img_name: &str,
+		gpu_device_uuid: u128,
+	) -> Result<IpcShmem, ResultError> {
+		match shmem_prefix {
+			Some(shmem_prefix) => IpcShmem::new(
+				&VkServer::shmem_name_for_device(shmem_prefix, img_name, gpu_device_uuid),
+				img_name,
+				true,
+			),
+			None => IpcShmem::new_memfd(img_name),
+		}
+		.map_err(|e| ResultError::new(ErrorCode::SharedMemory, e))
+	}
+
+	// Image names can't contain NUL, so the segment of an image shared through RAM can't collide
+	// with the one of another image
+	fn shmem_name_for_device(shmem_prefix: &str, img_name: &str, gpu_device_uuid: u128) -> String {
+		match VkServer::is_ram_device(gpu_device_uuid) {
+			true => ImgData::shmem_name_from_image_name(shmem_prefix, &format!("{}\0ram", img_name)),
+			false => ImgData::shmem_name_from_image_name(shmem_prefix, img_name),
+		}
+	}
+
+	pub(crate) fn is_ram_device(gpu_device_uuid: u128) -> bool {
+		gpu_device_uuid == uuid::Uuid::nil().as_u128()
+	}
+
+	// Clients that negotiated RAM images request them with the nil GPU uuid
+	fn is_ram_request(connection: &IpcConnection, gpu_device_uuid: u128) -> bool {
+		let features = connection.get_features().unwrap_or(features::NONE);
+		features & features::RAM_IMAGES != 0 && VkServer::is_ram_device(gpu_device_uuid)
 	}
 }
 
@@ -603,10 +1611,21 @@ mod tests {
 			Arc,
 		},
 		thread::spawn,
-		time::Duration,
+		time::{Duration, Instant},
 	};
 
-	use texture_share_vk_base::ipc::IpcConnection;
+	use std::io::Write;
+
+	use texture_share_ipc::platform::img_data::{ImgData, ImgFormat};
+	use texture_share_ipc::platform::ipc_commands::{
+		features, ClientKind, CommFindImage, CommHello, CommInitImage, CommListImages,
+		CommRamTransfer, CommandMsg, ErrorCode, RamTransferDirection, ResultMsg,
+		MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+	};
+	use texture_share_ipc::platform::ipc_wire::WireHeader;
+	use texture_share_ipc::platform::Timeout;
+	use texture_share_ipc::uuid;
+	use texture_share_ipc::{IpcConnection, IpcShmem, RamImage};
 
 	use super::VkServer;
 
@@ -616,18 +1635,19 @@ mod tests {
 	const SOCKET_PATH: &str = "test_socket.sock";
 	const SHMEM_PREFIX: &str = "shared_images_";
 
+	// The protocol doesn't depend on the backend, so tests run without a GPU
 	fn _server_create() -> VkServer {
-		VkServer::new(
+		VkServer::new_headless(
 			SOCKET_PATH,
 			SHMEM_PREFIX,
 			SOCKET_TIMEOUT,
 			NO_CONNECTION_TIMEOUT,
 			IPC_TIMEOUT,
-			None,
 		)
 		.unwrap()
 	}
 
+	#[cfg(feature = "vulkan")]
 	#[test]
 	fn server_create() {
 		let _ = VkServer::new(
@@ -678,4 +1698,292 @@ mod tests {
 
 		server_thread.join().unwrap();
 	}
+
+	#[test]
+	fn server_handshake() {
+		let _ = fs::remove_file(SOCKET_PATH);
+		let stop_bit = Arc::new(AtomicBool::new(false));
+
+		let stop_clone = stop_bit.clone();
+		let server_thread = spawn(move || {
+			let server = _server_create();
+			server.loop_server(stop_clone).expect("Server loop failed")
+		});
+
+		// Matching protocol versions are accepted
+		let conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		let features = conn
+			.handshake(ClientKind::Other, features::SUPPORTED)
+			.expect("Handshake failed");
+		assert_eq!(features, features::SUPPORTED);
+
+		// Older clients are accepted down to the minimum version, newer ones are rejected
+		for (protocol_version, accepted) in [
+			(MIN_PROTOCOL_VERSION, true),
+			(MIN_PROTOCOL_VERSION - 1, false),
+			(PROTOCOL_VERSION + 1, false),
+		] {
+			let conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+				.unwrap()
+				.unwrap();
+			let mut hello = CommHello::new(ClientKind::Other, features::NONE);
+			hello.protocol_version = protocol_version;
+			conn.send_command(CommandMsg::Hello(hello)).unwrap();
+			let res = match conn.recv_result().unwrap().unwrap() {
+				ResultMsg::Hello(res) => res,
+				res => panic!("Received wrong result {:?}", res.tag()),
+			};
+			assert_eq!(res.accepted, accepted);
+			assert_eq!(res.protocol_version, PROTOCOL_VERSION);
+		}
+
+		stop_bit.store(true, Ordering::Relaxed);
+
+		server_thread.join().unwrap();
+	}
+
+	#[test]
+	fn server_invalid_message() {
+		let _ = fs::remove_file(SOCKET_PATH);
+		let stop_bit = Arc::new(AtomicBool::new(false));
+
+		let stop_clone = stop_bit.clone();
+		let server_thread = spawn(move || {
+			let server = _server_create();
+			server.loop_server(stop_clone).expect("Server loop failed")
+		});
+
+		let conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		conn.handshake(ClientKind::Other, features::SUPPORTED)
+			.unwrap();
+
+		// Send a message with an unknown tag
+		let header = WireHeader {
+			tag: 0xDEAD,
+			payload_len: 0,
+		};
+		(&*conn.get_socket()).write_all(&header.to_bytes()).unwrap();
+
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::Error(err) => assert_eq!(err.code, ErrorCode::InvalidMessage),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		// Server must still accept other clients
+		let conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		conn.handshake(ClientKind::Other, features::SUPPORTED)
+			.expect("Server stopped after invalid message");
+		assert!(!server_thread.is_finished());
+
+		stop_bit.store(true, Ordering::Relaxed);
+
+		server_thread.join().unwrap();
+	}
+
+	#[test]
+	fn server_error_result() {
+		let _ = fs::remove_file(SOCKET_PATH);
+		let stop_bit = Arc::new(AtomicBool::new(false));
+
+		let stop_clone = stop_bit.clone();
+		let server_thread = spawn(move || {
+			let server = _server_create();
+			server.loop_server(stop_clone).expect("Server loop failed")
+		});
+
+		let conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		conn.handshake(ClientKind::Other, features::SUPPORTED)
+			.unwrap();
+
+		// No GPU with this uuid exists
+		let mut cmd = CommFindImage::default();
+		cmd.image_name = "test".to_string();
+		cmd.gpu_device_uuid = u128::MAX;
+		conn.send_command(CommandMsg::FindImage(cmd)).unwrap();
+
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::Error(err) => assert_eq!(err.code, ErrorCode::DeviceUnavailable),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		// Names are validated when decoding the command
+		conn.send_command(CommandMsg::FindImage(CommFindImage::default()))
+			.unwrap();
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::Error(err) => assert_eq!(err.code, ErrorCode::InvalidName),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		// The connection stays usable after a failed command
+		conn.send_command(CommandMsg::ListImages(CommListImages {}))
+			.unwrap();
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::ListImages(res) => assert!(res.images.is_empty()),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+		assert!(!server_thread.is_finished());
+
+		stop_bit.store(true, Ordering::Relaxed);
+
+		server_thread.join().unwrap();
+	}
+
+	#[test]
+	fn server_headless_ram_image() {
+		let _ = fs::remove_file(SOCKET_PATH);
+		let stop_bit = Arc::new(AtomicBool::new(false));
+
+		let stop_clone = stop_bit.clone();
+		let server_thread = spawn(move || {
+			let server = VkServer::new_headless(
+				SOCKET_PATH,
+				SHMEM_PREFIX,
+				SOCKET_TIMEOUT,
+				NO_CONNECTION_TIMEOUT,
+				IPC_TIMEOUT,
+			)
+			.unwrap();
+			server.loop_server(stop_clone).expect("Server loop failed")
+		});
+
+		let conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		conn.handshake(ClientKind::Other, features::SUPPORTED)
+			.unwrap();
+
+		// Images can't be stored on a GPU without Vulkan
+		let mut cmd = CommFindImage::default();
+		cmd.image_name = "headless".to_string();
+		cmd.gpu_device_uuid = u128::MAX;
+		conn.send_command(CommandMsg::FindImage(cmd)).unwrap();
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::Error(err) => assert_eq!(err.code, ErrorCode::DeviceUnavailable),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		// Formats without a known pixel size can't be stored in RAM
+		conn.send_command(CommandMsg::InitImage(CommInitImage {
+			image_name: "headless".to_string(),
+			shmem_name: String::new(),
+			width: 4,
+			height: 2,
+			format: ImgFormat::Undefined,
+			overwrite_existing: true,
+			gpu_device_uuid: uuid::Uuid::nil().as_u128(),
+			buffer_count: 1,
+		}))
+		.unwrap();
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::Error(err) => assert_eq!(err.code, ErrorCode::UnsupportedFormat),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		// Images shared through RAM are stored in a memfd
+		conn.send_command(CommandMsg::InitImage(CommInitImage {
+			image_name: "headless".to_string(),
+			shmem_name: String::new(),
+			width: 4,
+			height: 2,
+			format: ImgFormat::R8G8B8A8,
+			overwrite_existing: true,
+			gpu_device_uuid: uuid::Uuid::nil().as_u128(),
+			buffer_count: 1,
+		}))
+		.unwrap();
+		let img_data = match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::InitImage(res) => {
+				assert!(res.image_created);
+				res.img_data
+			}
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+		let mut fds = conn.recv_ancillary(2).unwrap().into_iter();
+		conn.send_ack().unwrap();
+
+		// The shared memory is a memfd sent ahead of the image's handles
+		assert!(ImgData::convert_shmem_array_to_str(&img_data.shmem_name).is_empty());
+		let ipc_info = IpcShmem::import_handle(fds.next().unwrap()).unwrap();
+		assert_eq!(
+			ipc_info.get_id_unchecked(IPC_TIMEOUT).unwrap(),
+			img_data.data.handle_id
+		);
+
+		let ram_image = RamImage::import_handle(fds.next().unwrap(), &img_data.data).unwrap();
+		assert_eq!(ram_image.as_slice().len(), 4 * 2 * 4);
+
+		// Clients that can only open shared memory by name need a server with named segments
+		let legacy_conn = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		legacy_conn
+			.handshake(ClientKind::Other, features::SUPPORTED & !features::SHMEM_HANDLES)
+			.unwrap();
+		let mut cmd = CommFindImage::default();
+		cmd.image_name = "headless".to_string();
+		cmd.gpu_device_uuid = uuid::Uuid::nil().as_u128();
+		legacy_conn.send_command(CommandMsg::FindImage(cmd)).unwrap();
+		match legacy_conn.recv_result().unwrap().unwrap() {
+			ResultMsg::Error(err) => assert_eq!(err.code, ErrorCode::SharedMemory),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		// Slots and timeline values reserved by a client that disconnected are released
+		let reserver = IpcConnection::try_connect(SOCKET_PATH, SOCKET_TIMEOUT)
+			.unwrap()
+			.unwrap();
+		reserver
+			.handshake(ClientKind::Other, features::SUPPORTED)
+			.unwrap();
+		assert_ne!(reserver.get_id(), 0);
+		assert_ne!(reserver.get_id(), conn.get_id());
+		{
+			let lock = ipc_info.acquire_lock(Timeout::Val(IPC_TIMEOUT)).unwrap();
+			let data = IpcShmem::acquire_data(&lock);
+			let slot = data.acquire_write_slot();
+			data.reserve_timeline_value(slot, true, reserver.get_id());
+		}
+		drop(reserver);
+
+		let start = Instant::now();
+		let data = || ipc_info.snapshot(IPC_TIMEOUT).unwrap();
+		while data().timeline_reservations[0].owner != 0 {
+			assert!(start.elapsed() < SOCKET_TIMEOUT);
+			thread::sleep(Duration::from_millis(10));
+		}
+		assert_eq!(data().writing_slots, 0);
+
+		// Without GPU images, transfers only publish the RAM image
+		conn.send_command(CommandMsg::RamTransfer(CommRamTransfer {
+			image_name: "headless".to_string(),
+			direction: RamTransferDirection::Upload,
+		}))
+		.unwrap();
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::RamTransfer(res) => assert!(res.success),
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		conn.send_command(CommandMsg::ListImages(CommListImages {}))
+			.unwrap();
+		match conn.recv_result().unwrap().unwrap() {
+			ResultMsg::ListImages(res) => {
+				assert_eq!(res.images.len(), 1);
+				assert_eq!(res.images[0].gpu_device_uuids, vec![uuid::Uuid::nil().as_u128()]);
+			}
+			res => panic!("Received wrong result {:?}", res.tag()),
+		};
+
+		stop_bit.store(true, Ordering::Relaxed);
+
+		server_thread.join().unwrap();
+	}
 }
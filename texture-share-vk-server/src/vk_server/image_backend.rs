@@ -0,0 +1,75 @@
+use std::alloc::Layout;
+use std::os::fd::OwnedFd;
+
+use texture_share_ipc::platform::img_data::ImgFormat;
+use texture_share_ipc::platform::ipc_commands::CommInitImage;
+use texture_share_ipc::platform::ShmemDataInternal;
+use texture_share_ipc::AlignedRamBuffer;
+
+// Allocates the images of the server. The backend is chosen when the server is started
+pub(crate) trait ImageBackend {
+	// Prepares the backend to create images for the given GPU. Fails if the GPU can't be used
+	fn open_device(&mut self, gpu_device_uuid: u128) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Fails if images of format can't be shared on an opened GPU
+	fn check_format(
+		&self,
+		gpu_device_uuid: u128,
+		format: ImgFormat,
+	) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Creates an empty image for an opened GPU. The nil uuid requests an image shared through RAM
+	fn create_image(&self, gpu_device_uuid: u128) -> Box<dyn ServerImage>;
+}
+
+// Ring of images stored for one GPU, indexed by the slots in the shared memory. Copies between
+// GPUs and RAM go through a RAM buffer shared by all images with the same name
+pub(crate) trait ServerImage {
+	// Recreates the ring with the size and buffer count of cmd. The RAM buffer has to be imported
+	// again afterwards
+	fn resize(&mut self, cmd: &CommInitImage, id: u32) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Size and alignment of the RAM buffer needed to copy a slot, None if any buffer works
+	fn ram_buffer_layout(&self) -> Option<Layout>;
+
+	// Makes the image copy its slots through ram_buffer, replacing any previously imported buffer
+	fn import_ram_buffer(
+		&mut self,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Stores size, format and id of the images in the shared memory
+	fn update_shmem_data(&self, shmem_data: &mut ShmemDataInternal);
+
+	// Handles that clients need to import the ring, in slot order
+	fn export_handles(&self, features: u64) -> Result<Vec<OwnedFd>, Box<dyn std::error::Error>>;
+
+	// Copies a slot into ram_buffer once all writes up to timeline_value completed. Fails if
+	// ram_buffer isn't the imported buffer
+	fn read_slot(
+		&self,
+		slot: usize,
+		timeline_value: u64,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Copies ram_buffer into a slot once all reads up to timeline_value completed. Fails if
+	// ram_buffer isn't the imported buffer
+	fn write_slot(
+		&mut self,
+		slot: usize,
+		timeline_value: u64,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Completes a timeline value that a client reserved for the slot but never submitted, once
+	// the accesses before it completed
+	fn skip_timeline_value(
+		&self,
+		slot: usize,
+		timeline_value: u64,
+	) -> Result<(), Box<dyn std::error::Error>>;
+
+	// Images shared through RAM are only updated by RamTransfer commands
+	fn is_ram_image(&self) -> bool;
+}
@@ -0,0 +1,162 @@
+use std::alloc::Layout;
+use std::io::{Error, ErrorKind};
+use std::os::fd::OwnedFd;
+
+use texture_share_ipc::platform::img_data::ImgFormat;
+use texture_share_ipc::platform::ipc_commands::CommInitImage;
+use texture_share_ipc::platform::ShmemDataInternal;
+use texture_share_ipc::uuid;
+use texture_share_ipc::AlignedRamBuffer;
+use texture_share_ipc::RamImage;
+
+use super::image_backend::{ImageBackend, ServerImage};
+
+// Backend that doesn't use Vulkan. Only images shared through RAM are available, which allows
+// running the server on hosts without a GPU driver
+#[derive(Default)]
+pub(crate) struct ShmImageBackend;
+
+impl ImageBackend for ShmImageBackend {
+	fn open_device(&mut self, gpu_device_uuid: u128) -> Result<(), Box<dyn std::error::Error>> {
+		Err(Box::new(Error::new(
+			ErrorKind::Unsupported,
+			format!(
+				"Can't share images with GPU {}, the server runs without Vulkan",
+				uuid::Uuid::from_u128(gpu_device_uuid)
+			),
+		)))
+	}
+
+	fn check_format(
+		&self,
+		_gpu_device_uuid: u128,
+		format: ImgFormat,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		Ok(ShmImage::check_format(format)?)
+	}
+
+	fn create_image(&self, _gpu_device_uuid: u128) -> Box<dyn ServerImage> {
+		Box::new(ShmImage::default())
+	}
+}
+
+// Image shared through a memfd, see features::RAM_IMAGES. Clients access it directly, so it
+// consists of a single image regardless of the buffer count
+#[derive(Default)]
+pub(crate) struct ShmImage {
+	ram_image: Option<RamImage>,
+}
+
+impl ShmImage {
+	// Allocates an image described by data
+	pub(crate) fn new(data: &ShmemDataInternal) -> Result<ShmImage, Error> {
+		Ok(ShmImage {
+			ram_image: Some(RamImage::new(data)?),
+		})
+	}
+
+	// RAM images hold tightly packed pixels, which works for any format of known size
+	pub(crate) fn check_format(format: ImgFormat) -> Result<(), Error> {
+		match format.bytes_per_pixel() {
+			0 => Err(Error::new(
+				ErrorKind::Unsupported,
+				format!("Can't share {:?} images through RAM", format),
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	fn not_allocated() -> Error {
+		Error::new(ErrorKind::NotFound, "RAM image was not allocated")
+	}
+
+	// Number of bytes that fit into both the image and ram_buffer. The buffer stays unallocated
+	// until an image is stored on a GPU
+	fn copy_size(pixels: &[u8], ram_buffer: &AlignedRamBuffer) -> usize {
+		match ram_buffer.ptr.is_null() {
+			true => 0,
+			false => pixels.len().min(ram_buffer.layout.size()),
+		}
+	}
+}
+
+impl ServerImage for ShmImage {
+	fn resize(&mut self, cmd: &CommInitImage, id: u32) -> Result<(), Box<dyn std::error::Error>> {
+		let mut data = ShmemDataInternal::default();
+		data.width = cmd.width;
+		data.height = cmd.height;
+		data.format = cmd.format;
+		data.handle_id = id;
+
+		*self = ShmImage::new(&data)?;
+		Ok(())
+	}
+
+	// Pixels are copied by the CPU, so only the size of the buffer limits the copies
+	fn ram_buffer_layout(&self) -> Option<Layout> {
+		None
+	}
+
+	fn import_ram_buffer(
+		&mut self,
+		_ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		Ok(())
+	}
+
+	fn update_shmem_data(&self, shmem_data: &mut ShmemDataInternal) {
+		if let Some(ram_image) = &self.ram_image {
+			shmem_data.width = ram_image.width;
+			shmem_data.height = ram_image.height;
+			shmem_data.format = ram_image.format;
+			shmem_data.allocation_size = ram_image.as_slice().len() as u64;
+			shmem_data.handle_id = ram_image.handle_id;
+		}
+	}
+
+	fn export_handles(&self, _features: u64) -> Result<Vec<OwnedFd>, Box<dyn std::error::Error>> {
+		let ram_image = self.ram_image.as_ref().ok_or_else(ShmImage::not_allocated)?;
+		Ok(vec![ram_image.export_handle()?])
+	}
+
+	fn read_slot(
+		&self,
+		_slot: usize,
+		_timeline_value: u64,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let ram_image = self.ram_image.as_ref().ok_or_else(ShmImage::not_allocated)?;
+		let pixels = ram_image.as_slice();
+		let len = ShmImage::copy_size(pixels, ram_buffer);
+		unsafe { std::ptr::copy_nonoverlapping(pixels.as_ptr(), ram_buffer.ptr as *mut u8, len) };
+		Ok(())
+	}
+
+	fn write_slot(
+		&mut self,
+		_slot: usize,
+		_timeline_value: u64,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let ram_image = self.ram_image.as_mut().ok_or_else(ShmImage::not_allocated)?;
+		let pixels = ram_image.as_mut_slice();
+		let len = ShmImage::copy_size(pixels, ram_buffer);
+		unsafe {
+			std::ptr::copy_nonoverlapping(ram_buffer.ptr as *const u8, pixels.as_mut_ptr(), len)
+		};
+		Ok(())
+	}
+
+	// RAM images have no timeline, accesses are ordered by the server
+	fn skip_timeline_value(
+		&self,
+		_slot: usize,
+		_timeline_value: u64,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		Ok(())
+	}
+
+	fn is_ram_image(&self) -> bool {
+		true
+	}
+}
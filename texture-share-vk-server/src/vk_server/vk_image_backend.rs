@@ -0,0 +1,372 @@
+use std::alloc::Layout;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::os::fd::OwnedFd;
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use texture_share_ipc::platform::img_data::ImgFormat;
+use texture_share_ipc::platform::ipc_commands::{features, CommInitImage};
+use texture_share_ipc::platform::ShmemDataInternal;
+use texture_share_ipc::uuid;
+use texture_share_ipc::AlignedRamBuffer;
+use texture_share_vk_base::ash::vk;
+use texture_share_vk_base::vk_cpu_buffer::VkCpuBuffer;
+use texture_share_vk_base::vk_device::{VkDevice, VkPhysicalDeviceOptions};
+use texture_share_vk_base::vk_instance::VkInstance;
+use texture_share_vk_base::vk_shared_image::VkSharedImage;
+
+use super::image_backend::{ImageBackend, ServerImage};
+use super::shm_image_backend::ShmImage;
+use super::VkServer;
+
+// Stores images in Vulkan memory that GPU clients import. Devices are created on demand for
+// every GPU a client uses
+pub(crate) struct VkImageBackend {
+	// Images keep their device alive, devices must be dropped before the instance
+	vk_devices: HashMap<u128, Rc<VkDevice>>,
+	vk_instance: Rc<VkInstance>,
+}
+
+impl VkImageBackend {
+	pub(crate) fn new(
+		physical_device_options: Option<VkPhysicalDeviceOptions>,
+	) -> Result<VkImageBackend, Box<dyn std::error::Error>> {
+		let vk_instance = VkInstance::new(
+			None,
+			c"VkServer",
+			false,
+		)?;
+		let vk_device = VkDevice::new(&vk_instance, physical_device_options)?;
+
+		let gpu_device_uuid =
+			VkDevice::get_gpu_device_uuid(&vk_instance.instance, vk_device.physical_device);
+
+		let mut vk_devices = HashMap::default();
+		vk_devices.insert(gpu_device_uuid.as_u128(), Rc::new(vk_device));
+
+		Ok(VkImageBackend {
+			vk_devices,
+			vk_instance: Rc::new(vk_instance),
+		})
+	}
+}
+
+impl ImageBackend for VkImageBackend {
+	fn open_device(&mut self, gpu_device_uuid: u128) -> Result<(), Box<dyn std::error::Error>> {
+		// Check that a device with the given uuid is initialized
+		if let Entry::Vacant(v) = self.vk_devices.entry(gpu_device_uuid) {
+			let new_vk_device = VkDevice::new(
+				&self.vk_instance,
+				Some(VkPhysicalDeviceOptions {
+					device_uuid: Some(uuid::Uuid::from_u128(gpu_device_uuid)),
+					..Default::default()
+				}),
+			)
+			.map_err(|err| {
+				Error::new(
+					ErrorKind::NotFound,
+					format!(
+						"Failed to create device for GPU {}: {}",
+						uuid::Uuid::from_u128(gpu_device_uuid),
+						err
+					),
+				)
+			})?;
+			v.insert(Rc::new(new_vk_device));
+		}
+		Ok(())
+	}
+
+	fn check_format(
+		&self,
+		gpu_device_uuid: u128,
+		format: ImgFormat,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		if VkServer::is_ram_device(gpu_device_uuid) {
+			return Ok(ShmImage::check_format(format)?);
+		}
+
+		let vk_device = self
+			.vk_devices
+			.get(&gpu_device_uuid)
+			.expect("Failed to find device for VkSharedImage");
+		VkSharedImage::check_format_support(
+			&self.vk_instance,
+			vk_device,
+			VkSharedImage::get_vk_format(format),
+			vk::ExternalMemoryFeatureFlags::EXPORTABLE,
+		)
+		.map_err(|err| {
+			Error::new(
+				ErrorKind::Unsupported,
+				format!(
+					"GPU {} can't share {:?} images: {}",
+					uuid::Uuid::from_u128(gpu_device_uuid),
+					format,
+					err
+				),
+			)
+		})?;
+		Ok(())
+	}
+
+	fn create_image(&self, gpu_device_uuid: u128) -> Box<dyn ServerImage> {
+		if VkServer::is_ram_device(gpu_device_uuid) {
+			return Box::new(ShmImage::default());
+		}
+
+		Box::new(VkServerImage {
+			vk_shared_images: Vec::new(),
+			cpu_buffer: None,
+			vk_device: self
+				.vk_devices
+				.get(&gpu_device_uuid)
+				.expect("Failed to find device for VkSharedImage")
+				.clone(),
+			vk_instance: self.vk_instance.clone(),
+		})
+	}
+}
+
+// Ring of images on one GPU. The images are destroyed when dropped
+pub(crate) struct VkServerImage {
+	vk_shared_images: Vec<VkSharedImage>,
+	// Imports the RAM buffer that all slots are copied through
+	cpu_buffer: Option<VkCpuBuffer>,
+	vk_device: Rc<VkDevice>,
+	vk_instance: Rc<VkInstance>,
+}
+
+impl Drop for VkServerImage {
+	fn drop(&mut self) {
+		self.vk_shared_images
+			.drain(..)
+			.for_each(|img| img.destroy(&self.vk_device));
+		if let Some(cpu_buffer) = self.cpu_buffer.take() {
+			cpu_buffer.destroy(&self.vk_device);
+		}
+	}
+}
+
+impl VkServerImage {
+	fn get_slot(&self, slot: usize) -> Result<&VkSharedImage, Error> {
+		self.vk_shared_images.get(slot).ok_or_else(|| {
+			Error::new(ErrorKind::InvalidInput, format!("Image has no slot {}", slot))
+		})
+	}
+
+	// The CPU buffer that imports ram_buffer. Fails if the image imports another buffer
+	fn get_cpu_buffer(&self, ram_buffer: &AlignedRamBuffer) -> Result<&VkCpuBuffer, Error> {
+		self.cpu_buffer
+			.as_ref()
+			.filter(|cpu_buffer| {
+				cpu_buffer.ram_memory == ram_buffer.ptr
+					&& cpu_buffer.buffer_size == ram_buffer.layout.size() as u64
+			})
+			.ok_or_else(|| Error::new(ErrorKind::NotFound, "RAM buffer was not imported"))
+	}
+}
+
+impl ServerImage for VkServerImage {
+	fn resize(&mut self, cmd: &CommInitImage, id: u32) -> Result<(), Box<dyn std::error::Error>> {
+		let format = VkSharedImage::get_vk_format(cmd.format);
+		while self.vk_shared_images.len() > cmd.buffer_count as usize {
+			self.vk_shared_images
+				.pop()
+				.unwrap()
+				.destroy(&self.vk_device);
+		}
+
+		// Existing slots are resized, only missing ones are created at the new size
+		for vk_shared_image in self.vk_shared_images.iter_mut() {
+			vk_shared_image.resize_image(
+				&self.vk_instance,
+				&self.vk_device,
+				cmd.width,
+				cmd.height,
+				format,
+				id,
+			)?;
+		}
+		while self.vk_shared_images.len() < cmd.buffer_count as usize {
+			self.vk_shared_images.push(VkSharedImage::new(
+				&self.vk_instance,
+				&self.vk_device,
+				cmd.width,
+				cmd.height,
+				format,
+				id,
+			)?);
+		}
+
+		Ok(())
+	}
+
+	// Imported host memory must be aligned to the device's requirement and span a multiple of it
+	fn ram_buffer_layout(&self) -> Option<Layout> {
+		let size = self
+			.vk_shared_images
+			.iter()
+			.map(|img| img.get_image_data().allocation_size as usize)
+			.max()?;
+		let align = VkDevice::get_external_memory_host_properties(
+			&self.vk_instance.instance,
+			self.vk_device.physical_device,
+		)
+		.min_imported_host_pointer_alignment as usize;
+
+		Layout::from_size_align(size, align)
+			.ok()
+			.map(|layout| layout.pad_to_align())
+	}
+
+	fn import_ram_buffer(
+		&mut self,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		if self.get_cpu_buffer(ram_buffer).is_ok() {
+			return Ok(());
+		}
+
+		if let Some(cpu_buffer) = self.cpu_buffer.take() {
+			cpu_buffer.destroy(&self.vk_device);
+		}
+		let ram_memory = NonNull::new(ram_buffer.ptr)
+			.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "RAM buffer is not allocated"))?;
+		self.cpu_buffer = Some(VkCpuBuffer::new(
+			&self.vk_instance,
+			&self.vk_device,
+			ram_buffer.layout.size() as u64,
+			Some(ram_memory),
+		)?);
+		Ok(())
+	}
+
+	fn update_shmem_data(&self, shmem_data: &mut ShmemDataInternal) {
+		let vk_data = self.vk_shared_images[0].get_image_data();
+
+		shmem_data.width = vk_data.width;
+		shmem_data.height = vk_data.height;
+		shmem_data.format = VkSharedImage::get_img_format(vk_data.format);
+		shmem_data.allocation_size = vk_data.allocation_size;
+		shmem_data.handle_id = vk_data.id;
+	}
+
+	// The memory fd of each slot followed by its semaphore fd
+	fn export_handles(&self, features: u64) -> Result<Vec<OwnedFd>, Box<dyn std::error::Error>> {
+		let mut handles = Vec::new();
+		for vk_shared_image in self.vk_shared_images.iter() {
+			handles.push(vk_shared_image.export_handle(&self.vk_device)?);
+			if features & features::TIMELINE_SEMAPHORE != 0 {
+				handles.push(vk_shared_image.export_semaphore_handle(&self.vk_device)?);
+			}
+		}
+
+		Ok(handles)
+	}
+
+	// The copies complete before returning, so waiting for the timeline value stored in the shared
+	// memory is enough to order them after the clients' blits
+	fn read_slot(
+		&self,
+		slot: usize,
+		timeline_value: u64,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let rimg = self.get_slot(slot)?;
+		rimg.wait_timeline(&self.vk_device, timeline_value)?;
+		self.get_cpu_buffer(ram_buffer)?.read_image_to_cpu(
+			&self.vk_device,
+			rimg.image,
+			rimg.image_layout,
+			rimg.get_image_data().width,
+			rimg.get_image_data().height,
+		)?;
+		Ok(())
+	}
+
+	fn write_slot(
+		&mut self,
+		slot: usize,
+		timeline_value: u64,
+		ram_buffer: &AlignedRamBuffer,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		let wimg = self.get_slot(slot)?;
+		wimg.wait_timeline(&self.vk_device, timeline_value)?;
+		self.get_cpu_buffer(ram_buffer)?.write_image_from_cpu(
+			&self.vk_device,
+			wimg.image,
+			wimg.image_layout,
+			wimg.get_image_data().width,
+			wimg.get_image_data().height,
+		)?;
+		Ok(())
+	}
+
+	fn skip_timeline_value(
+		&self,
+		slot: usize,
+		timeline_value: u64,
+	) -> Result<(), Box<dyn std::error::Error>> {
+		self.get_slot(slot)?
+			.skip_timeline_value(&self.vk_device, timeline_value)?;
+		Ok(())
+	}
+
+	fn is_ram_image(&self) -> bool {
+		false
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use texture_share_ipc::platform::img_data::ImgFormat;
+	use texture_share_ipc::platform::ipc_commands::CommInitImage;
+	use texture_share_ipc::AlignedRamBuffer;
+
+	use super::super::image_backend::ImageBackend;
+	use super::super::VkServer;
+	use super::VkImageBackend;
+
+	#[test]
+	fn vk_server_image_ram_buffer() {
+		let backend = VkImageBackend::new(None).unwrap();
+		let gpu_device_uuid = *backend.vk_devices.keys().next().unwrap();
+		let mut ram_buffer = AlignedRamBuffer::default();
+		let mut image = backend.create_image(gpu_device_uuid);
+
+		let mut cmd = CommInitImage {
+			image_name: "test_image".to_string(),
+			shmem_name: String::new(),
+			width: 4,
+			height: 4,
+			format: ImgFormat::R8G8B8A8,
+			overwrite_existing: true,
+			gpu_device_uuid,
+			buffer_count: 3,
+		};
+		image.resize(&cmd, 1).unwrap();
+		VkServer::import_ram_buffer(&mut [&mut image], &mut ram_buffer).unwrap();
+
+		// Growing the slots reallocates the buffer once, every slot imports the new one
+		cmd.width = 1920;
+		cmd.height = 1080;
+		image.resize(&cmd, 2).unwrap();
+		VkServer::import_ram_buffer(&mut [&mut image], &mut ram_buffer).unwrap();
+		let len = 1920 * 1080 * 4;
+		assert!(ram_buffer.layout.size() >= len);
+
+		let pixels = || unsafe { std::slice::from_raw_parts_mut(ram_buffer.ptr as *mut u8, len) };
+		for slot in 0..3 {
+			pixels().fill(slot as u8 + 1);
+			image.write_slot(slot, 0, &ram_buffer).unwrap();
+		}
+		for slot in 0..3 {
+			pixels().fill(0);
+			image.read_slot(slot, 0, &ram_buffer).unwrap();
+			assert!(pixels().iter().all(|&p| p == slot as u8 + 1));
+		}
+	}
+}